@@ -0,0 +1,326 @@
+// Just enough JSON for baseline files
+//
+// The workspace builds without external crates, so instead of serde this
+// is a small reader and writer for the values a baseline uses: objects,
+// arrays, strings, numbers, booleans and null.
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // Keeps the order the fields were written in
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    // Pretty-printed with two-space indentation
+    pub(crate) fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Value::Null => out.push_str("null"),
+            Value::Bool(b) => write!(out, "{}", b).unwrap(),
+            // JSON has no NaN or infinity
+            Value::Number(x) if !x.is_finite() => out.push_str("null"),
+            Value::Number(x) => write!(out, "{}", x).unwrap(),
+            Value::String(s) => write_string(out, s),
+            Value::Array(items) if items.is_empty() => out.push_str("[]"),
+            Value::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    out.push_str(if i == 0 { "\n" } else { ",\n" });
+                    push_indent(out, indent + 1);
+                    item.write(out, indent + 1);
+                }
+                out.push('\n');
+                push_indent(out, indent);
+                out.push(']');
+            }
+            Value::Object(fields) if fields.is_empty() => out.push_str("{}"),
+            Value::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    out.push_str(if i == 0 { "\n" } else { ",\n" });
+                    push_indent(out, indent + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                }
+                out.push('\n');
+                push_indent(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Parses a whole document; the error says what was expected and where
+pub(crate) fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("end of input"));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, expected: &str) -> String {
+        match self.chars.get(self.pos) {
+            Some(c) => format!(
+                "expected {} at offset {}, found {:?}",
+                expected, self.pos, c
+            ),
+            None => format!(
+                "expected {} at offset {}, found end of input",
+                expected, self.pos
+            ),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        let end = self.pos + word.len();
+        if self
+            .chars
+            .get(self.pos..end)
+            .is_some_and(|s| s.iter().copied().eq(word.chars()))
+        {
+            self.pos = end;
+            Ok(value)
+        } else {
+            Err(self.error(word))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some('n') => self.keyword("null", Value::Null),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return Err(self.error("',' or ']'"));
+                        }
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if !self.eat('}') {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        if !self.eat(':') {
+                            return Err(self.error("':'"));
+                        }
+                        fields.push((key, self.value()?));
+                        if self.eat('}') {
+                            break;
+                        }
+                        if !self.eat(',') {
+                            return Err(self.error("',' or '}'"));
+                        }
+                    }
+                }
+                Ok(Value::Object(fields))
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(self.error("a value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+        {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().map(Value::Number).map_err(|_| {
+            self.pos = start;
+            self.error("a number")
+        })
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.chars.get(self.pos) != Some(&'"') {
+            return Err(self.error("a string"));
+        }
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = *self
+                .chars
+                .get(self.pos)
+                .ok_or_else(|| self.error("closing '\"'"))?;
+            self.pos += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let escape = self.chars.get(self.pos).copied();
+                    self.pos += 1;
+                    match escape {
+                        Some('"') => s.push('"'),
+                        Some('\\') => s.push('\\'),
+                        Some('/') => s.push('/'),
+                        Some('n') => s.push('\n'),
+                        Some('r') => s.push('\r'),
+                        Some('t') => s.push('\t'),
+                        Some('b') => s.push('\u{8}'),
+                        Some('f') => s.push('\u{c}'),
+                        Some('u') => {
+                            // Surrogate pairs are not needed for baselines
+                            let hex: String = self.chars.iter().skip(self.pos).take(4).collect();
+                            let code = u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("four hex digits"))?;
+                            self.pos += 4;
+                            s.push(code);
+                        }
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.error("an escape sequence"));
+                        }
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let value = Value::Object(vec![
+            (
+                "name".to_string(),
+                Value::String("a \"quoted\"\n\tname".to_string()),
+            ),
+            (
+                "values".to_string(),
+                Value::Array(vec![
+                    Value::Number(1.5),
+                    Value::Number(-2e-7),
+                    Value::Bool(true),
+                    Value::Null,
+                ]),
+            ),
+            ("empty".to_string(), Value::Object(vec![])),
+        ]);
+        let mut text = String::new();
+        value.write(&mut text, 0);
+        assert_eq!(parse(&text), Ok(value));
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_problem() {
+        assert_eq!(
+            parse("{\"a\" 1}"),
+            Err("expected ':' at offset 5, found '1'".to_string())
+        );
+        assert_eq!(
+            parse("[1, 2"),
+            Err("expected ',' or ']' at offset 5, found end of input".to_string())
+        );
+        assert!(parse("[1] x").is_err());
+        assert_eq!(parse(" \"\\u00e9\" "), Ok(Value::String("é".to_string())));
+    }
+}
@@ -0,0 +1,623 @@
+// Micro-benchmarks with warmup, repeated sampling and saved baselines
+//
+// A Suite holds named benchmarks. run() measures each one in turn:
+//
+// 1. Warmup: call it until the warmup time is used up, which also warms
+//    caches and the branch predictor, and estimates the time per call.
+// 2. Sampling: time `samples` batches of calls, each batch sized to take
+//    about `sample_time`, so that even nanosecond routines are measured
+//    well above the timer's resolution.
+//
+// The Report has statistics per benchmark (see stats) and prints as a
+// table. It can be saved as a JSON baseline and a later run compared with
+// it to spot regressions:
+//
+//     let mut suite = Suite::new();
+//     suite.bench("sum", || (0..1000u64).sum::<u64>());
+//     let report = suite.run();
+//     println!("{}", report);
+//     report.compare_with_saved("sums");
+//
+// compare_with_saved keeps one baseline per name under the target directory
+// and prints how the run compares; update_baseline does the same for any
+// path and threshold, and leaves the printing to the caller.
+pub mod stats;
+
+mod json;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hint::black_box;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use json::Value;
+pub use stats::{format_nanos, Outliers, Stats};
+
+pub struct Suite<'a> {
+    warmup: Duration,
+    samples: usize,
+    sample_time: Duration,
+    benches: Vec<(String, Box<dyn FnMut() + 'a>)>,
+}
+
+impl<'a> Suite<'a> {
+    pub fn new() -> Self {
+        Suite {
+            warmup: Duration::from_millis(100),
+            samples: 30,
+            sample_time: Duration::from_millis(10),
+            benches: Vec::new(),
+        }
+    }
+
+    pub fn warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    // At least one sample is always taken
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    // Target duration of one batch of calls; a routine slower than this
+    // is called once per sample
+    pub fn sample_time(mut self, sample_time: Duration) -> Self {
+        self.sample_time = sample_time;
+        self
+    }
+
+    // The routine's result goes through black_box, so the work that
+    // produces it cannot be optimised away
+    pub fn bench<R, F>(&mut self, name: &str, mut routine: F) -> &mut Self
+    where
+        F: FnMut() -> R + 'a,
+    {
+        self.benches.push((
+            name.to_string(),
+            Box::new(move || {
+                black_box(routine());
+            }),
+        ));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.benches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.benches.is_empty()
+    }
+
+    // Runs every benchmark, in the order they were added
+    pub fn run(&mut self) -> Report {
+        let mut results = Vec::with_capacity(self.benches.len());
+        for (name, routine) in &mut self.benches {
+            let iterations = warm_up(routine, self.warmup, self.sample_time);
+            let samples: Vec<f64> = (0..self.samples)
+                .map(|_| {
+                    let start = Instant::now();
+                    for _ in 0..iterations {
+                        routine();
+                    }
+                    start.elapsed().as_nanos() as f64 / iterations as f64
+                })
+                .collect();
+            results.push(BenchResult {
+                name: name.clone(),
+                iterations,
+                stats: Stats::from_samples(&samples).expect("at least one sample"),
+            });
+        }
+        Report { results }
+    }
+}
+
+impl Default for Suite<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Calls the routine for the warmup time (at least once) and returns how
+// many calls fit in one sample
+fn warm_up(routine: &mut dyn FnMut(), warmup: Duration, sample_time: Duration) -> u64 {
+    let start = Instant::now();
+    let mut calls = 0u64;
+    while calls == 0 || start.elapsed() < warmup {
+        routine();
+        calls += 1;
+    }
+    let per_call = start.elapsed().as_nanos() / calls as u128;
+    (sample_time.as_nanos() / per_call.max(1)).clamp(1, u64::MAX as u128) as u64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub name: String,
+    // Calls per sample
+    pub iterations: u64,
+    pub stats: Stats,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub results: Vec<BenchResult>,
+}
+
+#[derive(Debug)]
+pub enum BaselineError {
+    Io(io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for BaselineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BaselineError::Io(e) => write!(f, "cannot access baseline: {}", e),
+            BaselineError::Parse(why) => write!(f, "malformed baseline: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for BaselineError {}
+
+impl From<io::Error> for BaselineError {
+    fn from(e: io::Error) -> Self {
+        BaselineError::Io(e)
+    }
+}
+
+const BASELINE_VERSION: f64 = 1.0;
+
+// Threshold used by compare_with_saved; demo timings are noisy
+const SAVED_THRESHOLD: f64 = 0.10;
+
+// Where compare_with_saved keeps the baseline called `name`: under bench/ in
+// $CARGO_TARGET_DIR if set, otherwise in the workspace's target directory.
+// Either way it doesn't depend on where cargo run was started.
+pub fn baseline_path(name: &str) -> PathBuf {
+    let target = env::var_os("CARGO_TARGET_DIR").map(PathBuf::from);
+    baseline_path_in(target.as_deref(), name)
+}
+
+// baseline_path with the target directory passed in instead of read from
+// the environment
+fn baseline_path_in(target_dir: Option<&Path>, name: &str) -> PathBuf {
+    let target = match target_dir {
+        Some(dir) => dir.to_path_buf(),
+        // This crate lives in <workspace>/crates/bench
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("../../target"),
+    };
+    target.join("bench").join(format!("{}.json", name))
+}
+
+impl Report {
+    pub fn get(&self, name: &str) -> Option<&BenchResult> {
+        self.results.iter().find(|result| result.name == name)
+    }
+
+    pub fn to_json(&self) -> String {
+        let number = |x: f64| Value::Number(x);
+        let benchmarks = self
+            .results
+            .iter()
+            .map(|result| {
+                let stats = &result.stats;
+                let outliers = &stats.outliers;
+                Value::Object(vec![
+                    ("name".to_string(), Value::String(result.name.clone())),
+                    ("iterations".to_string(), number(result.iterations as f64)),
+                    ("samples".to_string(), number(stats.samples as f64)),
+                    ("mean".to_string(), number(stats.mean)),
+                    ("median".to_string(), number(stats.median)),
+                    ("p99".to_string(), number(stats.p99)),
+                    ("stddev".to_string(), number(stats.stddev)),
+                    ("min".to_string(), number(stats.min)),
+                    ("max".to_string(), number(stats.max)),
+                    (
+                        "outliers".to_string(),
+                        Value::Object(vec![
+                            ("low_severe".to_string(), number(outliers.low_severe as f64)),
+                            ("low_mild".to_string(), number(outliers.low_mild as f64)),
+                            ("high_mild".to_string(), number(outliers.high_mild as f64)),
+                            (
+                                "high_severe".to_string(),
+                                number(outliers.high_severe as f64),
+                            ),
+                        ]),
+                    ),
+                ])
+            })
+            .collect();
+        let document = Value::Object(vec![
+            ("version".to_string(), number(BASELINE_VERSION)),
+            ("unit".to_string(), Value::String("ns/iter".to_string())),
+            ("benchmarks".to_string(), Value::Array(benchmarks)),
+        ]);
+        let mut out = String::new();
+        document.write(&mut out, 0);
+        out.push('\n');
+        out
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, BaselineError> {
+        let document = json::parse(text).map_err(BaselineError::Parse)?;
+        let missing = |field: &str| BaselineError::Parse(format!("missing or invalid {}", field));
+        let version = document.get("version").and_then(Value::as_f64);
+        if version != Some(BASELINE_VERSION) {
+            return Err(BaselineError::Parse(format!(
+                "unsupported version {:?}",
+                version
+            )));
+        }
+        let benchmarks = document
+            .get("benchmarks")
+            .and_then(Value::as_array)
+            .ok_or_else(|| missing("benchmarks"))?;
+
+        let mut results = Vec::with_capacity(benchmarks.len());
+        for bench in benchmarks {
+            let field = |value: &Value, name: &str| {
+                value
+                    .get(name)
+                    .and_then(Value::as_f64)
+                    .ok_or_else(|| missing(name))
+            };
+            let name = bench
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| missing("name"))?;
+            let outliers = bench.get("outliers").ok_or_else(|| missing("outliers"))?;
+            results.push(BenchResult {
+                name: name.to_string(),
+                iterations: field(bench, "iterations")? as u64,
+                stats: Stats {
+                    samples: field(bench, "samples")? as usize,
+                    mean: field(bench, "mean")?,
+                    median: field(bench, "median")?,
+                    p99: field(bench, "p99")?,
+                    stddev: field(bench, "stddev")?,
+                    min: field(bench, "min")?,
+                    max: field(bench, "max")?,
+                    outliers: Outliers {
+                        low_severe: field(outliers, "low_severe")? as usize,
+                        low_mild: field(outliers, "low_mild")? as usize,
+                        high_mild: field(outliers, "high_mild")? as usize,
+                        high_severe: field(outliers, "high_severe")? as usize,
+                    },
+                },
+            });
+        }
+        Ok(Report { results })
+    }
+
+    // Creates missing parent directories
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BaselineError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, BaselineError> {
+        Report::from_json(&fs::read_to_string(path)?)
+    }
+
+    // A benchmark counts as changed when its median moved by more than
+    // `threshold` (0.05 = 5%) relative to the baseline. Medians are used
+    // because a few outliers move the mean far more easily.
+    pub fn compare(&self, baseline: &Report, threshold: f64) -> Comparison {
+        let rows = self
+            .results
+            .iter()
+            .map(|result| {
+                let current = result.stats.median;
+                let previous = baseline.get(&result.name).map(|b| b.stats.median);
+                let verdict = match previous {
+                    None => Verdict::New,
+                    Some(previous) if current > previous * (1.0 + threshold) => Verdict::Slower,
+                    Some(previous) if current < previous * (1.0 - threshold) => Verdict::Faster,
+                    Some(_) => Verdict::Unchanged,
+                };
+                ComparisonRow {
+                    name: result.name.clone(),
+                    baseline: previous,
+                    current,
+                    verdict,
+                }
+            })
+            .collect();
+        Comparison { rows }
+    }
+
+    // Compares with the baseline at `path`, if there is one, and then
+    // replaces it with this report
+    pub fn update_baseline(
+        &self,
+        path: impl AsRef<Path>,
+        threshold: f64,
+    ) -> Result<Option<Comparison>, BaselineError> {
+        let path = path.as_ref();
+        let comparison = match Report::load(path) {
+            Ok(baseline) => Some(self.compare(&baseline, threshold)),
+            Err(BaselineError::Io(e)) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.save(path)?;
+        Ok(comparison)
+    }
+
+    // update_baseline on baseline_path(name), printing what happened
+    pub fn compare_with_saved(&self, name: &str) {
+        match self.update_baseline(baseline_path(name), SAVED_THRESHOLD) {
+            Ok(Some(comparison)) => println!("Compared with the previous run:\n{}", comparison),
+            Ok(None) => println!("Saved a baseline for the next run"),
+            Err(e) => println!("Could not update the baseline: {}", e),
+        }
+    }
+}
+
+// Left-aligned name column as wide as the longest name
+fn name_width<'n>(names: impl Iterator<Item = &'n str>) -> usize {
+    names
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(9)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = name_width(self.results.iter().map(|r| r.name.as_str()));
+        write!(
+            f,
+            "{:<width$} {:>12} {:>12} {:>12} {:>12}  outliers",
+            "benchmark",
+            "mean",
+            "median",
+            "p99",
+            "stddev",
+            width = width
+        )?;
+        for result in &self.results {
+            let stats = &result.stats;
+            write!(
+                f,
+                "\n{:<width$} {:>12} {:>12} {:>12} {:>12}  {}/{}",
+                result.name,
+                format_nanos(stats.mean),
+                format_nanos(stats.median),
+                format_nanos(stats.p99),
+                format_nanos(stats.stddev),
+                stats.outliers.total(),
+                stats.samples,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Faster,
+    Slower,
+    Unchanged,
+    // Not in the baseline
+    New,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonRow {
+    pub name: String,
+    // Medians, in nanoseconds per iteration
+    pub baseline: Option<f64>,
+    pub current: f64,
+    pub verdict: Verdict,
+}
+
+impl ComparisonRow {
+    // Relative change of the median: 0.1 is 10% slower
+    pub fn change(&self) -> Option<f64> {
+        self.baseline.map(|baseline| self.current / baseline - 1.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub rows: Vec<ComparisonRow>,
+}
+
+impl Comparison {
+    pub fn regressions(&self) -> impl Iterator<Item = &ComparisonRow> {
+        self.rows
+            .iter()
+            .filter(|row| row.verdict == Verdict::Slower)
+    }
+
+    pub fn has_regressions(&self) -> bool {
+        self.regressions().next().is_some()
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = name_width(self.rows.iter().map(|r| r.name.as_str()));
+        write!(
+            f,
+            "{:<width$} {:>12} {:>12} {:>9}",
+            "benchmark",
+            "baseline",
+            "current",
+            "change",
+            width = width
+        )?;
+        for row in &self.rows {
+            let (baseline, change) = match row.change() {
+                Some(change) => (
+                    format_nanos(row.baseline.unwrap_or_default()),
+                    format!("{:+.1}%", change * 100.0),
+                ),
+                None => ("-".to_string(), "-".to_string()),
+            };
+            let verdict = match row.verdict {
+                Verdict::Faster => "faster",
+                Verdict::Slower => "REGRESSION",
+                Verdict::Unchanged => "no change",
+                Verdict::New => "new",
+            };
+            write!(
+                f,
+                "\n{:<width$} {:>12} {:>12} {:>9}  {}",
+                row.name,
+                baseline,
+                format_nanos(row.current),
+                change,
+                verdict,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quick_suite<'a>() -> Suite<'a> {
+        Suite::new()
+            .warmup(Duration::from_millis(1))
+            .samples(5)
+            .sample_time(Duration::from_micros(200))
+    }
+
+    fn result(name: &str, median: f64) -> BenchResult {
+        BenchResult {
+            name: name.to_string(),
+            iterations: 10,
+            stats: Stats::from_samples(&[median * 0.9, median, median * 1.1]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_suite_measures_every_benchmark() {
+        let data: Vec<u64> = (0..1000).collect();
+        let mut suite = quick_suite();
+        suite
+            .bench("sum", || data.iter().sum::<u64>())
+            .bench("sleep", || std::thread::sleep(Duration::from_micros(500)));
+        assert_eq!(suite.len(), 2);
+
+        let report = suite.run();
+        let names: Vec<_> = report.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["sum", "sleep"]);
+        for result in &report.results {
+            assert_eq!(result.stats.samples, 5);
+            assert!(result.stats.min <= result.stats.median);
+            assert!(result.stats.median <= result.stats.max);
+        }
+        // Fast routines are batched, slow ones run once per sample
+        assert!(report.get("sum").unwrap().iterations > 1);
+        assert_eq!(report.get("sleep").unwrap().iterations, 1);
+        assert!(report.get("sleep").unwrap().stats.min >= 500_000.0);
+
+        let table = report.to_string();
+        assert_eq!(table.lines().count(), 3);
+        assert!(table.lines().nth(2).unwrap().starts_with("sleep "));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let report = Report {
+            results: vec![result("fast \"one\"", 12.5), result("slow", 3.25e6)],
+        };
+        assert_eq!(Report::from_json(&report.to_json()).unwrap(), report);
+
+        assert!(matches!(
+            Report::from_json("{\"version\": 2, \"benchmarks\": []}"),
+            Err(BaselineError::Parse(_))
+        ));
+        assert!(matches!(
+            Report::from_json("{\"version\": 1}"),
+            Err(BaselineError::Parse(why)) if why == "missing or invalid benchmarks"
+        ));
+    }
+
+    #[test]
+    fn test_compare_flags_changes_beyond_threshold() {
+        let baseline = Report {
+            results: vec![result("a", 100.0), result("b", 100.0), result("c", 100.0)],
+        };
+        let current = Report {
+            results: vec![
+                result("a", 104.0),
+                result("b", 120.0),
+                result("c", 80.0),
+                result("d", 50.0),
+            ],
+        };
+        let comparison = current.compare(&baseline, 0.05);
+        let verdicts: Vec<_> = comparison.rows.iter().map(|row| row.verdict).collect();
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Unchanged,
+                Verdict::Slower,
+                Verdict::Faster,
+                Verdict::New
+            ]
+        );
+        let regressions: Vec<_> = comparison.regressions().map(|r| r.name.as_str()).collect();
+        assert_eq!(regressions, ["b"]);
+        assert!((comparison.rows[1].change().unwrap() - 0.2).abs() < 1e-9);
+        assert!(comparison.to_string().contains("+20.0%  REGRESSION"));
+    }
+
+    #[test]
+    fn test_update_baseline_compares_with_previous_run() {
+        let path = std::env::temp_dir()
+            .join(format!("bench-test-{}", std::process::id()))
+            .join("baseline.json");
+        let _ = fs::remove_file(&path);
+
+        let first = Report {
+            results: vec![result("a", 100.0)],
+        };
+        assert_eq!(first.update_baseline(&path, 0.05).unwrap(), None);
+        let second = Report {
+            results: vec![result("a", 200.0)],
+        };
+        let comparison = second.update_baseline(&path, 0.05).unwrap().unwrap();
+        assert!(comparison.has_regressions());
+        assert_eq!(Report::load(&path).unwrap(), second);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            first.update_baseline(&path, 0.05),
+            Err(BaselineError::Parse(_))
+        ));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_baseline_path_ignores_working_directory() {
+        let path = baseline_path_in(None, "demo");
+        assert!(path.is_absolute());
+        assert!(path.ends_with("target/bench/demo.json"));
+
+        let target = Path::new("/tmp/custom-target");
+        assert_eq!(
+            baseline_path_in(Some(target), "demo"),
+            target.join("bench/demo.json")
+        );
+    }
+}
@@ -0,0 +1,172 @@
+// Summary statistics over benchmark samples
+//
+// Each sample is the average time of one iteration, in nanoseconds, over a
+// batch of iterations. Outliers are classified with Tukey's fences: a
+// sample more than 1.5 interquartile ranges outside the middle half is a
+// mild outlier, more than 3 a severe one. They are counted, not dropped, so
+// a noisy machine shows up in the report instead of being hidden by it.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Outliers {
+    pub low_severe: usize,
+    pub low_mild: usize,
+    pub high_mild: usize,
+    pub high_severe: usize,
+}
+
+impl Outliers {
+    pub fn total(&self) -> usize {
+        self.low_severe + self.low_mild + self.high_mild + self.high_severe
+    }
+
+    fn classify(sorted: &[f64]) -> Self {
+        let q1 = percentile(sorted, 25.0);
+        let q3 = percentile(sorted, 75.0);
+        let iqr = q3 - q1;
+        let mut outliers = Outliers::default();
+        for &x in sorted {
+            if x < q1 - 3.0 * iqr {
+                outliers.low_severe += 1;
+            } else if x < q1 - 1.5 * iqr {
+                outliers.low_mild += 1;
+            } else if x > q3 + 3.0 * iqr {
+                outliers.high_severe += 1;
+            } else if x > q3 + 1.5 * iqr {
+                outliers.high_mild += 1;
+            }
+        }
+        outliers
+    }
+}
+
+impl fmt::Display for Outliers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} low, {} high ({} severe)",
+            self.low_severe + self.low_mild,
+            self.high_mild + self.high_severe,
+            self.low_severe + self.high_severe
+        )
+    }
+}
+
+// All times are nanoseconds per iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub samples: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub p99: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub outliers: Outliers,
+}
+
+impl Stats {
+    // None when there are no samples to summarise
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / n;
+        // Sample standard deviation; a single sample has none
+        let stddev = if sorted.len() > 1 {
+            let squares: f64 = sorted.iter().map(|x| (x - mean).powi(2)).sum();
+            (squares / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        Some(Stats {
+            samples: sorted.len(),
+            mean,
+            median: percentile(&sorted, 50.0),
+            p99: percentile(&sorted, 99.0),
+            stddev,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            outliers: Outliers::classify(&sorted),
+        })
+    }
+
+    // Iterations per second, from the median
+    pub fn throughput(&self) -> f64 {
+        1e9 / self.median
+    }
+}
+
+// Linear interpolation between the two closest ranks; `sorted` must be
+// non-empty and in ascending order
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let below = rank.floor() as usize;
+    let above = rank.ceil() as usize;
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+// Picks a unit so that the number stays readable: 12.34 ns, 5.67 ms, ...
+pub fn format_nanos(nanos: f64) -> String {
+    if nanos < 1e3 {
+        format!("{:.2} ns", nanos)
+    } else if nanos < 1e6 {
+        format!("{:.2} µs", nanos / 1e3)
+    } else if nanos < 1e9 {
+        format!("{:.2} ms", nanos / 1e6)
+    } else {
+        format!("{:.2} s", nanos / 1e9)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_of_known_samples() {
+        let stats = Stats::from_samples(&[4.0, 2.0, 8.0, 6.0]).unwrap();
+        assert_eq!(stats.samples, 4);
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!(stats.median, 5.0);
+        assert_eq!((stats.min, stats.max), (2.0, 8.0));
+        assert!((stats.stddev - 2.581_988_897).abs() < 1e-6);
+        assert!((stats.p99 - 7.94).abs() < 1e-9);
+        assert_eq!(stats.outliers.total(), 0);
+
+        let single = Stats::from_samples(&[3.0]).unwrap();
+        assert_eq!((single.median, single.p99, single.stddev), (3.0, 3.0, 0.0));
+        assert_eq!(Stats::from_samples(&[]), None);
+    }
+
+    #[test]
+    fn test_tukey_fences() {
+        // Quartiles 10 and 11, so the fences sit at 8.5/12.5 (mild) and
+        // 7/14 (severe)
+        let mut samples = vec![10.0, 11.0, 10.0, 11.0, 10.0, 11.0, 10.0, 11.0];
+        samples.extend([7.5, 50.0]);
+        let stats = Stats::from_samples(&samples).unwrap();
+        assert_eq!(
+            stats.outliers,
+            Outliers {
+                low_severe: 0,
+                low_mild: 1,
+                high_mild: 0,
+                high_severe: 1,
+            }
+        );
+        assert_eq!(stats.outliers.to_string(), "1 low, 1 high (1 severe)");
+    }
+
+    #[test]
+    fn test_format_nanos() {
+        assert_eq!(format_nanos(12.346), "12.35 ns");
+        assert_eq!(format_nanos(1_500.0), "1.50 µs");
+        assert_eq!(format_nanos(2_500_000.0), "2.50 ms");
+        assert_eq!(format_nanos(3e9), "3.00 s");
+    }
+}
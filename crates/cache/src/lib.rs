@@ -0,0 +1,432 @@
+// Bounded caches with pluggable eviction and per-entry expiry
+//
+// Cache<K, V, P> is the single-threaded cache; SyncCache<K, V, P> wraps it in
+// a Mutex for sharing between threads. P decides what to evict when the
+// cache is full:
+//
+// - Lru: least recently used
+// - Lfu: least frequently used
+// - WTinyLfu: small LRU window in front of a frequency-filtered main area
+pub mod policy;
+pub mod sync;
+
+mod sketch;
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+pub use policy::{EvictionPolicy, Lfu, Lru, WTinyLfu};
+pub use sync::SyncCache;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub loads: u64,
+    pub inserts: u64,
+    pub evictions: u64,
+    pub expirations: u64,
+}
+
+impl CacheStats {
+    pub fn requests(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        if self.requests() == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.requests() as f64
+        }
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate), {} evictions, {} expirations",
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.evictions,
+            self.expirations
+        )
+    }
+}
+
+struct Slot<V> {
+    value: V,
+    expires_at: Option<Instant>,
+}
+
+impl<V> Slot<V> {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+}
+
+pub struct Cache<K, V, P = Lru<K>> {
+    map: HashMap<K, Slot<V>>,
+    policy: P,
+    capacity: usize,
+    default_ttl: Option<Duration>,
+    stats: CacheStats,
+}
+
+impl<K: Hash + Eq + Clone, V> Cache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Cache::with_policy(capacity, Lru::new())
+    }
+}
+
+impl<K, V, P> Cache<K, V, P>
+where
+    K: Hash + Eq + Clone,
+    P: EvictionPolicy<K>,
+{
+    pub fn with_policy(capacity: usize, policy: P) -> Self {
+        assert!(capacity > 0, "cache capacity must be at least 1");
+        Cache {
+            map: HashMap::with_capacity(capacity),
+            policy,
+            capacity,
+            default_ttl: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    // Entries inserted with insert() expire this long after being written
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // May include expired entries that have not been looked at since
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let ttl = self.default_ttl;
+        self.insert_entry(key, value, ttl)
+    }
+
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.insert_entry(key, value, Some(ttl))
+    }
+
+    fn insert_entry(&mut self, key: K, value: V, ttl: Option<Duration>) -> Option<V> {
+        let slot = Slot {
+            value,
+            // A TTL too long to represent (e.g. Duration::MAX) never expires
+            expires_at: ttl.and_then(|ttl| Instant::now().checked_add(ttl)),
+        };
+        if let Some(existing) = self.map.get_mut(&key) {
+            self.policy.on_access(&key);
+            return Some(std::mem::replace(existing, slot).value);
+        }
+        // Make room before inserting, so the new entry is never the victim
+        while self.map.len() >= self.capacity {
+            match self.policy.evict() {
+                Some(victim) => {
+                    self.map.remove(&victim);
+                    self.stats.evictions += 1;
+                }
+                None => break,
+            }
+        }
+        self.policy.on_insert(&key);
+        self.map.insert(key, slot);
+        self.stats.inserts += 1;
+        None
+    }
+
+    // Drops the entry for `key` if its TTL has run out
+    fn expire<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let expired = self
+            .map
+            .get(key)
+            .is_some_and(|slot| slot.is_expired(Instant::now()));
+        if expired {
+            if let Some((key, _)) = self.map.remove_entry(key) {
+                self.policy.on_remove(&key);
+                self.stats.expirations += 1;
+            }
+        }
+        expired
+    }
+
+    // Looks up `key`, counting a hit or miss and marking it as used
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.expire(key);
+        match self.map.get_key_value(key) {
+            Some((key, slot)) => {
+                self.policy.on_access(key);
+                self.stats.hits += 1;
+                Some(&slot.value)
+            }
+            None => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Looks up `key` without touching stats or eviction order
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map
+            .get(key)
+            .filter(|slot| !slot.is_expired(Instant::now()))
+            .map(|slot| &slot.value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.peek(key).is_some()
+    }
+
+    // Read-through lookup: on a miss, `load` produces the value, which is
+    // cached before being returned
+    pub fn get_or_load<F>(&mut self, key: K, load: F) -> &V
+    where
+        F: FnOnce(&K) -> V,
+    {
+        match self.try_get_or_load(key, |key| Ok::<V, std::convert::Infallible>(load(key))) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    // Like get_or_load, but a failed load is returned and nothing is cached
+    pub fn try_get_or_load<F, E>(&mut self, key: K, load: F) -> Result<&V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        self.expire(&key);
+        if self.map.contains_key(&key) {
+            self.policy.on_access(&key);
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+            let value = load(&key)?;
+            self.stats.loads += 1;
+            self.insert(key.clone(), value);
+        }
+        Ok(&self.map[&key].value)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let (key, slot) = self.map.remove_entry(key)?;
+        self.policy.on_remove(&key);
+        Some(slot.value)
+    }
+
+    // Removes every expired entry and returns how many there were
+    pub fn purge_expired(&mut self) -> usize {
+        let now = Instant::now();
+        let expired: Vec<K> = self
+            .map
+            .iter()
+            .filter(|(_, slot)| slot.is_expired(now))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            self.map.remove(key);
+            self.policy.on_remove(key);
+        }
+        self.stats.expirations += expired.len() as u64;
+        expired.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+        self.policy.clear();
+    }
+
+    // Live entries in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let now = Instant::now();
+        self.map
+            .iter()
+            .filter(move |(_, slot)| !slot.is_expired(now))
+            .map(|(key, slot)| (key, &slot.value))
+    }
+
+    // For SyncCache, which runs loaders outside its lock
+    pub(crate) fn record_load(&mut self) {
+        self.stats.loads += 1;
+    }
+}
+
+impl<K, V, P> fmt::Debug for Cache<K, V, P>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: fmt::Debug,
+    P: EvictionPolicy<K>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capacity_is_enforced() {
+        let mut cache = Cache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.get("a");
+        cache.insert("c", 3);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains_key("a"));
+        assert!(!cache.contains_key("b"));
+        assert_eq!(cache.stats().evictions, 1);
+    }
+
+    #[test]
+    fn test_update_does_not_evict() {
+        let mut cache = Cache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.insert("a", 10), Some(1));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.peek("a"), Some(&10));
+        assert_eq!(cache.stats().evictions, 0);
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let mut cache = Cache::new(10).with_ttl(Duration::from_secs(3600));
+        cache.insert("fresh", 1);
+        cache.insert_with_ttl("stale", 2, Duration::ZERO);
+
+        assert!(!cache.contains_key("stale"));
+        assert_eq!(cache.get("stale"), None);
+        assert_eq!(cache.get("fresh"), Some(&1));
+        assert_eq!(cache.stats().expirations, 1);
+
+        cache.insert_with_ttl("stale2", 3, Duration::ZERO);
+        cache.insert_with_ttl("stale3", 4, Duration::ZERO);
+        assert_eq!(cache.purge_expired(), 2);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_ttl_too_long_never_expires() {
+        let mut cache = Cache::new(10).with_ttl(Duration::MAX);
+        cache.insert("default", 1);
+        cache.insert_with_ttl("explicit", 2, Duration::MAX);
+        assert_eq!(cache.purge_expired(), 0);
+        assert_eq!(cache.get("default"), Some(&1));
+        assert_eq!(cache.get("explicit"), Some(&2));
+    }
+
+    #[test]
+    fn test_hit_miss_stats() {
+        let mut cache = Cache::new(4);
+        cache.insert(1, "one");
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&2);
+        cache.peek(&3);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (2, 1));
+        assert!((stats.hit_rate() - 2.0 / 3.0).abs() < 1e-9);
+        cache.reset_stats();
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_loader_runs_only_on_miss() {
+        let mut cache = Cache::new(4);
+        let mut calls = 0;
+        for _ in 0..3 {
+            let value = cache.get_or_load(7, |k| {
+                calls += 1;
+                k * 6
+            });
+            assert_eq!(*value, 42);
+        }
+        assert_eq!(calls, 1);
+        assert_eq!(cache.stats().loads, 1);
+        assert_eq!(cache.stats().hits, 2);
+    }
+
+    #[test]
+    fn test_failed_load_is_not_cached() {
+        let mut cache: Cache<&str, i32> = Cache::new(4);
+        let result = cache.try_get_or_load("bad", |_| "nope".parse::<i32>());
+        assert!(result.is_err());
+        assert!(cache.is_empty());
+        assert_eq!(*cache.try_get_or_load("good", |_| "5".parse::<i32>()).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_string_keys_borrow_as_str() {
+        let mut cache: Cache<String, String> = Cache::new(2);
+        cache.insert("key".to_string(), "value".to_string());
+        assert_eq!(cache.get("key").map(String::as_str), Some("value"));
+        assert_eq!(cache.remove("key"), Some("value".to_string()));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_new_entry_survives_lfu_eviction() {
+        // Plain LFU would pick the newcomer (frequency 1) as the victim
+        let mut cache = Cache::with_policy(2, Lfu::new());
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        for _ in 0..5 {
+            cache.get("a");
+            cache.get("b");
+        }
+        cache.insert("c", 3);
+        assert!(cache.contains_key("c"));
+        assert_eq!(cache.len(), 2);
+    }
+}
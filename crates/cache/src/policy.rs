@@ -0,0 +1,363 @@
+// Eviction policies
+//
+// A policy only tracks keys; the cache owns the values. It is told about
+// every insert, access and removal, and names a victim when the cache needs
+// room.
+
+use crate::sketch::FrequencySketch;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+pub trait EvictionPolicy<K> {
+    fn on_insert(&mut self, key: &K);
+    fn on_access(&mut self, key: &K);
+    fn on_remove(&mut self, key: &K);
+    // Picks and forgets a key to evict; None if nothing is tracked
+    fn evict(&mut self) -> Option<K>;
+    fn clear(&mut self);
+}
+
+// Least recently used. Every use stamps the key with a new tick; the key
+// with the oldest tick is evicted.
+#[derive(Debug, Clone)]
+pub struct Lru<K> {
+    tick: u64,
+    ticks: HashMap<K, u64>,
+    order: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone> Lru<K> {
+    pub fn new() -> Self {
+        Lru {
+            tick: 0,
+            ticks: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.ticks.contains_key(key)
+    }
+
+    // Inserts `key` as the most recently used, or moves it there
+    pub fn push(&mut self, key: K) {
+        self.tick += 1;
+        if let Some(old) = self.ticks.insert(key.clone(), self.tick) {
+            self.order.remove(&old);
+        }
+        self.order.insert(self.tick, key);
+    }
+
+    pub fn touch(&mut self, key: &K) -> bool {
+        match self.ticks.get_mut(key) {
+            Some(tick) => {
+                let key = self.order.remove(tick).expect("tick without key");
+                self.tick += 1;
+                *tick = self.tick;
+                self.order.insert(self.tick, key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) -> bool {
+        match self.ticks.remove(key) {
+            Some(tick) => {
+                self.order.remove(&tick);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn peek_lru(&self) -> Option<&K> {
+        self.order.values().next()
+    }
+
+    pub fn pop_lru(&mut self) -> Option<K> {
+        let (_, key) = self.order.pop_first()?;
+        self.ticks.remove(&key);
+        Some(key)
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for Lru<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone> EvictionPolicy<K> for Lru<K> {
+    fn on_insert(&mut self, key: &K) {
+        self.push(key.clone());
+    }
+
+    fn on_access(&mut self, key: &K) {
+        self.touch(key);
+    }
+
+    fn on_remove(&mut self, key: &K) {
+        self.remove(key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        self.pop_lru()
+    }
+
+    fn clear(&mut self) {
+        self.ticks.clear();
+        self.order.clear();
+    }
+}
+
+// Least frequently used, oldest first among equal counts. Keys that were
+// popular once keep their high counts forever, so LFU adapts badly when
+// the workload shifts; WTinyLfu ages its counts to avoid that.
+#[derive(Debug, Clone)]
+pub struct Lfu<K> {
+    tick: u64,
+    entries: HashMap<K, (u64, u64)>,
+    order: BTreeMap<(u64, u64), K>,
+}
+
+impl<K: Hash + Eq + Clone> Lfu<K> {
+    pub fn new() -> Self {
+        Lfu {
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    pub fn frequency(&self, key: &K) -> Option<u64> {
+        self.entries.get(key).map(|&(count, _)| count)
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for Lfu<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone> EvictionPolicy<K> for Lfu<K> {
+    fn on_insert(&mut self, key: &K) {
+        self.on_remove(key);
+        self.tick += 1;
+        self.entries.insert(key.clone(), (1, self.tick));
+        self.order.insert((1, self.tick), key.clone());
+    }
+
+    fn on_access(&mut self, key: &K) {
+        if let Some(rank) = self.entries.get_mut(key) {
+            let key = self.order.remove(rank).expect("rank without key");
+            self.tick += 1;
+            *rank = (rank.0 + 1, self.tick);
+            self.order.insert(*rank, key);
+        }
+    }
+
+    fn on_remove(&mut self, key: &K) {
+        if let Some(rank) = self.entries.remove(key) {
+            self.order.remove(&rank);
+        }
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        let (_, key) = self.order.pop_first()?;
+        self.entries.remove(&key);
+        Some(key)
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+// Window TinyLFU, the policy used by Caffeine.
+//
+// New keys land in a small LRU window (1% of capacity). When the window
+// overflows, its oldest key becomes a candidate for the main area, which
+// is a segmented LRU: keys start in `probation` and move to `protected`
+// (80% of the main area) when used again. Once the main area is full a
+// candidate is only admitted if the frequency sketch says it is used more
+// often than the main area's victim. One-off scans therefore pass through
+// the window without flushing out the popular keys.
+#[derive(Debug, Clone)]
+pub struct WTinyLfu<K> {
+    window: Lru<K>,
+    probation: Lru<K>,
+    protected: Lru<K>,
+    window_capacity: usize,
+    main_capacity: usize,
+    protected_capacity: usize,
+    sketch: FrequencySketch,
+}
+
+impl<K: Hash + Eq + Clone> WTinyLfu<K> {
+    // `capacity` should match the cache's capacity
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let window_capacity = (capacity / 100).max(1);
+        let main_capacity = capacity.saturating_sub(window_capacity);
+        WTinyLfu {
+            window: Lru::new(),
+            probation: Lru::new(),
+            protected: Lru::new(),
+            window_capacity,
+            main_capacity,
+            protected_capacity: main_capacity * 4 / 5,
+            sketch: FrequencySketch::new(capacity),
+        }
+    }
+
+    pub fn frequency(&self, key: &K) -> u8 {
+        self.sketch.frequency(key)
+    }
+
+    fn main_len(&self) -> usize {
+        self.probation.len() + self.protected.len()
+    }
+
+    // Fills the main area from the window while it has room
+    fn spill_window(&mut self) {
+        while self.window.len() > self.window_capacity && self.main_len() < self.main_capacity {
+            let key = self.window.pop_lru().expect("window is not empty");
+            self.probation.push(key);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> EvictionPolicy<K> for WTinyLfu<K> {
+    fn on_insert(&mut self, key: &K) {
+        self.sketch.increment(key);
+        self.window.push(key.clone());
+        self.spill_window();
+    }
+
+    fn on_access(&mut self, key: &K) {
+        self.sketch.increment(key);
+        if self.window.touch(key) || self.protected.touch(key) {
+            return;
+        }
+        if self.probation.remove(key) {
+            self.protected.push(key.clone());
+            if self.protected.len() > self.protected_capacity {
+                let demoted = self.protected.pop_lru().expect("protected is not empty");
+                self.probation.push(demoted);
+            }
+        }
+    }
+
+    fn on_remove(&mut self, key: &K) {
+        let _ = self.window.remove(key) || self.probation.remove(key) || self.protected.remove(key);
+    }
+
+    fn evict(&mut self) -> Option<K> {
+        // The cache evicts before inserting, so a full window means the
+        // incoming key is about to push out the window's oldest key
+        if self.window.len() >= self.window_capacity {
+            let candidate = self.window.pop_lru()?;
+            let victim = self.probation.peek_lru().or_else(|| self.protected.peek_lru());
+            let Some(victim) = victim.cloned() else {
+                return Some(candidate);
+            };
+            // The admission filter: ties go to the incumbent
+            if self.sketch.frequency(&candidate) > self.sketch.frequency(&victim) {
+                self.on_remove(&victim);
+                self.probation.push(candidate);
+                Some(victim)
+            } else {
+                Some(candidate)
+            }
+        } else {
+            self.probation
+                .pop_lru()
+                .or_else(|| self.protected.pop_lru())
+                .or_else(|| self.window.pop_lru())
+        }
+    }
+
+    fn clear(&mut self) {
+        self.window.clear();
+        self.probation.clear();
+        self.protected.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Cache;
+
+    #[test]
+    fn test_lru_order() {
+        let mut lru = Lru::new();
+        for key in [1, 2, 3] {
+            lru.on_insert(&key);
+        }
+        lru.on_access(&1);
+        assert_eq!(lru.evict(), Some(2));
+        lru.on_remove(&3);
+        assert_eq!(lru.evict(), Some(1));
+        assert_eq!(lru.evict(), None);
+    }
+
+    #[test]
+    fn test_lfu_evicts_least_used_then_oldest() {
+        let mut lfu = Lfu::new();
+        for key in ["a", "b", "c"] {
+            lfu.on_insert(&key);
+        }
+        lfu.on_access(&"a");
+        lfu.on_access(&"a");
+        lfu.on_access(&"c");
+        assert_eq!(lfu.frequency(&"a"), Some(3));
+        assert_eq!(lfu.evict(), Some("b"));
+        assert_eq!(lfu.evict(), Some("c"));
+        assert_eq!(lfu.evict(), Some("a"));
+    }
+
+    #[test]
+    fn test_wtinylfu_resists_scans() {
+        // A hot set of 50 keys, then a one-off scan of 1000 cold keys. LRU
+        // loses the hot set; W-TinyLFU keeps it because the cold keys
+        // never beat the hot keys' frequencies.
+        fn hot_keys_kept<P: EvictionPolicy<u32>>(mut cache: Cache<u32, (), P>) -> usize {
+            for _ in 0..10 {
+                for key in 0..50 {
+                    cache.get_or_load(key, |_| ());
+                }
+            }
+            for key in 1000..2000 {
+                cache.get_or_load(key, |_| ());
+            }
+            (0..50).filter(|key| cache.contains_key(key)).count()
+        }
+
+        assert_eq!(hot_keys_kept(Cache::with_policy(100, Lru::new())), 0);
+        assert!(hot_keys_kept(Cache::with_policy(100, WTinyLfu::new(100))) >= 45);
+    }
+
+    #[test]
+    fn test_wtinylfu_promotes_on_reuse() {
+        let mut policy = WTinyLfu::new(100);
+        for key in 0..10 {
+            policy.on_insert(&key);
+        }
+        // Key 0 left the window for probation; using it again protects it
+        policy.on_access(&0);
+        assert!(policy.protected.contains(&0));
+        assert!(policy.window.contains(&9));
+    }
+}
@@ -0,0 +1,123 @@
+// Count-min sketch of access frequencies, used by WTinyLfu
+//
+// Four rows of small saturating counters. A key bumps one counter per row
+// and its estimate is the smallest of them, so collisions can only make a
+// key look more popular than it is. Every `sample_size` increments all
+// counters are halved, so keys that stop being used fade out.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+const ROWS: usize = 4;
+const MAX_COUNT: u8 = 15;
+
+#[derive(Debug, Clone)]
+pub(crate) struct FrequencySketch {
+    counters: Vec<u8>,
+    width: usize,
+    hasher: RandomState,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl FrequencySketch {
+    pub(crate) fn new(capacity: usize) -> Self {
+        let width = (capacity * 2).next_power_of_two().max(64);
+        FrequencySketch {
+            counters: vec![0; width * ROWS],
+            width,
+            hasher: RandomState::new(),
+            additions: 0,
+            sample_size: capacity.max(1) * 10,
+        }
+    }
+
+    fn indices<K: Hash + ?Sized>(&self, key: &K) -> [usize; ROWS] {
+        let mut state = self.hasher.build_hasher();
+        key.hash(&mut state);
+        let hash = state.finish();
+        // Each row re-mixes the hash with its own seed. Cheaper tricks such
+        // as h1 + i * h2 make a key that collides in one row likely to
+        // collide in all of them when the table is small.
+        let mut indices = [0; ROWS];
+        for (row, index) in indices.iter_mut().enumerate() {
+            let mixed = mix(hash.wrapping_add((row as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)));
+            *index = row * self.width + (mixed as usize & (self.width - 1));
+        }
+        indices
+    }
+
+    pub(crate) fn frequency<K: Hash + ?Sized>(&self, key: &K) -> u8 {
+        self.indices(key)
+            .iter()
+            .map(|&i| self.counters[i])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn increment<K: Hash + ?Sized>(&mut self, key: &K) {
+        // Conservative update: only the counters holding the minimum are
+        // bumped, which keeps over-estimates from collisions down
+        let indices = self.indices(key);
+        let min = indices.iter().map(|&i| self.counters[i]).min().unwrap_or(0);
+        if min == MAX_COUNT {
+            return;
+        }
+        for i in indices {
+            if self.counters[i] == min {
+                self.counters[i] += 1;
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample_size {
+            self.age();
+        }
+    }
+
+    fn age(&mut self) {
+        for counter in &mut self.counters {
+            *counter /= 2;
+        }
+        self.additions /= 2;
+    }
+}
+
+// splitmix64's finaliser: every output bit depends on every input bit
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_and_saturates() {
+        let mut sketch = FrequencySketch::new(1000);
+        for _ in 0..5 {
+            sketch.increment("hot");
+        }
+        assert_eq!(sketch.frequency("hot"), 5);
+        assert_eq!(sketch.frequency("never seen"), 0);
+
+        for _ in 0..100 {
+            sketch.increment("hot");
+        }
+        assert_eq!(sketch.frequency("hot"), MAX_COUNT);
+    }
+
+    #[test]
+    fn test_aging_halves_counts() {
+        let mut sketch = FrequencySketch::new(1);
+        for _ in 0..8 {
+            sketch.increment(&1);
+        }
+        // Sample size is 10; two more increments trigger a reset
+        sketch.increment(&2);
+        sketch.increment(&2);
+        assert_eq!(sketch.frequency(&1), 4);
+        assert_eq!(sketch.frequency(&2), 1);
+    }
+}
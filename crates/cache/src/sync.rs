@@ -0,0 +1,202 @@
+// Thread-safe cache
+//
+// Every operation locks the whole Cache: even a lookup updates recency and
+// stats. Values are cloned out, so keep them cheap to clone (wrap large
+// values in an Arc).
+
+use crate::{Cache, CacheStats, EvictionPolicy, Lru};
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+pub struct SyncCache<K, V, P = Lru<K>> {
+    inner: Mutex<Cache<K, V, P>>,
+}
+
+impl<K: Hash + Eq + Clone, V> SyncCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        SyncCache::from(Cache::new(capacity))
+    }
+}
+
+impl<K, V, P> From<Cache<K, V, P>> for SyncCache<K, V, P> {
+    fn from(cache: Cache<K, V, P>) -> Self {
+        SyncCache {
+            inner: Mutex::new(cache),
+        }
+    }
+}
+
+impl<K, V, P> SyncCache<K, V, P>
+where
+    K: Hash + Eq + Clone,
+    P: EvictionPolicy<K>,
+{
+    pub fn with_policy(capacity: usize, policy: P) -> Self {
+        SyncCache::from(Cache::with_policy(capacity, policy))
+    }
+
+    pub fn with_ttl(self, ttl: Duration) -> Self {
+        SyncCache::from(self.into_inner().with_ttl(ttl))
+    }
+
+    pub fn into_inner(self) -> Cache<K, V, P> {
+        self.inner.into_inner().unwrap()
+    }
+
+    // Direct access for doing several operations under one lock
+    pub fn lock(&self) -> MutexGuard<'_, Cache<K, V, P>> {
+        self.inner.lock().unwrap()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.lock().get(key).cloned()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.lock().insert(key, value)
+    }
+
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.lock().insert_with_ttl(key, value, ttl)
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().remove(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.lock().contains_key(key)
+    }
+
+    // Read-through lookup. The loader runs without the lock held, so a slow
+    // load doesn't block other keys; if two threads miss on the same key at
+    // once both may load, and the first value stored wins.
+    pub fn get_or_load<F>(&self, key: K, load: F) -> V
+    where
+        F: FnOnce(&K) -> V,
+        V: Clone,
+    {
+        if let Some(value) = self.get(&key) {
+            return value;
+        }
+        let value = load(&key);
+        let mut cache = self.lock();
+        cache.record_load();
+        if let Some(existing) = cache.peek(&key) {
+            return existing.clone();
+        }
+        cache.insert(key, value.clone());
+        value
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats()
+    }
+
+    pub fn purge_expired(&self) -> usize {
+        self.lock().purge_expired()
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::WTinyLfu;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_concurrent_inserts_respect_capacity() {
+        let cache = Arc::new(SyncCache::with_policy(64, WTinyLfu::new(64)));
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        cache.insert(t * 1000 + i, i);
+                        cache.get(&(t * 1000 + i / 2));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(cache.len(), 64);
+        let stats = cache.stats();
+        assert_eq!(stats.inserts - stats.evictions, 64);
+        assert_eq!(stats.requests(), 2000);
+    }
+
+    #[test]
+    fn test_get_or_load_shares_values() {
+        let cache = Arc::new(SyncCache::new(16));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                let loads = Arc::clone(&loads);
+                thread::spawn(move || {
+                    for key in 0..8 {
+                        let value = cache.get_or_load(key, |k| {
+                            loads.fetch_add(1, Ordering::SeqCst);
+                            k.to_string()
+                        });
+                        assert_eq!(value, key.to_string());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // Racing misses may load twice, but never more than once per thread
+        let loads = loads.load(Ordering::SeqCst);
+        assert!((8..=32).contains(&loads));
+        assert_eq!(cache.stats().loads as usize, loads);
+        assert_eq!(cache.len(), 8);
+    }
+
+    #[test]
+    fn test_ttl_through_sync_cache() {
+        let cache = SyncCache::new(4).with_ttl(Duration::from_secs(3600));
+        cache.insert("a", 1);
+        cache.insert_with_ttl("b", 2, Duration::ZERO);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.purge_expired(), 0);
+        assert_eq!(cache.stats().expirations, 1);
+    }
+}
@@ -0,0 +1,441 @@
+// Graph traversals and algorithms
+//
+// Everything here is iterative rather than recursive, so long paths cannot
+// overflow the stack. Per-node bookkeeping is kept in Vecs indexed by
+// NodeId::index, sized by Graph::node_bound.
+
+use crate::{EdgeId, EdgeRef, Graph, NodeId};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::ops::Add;
+
+// Breadth-first: nodes in order of their distance in edges from the start
+pub struct Bfs<'a, N, E> {
+    graph: &'a Graph<N, E>,
+    queue: VecDeque<NodeId>,
+    discovered: Vec<bool>,
+}
+
+impl<'a, N, E> Bfs<'a, N, E> {
+    // Visits nothing if `start` is not in the graph
+    pub fn new(graph: &'a Graph<N, E>, start: NodeId) -> Self {
+        let mut discovered = vec![false; graph.node_bound()];
+        let mut queue = VecDeque::new();
+        if graph.contains_node(start) {
+            discovered[start.index()] = true;
+            queue.push_back(start);
+        }
+        Bfs {
+            graph,
+            queue,
+            discovered,
+        }
+    }
+}
+
+impl<N, E> Iterator for Bfs<'_, N, E> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let node = self.queue.pop_front()?;
+        for next in self.graph.neighbors(node) {
+            if !self.discovered[next.index()] {
+                self.discovered[next.index()] = true;
+                self.queue.push_back(next);
+            }
+        }
+        Some(node)
+    }
+}
+
+// Depth-first pre-order: each node before the nodes first reached through it
+pub struct Dfs<'a, N, E> {
+    graph: &'a Graph<N, E>,
+    stack: Vec<NodeId>,
+    visited: Vec<bool>,
+}
+
+impl<'a, N, E> Dfs<'a, N, E> {
+    pub fn new(graph: &'a Graph<N, E>, start: NodeId) -> Self {
+        let mut stack = Vec::new();
+        if graph.contains_node(start) {
+            stack.push(start);
+        }
+        Dfs {
+            graph,
+            stack,
+            visited: vec![false; graph.node_bound()],
+        }
+    }
+}
+
+impl<N, E> Iterator for Dfs<'_, N, E> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        loop {
+            let node = self.stack.pop()?;
+            if self.visited[node.index()] {
+                continue;
+            }
+            self.visited[node.index()] = true;
+            // Reversed, so the first edge is explored first
+            let unvisited: Vec<_> = self
+                .graph
+                .neighbors(node)
+                .filter(|next| !self.visited[next.index()])
+                .collect();
+            self.stack.extend(unvisited.into_iter().rev());
+            return Some(node);
+        }
+    }
+}
+
+// Result of dijkstra: the cheapest cost and route to every reachable node
+#[derive(Debug, Clone)]
+pub struct ShortestPaths<W> {
+    start: NodeId,
+    distances: HashMap<NodeId, W>,
+    // Node -> the edge it was reached by, and where that edge starts
+    previous: HashMap<NodeId, (NodeId, EdgeId)>,
+}
+
+impl<W: Copy> ShortestPaths<W> {
+    pub fn start(&self) -> NodeId {
+        self.start
+    }
+
+    // None if `to` cannot be reached
+    pub fn distance(&self, to: NodeId) -> Option<W> {
+        self.distances.get(&to).copied()
+    }
+
+    // Nodes from the start to `to`, both included
+    pub fn path_to(&self, to: NodeId) -> Option<Vec<NodeId>> {
+        self.distances.get(&to)?;
+        let mut path = vec![to];
+        let mut node = to;
+        while let Some(&(from, _)) = self.previous.get(&node) {
+            path.push(from);
+            node = from;
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    // Edges taken from the start to `to`
+    pub fn edges_to(&self, to: NodeId) -> Option<Vec<EdgeId>> {
+        self.distances.get(&to)?;
+        let mut edges = vec![];
+        let mut node = to;
+        while let Some(&(from, edge)) = self.previous.get(&node) {
+            edges.push(edge);
+            node = from;
+        }
+        edges.reverse();
+        Some(edges)
+    }
+
+    pub fn reachable(&self) -> impl Iterator<Item = (NodeId, W)> + '_ {
+        self.distances.iter().map(|(&node, &cost)| (node, cost))
+    }
+}
+
+// Cheapest paths from `start`, where `cost` prices each edge. Costs must not
+// be negative; W::default() is taken as zero.
+pub fn dijkstra<N, E, W, F>(graph: &Graph<N, E>, start: NodeId, mut cost: F) -> ShortestPaths<W>
+where
+    W: Copy + Ord + Add<Output = W> + Default,
+    F: FnMut(EdgeRef<'_, E>) -> W,
+{
+    let mut paths = ShortestPaths {
+        start,
+        distances: HashMap::new(),
+        previous: HashMap::new(),
+    };
+    if !graph.contains_node(start) {
+        return paths;
+    }
+    let mut done = vec![false; graph.node_bound()];
+    let mut heap = BinaryHeap::new();
+    paths.distances.insert(start, W::default());
+    heap.push(Reverse((W::default(), start)));
+
+    while let Some(Reverse((distance, node))) = heap.pop() {
+        // Already settled through a cheaper entry
+        if done[node.index()] {
+            continue;
+        }
+        done[node.index()] = true;
+        for edge in graph.out_edges(node) {
+            if done[edge.to.index()] {
+                continue;
+            }
+            let candidate = distance + cost(edge);
+            let better = paths
+                .distances
+                .get(&edge.to)
+                .map_or(true, |&known| candidate < known);
+            if better {
+                paths.distances.insert(edge.to, candidate);
+                paths.previous.insert(edge.to, (node, edge.id));
+                heap.push(Reverse((candidate, edge.to)));
+            }
+        }
+    }
+    paths
+}
+
+// Returned by toposort when the graph has a cycle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    // Each node has an edge to the next, and the last one back to the first
+    pub nodes: Vec<NodeId>,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the graph has a cycle through {} nodes",
+            self.nodes.len()
+        )
+    }
+}
+
+impl Error for Cycle {}
+
+// Orders the nodes so that every edge goes from an earlier node to a later
+// one (Kahn's algorithm). Among nodes that are free to go next, the order
+// follows the graph's node order.
+pub fn toposort<N, E>(graph: &Graph<N, E>) -> Result<Vec<NodeId>, Cycle> {
+    let mut in_degree = vec![0; graph.node_bound()];
+    for edge in graph.edges() {
+        in_degree[edge.to.index()] += 1;
+    }
+    let mut ready: VecDeque<_> = graph
+        .node_ids()
+        .filter(|node| in_degree[node.index()] == 0)
+        .collect();
+    let mut order = Vec::with_capacity(graph.node_count());
+    while let Some(node) = ready.pop_front() {
+        order.push(node);
+        for next in graph.neighbors(node) {
+            in_degree[next.index()] -= 1;
+            if in_degree[next.index()] == 0 {
+                ready.push_back(next);
+            }
+        }
+    }
+    if order.len() == graph.node_count() {
+        return Ok(order);
+    }
+
+    // Every node left over still has an incoming edge from another left
+    // over node, so walking those edges backwards must come round in a loop
+    let mut node = graph
+        .node_ids()
+        .find(|node| in_degree[node.index()] > 0)
+        .unwrap();
+    let mut seen_at = HashMap::new();
+    let mut walk = vec![];
+    while !seen_at.contains_key(&node) {
+        seen_at.insert(node, walk.len());
+        walk.push(node);
+        node = graph
+            .in_edges(node)
+            .map(|edge| edge.from)
+            .find(|from| in_degree[from.index()] > 0)
+            .unwrap();
+    }
+    let mut nodes = walk.split_off(seen_at[&node]);
+    nodes.reverse();
+    Err(Cycle { nodes })
+}
+
+// Bookkeeping for Tarjan's algorithm, per node index
+struct Tarjan {
+    index: Vec<usize>,
+    low_link: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<NodeId>,
+    next_index: usize,
+}
+
+impl Tarjan {
+    const UNVISITED: usize = usize::MAX;
+
+    // Numbers the node, and returns its frame for the explicit call stack:
+    // (node, its successors, next successor to try)
+    fn visit<N, E>(&mut self, graph: &Graph<N, E>, node: NodeId) -> (NodeId, Vec<NodeId>, usize) {
+        self.index[node.index()] = self.next_index;
+        self.low_link[node.index()] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(node);
+        self.on_stack[node.index()] = true;
+        (node, graph.neighbors(node).collect(), 0)
+    }
+}
+
+// Groups nodes that can all reach each other (Tarjan's algorithm).
+// Components come out in reverse topological order: edges leaving a
+// component only lead to components listed before it.
+pub fn strongly_connected_components<N, E>(graph: &Graph<N, E>) -> Vec<Vec<NodeId>> {
+    let mut tarjan = Tarjan {
+        index: vec![Tarjan::UNVISITED; graph.node_bound()],
+        low_link: vec![0; graph.node_bound()],
+        on_stack: vec![false; graph.node_bound()],
+        stack: vec![],
+        next_index: 0,
+    };
+    let mut components = vec![];
+
+    for root in graph.node_ids() {
+        if tarjan.index[root.index()] != Tarjan::UNVISITED {
+            continue;
+        }
+        let mut calls = vec![tarjan.visit(graph, root)];
+        while let Some((node, successors, next)) = calls.last_mut() {
+            let node = *node;
+            if let Some(&successor) = successors.get(*next) {
+                *next += 1;
+                if tarjan.index[successor.index()] == Tarjan::UNVISITED {
+                    calls.push(tarjan.visit(graph, successor));
+                } else if tarjan.on_stack[successor.index()] {
+                    let low = tarjan.low_link[node.index()].min(tarjan.index[successor.index()]);
+                    tarjan.low_link[node.index()] = low;
+                }
+                continue;
+            }
+            calls.pop();
+            if tarjan.low_link[node.index()] == tarjan.index[node.index()] {
+                let mut component = vec![];
+                loop {
+                    let member = tarjan.stack.pop().unwrap();
+                    tarjan.on_stack[member.index()] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+            if let Some((caller, _, _)) = calls.last() {
+                let low = tarjan.low_link[caller.index()].min(tarjan.low_link[node.index()]);
+                tarjan.low_link[caller.index()] = low;
+            }
+        }
+    }
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a -> b -> d
+    // |         ^
+    // v         |
+    // c --------+   e (unreachable)
+    fn diamond() -> (Graph<char, u32>, [NodeId; 5]) {
+        let mut graph = Graph::new();
+        let ids = ['a', 'b', 'c', 'd', 'e'].map(|name| graph.add_node(name));
+        let [a, b, c, d, _] = ids;
+        graph.add_edge(a, b, 1).unwrap();
+        graph.add_edge(a, c, 4).unwrap();
+        graph.add_edge(b, d, 5).unwrap();
+        graph.add_edge(c, d, 1).unwrap();
+        (graph, ids)
+    }
+
+    fn names(graph: &Graph<char, u32>, nodes: impl IntoIterator<Item = NodeId>) -> String {
+        nodes.into_iter().map(|node| graph[node]).collect()
+    }
+
+    #[test]
+    fn test_bfs_and_dfs() {
+        let (mut graph, [a, b, _, _, e]) = diamond();
+        assert_eq!(names(&graph, Bfs::new(&graph, a)), "abcd");
+        assert_eq!(names(&graph, Dfs::new(&graph, a)), "abdc");
+        assert_eq!(names(&graph, Dfs::new(&graph, e)), "e");
+
+        graph.remove_node(b);
+        assert_eq!(names(&graph, Bfs::new(&graph, a)), "acd");
+        assert_eq!(Bfs::new(&graph, b).count(), 0);
+    }
+
+    #[test]
+    fn test_dijkstra() {
+        let (graph, [a, b, c, d, e]) = diamond();
+        let paths = dijkstra(&graph, a, |edge| *edge.weight);
+        assert_eq!(paths.distance(b), Some(1));
+        assert_eq!(paths.distance(d), Some(5));
+        assert_eq!(paths.path_to(d), Some(vec![a, c, d]));
+        assert_eq!(paths.edges_to(d).unwrap().len(), 2);
+        assert_eq!(paths.path_to(a), Some(vec![a]));
+        assert_eq!(paths.distance(e), None);
+        assert_eq!(paths.reachable().count(), 4);
+
+        // Counting hops instead of weights prefers the first route found
+        let hops = dijkstra(&graph, a, |_| 1);
+        assert_eq!(hops.distance(d), Some(2));
+        assert_eq!(hops.path_to(c), Some(vec![a, c]));
+    }
+
+    #[test]
+    fn test_toposort() {
+        let (mut graph, [a, b, c, d, e]) = diamond();
+        let order = toposort(&graph).unwrap();
+        assert_eq!(order, [a, e, b, c, d]);
+        let position = |node| order.iter().position(|&n| n == node).unwrap();
+        assert!(graph
+            .edges()
+            .all(|edge| position(edge.from) < position(edge.to)));
+
+        // d -> a closes the loop a -> b -> d -> a (and a -> c -> d -> a)
+        graph.add_edge(d, a, 1).unwrap();
+        graph.add_edge(e, a, 1).unwrap();
+        let cycle = toposort(&graph).unwrap_err();
+        assert!(cycle.nodes.contains(&a) && cycle.nodes.contains(&d));
+        assert!(!cycle.nodes.contains(&e));
+        for (i, &from) in cycle.nodes.iter().enumerate() {
+            let to = cycle.nodes[(i + 1) % cycle.nodes.len()];
+            assert!(graph.find_edge(from, to).is_some());
+        }
+    }
+
+    #[test]
+    fn test_strongly_connected_components() {
+        // {a, b, c} is a loop feeding {d, e}, which feeds f
+        let mut graph: Graph<char, ()> = Graph::new();
+        let [a, b, c, d, e, f] = ['a', 'b', 'c', 'd', 'e', 'f'].map(|name| graph.add_node(name));
+        for (from, to) in [(a, b), (b, c), (c, a), (c, d), (d, e), (e, d), (e, f)] {
+            graph.add_edge(from, to, ()).unwrap();
+        }
+        let mut components = strongly_connected_components(&graph);
+        for component in &mut components {
+            component.sort();
+        }
+        assert_eq!(components, [vec![f], vec![d, e], vec![a, b, c]]);
+    }
+
+    #[test]
+    fn test_long_paths_do_not_recurse() {
+        let mut graph: Graph<(), ()> = Graph::new();
+        let nodes: Vec<_> = (0..100_000).map(|_| graph.add_node(())).collect();
+        for pair in nodes.windows(2) {
+            graph.add_edge(pair[0], pair[1], ()).unwrap();
+        }
+        assert_eq!(Dfs::new(&graph, nodes[0]).count(), nodes.len());
+        assert_eq!(toposort(&graph).unwrap(), nodes);
+        assert_eq!(strongly_connected_components(&graph).len(), nodes.len());
+
+        graph
+            .add_edge(nodes[nodes.len() - 1], nodes[0], ())
+            .unwrap();
+        assert_eq!(strongly_connected_components(&graph).len(), 1);
+        assert_eq!(toposort(&graph).unwrap_err().nodes.len(), nodes.len());
+    }
+}
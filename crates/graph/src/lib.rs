@@ -0,0 +1,452 @@
+// Directed graphs stored in arenas instead of Rc<RefCell> webs
+//
+// Graph<N, E> keeps its nodes and edges in two Vecs and hands out NodeId and
+// EdgeId handles instead of pointers, so cycles cost nothing special and
+// there is no reference counting or runtime borrow checking.
+//
+// Ids are generational: each slot counts how many times it has been
+// reused, and an id remembers the count from when it was issued. Once a
+// node or edge is removed its old id no longer matches the slot, so a stale
+// handle is detected (lookups return None) instead of silently reaching
+// whatever took the slot over. Ids don't record which graph issued them,
+// though: an id used on another graph reaches whatever node sits in that
+// slot there, if the generation happens to match.
+//
+// Traversals and the classic algorithms live in the algo module:
+// - Bfs and Dfs: iterators over the nodes reachable from a start node
+// - dijkstra: shortest paths for non-negative edge costs
+// - toposort: an order where every edge points forward, or a cycle
+// - strongly_connected_components: Tarjan's algorithm
+pub mod algo;
+
+use std::error::Error;
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+pub use algo::{dijkstra, strongly_connected_components, toposort, Bfs, Cycle, Dfs, ShortestPaths};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EdgeId {
+    index: u32,
+    generation: u32,
+}
+
+impl NodeId {
+    // Position in the arena, for per-node side tables
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+impl EdgeId {
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphError {
+    // The node was removed (or the id came from another graph and doesn't
+    // match a node there; one that does match isn't detected)
+    MissingNode(NodeId),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::MissingNode(id) => write!(f, "no node {:?} in the graph", id),
+        }
+    }
+}
+
+impl Error for GraphError {}
+
+// One arena cell; `value` is None while the slot is free
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+#[derive(Debug, Clone)]
+struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Arena<T> {
+    fn new() -> Self {
+        Arena {
+            slots: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn insert(&mut self, value: T) -> (u32, u32) {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                (index, slot.generation)
+            }
+            None => {
+                let index = u32::try_from(self.slots.len()).expect("arena is full");
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                (index, 0)
+            }
+        }
+    }
+
+    fn get(&self, index: u32, generation: u32) -> Option<&T> {
+        let slot = self.slots.get(index as usize)?;
+        if slot.generation != generation {
+            return None;
+        }
+        slot.value.as_ref()
+    }
+
+    fn get_mut(&mut self, index: u32, generation: u32) -> Option<&mut T> {
+        let slot = self.slots.get_mut(index as usize)?;
+        if slot.generation != generation {
+            return None;
+        }
+        slot.value.as_mut()
+    }
+
+    fn remove(&mut self, index: u32, generation: u32) -> Option<T> {
+        let slot = self.slots.get_mut(index as usize)?;
+        if slot.generation != generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        // Outstanding ids for this slot no longer match
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(index);
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = (u32, u32, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let value = slot.value.as_ref()?;
+            Some((index as u32, slot.generation, value))
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct NodeData<N> {
+    weight: N,
+    outgoing: Vec<EdgeId>,
+    incoming: Vec<EdgeId>,
+}
+
+#[derive(Debug, Clone)]
+struct EdgeData<E> {
+    weight: E,
+    from: NodeId,
+    to: NodeId,
+}
+
+// A borrowed view of one edge
+#[derive(Debug, PartialEq, Eq)]
+pub struct EdgeRef<'a, E> {
+    pub id: EdgeId,
+    pub from: NodeId,
+    pub to: NodeId,
+    pub weight: &'a E,
+}
+
+// Copy whatever E is, since only a reference to it is held
+impl<E> Clone for EdgeRef<'_, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for EdgeRef<'_, E> {}
+
+#[derive(Debug, Clone)]
+pub struct Graph<N, E> {
+    nodes: Arena<NodeData<N>>,
+    edges: Arena<EdgeData<E>>,
+}
+
+impl<N, E> Graph<N, E> {
+    pub fn new() -> Self {
+        Graph {
+            nodes: Arena::new(),
+            edges: Arena::new(),
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len
+    }
+
+    // One more than the largest NodeId::index in use, for sizing side tables
+    pub fn node_bound(&self) -> usize {
+        self.nodes.slots.len()
+    }
+
+    pub fn add_node(&mut self, weight: N) -> NodeId {
+        let (index, generation) = self.nodes.insert(NodeData {
+            weight,
+            outgoing: Vec::new(),
+            incoming: Vec::new(),
+        });
+        NodeId { index, generation }
+    }
+
+    // Parallel edges and self-loops are allowed
+    pub fn add_edge(&mut self, from: NodeId, to: NodeId, weight: E) -> Result<EdgeId, GraphError> {
+        for node in [from, to] {
+            if !self.contains_node(node) {
+                return Err(GraphError::MissingNode(node));
+            }
+        }
+        let (index, generation) = self.edges.insert(EdgeData { weight, from, to });
+        let id = EdgeId { index, generation };
+        self.node_data_mut(from).outgoing.push(id);
+        self.node_data_mut(to).incoming.push(id);
+        Ok(id)
+    }
+
+    // Removes the node and every edge touching it
+    pub fn remove_node(&mut self, id: NodeId) -> Option<N> {
+        let data = self.nodes.remove(id.index, id.generation)?;
+        for edge in data.outgoing.iter().chain(&data.incoming) {
+            self.remove_edge(*edge);
+        }
+        Some(data.weight)
+    }
+
+    pub fn remove_edge(&mut self, id: EdgeId) -> Option<E> {
+        let data = self.edges.remove(id.index, id.generation)?;
+        // Either end may be the node being removed right now
+        if let Some(from) = self.nodes.get_mut(data.from.index, data.from.generation) {
+            from.outgoing.retain(|&e| e != id);
+        }
+        if let Some(to) = self.nodes.get_mut(data.to.index, data.to.generation) {
+            to.incoming.retain(|&e| e != id);
+        }
+        Some(data.weight)
+    }
+
+    pub fn contains_node(&self, id: NodeId) -> bool {
+        self.node(id).is_some()
+    }
+
+    pub fn contains_edge(&self, id: EdgeId) -> bool {
+        self.edge(id).is_some()
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&N> {
+        self.nodes
+            .get(id.index, id.generation)
+            .map(|data| &data.weight)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut N> {
+        self.nodes
+            .get_mut(id.index, id.generation)
+            .map(|data| &mut data.weight)
+    }
+
+    pub fn edge(&self, id: EdgeId) -> Option<&E> {
+        self.edges
+            .get(id.index, id.generation)
+            .map(|data| &data.weight)
+    }
+
+    pub fn edge_mut(&mut self, id: EdgeId) -> Option<&mut E> {
+        self.edges
+            .get_mut(id.index, id.generation)
+            .map(|data| &mut data.weight)
+    }
+
+    // (from, to)
+    pub fn endpoints(&self, id: EdgeId) -> Option<(NodeId, NodeId)> {
+        self.edges
+            .get(id.index, id.generation)
+            .map(|data| (data.from, data.to))
+    }
+
+    // Some edge from `from` to `to`, if there is one
+    pub fn find_edge(&self, from: NodeId, to: NodeId) -> Option<EdgeId> {
+        self.out_edges(from).find(|e| e.to == to).map(|e| e.id)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (NodeId, &N)> {
+        self.nodes
+            .iter()
+            .map(|(index, generation, data)| (NodeId { index, generation }, &data.weight))
+    }
+
+    pub fn node_ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes().map(|(id, _)| id)
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = EdgeRef<'_, E>> {
+        self.edges.iter().map(|(index, generation, data)| EdgeRef {
+            id: EdgeId { index, generation },
+            from: data.from,
+            to: data.to,
+            weight: &data.weight,
+        })
+    }
+
+    // Edges leaving `id`, in the order they were added; empty for a
+    // missing node
+    pub fn out_edges(&self, id: NodeId) -> impl Iterator<Item = EdgeRef<'_, E>> {
+        self.edge_refs(id, |data| &data.outgoing)
+    }
+
+    pub fn in_edges(&self, id: NodeId) -> impl Iterator<Item = EdgeRef<'_, E>> {
+        self.edge_refs(id, |data| &data.incoming)
+    }
+
+    // Targets of the edges leaving `id`
+    pub fn neighbors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.out_edges(id).map(|e| e.to)
+    }
+
+    fn edge_refs<'a>(
+        &'a self,
+        id: NodeId,
+        list: fn(&NodeData<N>) -> &Vec<EdgeId>,
+    ) -> impl Iterator<Item = EdgeRef<'a, E>> {
+        let ids = self
+            .nodes
+            .get(id.index, id.generation)
+            .map_or(&[][..], |data| list(data).as_slice());
+        ids.iter().map(move |&id| {
+            let data = self.edges.get(id.index, id.generation).unwrap();
+            EdgeRef {
+                id,
+                from: data.from,
+                to: data.to,
+                weight: &data.weight,
+            }
+        })
+    }
+
+    fn node_data_mut(&mut self, id: NodeId) -> &mut NodeData<N> {
+        self.nodes.get_mut(id.index, id.generation).unwrap()
+    }
+}
+
+impl<N, E> Default for Graph<N, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Panics on a stale id; use node() to check first
+impl<N, E> Index<NodeId> for Graph<N, E> {
+    type Output = N;
+
+    fn index(&self, id: NodeId) -> &N {
+        self.node(id).expect("stale NodeId")
+    }
+}
+
+impl<N, E> IndexMut<NodeId> for Graph<N, E> {
+    fn index_mut(&mut self, id: NodeId) -> &mut N {
+        self.node_mut(id).expect("stale NodeId")
+    }
+}
+
+impl<N, E> Index<EdgeId> for Graph<N, E> {
+    type Output = E;
+
+    fn index(&self, id: EdgeId) -> &E {
+        self.edge(id).expect("stale EdgeId")
+    }
+}
+
+impl<N, E> IndexMut<EdgeId> for Graph<N, E> {
+    fn index_mut(&mut self, id: EdgeId) -> &mut E {
+        self.edge_mut(id).expect("stale EdgeId")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_and_look_up() {
+        let mut graph = Graph::new();
+        let a = graph.add_node("a");
+        let b = graph.add_node("b");
+        let ab = graph.add_edge(a, b, 1.5).unwrap();
+        let aa = graph.add_edge(a, a, 0.0).unwrap();
+
+        assert_eq!((graph.node_count(), graph.edge_count()), (2, 2));
+        assert_eq!(graph[a], "a");
+        assert_eq!(graph[ab], 1.5);
+        assert_eq!(graph.endpoints(ab), Some((a, b)));
+        assert_eq!(graph.find_edge(a, b), Some(ab));
+        assert_eq!(graph.find_edge(b, a), None);
+        assert_eq!(graph.neighbors(a).collect::<Vec<_>>(), [b, a]);
+        assert_eq!(graph.in_edges(a).map(|e| e.id).collect::<Vec<_>>(), [aa]);
+
+        graph[b] = "B";
+        *graph.edge_mut(ab).unwrap() += 1.0;
+        assert_eq!(graph.node(b), Some(&"B"));
+        assert_eq!(graph.edge(ab), Some(&2.5));
+    }
+
+    #[test]
+    fn test_removing_a_node_removes_its_edges() {
+        let mut graph = Graph::new();
+        let a = graph.add_node('a');
+        let b = graph.add_node('b');
+        let c = graph.add_node('c');
+        let ab = graph.add_edge(a, b, ()).unwrap();
+        let bc = graph.add_edge(b, c, ()).unwrap();
+        let ca = graph.add_edge(c, a, ()).unwrap();
+
+        assert_eq!(graph.remove_node(b), Some('b'));
+        assert_eq!(graph.remove_node(b), None);
+        assert_eq!((graph.node_count(), graph.edge_count()), (2, 1));
+        assert!(!graph.contains_edge(ab) && !graph.contains_edge(bc));
+        assert!(graph.contains_edge(ca));
+        assert_eq!(graph.neighbors(a).count(), 0);
+        assert_eq!(graph.in_edges(a).map(|e| e.from).collect::<Vec<_>>(), [c]);
+        assert_eq!(graph.add_edge(a, b, ()), Err(GraphError::MissingNode(b)));
+    }
+
+    #[test]
+    fn test_stale_ids_are_detected_after_reuse() {
+        let mut graph: Graph<&str, ()> = Graph::new();
+        let old = graph.add_node("old");
+        graph.remove_node(old);
+        let new = graph.add_node("new");
+
+        // Same slot, different generation
+        assert_eq!(old.index(), new.index());
+        assert_ne!(old, new);
+        assert_eq!(graph.node(old), None);
+        assert_eq!(graph.node(new), Some(&"new"));
+        assert_eq!(graph.nodes().map(|(id, _)| id).collect::<Vec<_>>(), [new]);
+        assert_eq!(graph.node_bound(), 1);
+    }
+}
@@ -0,0 +1,171 @@
+// chunks and windows for any iterator, not just slices
+//
+// Slices can hand out sub-slices; an iterator has nowhere to borrow from, so
+// both adapters yield owned Vecs. Windows keeps the last size - 1 items in a
+// ring buffer and clones them into each window.
+
+use std::collections::VecDeque;
+use std::iter::{Fuse, FusedIterator};
+
+fn div_round_up(n: usize, size: usize) -> usize {
+    n / size + usize::from(n % size != 0)
+}
+
+#[derive(Debug, Clone)]
+pub struct Chunks<I> {
+    iter: Fuse<I>,
+    size: usize,
+}
+
+impl<I: Iterator> Chunks<I> {
+    pub(crate) fn new(iter: I, size: usize) -> Self {
+        assert!(size > 0, "chunk size must be positive");
+        Chunks {
+            iter: iter.fuse(),
+            size,
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Chunks<I> {
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Vec<I::Item>> {
+        let first = self.iter.next()?;
+        let mut chunk = Vec::with_capacity(self.size);
+        chunk.push(first);
+        chunk.extend(self.iter.by_ref().take(self.size - 1));
+        Some(chunk)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.iter.size_hint();
+        (
+            div_round_up(lower, self.size),
+            upper.map(|upper| div_round_up(upper, self.size)),
+        )
+    }
+}
+
+impl<I: Iterator> FusedIterator for Chunks<I> {}
+
+#[derive(Debug, Clone)]
+pub struct Windows<I: Iterator> {
+    iter: Fuse<I>,
+    size: usize,
+    buffer: VecDeque<I::Item>,
+}
+
+impl<I: Iterator> Windows<I> {
+    pub(crate) fn new(iter: I, size: usize) -> Self {
+        assert!(size > 0, "window size must be positive");
+        Windows {
+            iter: iter.fuse(),
+            size,
+            buffer: VecDeque::with_capacity(size),
+        }
+    }
+}
+
+impl<I> Iterator for Windows<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Vec<I::Item>> {
+        while self.buffer.len() < self.size {
+            self.buffer.push_back(self.iter.next()?);
+        }
+        let window = self.buffer.iter().cloned().collect();
+        // Keep the overlap with the next window
+        self.buffer.pop_front();
+        Some(window)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Every item still to come completes one window, once the buffer
+        // holds size - 1 items
+        let buffered = self.buffer.len();
+        let (lower, upper) = self.iter.size_hint();
+        let overlap = self.size - 1;
+        (
+            lower.saturating_add(buffered).saturating_sub(overlap),
+            upper
+                .and_then(|upper| upper.checked_add(buffered))
+                .map(|upper| upper.saturating_sub(overlap)),
+        )
+    }
+}
+
+impl<I> FusedIterator for Windows<I>
+where
+    I: Iterator,
+    I::Item: Clone,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{check_fused, check_size_hints, Flaky};
+    use crate::IterExt;
+
+    #[test]
+    fn test_chunks() {
+        let chunks: Vec<Vec<i32>> = (1..=7).chunks(3).collect();
+        assert_eq!(chunks, vec![vec![1, 2, 3], vec![4, 5, 6], vec![7]]);
+        assert_eq!((1..=6).chunks(3).count(), 2);
+        assert_eq!((0..0).chunks(3).next(), None);
+
+        assert_eq!((0..7).chunks(3).size_hint(), (3, Some(3)));
+        for len in 0..8 {
+            for size in 1..5 {
+                check_size_hints(|| (0..len).chunks(size));
+                check_size_hints(|| (0..len).filter(|x| x % 2 == 0).chunks(size));
+            }
+        }
+    }
+
+    #[test]
+    fn test_windows() {
+        let windows: Vec<Vec<char>> = "rust".chars().windows(2).collect();
+        assert_eq!(
+            windows,
+            vec![vec!['r', 'u'], vec!['u', 's'], vec!['s', 't']]
+        );
+        assert_eq!((0..2).windows(3).next(), None);
+        assert_eq!((0..5).windows(1).count(), 5);
+
+        let mut windows = (0..10).windows(4);
+        assert_eq!(windows.size_hint(), (7, Some(7)));
+        windows.next();
+        assert_eq!(windows.size_hint(), (6, Some(6)));
+        for len in 0..8 {
+            for size in 1..5 {
+                check_size_hints(|| (0..len).windows(size));
+                check_size_hints(|| (0..len).filter(|x| x % 3 != 0).windows(size));
+            }
+        }
+    }
+
+    #[test]
+    fn test_unfused_sources_stay_finished() {
+        // The source pauses after every 2 items; the adapters stop at the
+        // first pause for good
+        let mut chunks = Flaky::new((0..10).collect(), 2).chunks(3);
+        assert_eq!(chunks.next(), Some(vec![0, 1]));
+        check_fused(chunks);
+
+        let mut windows = Flaky::new((0..10).collect(), 3).windows(2);
+        assert_eq!(windows.next(), Some(vec![0, 1]));
+        assert_eq!(windows.next(), Some(vec![1, 2]));
+        check_fused(windows);
+    }
+
+    #[test]
+    #[should_panic(expected = "window size must be positive")]
+    fn test_empty_windows_panic() {
+        let _ = (0..3).windows(0);
+    }
+}
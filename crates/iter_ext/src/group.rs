@@ -0,0 +1,206 @@
+// Adapters over runs of consecutive items
+//
+// Both look one item past the end of the current run to know it has ended,
+// and hold on to that item as the start of the next run.
+
+use std::iter::{Fuse, FusedIterator};
+
+// At least one run is left when at least one item is
+fn runs_hint(pending: bool, rest: (usize, Option<usize>)) -> (usize, Option<usize>) {
+    let (lower, upper) = crate::add_hints((usize::from(pending), Some(usize::from(pending))), rest);
+    (lower.min(1), upper)
+}
+
+#[derive(Debug, Clone)]
+pub struct GroupByKey<I: Iterator, K, F> {
+    iter: Fuse<I>,
+    key: F,
+    // First item of the next run, with its key
+    pending: Option<(K, I::Item)>,
+}
+
+impl<I, K, F> GroupByKey<I, K, F>
+where
+    I: Iterator,
+    K: PartialEq,
+    F: FnMut(&I::Item) -> K,
+{
+    pub(crate) fn new(iter: I, key: F) -> Self {
+        GroupByKey {
+            iter: iter.fuse(),
+            key,
+            pending: None,
+        }
+    }
+}
+
+impl<I, K, F> Iterator for GroupByKey<I, K, F>
+where
+    I: Iterator,
+    K: PartialEq,
+    F: FnMut(&I::Item) -> K,
+{
+    type Item = (K, Vec<I::Item>);
+
+    fn next(&mut self) -> Option<(K, Vec<I::Item>)> {
+        let (key, first) = match self.pending.take() {
+            Some(pending) => pending,
+            None => {
+                let item = self.iter.next()?;
+                ((self.key)(&item), item)
+            }
+        };
+        let mut group = vec![first];
+        for item in self.iter.by_ref() {
+            let next_key = (self.key)(&item);
+            if next_key != key {
+                self.pending = Some((next_key, item));
+                break;
+            }
+            group.push(item);
+        }
+        Some((key, group))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        runs_hint(self.pending.is_some(), self.iter.size_hint())
+    }
+}
+
+impl<I, K, F> FusedIterator for GroupByKey<I, K, F>
+where
+    I: Iterator,
+    K: PartialEq,
+    F: FnMut(&I::Item) -> K,
+{
+}
+
+// What dedup() returns: DedupBy comparing with ==
+pub type Dedup<I> = DedupBy<I, fn(&<I as Iterator>::Item, &<I as Iterator>::Item) -> bool>;
+
+#[derive(Debug, Clone)]
+pub struct DedupBy<I: Iterator, F> {
+    iter: Fuse<I>,
+    same: F,
+    pending: Option<I::Item>,
+}
+
+impl<I, F> DedupBy<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item, &I::Item) -> bool,
+{
+    pub(crate) fn new(iter: I, same: F) -> Self {
+        DedupBy {
+            iter: iter.fuse(),
+            same,
+            pending: None,
+        }
+    }
+}
+
+impl<I, F> Iterator for DedupBy<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item, &I::Item) -> bool,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let kept = match self.pending.take() {
+            Some(item) => item,
+            None => self.iter.next()?,
+        };
+        // Like Vec::dedup_by, every item is compared with the one kept, not
+        // with the one just dropped
+        for item in self.iter.by_ref() {
+            if !(self.same)(&kept, &item) {
+                self.pending = Some(item);
+                break;
+            }
+        }
+        Some(kept)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        runs_hint(self.pending.is_some(), self.iter.size_hint())
+    }
+}
+
+impl<I, F> FusedIterator for DedupBy<I, F>
+where
+    I: Iterator,
+    F: FnMut(&I::Item, &I::Item) -> bool,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{check_fused, check_size_hints, Flaky};
+    use crate::IterExt;
+
+    #[test]
+    fn test_group_by_key() {
+        let words = [
+            "apple",
+            "avocado",
+            "banana",
+            "blueberry",
+            "cherry",
+            "apricot",
+        ];
+        let groups: Vec<(char, Vec<&str>)> = words
+            .into_iter()
+            .group_by_key(|word| word.chars().next().unwrap())
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ('a', vec!["apple", "avocado"]),
+                ('b', vec!["banana", "blueberry"]),
+                ('c', vec!["cherry"]),
+                // A key can start a new run later on
+                ('a', vec!["apricot"]),
+            ]
+        );
+
+        let mut runs = [1, 1, 2].into_iter().group_by_key(|&x| x);
+        assert_eq!(runs.size_hint(), (1, Some(3)));
+        runs.next();
+        assert_eq!(runs.size_hint(), (1, Some(1)));
+        for len in 0..10 {
+            check_size_hints(|| (0..len).group_by_key(|x| x / 3));
+            check_size_hints(|| (0..len).filter(|x| x % 4 != 0).group_by_key(|x| x % 2));
+        }
+    }
+
+    #[test]
+    fn test_dedup() {
+        let deduped: Vec<i32> = [1, 1, 2, 3, 3, 3, 1].into_iter().dedup().collect();
+        assert_eq!(deduped, vec![1, 2, 3, 1]);
+
+        // Compared with the kept item: 1, 2, 3 are all within 2 of 1
+        let close: Vec<i32> = [1_i32, 2, 3, 4, 10, 11]
+            .into_iter()
+            .dedup_by(|a, b| (a - b).abs() <= 2)
+            .collect();
+        assert_eq!(close, vec![1, 4, 10]);
+
+        for len in 0..10 {
+            check_size_hints(|| (0..len).map(|x| x / 2).dedup());
+            check_size_hints(|| (0..len).filter(|x| x % 3 != 0).dedup_by(|a, b| b - a < 2));
+        }
+    }
+
+    #[test]
+    fn test_unfused_sources_stay_finished() {
+        let mut groups = Flaky::new(vec![1, 1, 2, 2], 3).group_by_key(|&x| x);
+        assert_eq!(groups.next(), Some((1, vec![1, 1])));
+        assert_eq!(groups.next(), Some((2, vec![2])));
+        check_fused(groups);
+
+        let mut deduped = Flaky::new(vec![1, 1, 1, 2], 2).dedup();
+        assert_eq!(deduped.next(), Some(1));
+        check_fused(deduped);
+    }
+}
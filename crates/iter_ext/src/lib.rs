@@ -0,0 +1,207 @@
+// Extra iterator adapters, in the spirit of the itertools crate
+//
+// Bring IterExt into scope and every iterator gains:
+// - chunks(n): Vecs of n items at a time, the last one possibly shorter
+// - windows(n): every run of n consecutive items, overlapping
+// - interleave(other): one item from each side in turn
+// - merge_sorted(other): merges two sorted iterators into one sorted one
+// - group_by_key(f): runs of consecutive items with the same key
+// - dedup_by(f) / dedup(): drops items equal to the one before them
+// - kmerge(): merges any number of sorted iterators
+// - cartesian_product(other): every pair (a, b)
+//
+// All adapters are lazy: they pull from their sources only as items are
+// asked for (kmerge has to look at the first item of every source up front).
+//
+// Every adapter is fused: once it has returned None it keeps returning
+// None, even if a source would start producing items again. Sources are
+// wrapped in Fuse for that, so they are never polled after their first None.
+//
+// size_hint is always correct: the lower bound never exceeds, and the upper
+// bound is never below, the number of items actually left. When the sources
+// report exact sizes, so do chunks, windows, interleave, the merges and
+// cartesian_product (up to overflow). group_by_key and the dedups can't
+// know how many runs are left, so their lower bound is at most 1.
+pub mod chunks;
+pub mod group;
+pub mod merge;
+pub mod product;
+
+pub use chunks::{Chunks, Windows};
+pub use group::{Dedup, DedupBy, GroupByKey};
+pub use merge::{Interleave, KMerge, MergeSorted};
+pub use product::CartesianProduct;
+
+pub trait IterExt: Iterator + Sized {
+    // Panics if size is 0
+    fn chunks(self, size: usize) -> Chunks<Self> {
+        Chunks::new(self, size)
+    }
+
+    // Panics if size is 0. Items are cloned into every window they are in
+    fn windows(self, size: usize) -> Windows<Self>
+    where
+        Self::Item: Clone,
+    {
+        Windows::new(self, size)
+    }
+
+    // Alternates between the two, starting with self; once one side runs
+    // out the rest of the other follows
+    fn interleave<J>(self, other: J) -> Interleave<Self, J::IntoIter>
+    where
+        J: IntoIterator<Item = Self::Item>,
+    {
+        Interleave::new(self, other.into_iter())
+    }
+
+    // Both sides must already be sorted. Equal items from self come first
+    fn merge_sorted<J>(self, other: J) -> MergeSorted<Self, J::IntoIter>
+    where
+        J: IntoIterator<Item = Self::Item>,
+        Self::Item: Ord,
+    {
+        MergeSorted::new(self, other.into_iter())
+    }
+
+    // Yields (key, items) for every run of consecutive items with equal
+    // keys. Unlike a HashMap grouping, a key can come back later in a new run
+    fn group_by_key<K, F>(self, key: F) -> GroupByKey<Self, K, F>
+    where
+        K: PartialEq,
+        F: FnMut(&Self::Item) -> K,
+    {
+        GroupByKey::new(self, key)
+    }
+
+    // Keeps the first item of every run for which same(previous, next) holds
+    fn dedup_by<F>(self, same: F) -> DedupBy<Self, F>
+    where
+        F: FnMut(&Self::Item, &Self::Item) -> bool,
+    {
+        DedupBy::new(self, same)
+    }
+
+    fn dedup(self) -> Dedup<Self>
+    where
+        Self::Item: PartialEq,
+    {
+        DedupBy::new(self, PartialEq::eq)
+    }
+
+    // Merges sorted iterators into one sorted iterator. Equal items come
+    // out in the order of their sources
+    fn kmerge(self) -> KMerge<<Self::Item as IntoIterator>::IntoIter>
+    where
+        Self::Item: IntoIterator,
+        <Self::Item as IntoIterator>::Item: Ord,
+    {
+        KMerge::new(self)
+    }
+
+    // Every (a, b) with a from self and b from other, a changing slowest.
+    // other is cloned once per item of self to start over
+    fn cartesian_product<J>(self, other: J) -> CartesianProduct<Self, J::IntoIter>
+    where
+        J: IntoIterator,
+        J::IntoIter: Clone,
+        Self::Item: Clone,
+    {
+        CartesianProduct::new(self, other.into_iter())
+    }
+}
+
+impl<I: Iterator> IterExt for I {}
+
+// Adds two size hints
+pub(crate) fn add_hints(
+    a: (usize, Option<usize>),
+    b: (usize, Option<usize>),
+) -> (usize, Option<usize>) {
+    let lower = a.0.saturating_add(b.0);
+    let upper = match (a.1, b.1) {
+        (Some(x), Some(y)) => x.checked_add(y),
+        _ => None,
+    };
+    (lower, upper)
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::iter::FusedIterator;
+
+    // Walks the iterator to the end and checks that every size_hint along
+    // the way brackets the number of items that were really left, then that
+    // it stays exhausted
+    pub fn check_size_hints<I, F>(make: F)
+    where
+        I: Iterator + FusedIterator,
+        F: Fn() -> I,
+    {
+        let total = make().count();
+        let mut iter = make();
+        for remaining in (0..=total).rev() {
+            let (lower, upper) = iter.size_hint();
+            assert!(
+                lower <= remaining,
+                "lower bound {} > {} left",
+                lower,
+                remaining
+            );
+            if let Some(upper) = upper {
+                assert!(
+                    upper >= remaining,
+                    "upper bound {} < {} left",
+                    upper,
+                    remaining
+                );
+            }
+            let item = iter.next();
+            assert_eq!(item.is_some(), remaining > 0);
+        }
+        check_fused(iter);
+    }
+
+    pub fn check_fused<I: Iterator>(mut iter: I) {
+        for _ in 0..3 {
+            assert!(iter.next().is_none(), "returned an item after None");
+        }
+        assert_eq!(iter.size_hint().0, 0);
+    }
+
+    // A source that is not fused: it returns None after every `gap` items
+    // and then carries on, and its size_hint makes no promises
+    #[derive(Clone)]
+    pub struct Flaky<T> {
+        items: Vec<T>,
+        position: usize,
+        gap: usize,
+        since_gap: usize,
+    }
+
+    impl<T: Clone> Flaky<T> {
+        pub fn new(items: Vec<T>, gap: usize) -> Self {
+            Flaky {
+                items,
+                position: 0,
+                gap,
+                since_gap: 0,
+            }
+        }
+    }
+
+    impl<T: Clone> Iterator for Flaky<T> {
+        type Item = T;
+
+        fn next(&mut self) -> Option<T> {
+            if self.since_gap == self.gap {
+                self.since_gap = 0;
+                return None;
+            }
+            self.since_gap += 1;
+            let item = self.items.get(self.position).cloned();
+            self.position += 1;
+            item
+        }
+    }
+}
@@ -0,0 +1,385 @@
+// Adapters that combine several iterators into one
+//
+// - Interleave alternates between two iterators
+// - MergeSorted merges two sorted iterators, peeking at the next item of
+//   each to pick the smaller one
+// - KMerge does the same for any number of sorted iterators, keeping the
+//   head of every source in a min-heap, so each item costs O(log k)
+
+use std::cmp::Ordering;
+use std::collections::binary_heap::{BinaryHeap, PeekMut};
+use std::fmt;
+use std::iter::{Fuse, FusedIterator, Peekable};
+use std::mem;
+
+use crate::add_hints;
+
+#[derive(Debug, Clone)]
+pub struct Interleave<I, J> {
+    a: Fuse<I>,
+    b: Fuse<J>,
+    b_next: bool,
+}
+
+impl<I: Iterator, J: Iterator<Item = I::Item>> Interleave<I, J> {
+    pub(crate) fn new(a: I, b: J) -> Self {
+        Interleave {
+            a: a.fuse(),
+            b: b.fuse(),
+            b_next: false,
+        }
+    }
+}
+
+impl<I, J> Iterator for Interleave<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.b_next = !self.b_next;
+        if self.b_next {
+            self.a.next().or_else(|| self.b.next())
+        } else {
+            self.b.next().or_else(|| self.a.next())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        add_hints(self.a.size_hint(), self.b.size_hint())
+    }
+}
+
+impl<I, J> FusedIterator for Interleave<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>,
+{
+}
+
+pub struct MergeSorted<I: Iterator, J: Iterator> {
+    a: Peekable<Fuse<I>>,
+    b: Peekable<Fuse<J>>,
+}
+
+// Written out because derive can't see the items held inside Peekable
+impl<I, J> Clone for MergeSorted<I, J>
+where
+    I: Iterator + Clone,
+    J: Iterator + Clone,
+    I::Item: Clone,
+    J::Item: Clone,
+{
+    fn clone(&self) -> Self {
+        MergeSorted {
+            a: self.a.clone(),
+            b: self.b.clone(),
+        }
+    }
+}
+
+impl<I, J> fmt::Debug for MergeSorted<I, J>
+where
+    I: Iterator + fmt::Debug,
+    J: Iterator + fmt::Debug,
+    I::Item: fmt::Debug,
+    J::Item: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MergeSorted")
+            .field("a", &self.a)
+            .field("b", &self.b)
+            .finish()
+    }
+}
+
+impl<I, J> MergeSorted<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>,
+    I::Item: Ord,
+{
+    pub(crate) fn new(a: I, b: J) -> Self {
+        MergeSorted {
+            a: a.fuse().peekable(),
+            b: b.fuse().peekable(),
+        }
+    }
+}
+
+impl<I, J> Iterator for MergeSorted<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>,
+    I::Item: Ord,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let take_b = match (self.a.peek(), self.b.peek()) {
+            // Ties go to a, so the merge is stable
+            (Some(a), Some(b)) => b < a,
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        if take_b {
+            self.b.next()
+        } else {
+            self.a.next()
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        add_hints(self.a.size_hint(), self.b.size_hint())
+    }
+}
+
+impl<I, J> FusedIterator for MergeSorted<I, J>
+where
+    I: Iterator,
+    J: Iterator<Item = I::Item>,
+    I::Item: Ord,
+{
+}
+
+// The next item of one source, plus the rest of it
+#[derive(Debug, Clone)]
+struct HeadTail<I: Iterator> {
+    head: I::Item,
+    tail: Fuse<I>,
+    source: usize,
+}
+
+impl<I: Iterator> HeadTail<I>
+where
+    I::Item: Ord,
+{
+    fn key(&self) -> (&I::Item, usize) {
+        (&self.head, self.source)
+    }
+}
+
+// BinaryHeap is a max-heap, so the order is reversed: the smallest head,
+// and among equal heads the earliest source, is the greatest
+impl<I: Iterator> Ord for HeadTail<I>
+where
+    I::Item: Ord,
+{
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.key().cmp(&self.key())
+    }
+}
+
+impl<I: Iterator> PartialOrd for HeadTail<I>
+where
+    I::Item: Ord,
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I: Iterator> PartialEq for HeadTail<I>
+where
+    I::Item: Ord,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<I: Iterator> Eq for HeadTail<I> where I::Item: Ord {}
+
+pub struct KMerge<I: Iterator> {
+    heap: BinaryHeap<HeadTail<I>>,
+}
+
+impl<I> Clone for KMerge<I>
+where
+    I: Iterator + Clone,
+    I::Item: Clone,
+{
+    fn clone(&self) -> Self {
+        KMerge {
+            heap: self.heap.clone(),
+        }
+    }
+}
+
+impl<I> fmt::Debug for KMerge<I>
+where
+    I: Iterator + fmt::Debug,
+    I::Item: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KMerge").field("heap", &self.heap).finish()
+    }
+}
+
+impl<I: Iterator> KMerge<I>
+where
+    I::Item: Ord,
+{
+    pub(crate) fn new<O>(sources: O) -> Self
+    where
+        O: Iterator,
+        O::Item: IntoIterator<IntoIter = I>,
+    {
+        let heap = sources
+            .enumerate()
+            .filter_map(|(source, iter)| {
+                let mut tail = iter.into_iter().fuse();
+                let head = tail.next()?;
+                Some(HeadTail { head, tail, source })
+            })
+            .collect();
+        KMerge { heap }
+    }
+}
+
+impl<I: Iterator> Iterator for KMerge<I>
+where
+    I::Item: Ord,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let mut top = self.heap.peek_mut()?;
+        match top.tail.next() {
+            // Dropping `top` moves the source to its new place in the heap
+            Some(next) => Some(mem::replace(&mut top.head, next)),
+            None => Some(PeekMut::pop(top).head),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.heap.iter().fold((0, Some(0)), |total, source| {
+            add_hints(total, add_hints((1, Some(1)), source.tail.size_hint()))
+        })
+    }
+}
+
+impl<I: Iterator> FusedIterator for KMerge<I> where I::Item: Ord {}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{check_fused, check_size_hints, Flaky};
+    use crate::IterExt;
+
+    #[test]
+    fn test_interleave() {
+        let mixed: Vec<i32> = [1, 3, 5].into_iter().interleave([2, 4, 6, 8, 10]).collect();
+        assert_eq!(mixed, vec![1, 2, 3, 4, 5, 6, 8, 10]);
+        let mixed: Vec<i32> = [1, 3, 5, 7].into_iter().interleave([2]).collect();
+        assert_eq!(mixed, vec![1, 2, 3, 5, 7]);
+
+        assert_eq!((0..3).interleave(0..4).size_hint(), (7, Some(7)));
+        for a in 0..5 {
+            for b in 0..5 {
+                check_size_hints(|| (0..a).interleave(10..10 + b));
+                check_size_hints(|| (0..a).filter(|x| x % 2 == 0).interleave(0..b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_sorted_is_stable() {
+        let merged: Vec<i32> = [1, 4, 4, 9]
+            .into_iter()
+            .merge_sorted([0, 4, 5, 10, 11])
+            .collect();
+        assert_eq!(merged, vec![0, 1, 4, 4, 4, 5, 9, 10, 11]);
+
+        // Equal keys: the left side's items come first
+        let left = [(1, 'a'), (2, 'a')].into_iter().map(Tagged);
+        let right = [(1, 'b'), (2, 'b')].into_iter().map(Tagged);
+        let tags: Vec<char> = left.merge_sorted(right).map(|t| t.0 .1).collect();
+        assert_eq!(tags, vec!['a', 'b', 'a', 'b']);
+
+        for a in 0..5 {
+            for b in 0..5 {
+                check_size_hints(|| (0..a).merge_sorted(0..b));
+                check_size_hints(|| (0..a).filter(|x| x % 2 == 0).merge_sorted(0..b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_kmerge() {
+        let sources = vec![vec![3, 6, 9], vec![], vec![1, 2, 10], vec![5]];
+        let merged: Vec<i32> = sources.into_iter().kmerge().collect();
+        assert_eq!(merged, vec![1, 2, 3, 5, 6, 9, 10]);
+
+        let tags: Vec<char> = (0..3)
+            .map(|source| {
+                [(0, source), (1, source)]
+                    .into_iter()
+                    .map(|(k, s)| Tagged((k, (b'a' + s) as char)))
+            })
+            .kmerge()
+            .map(|t| t.0 .1)
+            .collect();
+        assert_eq!(tags, vec!['a', 'b', 'c', 'a', 'b', 'c']);
+
+        assert_eq!(
+            vec![0..3, 5..9].into_iter().kmerge().size_hint(),
+            (7, Some(7))
+        );
+        for k in 0..4 {
+            for len in 0..4 {
+                check_size_hints(|| (0..k).map(move |i| i..i + len).kmerge());
+                check_size_hints(|| {
+                    (0..k)
+                        .map(move |i| (i..i + len).filter(|x| x % 2 == 0))
+                        .kmerge()
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_unfused_sources_stay_finished() {
+        let mut mixed = Flaky::new(vec![1, 2, 3], 1).interleave(Flaky::new(vec![10, 20], 1));
+        assert_eq!(mixed.next(), Some(1));
+        assert_eq!(mixed.next(), Some(10));
+        check_fused(mixed);
+
+        let mut merged = Flaky::new(vec![1, 5], 1).merge_sorted(Flaky::new(vec![2, 3], 1));
+        assert_eq!(merged.next(), Some(1));
+        assert_eq!(merged.next(), Some(2));
+        check_fused(merged);
+
+        let mut merged = vec![Flaky::new(vec![1, 4], 1), Flaky::new(vec![2], 2)]
+            .into_iter()
+            .kmerge();
+        assert_eq!(merged.next(), Some(1));
+        assert_eq!(merged.next(), Some(2));
+        check_fused(merged);
+    }
+
+    // Ordered by the number only, so equal numbers can be told apart
+    #[derive(Debug)]
+    struct Tagged((i32, char));
+
+    impl PartialEq for Tagged {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 .0 == other.0 .0
+        }
+    }
+
+    impl Eq for Tagged {}
+
+    impl PartialOrd for Tagged {
+        fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Tagged {
+        fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+            self.0 .0.cmp(&other.0 .0)
+        }
+    }
+}
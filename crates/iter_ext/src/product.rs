@@ -0,0 +1,124 @@
+// Cartesian product of two iterators
+//
+// The outer iterator is walked once. The inner one has to be walked once
+// per outer item, so it is kept around unused and cloned for every pass.
+
+use std::iter::{Fuse, FusedIterator};
+
+#[derive(Debug, Clone)]
+pub struct CartesianProduct<I: Iterator, J> {
+    outer: Fuse<I>,
+    // The current outer item and the rest of its pass over the inner iterator
+    current: Option<(I::Item, Fuse<J>)>,
+    inner: J,
+}
+
+impl<I, J> CartesianProduct<I, J>
+where
+    I: Iterator,
+    I::Item: Clone,
+    J: Iterator + Clone,
+{
+    pub(crate) fn new(outer: I, inner: J) -> Self {
+        CartesianProduct {
+            outer: outer.fuse(),
+            current: None,
+            inner,
+        }
+    }
+}
+
+impl<I, J> Iterator for CartesianProduct<I, J>
+where
+    I: Iterator,
+    I::Item: Clone,
+    J: Iterator + Clone,
+{
+    type Item = (I::Item, J::Item);
+
+    fn next(&mut self) -> Option<(I::Item, J::Item)> {
+        loop {
+            if let Some((a, pass)) = &mut self.current {
+                if let Some(b) = pass.next() {
+                    return Some((a.clone(), b));
+                }
+            }
+            let a = self.outer.next()?;
+            self.current = Some((a, self.inner.clone().fuse()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // What is left of this pass, plus a full pass per outer item
+        let (outer_lower, outer_upper) = self.outer.size_hint();
+        let (inner_lower, inner_upper) = self.inner.size_hint();
+        let (pass_lower, pass_upper) = match &self.current {
+            Some((_, pass)) => pass.size_hint(),
+            None => (0, Some(0)),
+        };
+        let lower = outer_lower
+            .saturating_mul(inner_lower)
+            .saturating_add(pass_lower);
+        let upper = match (outer_upper, inner_upper, pass_upper) {
+            (Some(outer), Some(inner), Some(pass)) => outer
+                .checked_mul(inner)
+                .and_then(|full| full.checked_add(pass)),
+            _ => None,
+        };
+        (lower, upper)
+    }
+}
+
+impl<I, J> FusedIterator for CartesianProduct<I, J>
+where
+    I: Iterator,
+    I::Item: Clone,
+    J: Iterator + Clone,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{check_fused, check_size_hints, Flaky};
+    use crate::IterExt;
+
+    #[test]
+    fn test_cartesian_product() {
+        let pairs: Vec<(char, i32)> = "ab".chars().cartesian_product(1..=3).collect();
+        assert_eq!(
+            pairs,
+            vec![('a', 1), ('a', 2), ('a', 3), ('b', 1), ('b', 2), ('b', 3)]
+        );
+        assert_eq!((0..3).cartesian_product(0..0).next(), None);
+        assert_eq!((0..0).cartesian_product(0..3).next(), None);
+
+        let mut pairs = (0..3).cartesian_product(0..4);
+        assert_eq!(pairs.size_hint(), (12, Some(12)));
+        pairs.nth(4);
+        assert_eq!(pairs.size_hint(), (7, Some(7)));
+        // Overflow leaves the upper bound open
+        assert_eq!(
+            (0..usize::MAX).cartesian_product(0..3).size_hint(),
+            (usize::MAX, None)
+        );
+
+        for a in 0..4 {
+            for b in 0..4 {
+                check_size_hints(|| (0..a).cartesian_product(0..b));
+                check_size_hints(|| {
+                    (0..a)
+                        .filter(|x| x % 2 == 0)
+                        .cartesian_product((0..b).filter(|x| x % 3 != 0))
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_unfused_sources_stay_finished() {
+        let mut pairs = Flaky::new(vec![1, 2, 3], 1).cartesian_product(Flaky::new(vec![10, 20], 1));
+        // Every pass over the inner iterator stops at its first None
+        assert_eq!(pairs.next(), Some((1, 10)));
+        check_fused(pairs);
+    }
+}
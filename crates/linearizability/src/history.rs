@@ -0,0 +1,264 @@
+// Recording what happened during a concurrent run
+//
+// Every operation gets two timestamps from a shared logical clock: one
+// taken before it starts and one after it returns. If a's response
+// timestamp is smaller than b's call timestamp, a really did finish before
+// b started, and any linearization has to keep them in that order.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation<Op, Ret> {
+    pub thread: usize,
+    pub op: Op,
+    // None while the operation has not returned
+    pub ret: Option<Ret>,
+    pub call: u64,
+    pub response: Option<u64>,
+}
+
+impl<Op, Ret> Operation<Op, Ret> {
+    pub fn is_complete(&self) -> bool {
+        self.response.is_some()
+    }
+
+    // Pending operations never return, so they never bound anything
+    pub(crate) fn returned_at(&self) -> u64 {
+        self.response.unwrap_or(u64::MAX)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct History<Op, Ret> {
+    ops: Vec<Operation<Op, Ret>>,
+}
+
+impl<Op, Ret> History<Op, Ret> {
+    pub fn new() -> Self {
+        History { ops: Vec::new() }
+    }
+
+    pub fn push(&mut self, operation: Operation<Op, Ret>) {
+        self.ops.push(operation);
+    }
+
+    // Adds an operation that ran from `call` to `response`
+    pub fn complete(&mut self, thread: usize, op: Op, ret: Ret, call: u64, response: u64) {
+        self.push(Operation {
+            thread,
+            op,
+            ret: Some(ret),
+            call,
+            response: Some(response),
+        });
+    }
+
+    // Adds an operation that was invoked at `call` but never returned
+    pub fn pending(&mut self, thread: usize, op: Op, call: u64) {
+        self.push(Operation {
+            thread,
+            op,
+            ret: None,
+            call,
+            response: None,
+        });
+    }
+
+    pub fn operations(&self) -> &[Operation<Op, Ret>] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<Op: Clone, Ret: Clone> History<Op, Ret> {
+    pub(crate) fn subset(&self, keep: &[bool]) -> Self {
+        History {
+            ops: self
+                .ops
+                .iter()
+                .zip(keep)
+                .filter(|(_, &keep)| keep)
+                .map(|(op, _)| op.clone())
+                .collect(),
+        }
+    }
+}
+
+impl<Op, Ret> Default for History<Op, Ret> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Op, Ret> FromIterator<Operation<Op, Ret>> for History<Op, Ret> {
+    fn from_iter<I: IntoIterator<Item = Operation<Op, Ret>>>(iter: I) -> Self {
+        History {
+            ops: iter.into_iter().collect(),
+        }
+    }
+}
+
+// One line per operation in call order, e.g. "t1 [   3,    7] Pop -> Some(2)"
+impl<Op: fmt::Debug, Ret: fmt::Debug> fmt::Display for History<Op, Ret> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut ops: Vec<_> = self.ops.iter().collect();
+        ops.sort_by_key(|op| op.call);
+        for op in ops {
+            write!(f, "t{} [{:>4}, ", op.thread, op.call)?;
+            match (op.response, &op.ret) {
+                (Some(response), Some(ret)) => {
+                    writeln!(f, "{:>4}] {:?} -> {:?}", response, op.op, ret)?
+                }
+                _ => writeln!(f, "   -] {:?} (pending)", op.op)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallId(usize);
+
+// Thread-safe log of operations. Share it by reference (or Arc) between the
+// threads of a run, wrap every operation in record(), then check history().
+pub struct Recorder<Op, Ret> {
+    clock: AtomicU64,
+    ops: Mutex<Vec<Operation<Op, Ret>>>,
+}
+
+impl<Op, Ret> Recorder<Op, Ret> {
+    pub fn new() -> Self {
+        Recorder {
+            clock: AtomicU64::new(0),
+            ops: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::SeqCst)
+    }
+
+    // Logs the start of an operation; pass the id to respond() when it
+    // returns. Operations that never get a response stay pending.
+    pub fn invoke(&self, thread: usize, op: Op) -> CallId {
+        let mut ops = self.ops.lock().unwrap();
+        ops.push(Operation {
+            thread,
+            op,
+            ret: None,
+            call: self.tick(),
+            response: None,
+        });
+        CallId(ops.len() - 1)
+    }
+
+    pub fn respond(&self, id: CallId, ret: Ret) {
+        let response = self.tick();
+        let mut ops = self.ops.lock().unwrap();
+        let operation = &mut ops[id.0];
+        assert!(
+            operation.response.is_none(),
+            "operation {:?} already returned",
+            id
+        );
+        operation.ret = Some(ret);
+        operation.response = Some(response);
+    }
+
+    // Runs `f` as operation `op` of `thread` and logs its result
+    pub fn record<F>(&self, thread: usize, op: Op, f: F) -> Ret
+    where
+        F: FnOnce() -> Ret,
+        Ret: Clone,
+    {
+        let id = self.invoke(thread, op);
+        let ret = f();
+        self.respond(id, ret.clone());
+        ret
+    }
+
+    pub fn into_history(self) -> History<Op, Ret> {
+        History {
+            ops: self.ops.into_inner().unwrap(),
+        }
+    }
+}
+
+impl<Op: Clone, Ret: Clone> Recorder<Op, Ret> {
+    pub fn history(&self) -> History<Op, Ret> {
+        History {
+            ops: self.ops.lock().unwrap().clone(),
+        }
+    }
+}
+
+impl<Op, Ret> Default for Recorder<Op, Ret> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_recorder_timestamps_respect_real_time() {
+        let recorder = Recorder::new();
+        recorder.record(0, "first", || 1);
+        let pending = recorder.invoke(0, "second");
+        let history = recorder.history();
+        let ops = history.operations();
+        assert_eq!(ops[0].ret, Some(1));
+        assert!(ops[0].returned_at() < ops[1].call);
+        assert!(!ops[1].is_complete());
+
+        recorder.respond(pending, 2);
+        let history = recorder.into_history();
+        assert!(history.operations().iter().all(Operation::is_complete));
+    }
+
+    #[test]
+    fn test_recorder_from_many_threads() {
+        let recorder = Recorder::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let recorder = &recorder;
+                s.spawn(move || {
+                    for i in 0..100 {
+                        recorder.record(t, i, || i * 2);
+                    }
+                });
+            }
+        });
+        let history = recorder.into_history();
+        assert_eq!(history.len(), 400);
+        for op in history.operations() {
+            assert!(op.call < op.returned_at());
+            assert_eq!(op.ret, Some(op.op * 2));
+        }
+    }
+
+    #[test]
+    fn test_display_in_call_order() {
+        let mut history = History::new();
+        history.complete(1, "b", 2, 2, 3);
+        history.complete(0, "a", 1, 0, 1);
+        history.pending(2, "c", 4);
+        let text = history.to_string();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "t0 [   0,    1] \"a\" -> 1");
+        assert_eq!(lines[1], "t1 [   2,    3] \"b\" -> 2");
+        assert_eq!(lines[2], "t2 [   4,    -] \"c\" (pending)");
+    }
+}
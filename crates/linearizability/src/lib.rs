@@ -0,0 +1,381 @@
+// Linearizability checking for concurrent data structures
+//
+// A Recorder logs when each operation of a concurrent run was invoked and
+// when it returned. check() then looks for a sequential order of those
+// operations that
+//
+// - respects real time: an operation that returned before another was
+//   invoked comes first, and
+// - gives the recorded results when replayed against a sequential Model.
+//
+// The search is the Wing & Gong algorithm: repeatedly pick an operation
+// that could take effect next, apply it to the model, and backtrack when a
+// result does not match. Like Lowe's version it remembers every (set of
+// applied operations, model state) pair it has given up on, so equivalent
+// branches are only explored once.
+//
+// If no order exists, the history is shrunk to a minimal counterexample.
+pub mod history;
+pub mod models;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+
+pub use history::{CallId, History, Operation, Recorder};
+pub use models::{ContainerOp, PriorityQueueModel, QueueModel, StackModel};
+
+// Sequential specification of a data structure
+pub trait Model: Clone + Eq + Hash {
+    type Op: Clone + fmt::Debug;
+    type Ret: Clone + PartialEq + fmt::Debug;
+    type Key: Eq + Hash;
+
+    fn apply(&mut self, op: &Self::Op) -> Self::Ret;
+
+    // Shrinking removes operations that share a key together, and
+    // operations without a key on their own. Removing one such group from a
+    // linearizable history must leave it linearizable; then a shrunk
+    // history that still fails is evidence against the original one.
+    fn shrink_key(op: &Self::Op, ret: Option<&Self::Ret>) -> Option<Self::Key>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample<Op, Ret> {
+    // Smallest sub-history found that still cannot be linearized
+    pub history: History<Op, Ret>,
+    // Number of operations in the history that was checked
+    pub original_len: usize,
+}
+
+impl<Op: fmt::Debug, Ret: fmt::Debug> fmt::Display for Counterexample<Op, Ret> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "not linearizable; minimal counterexample ({} of {} operations):",
+            self.history.len(),
+            self.original_len
+        )?;
+        write!(f, "{}", self.history)
+    }
+}
+
+impl<Op: fmt::Debug, Ret: fmt::Debug> std::error::Error for Counterexample<Op, Ret> {}
+
+// Returns a linearization as indices into history.operations(), in the
+// order they take effect. Pending operations appear only if they had to
+// take effect to explain the other results.
+pub fn check<M: Model>(
+    model: &M,
+    history: &History<M::Op, M::Ret>,
+) -> Result<Vec<usize>, Counterexample<M::Op, M::Ret>> {
+    match linearize(model, history) {
+        Some(order) => Ok(order),
+        None => Err(Counterexample {
+            history: shrink(model, history),
+            original_len: history.len(),
+        }),
+    }
+}
+
+pub fn is_linearizable<M: Model>(model: &M, history: &History<M::Op, M::Ret>) -> bool {
+    linearize(model, history).is_some()
+}
+
+struct Search<'a, M: Model> {
+    ops: &'a [Operation<M::Op, M::Ret>],
+    // Indices into ops, sorted by call time
+    by_call: Vec<usize>,
+    applied: Vec<bool>,
+    order: Vec<usize>,
+    dead_ends: HashSet<(Vec<bool>, M)>,
+}
+
+impl<M: Model> Search<'_, M> {
+    fn run(&mut self, state: &M, completed_left: usize) -> bool {
+        if completed_left == 0 {
+            return true;
+        }
+        if self
+            .dead_ends
+            .contains(&(self.applied.clone(), state.clone()))
+        {
+            return false;
+        }
+
+        // Anything invoked before the earliest outstanding response could
+        // be next; anything invoked after it has to wait for that operation
+        let horizon = self
+            .by_call
+            .iter()
+            .filter(|&&i| !self.applied[i])
+            .map(|&i| self.ops[i].returned_at())
+            .min()
+            .unwrap_or(u64::MAX);
+
+        for position in 0..self.by_call.len() {
+            let i = self.by_call[position];
+            let op = &self.ops[i];
+            if op.call > horizon {
+                break;
+            }
+            if self.applied[i] {
+                continue;
+            }
+            let mut next = state.clone();
+            let ret = next.apply(&op.op);
+            if op.ret.as_ref().is_some_and(|expected| *expected != ret) {
+                continue;
+            }
+            self.applied[i] = true;
+            self.order.push(i);
+            let left = completed_left - usize::from(op.is_complete());
+            if self.run(&next, left) {
+                return true;
+            }
+            self.applied[i] = false;
+            self.order.pop();
+        }
+
+        self.dead_ends.insert((self.applied.clone(), state.clone()));
+        false
+    }
+}
+
+fn linearize<M: Model>(model: &M, history: &History<M::Op, M::Ret>) -> Option<Vec<usize>> {
+    let ops = history.operations();
+    let mut by_call: Vec<usize> = (0..ops.len()).collect();
+    by_call.sort_by_key(|&i| ops[i].call);
+    let mut search = Search {
+        ops,
+        by_call,
+        applied: vec![false; ops.len()],
+        order: Vec::new(),
+        dead_ends: HashSet::new(),
+    };
+    let completed = ops.iter().filter(|op| op.is_complete()).count();
+    if search.run(model, completed) {
+        Some(search.order)
+    } else {
+        None
+    }
+}
+
+// Removes groups of operations (see Model::shrink_key) for as long as the
+// rest still fails: first large chunks, then one group at a time until no
+// single group can go.
+fn shrink<M: Model>(model: &M, history: &History<M::Op, M::Ret>) -> History<M::Op, M::Ret> {
+    let ops = history.operations();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut keyed: HashMap<M::Key, usize> = HashMap::new();
+    for (i, op) in ops.iter().enumerate() {
+        match M::shrink_key(&op.op, op.ret.as_ref()) {
+            Some(key) => {
+                let group = *keyed.entry(key).or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+                groups[group].push(i);
+            }
+            None => groups.push(vec![i]),
+        }
+    }
+
+    let mut keep = vec![true; ops.len()];
+    let try_remove = |keep: &mut [bool], groups: &[Vec<usize>]| {
+        let removed: Vec<usize> = groups
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|&i| keep[i])
+            .collect();
+        if removed.is_empty() {
+            return false;
+        }
+        for &i in &removed {
+            keep[i] = false;
+        }
+        if is_linearizable(model, &history.subset(keep)) {
+            for &i in &removed {
+                keep[i] = true;
+            }
+            return false;
+        }
+        true
+    };
+
+    let mut chunk = groups.len() / 2;
+    while chunk > 1 {
+        for start in (0..groups.len()).step_by(chunk) {
+            let end = (start + chunk).min(groups.len());
+            try_remove(&mut keep, &groups[start..end]);
+        }
+        chunk /= 2;
+    }
+    loop {
+        let mut changed = false;
+        for group in 0..groups.len() {
+            changed |= try_remove(&mut keep, &groups[group..=group]);
+        }
+        if !changed {
+            break;
+        }
+    }
+    history.subset(&keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+    use std::thread;
+    use ContainerOp::{Pop, Push};
+
+    type QueueHistory = History<ContainerOp<i32>, Option<i32>>;
+
+    // Runs ops back to back on one thread, one tick each
+    fn sequential(ops: &[(ContainerOp<i32>, Option<i32>)]) -> QueueHistory {
+        let mut history = History::new();
+        for (t, (op, ret)) in ops.iter().enumerate() {
+            let t = t as u64;
+            history.complete(0, op.clone(), *ret, 2 * t, 2 * t + 1);
+        }
+        history
+    }
+
+    #[test]
+    fn test_sequential_history_follows_the_model() {
+        let history = sequential(&[
+            (Push(1), None),
+            (Push(2), None),
+            (Pop, Some(1)),
+            (Pop, Some(2)),
+            (Pop, None),
+        ]);
+        assert_eq!(check(&QueueModel::new(), &history), Ok(vec![0, 1, 2, 3, 4]));
+
+        let lifo = sequential(&[(Push(1), None), (Push(2), None), (Pop, Some(2))]);
+        assert!(is_linearizable(&StackModel::new(), &lifo));
+        assert!(!is_linearizable(&QueueModel::new(), &lifo));
+    }
+
+    #[test]
+    fn test_overlapping_operations_may_take_effect_in_either_order() {
+        // Two pushes overlap, so a later pop may see either one first
+        let mut history = QueueHistory::new();
+        history.complete(0, Push(1), None, 0, 3);
+        history.complete(1, Push(2), None, 1, 2);
+        history.complete(0, Pop, Some(2), 4, 5);
+        assert_eq!(check(&QueueModel::new(), &history), Ok(vec![1, 0, 2]));
+
+        // Once push(1) has returned before push(2) starts, it must go first
+        let mut history = QueueHistory::new();
+        history.complete(0, Push(1), None, 0, 1);
+        history.complete(1, Push(2), None, 2, 3);
+        history.complete(0, Pop, Some(2), 4, 5);
+        assert!(!is_linearizable(&QueueModel::new(), &history));
+    }
+
+    #[test]
+    fn test_pending_operation_may_or_may_not_take_effect() {
+        let mut history = QueueHistory::new();
+        history.pending(0, Push(7), 0);
+        history.complete(1, Pop, Some(7), 1, 2);
+        history.complete(1, Pop, None, 3, 4);
+        assert_eq!(check(&QueueModel::new(), &history), Ok(vec![0, 1, 2]));
+
+        let mut history = QueueHistory::new();
+        history.pending(0, Push(7), 0);
+        history.complete(1, Pop, None, 1, 2);
+        assert_eq!(check(&QueueModel::new(), &history), Ok(vec![1]));
+    }
+
+    #[test]
+    fn test_counterexample_is_minimal() {
+        // A FIFO violation buried among unrelated operations
+        let mut ops = Vec::new();
+        for value in 10..20 {
+            ops.push((Push(value), None));
+            ops.push((Pop, Some(value)));
+        }
+        ops.extend([
+            (Push(1), None),
+            (Push(2), None),
+            (Pop, Some(2)),
+            (Pop, Some(1)),
+        ]);
+        for value in 20..30 {
+            ops.push((Push(value), None));
+        }
+        let history = sequential(&ops);
+
+        let counterexample = check(&QueueModel::new(), &history).unwrap_err();
+        assert_eq!(counterexample.original_len, 34);
+        let kept: Vec<_> = counterexample
+            .history
+            .operations()
+            .iter()
+            .map(|op| op.op.clone())
+            .collect();
+        assert_eq!(kept, [Push(1), Push(2), Pop, Pop]);
+        assert!(counterexample.to_string().contains("4 of 34 operations"));
+    }
+
+    #[test]
+    fn test_concurrent_queue_is_linearizable() {
+        let queue = Mutex::new(VecDeque::new());
+        let recorder = Recorder::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let (queue, recorder) = (&queue, &recorder);
+                s.spawn(move || {
+                    for i in 0..25 {
+                        let value = t * 100 + i;
+                        recorder.record(t as usize, Push(value), || {
+                            queue.lock().unwrap().push_back(value);
+                            None
+                        });
+                        recorder.record(t as usize, Pop, || queue.lock().unwrap().pop_front());
+                    }
+                });
+            }
+        });
+        let history = recorder.into_history();
+        assert_eq!(
+            check(&QueueModel::new(), &history).map(|order| order.len()),
+            Ok(200)
+        );
+    }
+
+    #[test]
+    fn test_broken_queue_is_caught() {
+        // A "queue" that is really a stack: each thread pushes two values
+        // and then pops, so some pop returns the newer of two queued values
+        let queue = Mutex::new(Vec::new());
+        let recorder = Recorder::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let (queue, recorder) = (&queue, &recorder);
+                s.spawn(move || {
+                    for value in [t * 10, t * 10 + 1] {
+                        recorder.record(t as usize, Push(value), || {
+                            queue.lock().unwrap().push(value);
+                            None
+                        });
+                    }
+                    for _ in 0..2 {
+                        recorder.record(t as usize, Pop, || queue.lock().unwrap().pop());
+                    }
+                });
+            }
+        });
+        let history = recorder.into_history();
+        let counterexample = check(&QueueModel::new(), &history).unwrap_err();
+        assert!(counterexample.history.len() < history.len());
+        assert!(!is_linearizable(
+            &QueueModel::new(),
+            &counterexample.history
+        ));
+    }
+}
@@ -0,0 +1,201 @@
+// Sequential models of the workspace's containers
+//
+// All three understand the same two operations. Push returns None so that
+// every operation has the same result type.
+
+use crate::Model;
+use std::collections::{BinaryHeap, VecDeque};
+use std::hash::{Hash, Hasher};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ContainerOp<T> {
+    Push(T),
+    Pop,
+}
+
+// Shrinking drops a pushed value together with the pops that returned it.
+// Removing such a pair (or a pop that found nothing) from a linearizable
+// history of any of these containers leaves it linearizable.
+fn container_key<T: Clone>(op: &ContainerOp<T>, ret: Option<&Option<T>>) -> Option<T> {
+    match (op, ret) {
+        (ContainerOp::Push(value), _) => Some(value.clone()),
+        (ContainerOp::Pop, Some(Some(value))) => Some(value.clone()),
+        (ContainerOp::Pop, _) => None,
+    }
+}
+
+// First in, first out
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QueueModel<T> {
+    items: VecDeque<T>,
+}
+
+impl<T> QueueModel<T> {
+    pub fn new() -> Self {
+        QueueModel {
+            items: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> Default for QueueModel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Eq + Hash + std::fmt::Debug> Model for QueueModel<T> {
+    type Op = ContainerOp<T>;
+    type Ret = Option<T>;
+    type Key = T;
+
+    fn apply(&mut self, op: &Self::Op) -> Self::Ret {
+        match op {
+            ContainerOp::Push(value) => {
+                self.items.push_back(value.clone());
+                None
+            }
+            ContainerOp::Pop => self.items.pop_front(),
+        }
+    }
+
+    fn shrink_key(op: &Self::Op, ret: Option<&Self::Ret>) -> Option<T> {
+        container_key(op, ret)
+    }
+}
+
+// Last in, first out
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StackModel<T> {
+    items: Vec<T>,
+}
+
+impl<T> StackModel<T> {
+    pub fn new() -> Self {
+        StackModel { items: Vec::new() }
+    }
+}
+
+impl<T> Default for StackModel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone + Eq + Hash + std::fmt::Debug> Model for StackModel<T> {
+    type Op = ContainerOp<T>;
+    type Ret = Option<T>;
+    type Key = T;
+
+    fn apply(&mut self, op: &Self::Op) -> Self::Ret {
+        match op {
+            ContainerOp::Push(value) => {
+                self.items.push(value.clone());
+                None
+            }
+            ContainerOp::Pop => self.items.pop(),
+        }
+    }
+
+    fn shrink_key(op: &Self::Op, ret: Option<&Self::Ret>) -> Option<T> {
+        container_key(op, ret)
+    }
+}
+
+// Largest first. Two heaps are the same state if they hold the same items,
+// however they are laid out inside.
+#[derive(Debug, Clone)]
+pub struct PriorityQueueModel<T: Ord> {
+    heap: BinaryHeap<T>,
+}
+
+impl<T: Ord + Clone> PriorityQueueModel<T> {
+    pub fn new() -> Self {
+        PriorityQueueModel {
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    fn sorted(&self) -> Vec<T> {
+        self.heap.clone().into_sorted_vec()
+    }
+}
+
+impl<T: Ord + Clone> Default for PriorityQueueModel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Clone> PartialEq for PriorityQueueModel<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.heap.len() == other.heap.len() && self.sorted() == other.sorted()
+    }
+}
+
+impl<T: Ord + Clone> Eq for PriorityQueueModel<T> {}
+
+impl<T: Ord + Clone + Hash> Hash for PriorityQueueModel<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted().hash(state);
+    }
+}
+
+impl<T: Ord + Clone + Hash + std::fmt::Debug> Model for PriorityQueueModel<T> {
+    type Op = ContainerOp<T>;
+    type Ret = Option<T>;
+    type Key = T;
+
+    fn apply(&mut self, op: &Self::Op) -> Self::Ret {
+        match op {
+            ContainerOp::Push(value) => {
+                self.heap.push(value.clone());
+                None
+            }
+            ContainerOp::Pop => self.heap.pop(),
+        }
+    }
+
+    fn shrink_key(op: &Self::Op, ret: Option<&Self::Ret>) -> Option<T> {
+        container_key(op, ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ContainerOp::{Pop, Push};
+
+    fn run<M: Model<Op = ContainerOp<i32>, Ret = Option<i32>>>(mut model: M) -> Vec<Option<i32>> {
+        [Push(2), Push(3), Push(1), Pop, Pop, Pop, Pop]
+            .iter()
+            .map(|op| model.apply(op))
+            .skip(3)
+            .collect()
+    }
+
+    #[test]
+    fn test_models_pop_in_their_own_order() {
+        assert_eq!(run(QueueModel::new()), [Some(2), Some(3), Some(1), None]);
+        assert_eq!(run(StackModel::new()), [Some(1), Some(3), Some(2), None]);
+        assert_eq!(
+            run(PriorityQueueModel::new()),
+            [Some(3), Some(2), Some(1), None]
+        );
+    }
+
+    #[test]
+    fn test_priority_queue_state_ignores_layout() {
+        let mut a = PriorityQueueModel::new();
+        let mut b = PriorityQueueModel::new();
+        for value in [1, 5, 3] {
+            a.apply(&Push(value));
+        }
+        for value in [5, 3, 1] {
+            b.apply(&Push(value));
+        }
+        assert_eq!(a, b);
+        b.apply(&Pop);
+        assert_ne!(a, b);
+    }
+}
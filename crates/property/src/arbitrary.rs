@@ -0,0 +1,266 @@
+// Generating and shrinking values
+//
+// arbitrary() builds a random value whose size is bounded by the Gen.
+// shrink() lists simpler variants of a value, simplest first; the runner
+// keeps the first one that still fails and shrinks again from there.
+
+use crate::gen::Gen;
+use std::fmt::Debug;
+
+pub trait Arbitrary: Clone + Debug + 'static {
+    fn arbitrary(g: &mut Gen) -> Self;
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl Arbitrary for () {
+    fn arbitrary(_: &mut Gen) -> Self {}
+}
+
+impl Arbitrary for bool {
+    fn arbitrary(g: &mut Gen) -> Self {
+        g.bool()
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new(self.then_some(false).into_iter())
+    }
+}
+
+// Candidates between 0 and x, closest to 0 first: x - x, x - x/2, ...
+fn shrink_integer(x: i128) -> impl Iterator<Item = i128> {
+    let mut candidates = Vec::new();
+    if x < 0 {
+        candidates.push(-x);
+    }
+    let mut delta = x;
+    while delta != 0 {
+        candidates.push(x - delta);
+        delta /= 2;
+    }
+    candidates.into_iter()
+}
+
+macro_rules! integer_arbitrary {
+    ($($t:ty),*) => {
+        $(
+            impl Arbitrary for $t {
+                fn arbitrary(g: &mut Gen) -> Self {
+                    // Now and then an edge value, where overflow bugs live
+                    if g.ratio(1, 20) {
+                        return *g.choose(&[<$t>::MIN, <$t>::MAX, 0, 1]);
+                    }
+                    let bound = (g.size() as i128).min(<$t>::MAX as i128);
+                    let low = (-bound).max(<$t>::MIN as i128);
+                    g.range(low as i64, bound as i64) as $t
+                }
+
+                fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+                    Box::new(shrink_integer(*self as i128).filter_map(|x| <$t>::try_from(x).ok()))
+                }
+            }
+        )*
+    };
+}
+
+integer_arbitrary!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+// Finite values only; properties that care about NaN and infinities should
+// generate them explicitly
+impl Arbitrary for f64 {
+    fn arbitrary(g: &mut Gen) -> Self {
+        if g.ratio(1, 10) {
+            return *g.choose(&[0.0, 1.0, -1.0]);
+        }
+        (g.unit_f64() * 2.0 - 1.0) * g.size() as f64
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let x = *self;
+        let mut candidates = Vec::new();
+        if x.is_finite() && x != 0.0 {
+            candidates.push(0.0);
+            if x < 0.0 {
+                candidates.push(-x);
+            }
+            if x.fract() != 0.0 {
+                candidates.push(x.trunc());
+            }
+            if x.abs() >= 2.0 {
+                candidates.push(x / 2.0);
+            }
+        }
+        Box::new(candidates.into_iter())
+    }
+}
+
+impl Arbitrary for char {
+    fn arbitrary(g: &mut Gen) -> Self {
+        if g.ratio(9, 10) {
+            g.range(0x20, 0x7e) as u8 as char
+        } else {
+            *g.choose(&['\t', '\n', 'é', 'ß', '中', '🦀'])
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new((*self != 'a').then_some('a').into_iter())
+    }
+}
+
+impl Arbitrary for String {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let len = g.index(g.size() + 1);
+        (0..len).map(|_| char::arbitrary(g)).collect()
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let chars: Vec<char> = self.chars().collect();
+        Box::new(chars.shrink().map(|chars| chars.into_iter().collect()))
+    }
+}
+
+impl<T: Arbitrary> Arbitrary for Vec<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let len = g.index(g.size() + 1);
+        (0..len).map(|_| T::arbitrary(g)).collect()
+    }
+
+    // First drop chunks (all, halves, quarters, ... single elements), then
+    // shrink the elements one at a time
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let len = self.len();
+        let items = self.clone();
+        let removals = std::iter::successors(Some(len), |&chunk| (chunk > 1).then_some(chunk / 2))
+            .filter(|&chunk| chunk > 0)
+            .flat_map(move |chunk| {
+                let items = items.clone();
+                (0..len).step_by(chunk).map(move |start| {
+                    let mut smaller = items.clone();
+                    smaller.drain(start..(start + chunk).min(len));
+                    smaller
+                })
+            });
+        let items = self.clone();
+        let elements = (0..len).flat_map(move |i| {
+            let items = items.clone();
+            items[i].shrink().map(move |item| {
+                let mut simpler = items.clone();
+                simpler[i] = item;
+                simpler
+            })
+        });
+        Box::new(removals.chain(elements))
+    }
+}
+
+impl<T: Arbitrary> Arbitrary for Option<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        if g.ratio(1, 4) {
+            None
+        } else {
+            Some(T::arbitrary(g))
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match self {
+            None => Box::new(std::iter::empty()),
+            Some(value) => Box::new(std::iter::once(None).chain(value.shrink().map(Some))),
+        }
+    }
+}
+
+impl<T: Arbitrary> Arbitrary for Box<T> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        Box::new(T::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        Box::new((**self).shrink().map(Box::new))
+    }
+}
+
+impl<A: Arbitrary, B: Arbitrary> Arbitrary for (A, B) {
+    fn arbitrary(g: &mut Gen) -> Self {
+        (A::arbitrary(g), B::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let (a, b) = self.clone();
+        let b2 = b.clone();
+        Box::new(
+            a.shrink()
+                .map(move |a| (a, b2.clone()))
+                .chain(b.shrink().map(move |b| (a.clone(), b))),
+        )
+    }
+}
+
+impl<A: Arbitrary, B: Arbitrary, C: Arbitrary> Arbitrary for (A, B, C) {
+    fn arbitrary(g: &mut Gen) -> Self {
+        (A::arbitrary(g), B::arbitrary(g), C::arbitrary(g))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        // Shrink as ((A, B), C) to reuse the pair implementation
+        let (a, b, c) = self.clone();
+        Box::new(((a, b), c).shrink().map(|((a, b), c)| (a, b, c)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integers_respect_size() {
+        let mut g = Gen::new(3, 10);
+        for _ in 0..1000 {
+            let x = i32::arbitrary(&mut g);
+            assert!((-10..=10).contains(&x) || [i32::MIN, i32::MAX].contains(&x));
+            let _ = u8::arbitrary(&mut g);
+        }
+    }
+
+    #[test]
+    fn test_integer_shrinks_head_for_zero() {
+        assert_eq!(
+            100u32.shrink().collect::<Vec<_>>(),
+            [0, 50, 75, 88, 94, 97, 99]
+        );
+        assert_eq!((-4i8).shrink().collect::<Vec<_>>(), [4, 0, -2, -3]);
+        assert_eq!(0i64.shrink().count(), 0);
+        // No candidate may overflow the type
+        assert!(i8::MIN.shrink().all(|x| x > i8::MIN));
+    }
+
+    #[test]
+    fn test_vec_shrinks_remove_then_simplify() {
+        let candidates: Vec<Vec<u8>> = vec![3u8, 1].shrink().collect();
+        assert_eq!(candidates[0], Vec::<u8>::new());
+        assert!(candidates.contains(&vec![1]));
+        assert!(candidates.contains(&vec![3]));
+        assert!(candidates.contains(&vec![0, 1]));
+        assert!(candidates.contains(&vec![3, 0]));
+    }
+
+    #[test]
+    fn test_string_and_option_shrinks() {
+        let shrunk: Vec<String> = "ab".to_string().shrink().collect();
+        assert_eq!(shrunk[0], "");
+        assert!(shrunk.contains(&"aa".to_string()));
+        assert_eq!(
+            Some(2u8).shrink().collect::<Vec<_>>(),
+            [None, Some(0), Some(1)]
+        );
+    }
+
+    #[test]
+    fn test_tuple_shrinks_one_side_at_a_time() {
+        let shrunk: Vec<(u8, bool)> = (2u8, true).shrink().collect();
+        assert_eq!(shrunk, [(0, true), (1, true), (2, false)]);
+    }
+}
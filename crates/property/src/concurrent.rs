@@ -0,0 +1,191 @@
+// Concurrent properties
+//
+// Parallel<Op> is a generated test case for a shared structure: a prefix
+// of operations run on one thread, then one sequence per thread run at the
+// same time. Shrinking drops threads and operations, so a failure ends up
+// as the fewest operations that still break the property.
+//
+// Thread scheduling is not under the runner's control, so a racy failure
+// may not reproduce on every run. Properties that hunt for races should
+// run each case several times and fail if any run does.
+
+use crate::arbitrary::Arbitrary;
+use crate::gen::Gen;
+use std::sync::Barrier;
+use std::thread;
+
+const MAX_THREADS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parallel<Op> {
+    pub prefix: Vec<Op>,
+    pub threads: Vec<Vec<Op>>,
+}
+
+impl<Op> Parallel<Op> {
+    pub fn len(&self) -> usize {
+        self.prefix.len() + self.threads.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn operations(&self) -> impl Iterator<Item = &Op> {
+        self.prefix.iter().chain(self.threads.iter().flatten())
+    }
+
+    // Runs the prefix, then every thread's operations on its own thread,
+    // all released at once by a barrier. `apply` gets the thread index
+    // (None for the prefix) and returns one result per operation.
+    pub fn run<S, R, F>(&self, target: &S, apply: F) -> (Vec<R>, Vec<Vec<R>>)
+    where
+        S: Sync,
+        R: Send,
+        Op: Sync,
+        F: Fn(&S, Option<usize>, &Op) -> R + Sync,
+    {
+        let prefix = self
+            .prefix
+            .iter()
+            .map(|op| apply(target, None, op))
+            .collect();
+        let barrier = Barrier::new(self.threads.len());
+        let threads = thread::scope(|s| {
+            let handles: Vec<_> = self
+                .threads
+                .iter()
+                .enumerate()
+                .map(|(t, ops)| {
+                    let (barrier, apply) = (&barrier, &apply);
+                    s.spawn(move || {
+                        barrier.wait();
+                        ops.iter().map(|op| apply(target, Some(t), op)).collect()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                })
+                .collect()
+        });
+        (prefix, threads)
+    }
+}
+
+impl<Op: Arbitrary> Arbitrary for Parallel<Op> {
+    // Between 2 and MAX_THREADS threads; the whole case stays within the
+    // size so that the interleavings stay meaningful
+    fn arbitrary(g: &mut Gen) -> Self {
+        let threads = 2 + g.index(MAX_THREADS - 1);
+        let per_thread = g.size() / (threads + 1) + 1;
+        g.with_size(per_thread, |g| Parallel {
+            prefix: Vec::arbitrary(g),
+            threads: (0..threads).map(|_| Vec::arbitrary(g)).collect(),
+        })
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        // Fewer threads (at least one), then a shorter prefix, then
+        // shorter or simpler thread sequences
+        let removable = if self.threads.len() > 1 {
+            self.threads.len()
+        } else {
+            0
+        };
+        let case = self.clone();
+        let fewer_threads = (0..removable).map(move |t| {
+            let mut fewer = case.clone();
+            fewer.threads.remove(t);
+            fewer
+        });
+        let threads = self.threads.clone();
+        let shorter_prefix = self.prefix.shrink().map(move |prefix| Parallel {
+            prefix,
+            threads: threads.clone(),
+        });
+        let case = self.clone();
+        let simpler_threads = (0..self.threads.len()).flat_map(move |t| {
+            let case = case.clone();
+            case.threads[t].shrink().map(move |ops| {
+                let mut simpler = case.clone();
+                simpler.threads[t] = ops;
+                simpler
+            })
+        });
+        Box::new(fewer_threads.chain(shorter_prefix).chain(simpler_threads))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::QuickCheck;
+    use std::sync::atomic::{AtomicI64, Ordering};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_generated_cases_have_threads() {
+        let mut g = Gen::new(5, 40);
+        for _ in 0..100 {
+            let case = Parallel::<u8>::arbitrary(&mut g);
+            assert!((2..=MAX_THREADS).contains(&case.threads.len()));
+            assert_eq!(case.len(), case.operations().count());
+        }
+    }
+
+    #[test]
+    fn test_run_returns_results_per_thread() {
+        let case = Parallel {
+            prefix: vec![1, 2],
+            threads: vec![vec![10], vec![20, 30]],
+        };
+        let total = AtomicI64::new(0);
+        let (prefix, threads) = case.run(&total, |total, thread, &x| {
+            total.fetch_add(x, Ordering::SeqCst);
+            (thread, x)
+        });
+        assert_eq!(prefix, [(None, 1), (None, 2)]);
+        assert_eq!(
+            threads,
+            [vec![(Some(0), 10)], vec![(Some(1), 20), (Some(1), 30)]]
+        );
+        assert_eq!(total.load(Ordering::SeqCst), 63);
+    }
+
+    #[test]
+    fn test_atomic_sum_property_holds() {
+        QuickCheck::new().seed(11).check(|case: Parallel<i32>| {
+            let total = AtomicI64::new(0);
+            case.run(&total, |total, _, &x| {
+                total.fetch_add(x as i64, Ordering::SeqCst)
+            });
+            total.load(Ordering::SeqCst) == case.operations().map(|&x| x as i64).sum::<i64>()
+        });
+    }
+
+    #[test]
+    fn test_failing_case_shrinks_to_fewest_operations() {
+        // The "bug": a log that silently drops the value 7
+        let failure = QuickCheck::new()
+            .seed(4)
+            .max_size(30)
+            .run(|case: Parallel<u8>| {
+                let log = Mutex::new(Vec::new());
+                case.run(&log, |log, _, &x| {
+                    if x != 7 {
+                        log.lock().unwrap().push(x);
+                    }
+                });
+                let logged = log.into_inner().unwrap().len();
+                logged == case.len()
+            })
+            .unwrap_err();
+        assert_eq!(failure.shrunk.len(), 1);
+        assert_eq!(failure.shrunk.operations().next(), Some(&7));
+    }
+}
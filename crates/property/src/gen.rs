@@ -0,0 +1,121 @@
+// Source of randomness for generators
+//
+// A Gen is a seeded splitmix64 stream plus a size. The size bounds how big
+// generated values get (collection lengths, number magnitudes, tree
+// depth); the runner starts small and grows it from test to test.
+
+#[derive(Debug, Clone)]
+pub struct Gen {
+    state: u64,
+    size: usize,
+}
+
+impl Gen {
+    pub fn new(seed: u64, size: usize) -> Gen {
+        Gen { state: seed, size }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Runs `f` with a different size, e.g. a smaller one for the children
+    // of a recursive structure
+    pub fn with_size<R>(&mut self, size: usize, f: impl FnOnce(&mut Gen) -> R) -> R {
+        let saved = std::mem::replace(&mut self.size, size);
+        let result = f(self);
+        self.size = saved;
+        result
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        mix(self.state)
+    }
+
+    // Uniform in 0..n; n must not be zero
+    pub fn below(&mut self, n: u64) -> u64 {
+        assert!(n > 0, "Gen::below(0)");
+        // Multiply-shift keeps the bias negligible without a retry loop
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+
+    pub fn index(&mut self, len: usize) -> usize {
+        self.below(len as u64) as usize
+    }
+
+    // Uniform in lo..=hi
+    pub fn range(&mut self, lo: i64, hi: i64) -> i64 {
+        assert!(lo <= hi, "Gen::range({}, {})", lo, hi);
+        let span = hi.wrapping_sub(lo) as u64;
+        if span == u64::MAX {
+            return self.next_u64() as i64;
+        }
+        lo.wrapping_add(self.below(span + 1) as i64)
+    }
+
+    pub fn bool(&mut self) -> bool {
+        self.next_u64() & 1 == 1
+    }
+
+    // True with probability numerator / denominator
+    pub fn ratio(&mut self, numerator: u64, denominator: u64) -> bool {
+        self.below(denominator) < numerator
+    }
+
+    // Uniform in [0, 1)
+    pub fn unit_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.index(items.len())]
+    }
+}
+
+// splitmix64's finaliser
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_stream() {
+        let mut a = Gen::new(7, 10);
+        let mut b = Gen::new(7, 10);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        assert_ne!(Gen::new(8, 10).next_u64(), Gen::new(7, 10).next_u64());
+    }
+
+    #[test]
+    fn test_ranges_stay_in_bounds() {
+        let mut g = Gen::new(1, 10);
+        let mut seen = [false; 7];
+        for _ in 0..1000 {
+            let x = g.range(-3, 3);
+            assert!((-3..=3).contains(&x));
+            seen[(x + 3) as usize] = true;
+            assert!(g.below(5) < 5);
+            let f = g.unit_f64();
+            assert!((0.0..1.0).contains(&f));
+        }
+        assert!(seen.iter().all(|&s| s));
+        // The full span must not overflow
+        g.range(i64::MIN, i64::MAX);
+    }
+
+    #[test]
+    fn test_with_size_restores_size() {
+        let mut g = Gen::new(1, 10);
+        let inner = g.with_size(3, |g| g.size());
+        assert_eq!(inner, 3);
+        assert_eq!(g.size(), 10);
+    }
+}
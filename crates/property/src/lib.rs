@@ -0,0 +1,302 @@
+// Property-based testing in the style of QuickCheck
+//
+// A property is a function from generated input to a Testable result
+// (bool, () or Result<(), E>; panics count as failures). The runner feeds
+// it `tests` random inputs of growing size. On the first failure it shrinks
+// the input to a minimal one that still fails and reports both, together
+// with the seed that reproduces the run:
+//
+//     QuickCheck::new().seed(0x2a).check(|v: Vec<u8>| v.len() < 5);
+//
+// The seed comes from PROPERTY_SEED when set, so a failure seen in CI can
+// be replayed locally without touching the test.
+pub mod arbitrary;
+pub mod concurrent;
+pub mod gen;
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+
+pub use arbitrary::Arbitrary;
+pub use concurrent::Parallel;
+pub use gen::Gen;
+
+pub trait Testable {
+    fn result(self) -> Result<(), String>;
+}
+
+impl Testable for bool {
+    fn result(self) -> Result<(), String> {
+        if self {
+            Ok(())
+        } else {
+            Err("property returned false".to_string())
+        }
+    }
+}
+
+impl Testable for () {
+    fn result(self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<E: fmt::Display> Testable for Result<(), E> {
+    fn result(self) -> Result<(), String> {
+        self.map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub tests: usize,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure<A> {
+    pub seed: u64,
+    // How many inputs passed before this one failed
+    pub passed: usize,
+    pub original: A,
+    pub shrunk: A,
+    pub shrinks: usize,
+    // Why the shrunk input failed
+    pub message: String,
+}
+
+impl<A: fmt::Debug> fmt::Display for Failure<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "property failed after {} passing tests (rerun with PROPERTY_SEED={})",
+            self.passed, self.seed
+        )?;
+        writeln!(f, "  input:  {:?}", self.original)?;
+        writeln!(f, "  shrunk: {:?} ({} steps)", self.shrunk, self.shrinks)?;
+        write!(f, "  error:  {}", self.message)
+    }
+}
+
+impl<A: fmt::Debug> std::error::Error for Failure<A> {}
+
+#[derive(Debug, Clone)]
+pub struct QuickCheck {
+    tests: usize,
+    max_size: usize,
+    max_shrinks: usize,
+    seed: Option<u64>,
+}
+
+impl QuickCheck {
+    pub fn new() -> Self {
+        QuickCheck {
+            tests: 100,
+            max_size: 100,
+            max_shrinks: 10_000,
+            seed: std::env::var("PROPERTY_SEED")
+                .ok()
+                .and_then(|s| s.parse().ok()),
+        }
+    }
+
+    pub fn tests(mut self, tests: usize) -> Self {
+        self.tests = tests;
+        self
+    }
+
+    // Sizes grow linearly from 0 up to this over the run
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    // Upper bound on property evaluations spent shrinking
+    pub fn max_shrinks(mut self, max_shrinks: usize) -> Self {
+        self.max_shrinks = max_shrinks;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn run<A, R, F>(&self, property: F) -> Result<Report, Failure<A>>
+    where
+        A: Arbitrary,
+        R: Testable,
+        F: Fn(A) -> R,
+    {
+        let seed = self.seed.unwrap_or_else(random_seed);
+        for test in 0..self.tests {
+            let size = test * self.max_size / self.tests.max(1);
+            let mut g = Gen::new(gen::mix(seed ^ test as u64), size);
+            let input = A::arbitrary(&mut g);
+            if let Err(message) = evaluate(&property, input.clone()) {
+                let (shrunk, shrinks, message) = self.shrink(&property, input.clone(), message);
+                return Err(Failure {
+                    seed,
+                    passed: test,
+                    original: input,
+                    shrunk,
+                    shrinks,
+                    message,
+                });
+            }
+        }
+        Ok(Report {
+            tests: self.tests,
+            seed,
+        })
+    }
+
+    // Like run, but panics with the failure report
+    pub fn check<A, R, F>(&self, property: F)
+    where
+        A: Arbitrary,
+        R: Testable,
+        F: Fn(A) -> R,
+    {
+        if let Err(failure) = self.run(property) {
+            panic!("{}", failure);
+        }
+    }
+
+    fn shrink<A, R, F>(&self, property: &F, mut input: A, mut message: String) -> (A, usize, String)
+    where
+        A: Arbitrary,
+        R: Testable,
+        F: Fn(A) -> R,
+    {
+        let mut shrinks = 0;
+        let mut budget = self.max_shrinks;
+        'outer: while budget > 0 {
+            for candidate in input.shrink() {
+                if budget == 0 {
+                    break 'outer;
+                }
+                budget -= 1;
+                if let Err(why) = evaluate(property, candidate.clone()) {
+                    input = candidate;
+                    message = why;
+                    shrinks += 1;
+                    continue 'outer;
+                }
+            }
+            break;
+        }
+        (input, shrinks, message)
+    }
+}
+
+impl Default for QuickCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Checks a property with the default settings, panicking on failure
+pub fn quickcheck<A, R, F>(property: F)
+where
+    A: Arbitrary,
+    R: Testable,
+    F: Fn(A) -> R,
+{
+    QuickCheck::new().check(property)
+}
+
+fn evaluate<A, R, F>(property: &F, input: A) -> Result<(), String>
+where
+    R: Testable,
+    F: Fn(A) -> R,
+{
+    match panic::catch_unwind(AssertUnwindSafe(|| property(input))) {
+        Ok(result) => result.result(),
+        Err(payload) => Err(if let Some(s) = payload.downcast_ref::<&str>() {
+            format!("panicked: {}", s)
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            format!("panicked: {}", s)
+        } else {
+            "panicked".to_string()
+        }),
+    }
+}
+
+fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passing_property() {
+        let report = QuickCheck::new()
+            .seed(1)
+            .run(|v: Vec<i32>| {
+                let mut reversed = v.clone();
+                reversed.reverse();
+                reversed.reverse();
+                reversed == v
+            })
+            .unwrap();
+        assert_eq!(
+            report,
+            Report {
+                tests: 100,
+                seed: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_failure_is_shrunk_to_minimal_input() {
+        let failure = QuickCheck::new()
+            .seed(7)
+            .run(|v: Vec<u32>| v.iter().all(|&x| x < 50))
+            .unwrap_err();
+        assert_eq!(failure.shrunk, vec![50]);
+        assert!(failure.original.iter().any(|&x| x >= 50));
+        assert_eq!(failure.message, "property returned false");
+    }
+
+    #[test]
+    fn test_same_seed_same_failure() {
+        let property = |(a, b): (i64, i64)| a.checked_add(b).is_some();
+        let first = QuickCheck::new().seed(99).run(property).unwrap_err();
+        let again = QuickCheck::new()
+            .seed(first.seed)
+            .run(property)
+            .unwrap_err();
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn test_panics_and_errors_are_failures() {
+        let failure = QuickCheck::new()
+            .seed(3)
+            .run(|x: u8| {
+                assert!(x < 200, "too big: {}", x);
+            })
+            .unwrap_err();
+        assert_eq!(failure.shrunk, 200);
+        assert_eq!(failure.message, "panicked: too big: 200");
+
+        let failure = QuickCheck::new()
+            .seed(3)
+            .run(|s: String| {
+                if s.contains('!') {
+                    Err(format!("{:?} has a bang", s))
+                } else {
+                    Ok(())
+                }
+            })
+            .unwrap_err();
+        assert_eq!(failure.shrunk, "!");
+        assert!(failure.to_string().contains("rerun with PROPERTY_SEED=3"));
+    }
+}
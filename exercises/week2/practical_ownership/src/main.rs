@@ -6,6 +6,8 @@
 // Check compilation: cargo check
 
 use std::collections::HashMap;
+use std::time::Duration;
+use cache::Cache;
 
 fn main() {
     // Scenario 1: Building a string from parts
@@ -57,8 +59,20 @@ fn main() {
     // Challenge: Implement a cache that borrows keys but owns values
     let mut cache = StringCache::new();
     cache.insert("key1", String::from("value1"));
-    // let value = cache.get("key1");
-    // println!("Cached value: {:?}", value);
+    let value = cache.get("key1");
+    println!("Cached value: {:?}", value);
+    
+    // The cache is bounded: inserting past capacity evicts the least
+    // recently used entry
+    for i in 2..=(StringCache::CAPACITY + 1) {
+        cache.insert(&format!("key{}", i), format!("value{}", i));
+    }
+    // get() borrows the cache mutably, so copy out what we need before the
+    // next lookup
+    let key1_cached = cache.get("key1").is_some();
+    let key2_cached = cache.get("key2").is_some();
+    println!("After filling up: key1 cached? {}, key2 cached? {}", key1_cached, key2_cached);
+    println!("Cache stats: {}", cache.data.stats());
 }
 
 // TODO: Implement this function
@@ -129,25 +143,32 @@ impl ConfigBuilder {
     }
 }
 
-// TODO: Implement a simple cache
+// A simple bounded cache: least recently used entries are evicted once it
+// holds CAPACITY entries, and entries expire after TTL
 struct StringCache {
-    data: HashMap<String, String>,
+    data: Cache<String, String>,
 }
 
 impl StringCache {
+    const CAPACITY: usize = 3;
+    const TTL: Duration = Duration::from_secs(60);
+    
     fn new() -> Self {
         StringCache {
-            data: HashMap::new(),
+            data: Cache::new(Self::CAPACITY).with_ttl(Self::TTL),
         }
     }
     
-    // TODO: Should key be &str or String? Why?
+    // Taking &str lets callers pass literals without allocating; the cache
+    // makes its own owned copy of the key
     fn insert(&mut self, key: &str, value: String) {
         self.data.insert(key.to_string(), value);
     }
     
-    // TODO: What should this return? &String? Option<&String>?
-    // fn get(&self, key: &str) -> ??? {
-    //     
-    // }
+    // Option<&String>: the value may be missing, and the cache keeps ownership
+    // so we hand out a borrow. Takes &mut self because a lookup also records
+    // the entry as recently used.
+    fn get(&mut self, key: &str) -> Option<&String> {
+        self.data.get(key)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,407 @@
+// Collection with a fixed capacity and a choice of what happens when full
+//
+// Container<T> in main.rs refuses items once full and hands back a String.
+// BoundedCollection<T> lets the caller pick an OverflowPolicy:
+// - Reject: add fails with a CapacityError that gives the item back
+// - DropOldest: the oldest item is evicted to make room (a ring buffer,
+//   e.g. for keeping the last N log lines)
+// - DropNewest: the incoming item is discarded and the contents kept
+// - Block: like Reject here; SharedCollection (see the shared module)
+//   makes the adding thread wait for room instead
+//
+// Items can come out oldest first (Fifo, a queue) or newest first (Lifo, a
+// stack). Whatever the order, eviction always drops the oldest item.
+
+pub mod shared;
+
+pub use shared::SharedCollection;
+
+use std::collections::{vec_deque, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::iter::FusedIterator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    Reject,
+    DropOldest,
+    DropNewest,
+    Block,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Fifo,
+    Lifo,
+}
+
+// Returned when an item doesn't fit; the item comes back with it
+#[derive(Clone, PartialEq, Eq)]
+pub struct CapacityError<T> {
+    item: T,
+    capacity: usize,
+}
+
+impl<T> CapacityError<T> {
+    pub fn item(&self) -> &T {
+        &self.item
+    }
+
+    pub fn into_item(self) -> T {
+        self.item
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+// Written out so that any T works, like std's SendError
+impl<T> fmt::Debug for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CapacityError")
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for CapacityError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Collection is full (capacity {})", self.capacity)
+    }
+}
+
+impl<T> Error for CapacityError<T> {}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct BoundedCollection<T> {
+    // Oldest at the front, whatever the order
+    items: VecDeque<T>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    order: Order,
+}
+
+impl<T> BoundedCollection<T> {
+    // Rejects when full and hands items out first in, first out; panics if
+    // capacity is 0
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be at least 1");
+        BoundedCollection {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            policy: OverflowPolicy::Reject,
+            order: Order::Fifo,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_order(mut self, order: Order) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn order(&self) -> Order {
+        self.order
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() == self.capacity
+    }
+
+    // Ok(None) when the item went in with room to spare, Ok(Some(dropped))
+    // when the policy dropped an item to deal with a full collection
+    pub fn add(&mut self, item: T) -> Result<Option<T>, CapacityError<T>> {
+        if !self.is_full() {
+            self.items.push_back(item);
+            return Ok(None);
+        }
+        match self.policy {
+            OverflowPolicy::Reject | OverflowPolicy::Block => Err(CapacityError {
+                item,
+                capacity: self.capacity,
+            }),
+            OverflowPolicy::DropOldest => {
+                let oldest = self.items.pop_front();
+                self.items.push_back(item);
+                Ok(oldest)
+            }
+            OverflowPolicy::DropNewest => Ok(Some(item)),
+        }
+    }
+
+    // The next item in the collection's order
+    pub fn remove(&mut self) -> Option<T> {
+        match self.order {
+            Order::Fifo => self.items.pop_front(),
+            Order::Lifo => self.items.pop_back(),
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        match self.order {
+            Order::Fifo => self.items.front(),
+            Order::Lifo => self.items.back(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    // In the order remove would hand the items out
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            inner: self.items.iter(),
+            order: self.order,
+        }
+    }
+
+    // Removes everything, in the order remove would
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain {
+            inner: self.items.drain(..),
+            order: self.order,
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BoundedCollection<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BoundedCollection")
+            .field("items", &self.iter().collect::<Vec<_>>())
+            .field("capacity", &self.capacity)
+            .field("policy", &self.policy)
+            .field("order", &self.order)
+            .finish()
+    }
+}
+
+// Both iterators walk the deque from the front for Fifo and from the back
+// for Lifo
+macro_rules! ordered_iterator {
+    ($name:ident, $item:ty, $($lt:lifetime)?) => {
+        impl<$($lt,)? T> Iterator for $name<$($lt,)? T> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<$item> {
+                match self.order {
+                    Order::Fifo => self.inner.next(),
+                    Order::Lifo => self.inner.next_back(),
+                }
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<$($lt,)? T> DoubleEndedIterator for $name<$($lt,)? T> {
+            fn next_back(&mut self) -> Option<$item> {
+                match self.order {
+                    Order::Fifo => self.inner.next_back(),
+                    Order::Lifo => self.inner.next(),
+                }
+            }
+        }
+
+        impl<$($lt,)? T> ExactSizeIterator for $name<$($lt,)? T> {}
+        impl<$($lt,)? T> FusedIterator for $name<$($lt,)? T> {}
+    };
+}
+
+pub struct Iter<'a, T> {
+    inner: vec_deque::Iter<'a, T>,
+    order: Order,
+}
+
+ordered_iterator!(Iter, &'a T, 'a);
+
+pub struct Drain<'a, T> {
+    inner: vec_deque::Drain<'a, T>,
+    order: Order,
+}
+
+ordered_iterator!(Drain, T, 'a);
+
+pub struct IntoIter<T> {
+    inner: vec_deque::IntoIter<T>,
+    order: Order,
+}
+
+ordered_iterator!(IntoIter, T,);
+
+impl<T> IntoIterator for BoundedCollection<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter {
+            inner: self.items.into_iter(),
+            order: self.order,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a BoundedCollection<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use property::{quickcheck, Arbitrary, Gen};
+
+    #[test]
+    fn test_policies_when_full() {
+        let full = |policy| {
+            let mut collection = BoundedCollection::new(3).with_policy(policy);
+            for i in 1..=3 {
+                assert_eq!(collection.add(i), Ok(None));
+            }
+            collection
+        };
+
+        let mut reject = full(OverflowPolicy::Reject);
+        let error = reject.add(4).unwrap_err();
+        assert_eq!(
+            (error.capacity(), error.to_string()),
+            (3, "Collection is full (capacity 3)".to_string())
+        );
+        assert_eq!(error.into_item(), 4);
+        assert_eq!(reject.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let mut block = full(OverflowPolicy::Block);
+        assert_eq!(block.add(4).map_err(CapacityError::into_item), Err(4));
+
+        let mut drop_oldest = full(OverflowPolicy::DropOldest);
+        assert_eq!(drop_oldest.add(4), Ok(Some(1)));
+        assert_eq!(drop_oldest.add(5), Ok(Some(2)));
+        assert_eq!(drop_oldest.drain().collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert!(drop_oldest.is_empty());
+
+        let mut drop_newest = full(OverflowPolicy::DropNewest);
+        assert_eq!(drop_newest.add(4), Ok(Some(4)));
+        assert_eq!(drop_newest.into_iter().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_lifo_order() {
+        let mut stack = BoundedCollection::new(3)
+            .with_order(Order::Lifo)
+            .with_policy(OverflowPolicy::DropOldest);
+        for word in ["a", "b", "c", "d"] {
+            stack.add(word).unwrap();
+        }
+        assert_eq!(stack.peek(), Some(&"d"));
+        assert_eq!(
+            stack.iter().rev().collect::<Vec<_>>(),
+            vec![&"b", &"c", &"d"]
+        );
+        assert_eq!(stack.remove(), Some("d"));
+        assert_eq!(stack.drain().collect::<Vec<_>>(), vec!["c", "b"]);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Add(u8),
+        Remove,
+        Drain,
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary(g: &mut Gen) -> Self {
+            match g.below(10) {
+                0..=5 => Op::Add(u8::arbitrary(g)),
+                6..=8 => Op::Remove,
+                _ => Op::Drain,
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_vec_deque() {
+        let policies = [
+            OverflowPolicy::Reject,
+            OverflowPolicy::DropOldest,
+            OverflowPolicy::DropNewest,
+            OverflowPolicy::Block,
+        ];
+        quickcheck(
+            |((capacity, policy), lifo, ops): ((u8, u8), bool, Vec<Op>)| {
+                let capacity = capacity as usize % 8 + 1;
+                let policy = policies[policy as usize % 4];
+                let order = if lifo { Order::Lifo } else { Order::Fifo };
+                let mut collection = BoundedCollection::new(capacity)
+                    .with_policy(policy)
+                    .with_order(order);
+                let mut model = VecDeque::new();
+                for op in ops {
+                    match op {
+                        Op::Add(x) => {
+                            let expected = if model.len() < capacity {
+                                model.push_back(x);
+                                Ok(None)
+                            } else {
+                                match policy {
+                                    OverflowPolicy::Reject | OverflowPolicy::Block => Err(x),
+                                    OverflowPolicy::DropOldest => {
+                                        model.push_back(x);
+                                        Ok(model.pop_front())
+                                    }
+                                    OverflowPolicy::DropNewest => Ok(Some(x)),
+                                }
+                            };
+                            assert_eq!(
+                                collection.add(x).map_err(CapacityError::into_item),
+                                expected
+                            );
+                        }
+                        Op::Remove => {
+                            let expected = if lifo {
+                                model.pop_back()
+                            } else {
+                                model.pop_front()
+                            };
+                            assert_eq!(collection.remove(), expected);
+                        }
+                        Op::Drain => {
+                            let mut expected: Vec<u8> = model.drain(..).collect();
+                            if lifo {
+                                expected.reverse();
+                            }
+                            assert_eq!(collection.drain().collect::<Vec<_>>(), expected);
+                        }
+                    }
+                    assert_eq!(collection.len(), model.len());
+                    assert_eq!(collection.is_full(), model.len() == capacity);
+                }
+            },
+        );
+    }
+}
@@ -0,0 +1,233 @@
+// Thread-safe BoundedCollection: a Mutex around it, and two Condvars so
+// that threads can wait for an item (remove_blocking) or, under
+// OverflowPolicy::Block, for room (add)
+//
+// Shared through an Arc, it makes a log buffer that any thread can write
+// to: with DropOldest it always holds the latest lines, and a reader can
+// take them all out at once with drain.
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::{BoundedCollection, CapacityError, OverflowPolicy};
+
+pub struct SharedCollection<T> {
+    inner: Mutex<BoundedCollection<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> SharedCollection<T> {
+    pub fn new(collection: BoundedCollection<T>) -> Self {
+        SharedCollection {
+            inner: Mutex::new(collection),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    // Same results as BoundedCollection::add, except that under Block it
+    // waits until there is room instead of failing
+    pub fn add(&self, item: T) -> Result<Option<T>, CapacityError<T>> {
+        let mut collection = self.lock();
+        if collection.policy() == OverflowPolicy::Block {
+            while collection.is_full() {
+                collection = self.not_full.wait(collection).unwrap();
+            }
+        }
+        let result = collection.add(item);
+        self.added(&result);
+        result
+    }
+
+    // Like add, but gives up after timeout and returns the item in the error
+    pub fn add_timeout(&self, item: T, timeout: Duration) -> Result<Option<T>, CapacityError<T>> {
+        // A timeout too long to represent as an Instant never runs out
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self.add(item);
+        };
+        let mut collection = self.lock();
+        if collection.policy() == OverflowPolicy::Block {
+            while collection.is_full() {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                collection = self
+                    .not_full
+                    .wait_timeout(collection, deadline - now)
+                    .unwrap()
+                    .0;
+            }
+        }
+        // Still full under Block: add rejects it
+        let result = collection.add(item);
+        self.added(&result);
+        result
+    }
+
+    pub fn remove(&self) -> Option<T> {
+        let item = self.lock().remove();
+        if item.is_some() {
+            self.not_full.notify_one();
+        }
+        item
+    }
+
+    // Waits until there is an item to take
+    pub fn remove_blocking(&self) -> T {
+        let mut collection = self.lock();
+        loop {
+            if let Some(item) = collection.remove() {
+                self.not_full.notify_one();
+                return item;
+            }
+            collection = self.not_empty.wait(collection).unwrap();
+        }
+    }
+
+    // Takes everything out in one go, in the collection's order
+    pub fn drain(&self) -> Vec<T> {
+        let items: Vec<T> = self.lock().drain().collect();
+        if !items.is_empty() {
+            self.not_full.notify_all();
+        }
+        items
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity()
+    }
+
+    // A copy of the contents, left in place
+    pub fn snapshot(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.lock().iter().cloned().collect()
+    }
+
+    pub fn into_inner(self) -> BoundedCollection<T> {
+        self.inner.into_inner().unwrap()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BoundedCollection<T>> {
+        self.inner.lock().unwrap()
+    }
+
+    // Wakes a remover if the item went in; when another one was dropped
+    // to make room, the count stayed the same and nobody needs waking
+    fn added(&self, result: &Result<Option<T>, CapacityError<T>>) {
+        if let Ok(None) = result {
+            self.not_empty.notify_one();
+        }
+    }
+}
+
+impl<T> From<BoundedCollection<T>> for SharedCollection<T> {
+    fn from(collection: BoundedCollection<T>) -> Self {
+        Self::new(collection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounded::Order;
+    use std::thread;
+
+    #[test]
+    fn test_block_waits_for_room() {
+        let shared =
+            SharedCollection::new(BoundedCollection::new(2).with_policy(OverflowPolicy::Block));
+        let produced: Vec<u32> = (0..200).collect();
+        let consumed = thread::scope(|s| {
+            s.spawn(|| {
+                for &i in &produced {
+                    assert_eq!(shared.add(i), Ok(None));
+                }
+            });
+            s.spawn(|| {
+                (0..produced.len())
+                    .map(|_| shared.remove_blocking())
+                    .collect::<Vec<_>>()
+            })
+            .join()
+            .unwrap()
+        });
+        // One producer and a FIFO collection: nothing lost, nothing reordered
+        assert_eq!(consumed, produced);
+        assert!(shared.is_empty());
+    }
+
+    #[test]
+    fn test_add_timeout_returns_the_item() {
+        let shared =
+            SharedCollection::from(BoundedCollection::new(1).with_policy(OverflowPolicy::Block));
+        shared.add("first").unwrap();
+        let error = shared
+            .add_timeout("second", Duration::from_millis(20))
+            .unwrap_err();
+        assert_eq!(error.into_item(), "second");
+        assert_eq!(shared.remove(), Some("first"));
+        assert_eq!(
+            shared.add_timeout("third", Duration::from_millis(20)),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_add_timeout_too_long_waits_like_add() {
+        let shared =
+            SharedCollection::new(BoundedCollection::new(1).with_policy(OverflowPolicy::Block));
+        shared.add(1).unwrap();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                assert_eq!(shared.remove(), Some(1));
+            });
+            assert_eq!(shared.add_timeout(2, Duration::MAX), Ok(None));
+        });
+        assert_eq!(shared.remove(), Some(2));
+    }
+
+    #[test]
+    fn test_ring_log_keeps_latest_lines() {
+        let log = SharedCollection::new(
+            BoundedCollection::new(8)
+                .with_policy(OverflowPolicy::DropOldest)
+                .with_order(Order::Fifo),
+        );
+        thread::scope(|s| {
+            for t in 0..4 {
+                let log = &log;
+                s.spawn(move || {
+                    for i in 0..50 {
+                        log.add(format!("thread {} line {}", t, i)).unwrap();
+                    }
+                });
+            }
+        });
+        let lines = log.drain();
+        assert_eq!(lines.len(), 8);
+        // Every thread's lines stay in order among themselves
+        for t in 0..4 {
+            let prefix = format!("thread {} ", t);
+            let numbers: Vec<u32> = lines
+                .iter()
+                .filter_map(|line| line.strip_prefix(&prefix))
+                .map(|rest| rest["line ".len()..].parse().unwrap())
+                .collect();
+            assert!(numbers.windows(2).all(|w| w[0] < w[1]));
+        }
+        assert!(log.is_empty());
+    }
+}
@@ -0,0 +1,2 @@
+// Library root file - exposes modules for integration testing
+pub mod bounded;
@@ -2,6 +2,10 @@
 // Combine structs, enums, and type aliases to build complex types
 
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+
+use custom_types::bounded::{BoundedCollection, Order, OverflowPolicy, SharedCollection};
 
 // TODO 1: Type aliases for clarity
 type UserId = u32;
@@ -180,7 +184,7 @@ impl<T> Container<T> {
 }
 
 // TODO 9: Recursive enum (for tree structures)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Expression {
     Number(f64),
     Add(Box<Expression>, Box<Expression>),
@@ -206,6 +210,51 @@ impl Config {
     const INITIAL_LIVES: u32 = 3;
 }
 
+// Container with a choice of overflow policy (src/bounded.rs)
+fn bounded_collection_examples() {
+    println!("\n--- Bounded Collections ---");
+    
+    let mut inventory = BoundedCollection::new(2);
+    inventory.add("Sword").unwrap();
+    inventory.add("Shield").unwrap();
+    match inventory.add("Potion") {
+        Ok(_) => println!("Added the potion"),
+        Err(e) => println!("Error: {}; got back {:?}", e, e.item()),
+    }
+    
+    let mut undo = BoundedCollection::new(3)
+        .with_order(Order::Lifo)
+        .with_policy(OverflowPolicy::DropOldest);
+    for action in ["move", "attack", "heal", "defend"] {
+        if let Ok(Some(forgotten)) = undo.add(action) {
+            println!("Undo history full, forgot {:?}", forgotten);
+        }
+    }
+    println!("Undo order: {:?}", undo.drain().collect::<Vec<_>>());
+    
+    // A ring log buffer shared by several threads: only the latest lines stay
+    let log = Arc::new(SharedCollection::new(
+        BoundedCollection::new(4).with_policy(OverflowPolicy::DropOldest),
+    ));
+    let writers: Vec<_> = (0..3)
+        .map(|id| {
+            let log = Arc::clone(&log);
+            thread::spawn(move || {
+                for turn in 1..=3 {
+                    log.add(format!("player {} took turn {}", id, turn)).unwrap();
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    println!("Last {} log lines:", log.capacity());
+    for line in log.drain() {
+        println!("  {}", line);
+    }
+}
+
 fn main() {
     // TODO 11: Using type aliases
     let user_scores: HashMap<UserId, Score> = HashMap::new();
@@ -262,6 +311,8 @@ fn main() {
         Err(e) => println!("Error: {}", e),
     }
     
+    bounded_collection_examples();
+    
     // TODO 16: Expression tree evaluation
     let expr = Expression::Add(
         Box::new(Expression::Multiply(
@@ -310,3 +361,147 @@ fn main() {
     println!("  Default Level: {}", Config::DEFAULT_LEVEL);
     println!("  Initial Lives: {}", Config::INITIAL_LIVES);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use property::{quickcheck, Arbitrary, Gen};
+
+    // Small whole numbers keep the arithmetic exact and finite
+    impl Arbitrary for Expression {
+        fn arbitrary(g: &mut Gen) -> Self {
+            if g.size() < 2 || g.ratio(1, 3) {
+                return Expression::Number(g.range(-10, 10) as f64);
+            }
+            let half = g.size() / 2;
+            let left = Box::new(g.with_size(half, Expression::arbitrary));
+            let right = Box::new(g.with_size(half, Expression::arbitrary));
+            if g.bool() {
+                Expression::Add(left, right)
+            } else {
+                Expression::Multiply(left, right)
+            }
+        }
+
+        // A subtree on its own first, then simpler subtrees in place
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            type Rebuild = fn(Box<Expression>, Box<Expression>) -> Expression;
+            let (left, right, rebuild): (_, _, Rebuild) = match self {
+                Expression::Number(n) => return Box::new(n.shrink().map(Expression::Number)),
+                Expression::Add(left, right) => (left, right, Expression::Add),
+                Expression::Multiply(left, right) => (left, right, Expression::Multiply),
+            };
+            let subtrees = vec![(**left).clone(), (**right).clone()];
+            let simpler = (left.clone(), right.clone()).shrink().map(move |(l, r)| rebuild(l, r));
+            Box::new(subtrees.into_iter().chain(simpler))
+        }
+    }
+
+    impl Arbitrary for StatBoost {
+        fn arbitrary(g: &mut Gen) -> Self {
+            StatBoost {
+                stat: g.choose(&["Speed", "Strength", "Defense"]).to_string(),
+                amount: i32::arbitrary(g),
+                duration: u32::arbitrary(g),
+            }
+        }
+    }
+
+    impl Arbitrary for Effect {
+        fn arbitrary(g: &mut Gen) -> Self {
+            match g.below(3) {
+                0 => Effect::Heal(u32::arbitrary(g)),
+                1 => Effect::Boost(StatBoost::arbitrary(g)),
+                _ => Effect::Teleport,
+            }
+        }
+    }
+
+    impl Arbitrary for Item {
+        fn arbitrary(g: &mut Gen) -> Self {
+            let name = String::arbitrary(g);
+            match g.below(3) {
+                0 => Item::Weapon { name, damage: u32::arbitrary(g) },
+                1 => Item::Armor { name, defense: u32::arbitrary(g) },
+                _ => Item::Consumable { name, effect: Effect::arbitrary(g) },
+            }
+        }
+    }
+
+    impl Arbitrary for Player {
+        fn arbitrary(g: &mut Gen) -> Self {
+            Player {
+                id: u32::arbitrary(g),
+                name: String::arbitrary(g),
+                health: g.below(Config::MAX_HEALTH as u64 + 1) as u32,
+                inventory: Vec::arbitrary(g),
+            }
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let id = self.id;
+            let parts = (self.name.clone(), self.health, self.inventory.clone());
+            Box::new(parts.shrink().map(move |(name, health, inventory)| Player {
+                id,
+                name,
+                health,
+                inventory,
+            }))
+        }
+    }
+
+    #[test]
+    fn test_addition_commutes() {
+        quickcheck(|(a, b): (Expression, Expression)| {
+            let ab = Expression::Add(Box::new(a.clone()), Box::new(b.clone()));
+            let ba = Expression::Add(Box::new(b), Box::new(a));
+            ab.evaluate() == ba.evaluate()
+        });
+    }
+
+    #[test]
+    fn test_identities() {
+        quickcheck(|e: Expression| {
+            let number = |n| Box::new(Expression::Number(n));
+            let times_one = Expression::Multiply(Box::new(e.clone()), number(1.0));
+            let plus_zero = Expression::Add(Box::new(e.clone()), number(0.0));
+            times_one.evaluate() == e.evaluate() && plus_zero.evaluate() == e.evaluate()
+        });
+    }
+
+    #[test]
+    fn test_player_display() {
+        quickcheck(|player: Player| {
+            let text = player.to_string();
+            text.contains(&player.name)
+                && text.contains(&format!("(ID: {})", player.id))
+                && text.ends_with(&format!("Items: {}", player.inventory.len()))
+        });
+    }
+
+    #[test]
+    fn test_game_state_round_trip() {
+        quickcheck(|(player, high_score): (Player, Score)| {
+            let mut game = GameState::Menu;
+            // Invalid transitions leave the state alone
+            game.transition(GameEvent::Pause);
+            assert!(matches!(game, GameState::Menu));
+
+            game.transition(GameEvent::StartGame(player.clone()));
+            game.transition(GameEvent::Pause);
+            assert!(matches!(game, GameState::Paused { level: 1, score: 0 }));
+
+            game.transition(GameEvent::Resume(player.clone()));
+            match &game {
+                GameState::Playing { player: resumed, .. } => {
+                    assert_eq!(resumed.id, player.id);
+                    assert_eq!(resumed.inventory.len(), player.inventory.len());
+                }
+                other => panic!("expected Playing, got {:?}", other),
+            }
+
+            game.transition(GameEvent::EndGame(high_score));
+            matches!(game, GameState::GameOver { final_score: 0, high_score: h } if h == high_score)
+        });
+    }
+}
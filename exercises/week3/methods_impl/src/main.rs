@@ -2,7 +2,7 @@
 // Learn how to add methods to structs and enums
 
 // TODO 1: Basic struct with methods
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Rectangle {
     width: f64,
     height: f64,
@@ -264,3 +264,60 @@ fn main() {
     println!("Final position: ({}, {})", x, y);
     // point can't be used after consume()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use property::{quickcheck, Arbitrary, Gen};
+
+    // Sides are never negative; shrinking keeps them that way
+    impl Arbitrary for Rectangle {
+        fn arbitrary(g: &mut Gen) -> Self {
+            Rectangle::new(f64::arbitrary(g).abs(), f64::arbitrary(g).abs())
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+            let sides = (self.width, self.height);
+            Box::new(
+                sides
+                    .shrink()
+                    .filter(|&(width, height)| width >= 0.0 && height >= 0.0)
+                    .map(|(width, height)| Rectangle::new(width, height)),
+            )
+        }
+    }
+
+    #[test]
+    fn test_area_and_perimeter() {
+        quickcheck(|r: Rectangle| {
+            r.area() == r.width * r.height && r.area() >= 0.0 && r.perimeter() >= 0.0
+        });
+    }
+
+    #[test]
+    fn test_square() {
+        quickcheck(|side: f64| {
+            let square = Rectangle::square(side.abs());
+            square.is_square() && square.perimeter() == 4.0 * side.abs()
+        });
+    }
+
+    #[test]
+    fn test_double_size_quadruples_area() {
+        quickcheck(|r: Rectangle| {
+            let mut doubled = r.clone();
+            doubled.double_size();
+            doubled.area() == 4.0 * r.area()
+        });
+    }
+
+    #[test]
+    fn test_can_hold_is_a_strict_order() {
+        quickcheck(|(a, b, c): (Rectangle, Rectangle, Rectangle)| {
+            let irreflexive = !a.can_hold(&a);
+            let asymmetric = !(a.can_hold(&b) && b.can_hold(&a));
+            let transitive = !(a.can_hold(&b) && b.can_hold(&c)) || a.can_hold(&c);
+            irreflexive && asymmetric && transitive
+        });
+    }
+}
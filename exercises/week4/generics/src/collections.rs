@@ -0,0 +1,130 @@
+// Generic collections, each built from scratch on top of Vec
+//
+// Stack<T> in main.rs is a Vec with a smaller interface. These do more:
+// - RingDeque: double-ended queue in a ring buffer (like VecDeque)
+// - MinStack: a stack that also knows its minimum in O(1)
+// - IndexedHeap: min-heap whose entries can be found again through a
+//   Handle, for decrease_key, update and remove (what Dijkstra needs)
+// - SkipList: sorted set made of linked levels of "express lanes"
+// - BTree: sorted map in a B-tree, the layout std's BTreeMap uses
+//
+// All of them implement FromIterator, Extend, IntoIterator (by value and by
+// reference), Debug and Clone, and the sorted ones support range queries.
+//
+// The tests are model-based: generated sequences of operations are applied
+// to a collection and to its std counterpart side by side, and every result
+// has to match (see the model module).
+pub mod btree;
+pub mod deque;
+pub mod heap;
+pub mod min_stack;
+pub mod skip_list;
+
+pub use btree::BTree;
+pub use deque::RingDeque;
+pub use heap::{Handle, HeapError, IndexedHeap};
+pub use min_stack::MinStack;
+pub use skip_list::SkipList;
+
+use std::ops::Bound;
+
+// Panics on ranges that std's BTreeMap::range rejects too
+fn check_range<Q: Ord + ?Sized>(start: Bound<&Q>, end: Bound<&Q>) {
+    match (start, end) {
+        (Bound::Excluded(s), Bound::Excluded(e)) if s == e => {
+            panic!("range start and end are equal and excluded")
+        }
+        (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e))
+            if s > e =>
+        {
+            panic!("range start is greater than range end")
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod model {
+    use property::{Arbitrary, Gen, QuickCheck};
+    use std::fmt::Debug;
+    use std::ops::Bound;
+
+    // A collection under test together with the std collection it should
+    // behave like
+    pub trait Model {
+        type Item: Arbitrary;
+        type Op: Arbitrary;
+        type Subject: Clone;
+        type Reference: Clone;
+
+        // Both built with FromIterator from the same items
+        fn from_items(items: Vec<Self::Item>) -> (Self::Subject, Self::Reference);
+
+        // Applies op to both, failing if their results differ
+        fn step(
+            op: &Self::Op,
+            subject: &mut Self::Subject,
+            reference: &mut Self::Reference,
+        ) -> Result<(), String>;
+
+        // Compares everything observable: length, iteration in both
+        // directions, Debug output, structural invariants
+        fn compare(subject: &Self::Subject, reference: &Self::Reference) -> Result<(), String>;
+    }
+
+    pub fn check_model<M: Model>() {
+        QuickCheck::new()
+            .tests(300)
+            .max_size(200)
+            .check(|(items, ops): (Vec<M::Item>, Vec<M::Op>)| run::<M>(items, &ops));
+    }
+
+    fn run<M: Model>(items: Vec<M::Item>, ops: &[M::Op]) -> Result<(), String> {
+        let (mut subject, mut reference) = M::from_items(items);
+        M::compare(&subject, &reference)?;
+        for (i, op) in ops.iter().enumerate() {
+            M::step(op, &mut subject, &mut reference)
+                .map_err(|e| format!("op {} ({:?}): {}", i, op, e))?;
+            M::compare(&subject, &reference)
+                .map_err(|e| format!("after op {} ({:?}): {}", i, op, e))?;
+        }
+        Ok(())
+    }
+
+    // Keys from a small range, so that inserts collide and removes hit
+    pub fn key(g: &mut Gen) -> i32 {
+        g.range(-40, 40) as i32
+    }
+
+    // Any range std accepts, the open and empty ones included
+    pub fn key_range(g: &mut Gen) -> (Bound<i32>, Bound<i32>) {
+        let (a, b) = (key(g), key(g));
+        let (low, high) = (a.min(b), a.max(b));
+        let mut bound = |k| match g.below(3) {
+            0 => Bound::Included(k),
+            1 => Bound::Excluded(k),
+            _ => Bound::Unbounded,
+        };
+        match (bound(low), bound(high)) {
+            (Bound::Excluded(s), Bound::Excluded(e)) if s == e => {
+                (Bound::Included(s), Bound::Excluded(e))
+            }
+            range => range,
+        }
+    }
+
+    pub fn expect_eq<T: PartialEq + Debug>(
+        what: &str,
+        subject: T,
+        reference: T,
+    ) -> Result<(), String> {
+        if subject == reference {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: got {:?}, expected {:?}",
+                what, subject, reference
+            ))
+        }
+    }
+}
@@ -0,0 +1,969 @@
+// Sorted map in a B-tree
+//
+// Each node holds up to 2B - 1 sorted keys, and an internal node has one
+// child more than it has keys: children[i] holds the keys between keys[i - 1]
+// and keys[i]. Keeping many keys per node makes the tree shallow and the
+// searches cache friendly, which is why std's BTreeMap is built this way.
+//
+// Insert and remove follow CLRS and fix the tree on the way down, so they
+// never have to walk back up:
+// - insert splits every full node it is about to enter, so there is always
+//   room for the median key a split pushes up
+// - remove makes sure every node it enters has a key to spare, by borrowing
+//   one from a sibling (a rotation) or merging with it
+//
+// Iteration keeps the path from the root to the current position on a
+// stack, one cursor per end.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::FusedIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+use std::vec;
+
+use super::check_range;
+
+// Minimum degree: nodes other than the root hold B - 1 to 2B - 1 keys
+const B: usize = 6;
+const CAPACITY: usize = 2 * B - 1;
+
+#[derive(Clone)]
+struct Node<K, V> {
+    keys: Vec<K>,
+    vals: Vec<V>,
+    // Empty for leaves
+    children: Vec<Node<K, V>>,
+}
+
+impl<K, V> Node<K, V> {
+    fn new() -> Self {
+        Node {
+            keys: Vec::with_capacity(CAPACITY),
+            vals: Vec::with_capacity(CAPACITY),
+            children: Vec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.keys.len() == CAPACITY
+    }
+
+    // Where key is in this node (Ok) or which child to look in (Err)
+    fn search<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.keys.binary_search_by(|k| k.borrow().cmp(key))
+    }
+
+    // Moves the upper half of the full child i into a new sibling, and its
+    // median key up into this node
+    fn split_child(&mut self, i: usize) {
+        let child = &mut self.children[i];
+        let mut right = Node::new();
+        right.keys = child.keys.split_off(B);
+        right.vals = child.vals.split_off(B);
+        if !child.is_leaf() {
+            right.children = child.children.split_off(B);
+        }
+        let key = child.keys.pop().expect("a full node has a median");
+        let val = child.vals.pop().expect("a full node has a median");
+        self.keys.insert(i, key);
+        self.vals.insert(i, val);
+        self.children.insert(i + 1, right);
+    }
+
+    // Assumes this node is not full
+    fn insert(&mut self, key: K, val: V) -> Option<V>
+    where
+        K: Ord,
+    {
+        match self.search(&key) {
+            Ok(i) => Some(mem::replace(&mut self.vals[i], val)),
+            Err(i) if self.is_leaf() => {
+                self.keys.insert(i, key);
+                self.vals.insert(i, val);
+                None
+            }
+            Err(mut i) => {
+                if self.children[i].is_full() {
+                    self.split_child(i);
+                    // The median that came up may be the key, or belong
+                    // before it
+                    match key.cmp(&self.keys[i]) {
+                        Ordering::Equal => return Some(mem::replace(&mut self.vals[i], val)),
+                        Ordering::Greater => i += 1,
+                        Ordering::Less => {}
+                    }
+                }
+                self.children[i].insert(key, val)
+            }
+        }
+    }
+
+    // Assumes this node has a key to spare, or is the root
+    fn remove<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        match self.search(key) {
+            Ok(i) if self.is_leaf() => Some((self.keys.remove(i), self.vals.remove(i))),
+            Ok(i) => {
+                // Replace the key with its predecessor or successor, taken
+                // from a child that can spare it, or else merge the two
+                // children around it and remove it from the result
+                if self.children[i].keys.len() >= B {
+                    let (k, v) = self.children[i].pop_last();
+                    Some(self.replace_entry(i, k, v))
+                } else if self.children[i + 1].keys.len() >= B {
+                    let (k, v) = self.children[i + 1].pop_first();
+                    Some(self.replace_entry(i, k, v))
+                } else {
+                    self.merge_children(i);
+                    self.children[i].remove(key)
+                }
+            }
+            Err(_) if self.is_leaf() => None,
+            Err(i) => {
+                let i = self.make_spare(i);
+                self.children[i].remove(key)
+            }
+        }
+    }
+
+    // Assumes this node is not empty and has a key to spare, or is the root
+    fn pop_first(&mut self) -> (K, V) {
+        if self.is_leaf() {
+            return (self.keys.remove(0), self.vals.remove(0));
+        }
+        let i = self.make_spare(0);
+        self.children[i].pop_first()
+    }
+
+    fn pop_last(&mut self) -> (K, V) {
+        if self.is_leaf() {
+            let key = self.keys.pop().expect("node is not empty");
+            let val = self.vals.pop().expect("node is not empty");
+            return (key, val);
+        }
+        let i = self.make_spare(self.children.len() - 1);
+        self.children[i].pop_last()
+    }
+
+    fn replace_entry(&mut self, i: usize, key: K, val: V) -> (K, V) {
+        (
+            mem::replace(&mut self.keys[i], key),
+            mem::replace(&mut self.vals[i], val),
+        )
+    }
+
+    // Gives child i at least B keys before the descent enters it, and
+    // returns where its keys are now (a merge with the left sibling moves
+    // them)
+    fn make_spare(&mut self, i: usize) -> usize {
+        if self.children[i].keys.len() >= B {
+            i
+        } else if i > 0 && self.children[i - 1].keys.len() >= B {
+            self.rotate_right(i - 1);
+            i
+        } else if i + 1 < self.children.len() && self.children[i + 1].keys.len() >= B {
+            self.rotate_left(i);
+            i
+        } else if i + 1 < self.children.len() {
+            self.merge_children(i);
+            i
+        } else {
+            self.merge_children(i - 1);
+            i - 1
+        }
+    }
+
+    // Moves key i down into child i + 1, and the last key of child i up
+    fn rotate_right(&mut self, i: usize) {
+        let (left, right) = self.children.split_at_mut(i + 1);
+        let (left, right) = (&mut left[i], &mut right[0]);
+        let key = left.keys.pop().expect("sibling has a key to spare");
+        let val = left.vals.pop().expect("sibling has a key to spare");
+        right.keys.insert(0, mem::replace(&mut self.keys[i], key));
+        right.vals.insert(0, mem::replace(&mut self.vals[i], val));
+        if let Some(child) = left.children.pop() {
+            right.children.insert(0, child);
+        }
+    }
+
+    // Moves key i down into child i, and the first key of child i + 1 up
+    fn rotate_left(&mut self, i: usize) {
+        let (left, right) = self.children.split_at_mut(i + 1);
+        let (left, right) = (&mut left[i], &mut right[0]);
+        let key = right.keys.remove(0);
+        let val = right.vals.remove(0);
+        left.keys.push(mem::replace(&mut self.keys[i], key));
+        left.vals.push(mem::replace(&mut self.vals[i], val));
+        if !right.is_leaf() {
+            left.children.push(right.children.remove(0));
+        }
+    }
+
+    // Joins children i and i + 1 around key i, which comes down
+    fn merge_children(&mut self, i: usize) {
+        let right = self.children.remove(i + 1);
+        let key = self.keys.remove(i);
+        let val = self.vals.remove(i);
+        let left = &mut self.children[i];
+        left.keys.push(key);
+        left.vals.push(val);
+        left.keys.extend(right.keys);
+        left.vals.extend(right.vals);
+        left.children.extend(right.children);
+    }
+
+    // In order, consuming the node
+    fn drain_into(self, out: &mut Vec<(K, V)>) {
+        let mut children = self.children.into_iter();
+        for entry in self.keys.into_iter().zip(self.vals) {
+            if let Some(child) = children.next() {
+                child.drain_into(out);
+            }
+            out.push(entry);
+        }
+        if let Some(child) = children.next() {
+            child.drain_into(out);
+        }
+    }
+}
+
+pub struct BTree<K, V> {
+    root: Node<K, V>,
+    len: usize,
+}
+
+impl<K: Ord, V> BTree<K, V> {
+    pub fn new() -> Self {
+        BTree {
+            root: Node::new(),
+            len: 0,
+        }
+    }
+
+    // Returns the old value if key was already there
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if self.root.is_full() {
+            // The only way the tree grows taller
+            let old_root = mem::replace(&mut self.root, Node::new());
+            self.root.children.push(old_root);
+            self.root.split_child(0);
+        }
+        let old = self.root.insert(key, val);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = &self.root;
+        loop {
+            match node.search(key) {
+                Ok(i) => return Some(&node.vals[i]),
+                Err(_) if node.is_leaf() => return None,
+                Err(i) => node = &node.children[i],
+            }
+        }
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut node = &mut self.root;
+        loop {
+            match node.search(key) {
+                Ok(i) => return Some(&mut node.vals[i]),
+                Err(_) if node.is_leaf() => return None,
+                Err(i) => node = &mut node.children[i],
+            }
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, val)| val)
+    }
+
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let entry = self.root.remove(key);
+        self.after_remove(entry)
+    }
+
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let entry = self.root.pop_first();
+        self.after_remove(Some(entry))
+    }
+
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let entry = self.root.pop_last();
+        self.after_remove(Some(entry))
+    }
+
+    // Iterates over the entries with keys in range, in order
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        check_range(range.start_bound(), range.end_bound());
+        let before_start = |k: &K| match range.start_bound() {
+            Bound::Included(start) => k.borrow() < start,
+            Bound::Excluded(start) => k.borrow() <= start,
+            Bound::Unbounded => false,
+        };
+        let up_to_end = |k: &K| match range.end_bound() {
+            Bound::Included(end) => k.borrow() <= end,
+            Bound::Excluded(end) => k.borrow() < end,
+            Bound::Unbounded => true,
+        };
+        Range {
+            front: Cursor::seek(&self.root, before_start),
+            back: Cursor::seek(&self.root, up_to_end),
+            // The keys on either side of the range, where the ends stop
+            front_stop: Cursor::seek(&self.root, up_to_end).next().map(|(k, _)| k),
+            back_stop: Cursor::seek(&self.root, before_start)
+                .next_back()
+                .map(|(k, _)| k),
+        }
+    }
+
+    // A merge can leave the root without keys; its only child takes over
+    fn after_remove(&mut self, entry: Option<(K, V)>) -> Option<(K, V)> {
+        if self.root.keys.is_empty() && !self.root.is_leaf() {
+            self.root = self.root.children.pop().expect("root has a child");
+        }
+        if entry.is_some() {
+            self.len -= 1;
+        }
+        entry
+    }
+
+    #[cfg(test)]
+    fn check_invariants(&self) -> Result<(), String> {
+        fn check<K: Ord, V>(
+            node: &Node<K, V>,
+            is_root: bool,
+            low: Option<&K>,
+            high: Option<&K>,
+        ) -> Result<(usize, usize), String> {
+            if node.keys.len() > CAPACITY || (!is_root && node.keys.len() < B - 1) {
+                return Err(format!("node with {} keys", node.keys.len()));
+            }
+            if node.vals.len() != node.keys.len() {
+                return Err("keys and values out of step".to_string());
+            }
+            let in_order = node.keys.windows(2).all(|w| w[0] < w[1])
+                && node
+                    .keys
+                    .first()
+                    .zip(low)
+                    .map_or(true, |(first, low)| low < first)
+                && node
+                    .keys
+                    .last()
+                    .zip(high)
+                    .map_or(true, |(last, high)| last < high);
+            if !in_order {
+                return Err("keys out of order".to_string());
+            }
+            if node.is_leaf() {
+                return Ok((node.keys.len(), 1));
+            }
+            if node.children.len() != node.keys.len() + 1 {
+                return Err("wrong number of children".to_string());
+            }
+            let mut count = node.keys.len();
+            let mut depth = None;
+            for (i, child) in node.children.iter().enumerate() {
+                let low = if i == 0 { low } else { node.keys.get(i - 1) };
+                let high = node.keys.get(i).or(high);
+                let (child_count, child_depth) = check(child, false, low, high)?;
+                if *depth.get_or_insert(child_depth) != child_depth {
+                    return Err("leaves at different depths".to_string());
+                }
+                count += child_count;
+            }
+            Ok((count, depth.unwrap_or(0) + 1))
+        }
+
+        let (count, _) = check(&self.root, true, None, None)?;
+        if count != self.len {
+            return Err(format!("{} entries, len is {}", count, self.len));
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> BTree<K, V> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.root = Node::new();
+        self.len = 0;
+    }
+
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = &self.root;
+        while !node.is_leaf() {
+            node = &node.children[0];
+        }
+        node.keys.first().zip(node.vals.first())
+    }
+
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        let mut node = &self.root;
+        while let Some(child) = node.children.last() {
+            node = child;
+        }
+        node.keys.last().zip(node.vals.last())
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            range: Range {
+                front: Cursor::seek(&self.root, |_| false),
+                back: Cursor::seek(&self.root, |_| true),
+                front_stop: None,
+                back_stop: None,
+            },
+            len: self.len,
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys(self.iter())
+    }
+
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values(self.iter())
+    }
+}
+
+impl<K: Ord, V> Default for BTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone, V: Clone> Clone for BTree<K, V> {
+    fn clone(&self) -> Self {
+        BTree {
+            root: self.root.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<K: PartialEq, V: PartialEq> PartialEq for BTree<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Eq, V: Eq> Eq for BTree<K, V> {}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for BTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for BTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut tree = BTree::new();
+        tree.extend(iter);
+        tree
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for BTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, val) in iter {
+            self.insert(key, val);
+        }
+    }
+}
+
+// The path from the root to a gap between two neighbouring keys. Each frame
+// is a node and the index of the child the gap is in; in a leaf, the gap is
+// just before keys[index]. Moving the gap forward yields the key after it,
+// moving it back the key before it. A cursor only moves in one direction.
+struct Cursor<'a, K, V> {
+    stack: Vec<(&'a Node<K, V>, usize)>,
+}
+
+impl<'a, K, V> Cursor<'a, K, V> {
+    // The gap after every key for which `before` holds
+    fn seek(root: &'a Node<K, V>, before: impl Fn(&K) -> bool) -> Self {
+        let mut cursor = Cursor { stack: Vec::new() };
+        cursor.descend(root, before);
+        cursor
+    }
+
+    fn descend(&mut self, mut node: &'a Node<K, V>, before: impl Fn(&K) -> bool) {
+        loop {
+            let i = node.keys.partition_point(&before);
+            self.stack.push((node, i));
+            if node.is_leaf() {
+                return;
+            }
+            node = &node.children[i];
+        }
+    }
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            let top = self.stack.last_mut()?;
+            let (node, index) = *top;
+            if index < node.keys.len() {
+                top.1 += 1;
+                if !node.is_leaf() {
+                    // The gap is now at the start of the next child
+                    self.descend(&node.children[index + 1], |_| false);
+                }
+                return Some((&node.keys[index], &node.vals[index]));
+            }
+            self.stack.pop();
+        }
+    }
+
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            let top = self.stack.last_mut()?;
+            let (node, i) = *top;
+            if i > 0 {
+                let index = i - 1;
+                top.1 = index;
+                if !node.is_leaf() {
+                    // The gap is now at the end of the previous child
+                    self.descend(&node.children[index], |_| true);
+                }
+                return Some((&node.keys[index], &node.vals[index]));
+            }
+            self.stack.pop();
+        }
+    }
+}
+
+pub struct Range<'a, K, V> {
+    front: Cursor<'a, K, V>,
+    back: Cursor<'a, K, V>,
+    // The key each end must not reach: first the neighbours of the range,
+    // then whatever the other end yielded last
+    front_stop: Option<&'a K>,
+    back_stop: Option<&'a K>,
+}
+
+impl<'a, K, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, val) = self.front.next()?;
+        // Keys are compared by address: no Ord needed, and no mixups
+        if self.front_stop.is_some_and(|stop| ptr::eq(stop, key)) {
+            self.front.stack.clear();
+            self.back.stack.clear();
+            return None;
+        }
+        self.back_stop = Some(key);
+        Some((key, val))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Range<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, val) = self.back.next_back()?;
+        if self.back_stop.is_some_and(|stop| ptr::eq(stop, key)) {
+            self.front.stack.clear();
+            self.back.stack.clear();
+            return None;
+        }
+        self.front_stop = Some(key);
+        Some((key, val))
+    }
+}
+
+impl<K, V> FusedIterator for Range<'_, K, V> {}
+
+// A range over the whole tree, which also knows its length
+pub struct Iter<'a, K, V> {
+    range: Range<'a, K, V>,
+    len: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        self.range.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        self.range.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+pub struct Keys<'a, K, V>(Iter<'a, K, V>);
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        self.0.next().map(|(key, _)| key)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Keys<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(key, _)| key)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+pub struct Values<'a, K, V>(Iter<'a, K, V>);
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        self.0.next().map(|(_, val)| val)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Values<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|(_, val)| val)
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+impl<K, V> FusedIterator for Values<'_, K, V> {}
+
+// Flattens the tree into a Vec up front, so both ends are cheap
+pub struct IntoIter<K, V>(vec::IntoIter<(K, V)>);
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.0.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        self.0.next_back()
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+impl<K, V> IntoIterator for BTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> IntoIter<K, V> {
+        let mut entries = Vec::with_capacity(self.len);
+        self.root.drain_into(&mut entries);
+        IntoIter(entries.into_iter())
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::model::{check_model, expect_eq, key, key_range, Model};
+    use property::{Arbitrary, Gen};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(i32, u8),
+        Remove(i32),
+        Get(i32),
+        AddTo(i32, u8),
+        PopFirst,
+        PopLast,
+        Range(Bound<i32>, Bound<i32>),
+        Extend(Vec<(i32, u8)>),
+        Clear,
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary(g: &mut Gen) -> Self {
+            match g.below(40) {
+                0..=14 => Op::Insert(key(g), u8::arbitrary(g)),
+                15..=24 => Op::Remove(key(g)),
+                25..=27 => Op::Get(key(g)),
+                28 | 29 => Op::AddTo(key(g), u8::arbitrary(g)),
+                30 | 31 => Op::PopFirst,
+                32 | 33 => Op::PopLast,
+                34..=37 => {
+                    let (start, end) = key_range(g);
+                    Op::Range(start, end)
+                }
+                38 => Op::Extend(
+                    (0..g.index(30))
+                        .map(|_| (key(g), u8::arbitrary(g)))
+                        .collect(),
+                ),
+                _ => Op::Clear,
+            }
+        }
+    }
+
+    struct BTreeModel;
+
+    impl Model for BTreeModel {
+        type Item = (i32, u8);
+        type Op = Op;
+        type Subject = BTree<i32, u8>;
+        type Reference = BTreeMap<i32, u8>;
+
+        fn from_items(items: Vec<(i32, u8)>) -> (BTree<i32, u8>, BTreeMap<i32, u8>) {
+            (items.iter().copied().collect(), items.into_iter().collect())
+        }
+
+        fn step(
+            op: &Op,
+            subject: &mut BTree<i32, u8>,
+            reference: &mut BTreeMap<i32, u8>,
+        ) -> Result<(), String> {
+            match op {
+                Op::Insert(k, v) => {
+                    expect_eq("insert", subject.insert(*k, *v), reference.insert(*k, *v))
+                }
+                Op::Remove(k) => expect_eq("remove", subject.remove(k), reference.remove(k)),
+                Op::Get(k) => expect_eq("get", subject.get(k), reference.get(k)),
+                Op::AddTo(k, v) => {
+                    if let Some(val) = subject.get_mut(k) {
+                        *val = val.wrapping_add(*v);
+                    }
+                    if let Some(val) = reference.get_mut(k) {
+                        *val = val.wrapping_add(*v);
+                    }
+                    Ok(())
+                }
+                Op::PopFirst => expect_eq("pop_first", subject.pop_first(), reference.pop_first()),
+                Op::PopLast => expect_eq("pop_last", subject.pop_last(), reference.pop_last()),
+                Op::Range(start, end) => {
+                    let range = (*start, *end);
+                    expect_eq(
+                        "range",
+                        subject.range(range).collect::<Vec<_>>(),
+                        reference.range(range).collect(),
+                    )?;
+                    expect_eq(
+                        "range().rev()",
+                        subject.range(range).rev().collect::<Vec<_>>(),
+                        reference.range(range).rev().collect(),
+                    )?;
+                    // Both ends at once, alternating
+                    let mut ours = subject.range(range);
+                    let mut theirs = reference.range(range);
+                    loop {
+                        let pair = (ours.next(), ours.next_back());
+                        expect_eq(
+                            "range next, next_back",
+                            pair,
+                            (theirs.next(), theirs.next_back()),
+                        )?;
+                        if pair.1.is_none() {
+                            return Ok(());
+                        }
+                    }
+                }
+                Op::Extend(items) => {
+                    subject.extend(items.iter().copied());
+                    reference.extend(items.iter().copied());
+                    Ok(())
+                }
+                Op::Clear => {
+                    subject.clear();
+                    reference.clear();
+                    Ok(())
+                }
+            }
+        }
+
+        fn compare(subject: &BTree<i32, u8>, reference: &BTreeMap<i32, u8>) -> Result<(), String> {
+            subject.check_invariants()?;
+            expect_eq("len", subject.len(), reference.len())?;
+            expect_eq(
+                "first_key_value",
+                subject.first_key_value(),
+                reference.first_key_value(),
+            )?;
+            expect_eq(
+                "last_key_value",
+                subject.last_key_value(),
+                reference.last_key_value(),
+            )?;
+            expect_eq(
+                "debug",
+                format!("{:?}", subject),
+                format!("{:?}", reference),
+            )?;
+            expect_eq(
+                "keys().rev()",
+                subject.keys().rev().collect::<Vec<_>>(),
+                reference.keys().rev().collect(),
+            )?;
+            expect_eq(
+                "values()",
+                subject.values().collect::<Vec<_>>(),
+                reference.values().collect(),
+            )?;
+            let mut owned = subject.clone().into_iter();
+            let mut reference_owned = reference.clone().into_iter();
+            expect_eq(
+                "into_iter().next_back()",
+                owned.next_back(),
+                reference_owned.next_back(),
+            )?;
+            expect_eq(
+                "into_iter()",
+                owned.collect::<Vec<_>>(),
+                reference_owned.collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_matches_btree_map() {
+        check_model::<BTreeModel>();
+    }
+
+    #[test]
+    fn test_grows_and_shrinks_in_height() {
+        // Enough keys for three levels, removed from the middle outwards
+        let mut tree: BTree<u32, u32> = (0..2000).map(|k| (k, k * k)).collect();
+        tree.check_invariants().unwrap();
+        assert_eq!(tree.get(&1999), Some(&(1999 * 1999)));
+        let mut keys: Vec<u32> = (0..2000).collect();
+        keys.sort_by_key(|&k| (k as i64 - 1000).abs());
+        for (removed, k) in keys.iter().enumerate() {
+            assert_eq!(tree.remove(k), Some(k * k));
+            if removed % 97 == 0 {
+                tree.check_invariants().unwrap();
+            }
+        }
+        assert!(tree.is_empty());
+        tree.check_invariants().unwrap();
+    }
+
+    #[test]
+    fn test_range_with_borrowed_keys() {
+        let tree: BTree<String, usize> = ["delta", "alpha", "echo", "charlie", "bravo"]
+            .into_iter()
+            .map(|word| (word.to_string(), word.len()))
+            .collect();
+        let keys: Vec<&String> = tree
+            .range::<str, _>((Bound::Included("b"), Bound::Excluded("d")))
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec!["bravo", "charlie"]);
+        assert_eq!(
+            tree.range::<str, _>((Bound::Included("c"), Bound::Unbounded))
+                .next_back(),
+            Some((&"echo".to_string(), &4))
+        );
+        assert_eq!(
+            format!(
+                "{:?}",
+                tree.range::<str, _>((Bound::Excluded("x"), Bound::Unbounded))
+                    .next()
+            ),
+            "None"
+        );
+    }
+}
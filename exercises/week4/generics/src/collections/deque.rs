@@ -0,0 +1,418 @@
+// Double-ended queue in a ring buffer
+//
+// The elements live in a Vec used as a circle: `head` is the slot of the
+// front element and the others follow it, wrapping around at the end. Both
+// ends can grow and shrink in O(1) without moving the rest. When the buffer
+// is full it doubles, and the elements are laid out from slot 0 again.
+//
+// Empty slots hold None, which keeps the code free of unsafe at the cost of
+// a little space per slot (nothing for types like Box or &T).
+
+use std::fmt;
+use std::iter::FusedIterator;
+use std::ops::{Index, IndexMut};
+
+pub struct RingDeque<T> {
+    buffer: Vec<Option<T>>,
+    head: usize,
+    len: usize,
+}
+
+impl<T> RingDeque<T> {
+    pub fn new() -> Self {
+        RingDeque {
+            buffer: Vec::new(),
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut buffer = Vec::with_capacity(capacity);
+        buffer.resize_with(capacity, || None);
+        RingDeque {
+            buffer,
+            head: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    // Buffer slot of the element at `index` from the front
+    fn slot(&self, index: usize) -> usize {
+        let slot = self.head + index;
+        if slot >= self.buffer.len() {
+            slot - self.buffer.len()
+        } else {
+            slot
+        }
+    }
+
+    fn grow_if_full(&mut self) {
+        if self.len < self.buffer.len() {
+            return;
+        }
+        let capacity = (self.buffer.len() * 2).max(4);
+        let mut buffer = Vec::with_capacity(capacity);
+        for i in 0..self.len {
+            let slot = self.slot(i);
+            buffer.push(self.buffer[slot].take());
+        }
+        buffer.resize_with(capacity, || None);
+        self.buffer = buffer;
+        self.head = 0;
+    }
+
+    pub fn push_back(&mut self, value: T) {
+        self.grow_if_full();
+        let slot = self.slot(self.len);
+        self.buffer[slot] = Some(value);
+        self.len += 1;
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        self.grow_if_full();
+        self.head = self.slot(self.buffer.len() - 1);
+        self.buffer[self.head] = Some(value);
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let value = self.buffer[self.head].take();
+        self.head = self.slot(1);
+        self.len -= 1;
+        value
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        let slot = self.slot(self.len);
+        self.buffer[slot].take()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            self.buffer[self.slot(index)].as_ref()
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index < self.len {
+            let slot = self.slot(index);
+            self.buffer[slot].as_mut()
+        } else {
+            None
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|last| self.get(last))
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_back().is_some() {}
+        self.head = 0;
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            deque: self,
+            front: 0,
+            back: self.len,
+        }
+    }
+}
+
+impl<T> Default for RingDeque<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for RingDeque<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: PartialEq> PartialEq for RingDeque<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for RingDeque<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for RingDeque<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> Index<usize> for RingDeque<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        let len = self.len;
+        self.get(index)
+            .unwrap_or_else(|| panic!("index {} out of range for length {}", index, len))
+    }
+}
+
+impl<T> IndexMut<usize> for RingDeque<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len;
+        self.get_mut(index)
+            .unwrap_or_else(|| panic!("index {} out of range for length {}", index, len))
+    }
+}
+
+impl<T> FromIterator<T> for RingDeque<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut deque = RingDeque::new();
+        deque.extend(iter);
+        deque
+    }
+}
+
+impl<T> Extend<T> for RingDeque<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push_back(value);
+        }
+    }
+}
+
+pub struct Iter<'a, T> {
+    deque: &'a RingDeque<T>,
+    front: usize,
+    back: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.front == self.back {
+            return None;
+        }
+        let value = self.deque.get(self.front);
+        self.front += 1;
+        value
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.back - self.front;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.deque.get(self.back)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> FusedIterator for Iter<'_, T> {}
+
+pub struct IntoIter<T>(RingDeque<T>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for RingDeque<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a RingDeque<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::model::{check_model, expect_eq, Model};
+    use property::{Arbitrary, Gen};
+    use std::collections::VecDeque;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        PushBack(i32),
+        PushFront(i32),
+        PopBack,
+        PopFront,
+        Get(usize),
+        Set(usize, i32),
+        Extend(Vec<i32>),
+        Clear,
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary(g: &mut Gen) -> Self {
+            match g.below(20) {
+                0..=4 => Op::PushBack(i32::arbitrary(g)),
+                5..=9 => Op::PushFront(i32::arbitrary(g)),
+                10..=12 => Op::PopBack,
+                13..=15 => Op::PopFront,
+                16 => Op::Get(g.index(20)),
+                17 => Op::Set(g.index(20), i32::arbitrary(g)),
+                18 => Op::Extend(Vec::arbitrary(g)),
+                _ => Op::Clear,
+            }
+        }
+    }
+
+    struct DequeModel;
+
+    impl Model for DequeModel {
+        type Item = i32;
+        type Op = Op;
+        type Subject = RingDeque<i32>;
+        type Reference = VecDeque<i32>;
+
+        fn from_items(items: Vec<i32>) -> (RingDeque<i32>, VecDeque<i32>) {
+            (items.iter().copied().collect(), items.into_iter().collect())
+        }
+
+        fn step(
+            op: &Op,
+            subject: &mut RingDeque<i32>,
+            reference: &mut VecDeque<i32>,
+        ) -> Result<(), String> {
+            match op {
+                Op::PushBack(x) => {
+                    subject.push_back(*x);
+                    reference.push_back(*x);
+                }
+                Op::PushFront(x) => {
+                    subject.push_front(*x);
+                    reference.push_front(*x);
+                }
+                Op::PopBack => expect_eq("pop_back", subject.pop_back(), reference.pop_back())?,
+                Op::PopFront => expect_eq("pop_front", subject.pop_front(), reference.pop_front())?,
+                Op::Get(i) => expect_eq("get", subject.get(*i), reference.get(*i))?,
+                Op::Set(i, x) => {
+                    if let (Some(a), Some(b)) = (subject.get_mut(*i), reference.get_mut(*i)) {
+                        *a = *x;
+                        *b = *x;
+                    }
+                }
+                Op::Extend(items) => {
+                    subject.extend(items.iter().copied());
+                    reference.extend(items.iter().copied());
+                }
+                Op::Clear => {
+                    subject.clear();
+                    reference.clear();
+                }
+            }
+            Ok(())
+        }
+
+        fn compare(subject: &RingDeque<i32>, reference: &VecDeque<i32>) -> Result<(), String> {
+            expect_eq("len", subject.len(), reference.len())?;
+            expect_eq("front", subject.front(), reference.front())?;
+            expect_eq("back", subject.back(), reference.back())?;
+            expect_eq(
+                "debug",
+                format!("{:?}", subject),
+                format!("{:?}", reference),
+            )?;
+            expect_eq(
+                "iter().rev()",
+                subject.iter().rev().collect::<Vec<_>>(),
+                reference.iter().rev().collect(),
+            )?;
+            let mut owned = subject.clone().into_iter();
+            expect_eq(
+                "into_iter().next_back()",
+                owned.next_back(),
+                reference.back().copied(),
+            )?;
+            expect_eq("into_iter()", owned.collect::<Vec<_>>(), {
+                let mut items: Vec<i32> = reference.iter().copied().collect();
+                items.pop();
+                items
+            })
+        }
+    }
+
+    #[test]
+    fn test_matches_vec_deque() {
+        check_model::<DequeModel>();
+    }
+
+    #[test]
+    fn test_wraps_around_and_grows() {
+        let mut deque = RingDeque::with_capacity(4);
+        deque.extend([1, 2, 3]);
+        deque.pop_front();
+        deque.pop_front();
+        deque.push_back(4);
+        deque.push_back(5); // wraps into slot 0
+        assert_eq!(deque.capacity(), 4);
+        deque.push_front(0);
+        deque.push_front(-1); // full: grows
+        assert_eq!(deque.capacity(), 8);
+        assert_eq!(format!("{:?}", deque), "[-1, 0, 3, 4, 5]");
+        deque[2] = 30;
+        assert_eq!(deque[2], 30);
+        assert_eq!(deque.iter().len(), 5);
+    }
+}
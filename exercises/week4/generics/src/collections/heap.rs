@@ -0,0 +1,477 @@
+// Min-heap with handles, for decrease_key
+//
+// std's BinaryHeap can't change an entry once it is pushed: nothing tells
+// you where it went. Here push returns a Handle, and a HashMap from handles
+// to positions in the heap is kept up to date on every swap. With it an
+// entry can be looked at, lowered (decrease_key, the step Dijkstra and Prim
+// repeat), changed either way (update) or removed, all in O(log n).
+//
+// Handles are numbered per heap, so a handle from another heap may name
+// some unrelated entry here.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::iter::FusedIterator;
+use std::slice;
+use std::vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Handle(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapError {
+    // The entry was popped or removed, or never was in this heap
+    NotFound,
+    // decrease_key was given a larger key; use update for that
+    KeyIncreased,
+}
+
+impl fmt::Display for HeapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeapError::NotFound => write!(f, "No entry with this handle in the heap"),
+            HeapError::KeyIncreased => write!(f, "decrease_key was given a larger key"),
+        }
+    }
+}
+
+impl Error for HeapError {}
+
+#[derive(Clone)]
+pub struct IndexedHeap<T> {
+    // entries[0] is the smallest; the children of i are 2i + 1 and 2i + 2
+    entries: Vec<(Handle, T)>,
+    positions: HashMap<Handle, usize>,
+    next_handle: u64,
+}
+
+impl<T: Ord> IndexedHeap<T> {
+    pub fn new() -> Self {
+        IndexedHeap {
+            entries: Vec::new(),
+            positions: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    pub fn push(&mut self, value: T) -> Handle {
+        let handle = Handle(self.next_handle);
+        self.next_handle += 1;
+        self.positions.insert(handle, self.entries.len());
+        self.entries.push((handle, value));
+        self.sift_up(self.entries.len() - 1);
+        handle
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.entries.first().map(|(_, value)| value)
+    }
+
+    pub fn peek_entry(&self) -> Option<(Handle, &T)> {
+        self.entries.first().map(|(handle, value)| (*handle, value))
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.pop_entry().map(|(_, value)| value)
+    }
+
+    pub fn pop_entry(&mut self) -> Option<(Handle, T)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        Some(self.remove_at(0))
+    }
+
+    // Lowers the key of an entry, returning the old one
+    pub fn decrease_key(&mut self, handle: Handle, value: T) -> Result<T, HeapError> {
+        let position = self.position(handle)?;
+        if value > self.entries[position].1 {
+            return Err(HeapError::KeyIncreased);
+        }
+        let old = std::mem::replace(&mut self.entries[position].1, value);
+        self.sift_up(position);
+        Ok(old)
+    }
+
+    // Changes the key of an entry in either direction, returning the old one
+    pub fn update(&mut self, handle: Handle, value: T) -> Result<T, HeapError> {
+        let position = self.position(handle)?;
+        let old = std::mem::replace(&mut self.entries[position].1, value);
+        self.restore(position);
+        Ok(old)
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Result<T, HeapError> {
+        let position = self.position(handle)?;
+        Ok(self.remove_at(position).1)
+    }
+
+    // Ascending, like BinaryHeap::into_sorted_vec
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut sorted = Vec::with_capacity(self.len());
+        while let Some(value) = self.pop() {
+            sorted.push(value);
+        }
+        sorted
+    }
+
+    fn position(&self, handle: Handle) -> Result<usize, HeapError> {
+        self.positions
+            .get(&handle)
+            .copied()
+            .ok_or(HeapError::NotFound)
+    }
+
+    fn remove_at(&mut self, position: usize) -> (Handle, T) {
+        let last = self.entries.len() - 1;
+        self.swap(position, last);
+        let (handle, value) = self.entries.pop().expect("heap is not empty");
+        self.positions.remove(&handle);
+        if position < self.entries.len() {
+            self.restore(position);
+        }
+        (handle, value)
+    }
+
+    // Moves the entry at position up or down, whichever it needs
+    fn restore(&mut self, position: usize) {
+        let position = self.sift_up(position);
+        self.sift_down(position);
+    }
+
+    fn sift_up(&mut self, mut position: usize) -> usize {
+        while position > 0 {
+            let parent = (position - 1) / 2;
+            if self.entries[position].1 >= self.entries[parent].1 {
+                break;
+            }
+            self.swap(position, parent);
+            position = parent;
+        }
+        position
+    }
+
+    fn sift_down(&mut self, mut position: usize) {
+        loop {
+            let mut smallest = position;
+            for child in [2 * position + 1, 2 * position + 2] {
+                if child < self.entries.len() && self.entries[child].1 < self.entries[smallest].1 {
+                    smallest = child;
+                }
+            }
+            if smallest == position {
+                return;
+            }
+            self.swap(position, smallest);
+            position = smallest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+        self.positions.insert(self.entries[a].0, a);
+        self.positions.insert(self.entries[b].0, b);
+    }
+
+    #[cfg(test)]
+    fn check_invariants(&self) -> Result<(), String> {
+        for (position, (handle, value)) in self.entries.iter().enumerate() {
+            if position > 0 && *value < self.entries[(position - 1) / 2].1 {
+                return Err(format!("entry {} is smaller than its parent", position));
+            }
+            if self.positions.get(handle) != Some(&position) {
+                return Err(format!("{:?} is not recorded at {}", handle, position));
+            }
+        }
+        if self.positions.len() != self.entries.len() {
+            return Err("stale handles in positions".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl<T> IndexedHeap<T> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.positions.contains_key(&handle)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.positions
+            .get(&handle)
+            .map(|&position| &self.entries[position].1)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.positions.clear();
+    }
+
+    // Entries in heap order (the smallest first, the rest in no useful order)
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.entries.iter())
+    }
+}
+
+impl<T: Ord> Default for IndexedHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for IndexedHeap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|(_, value)| value))
+            .finish()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for IndexedHeap<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut heap = IndexedHeap::new();
+        heap.extend(iter);
+        heap
+    }
+}
+
+impl<T: Ord> Extend<T> for IndexedHeap<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+pub struct Iter<'a, T>(slice::Iter<'a, (Handle, T)>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (Handle, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(handle, value)| (*handle, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> FusedIterator for Iter<'_, T> {}
+
+// Values in heap order, like BinaryHeap's IntoIter
+pub struct IntoIter<T>(vec::IntoIter<(Handle, T)>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.next().map(|(_, value)| value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for IndexedHeap<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self.entries.into_iter())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a IndexedHeap<T> {
+    type Item = (Handle, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::model::{check_model, expect_eq, key, Model};
+    use property::{Arbitrary, Gen};
+    use std::collections::BinaryHeap;
+
+    // Entries are picked by index into the live handles, sorted; an index
+    // past the end picks a handle that is gone
+    #[derive(Debug, Clone)]
+    enum Op {
+        Push(i32),
+        Pop,
+        DecreaseKey(usize, i32),
+        Update(usize, i32),
+        Remove(usize),
+        Get(usize),
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary(g: &mut Gen) -> Self {
+            match g.below(12) {
+                0..=3 => Op::Push(key(g)),
+                4 | 5 => Op::Pop,
+                6 | 7 => Op::DecreaseKey(g.index(40), key(g)),
+                8 => Op::Update(g.index(40), key(g)),
+                9 | 10 => Op::Remove(g.index(40)),
+                _ => Op::Get(g.index(40)),
+            }
+        }
+    }
+
+    // The reference keeps every live entry by handle
+    #[derive(Clone)]
+    struct Entries {
+        values: HashMap<Handle, i32>,
+        next_handle: u64,
+    }
+
+    impl Entries {
+        fn handle(&self, index: usize) -> Handle {
+            let mut handles: Vec<Handle> = self.values.keys().copied().collect();
+            handles.sort();
+            handles
+                .get(index)
+                .copied()
+                .unwrap_or(Handle(self.next_handle + 1))
+        }
+
+        fn min(&self) -> Option<i32> {
+            self.values.values().min().copied()
+        }
+    }
+
+    struct HeapModel;
+
+    impl Model for HeapModel {
+        type Item = i32;
+        type Op = Op;
+        type Subject = IndexedHeap<i32>;
+        type Reference = Entries;
+
+        fn from_items(items: Vec<i32>) -> (IndexedHeap<i32>, Entries) {
+            let values: HashMap<Handle, i32> = items
+                .iter()
+                .enumerate()
+                .map(|(i, &value)| (Handle(i as u64), value))
+                .collect();
+            let next_handle = items.len() as u64;
+            (
+                items.into_iter().collect(),
+                Entries {
+                    values,
+                    next_handle,
+                },
+            )
+        }
+
+        fn step(
+            op: &Op,
+            subject: &mut IndexedHeap<i32>,
+            reference: &mut Entries,
+        ) -> Result<(), String> {
+            match op {
+                Op::Push(x) => {
+                    let handle = subject.push(*x);
+                    expect_eq("push", handle, Handle(reference.next_handle))?;
+                    reference.values.insert(handle, *x);
+                    reference.next_handle += 1;
+                }
+                Op::Pop => {
+                    let min = reference.min();
+                    let popped = subject.pop_entry();
+                    expect_eq("pop", popped.map(|(_, value)| value), min)?;
+                    if let Some((handle, value)) = popped {
+                        // Ties may come out in any order, but the handle
+                        // has to belong to the value
+                        expect_eq("pop handle", reference.values.remove(&handle), Some(value))?;
+                    }
+                }
+                Op::DecreaseKey(i, x) => {
+                    let handle = reference.handle(*i);
+                    let expected = match reference.values.get_mut(&handle) {
+                        None => Err(HeapError::NotFound),
+                        Some(value) if *x > *value => Err(HeapError::KeyIncreased),
+                        Some(value) => Ok(std::mem::replace(value, *x)),
+                    };
+                    expect_eq("decrease_key", subject.decrease_key(handle, *x), expected)?;
+                }
+                Op::Update(i, x) => {
+                    let handle = reference.handle(*i);
+                    let expected = match reference.values.get_mut(&handle) {
+                        None => Err(HeapError::NotFound),
+                        Some(value) => Ok(std::mem::replace(value, *x)),
+                    };
+                    expect_eq("update", subject.update(handle, *x), expected)?;
+                }
+                Op::Remove(i) => {
+                    let handle = reference.handle(*i);
+                    let expected = reference.values.remove(&handle).ok_or(HeapError::NotFound);
+                    expect_eq("remove", subject.remove(handle), expected)?;
+                }
+                Op::Get(i) => {
+                    let handle = reference.handle(*i);
+                    expect_eq("get", subject.get(handle), reference.values.get(&handle))?;
+                    expect_eq(
+                        "contains",
+                        subject.contains(handle),
+                        reference.values.contains_key(&handle),
+                    )?;
+                }
+            }
+            Ok(())
+        }
+
+        fn compare(subject: &IndexedHeap<i32>, reference: &Entries) -> Result<(), String> {
+            subject.check_invariants()?;
+            expect_eq("len", subject.len(), reference.values.len())?;
+            expect_eq("peek", subject.peek().copied(), reference.min())?;
+            let entries: HashMap<Handle, i32> = subject.iter().map(|(h, &v)| (h, v)).collect();
+            expect_eq("iter", &entries, &reference.values)?;
+            let std_heap: BinaryHeap<i32> = reference.values.values().copied().collect();
+            expect_eq(
+                "into_sorted_vec",
+                subject.clone().into_sorted_vec(),
+                std_heap.into_sorted_vec(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_matches_binary_heap() {
+        check_model::<HeapModel>();
+    }
+
+    #[test]
+    fn test_decrease_key() {
+        let mut heap = IndexedHeap::new();
+        let a = heap.push(10);
+        let b = heap.push(20);
+        let c = heap.push(30);
+        assert_eq!(heap.decrease_key(c, 5), Ok(30));
+        assert_eq!(heap.peek_entry(), Some((c, &5)));
+        assert_eq!(heap.decrease_key(b, 25), Err(HeapError::KeyIncreased));
+        assert_eq!(heap.update(b, 1), Ok(20));
+        assert_eq!(heap.pop_entry(), Some((b, 1)));
+        assert_eq!(heap.decrease_key(b, 0), Err(HeapError::NotFound));
+        assert_eq!(heap.remove(a), Ok(10));
+        assert_eq!(heap.into_sorted_vec(), vec![5]);
+    }
+}
@@ -0,0 +1,240 @@
+// Stack that knows its minimum
+//
+// Next to the items it keeps a second stack with the positions of the
+// minimums seen so far: a push that is <= the current minimum also pushes
+// its position there, and popping that item pops the position again. So
+// min() is a lookup, and push and pop stay O(1).
+
+use std::fmt;
+use std::iter::FusedIterator;
+use std::slice;
+use std::vec;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct MinStack<T> {
+    items: Vec<T>,
+    // Positions in items, each minimum sitting above the one it replaced
+    mins: Vec<usize>,
+}
+
+impl<T: Ord> MinStack<T> {
+    pub fn new() -> Self {
+        MinStack {
+            items: Vec::new(),
+            mins: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, value: T) {
+        // <= so that equal minimums each get an entry and pop cleanly
+        if self.min().map_or(true, |min| value <= *min) {
+            self.mins.push(self.items.len());
+        }
+        self.items.push(value);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let value = self.items.pop()?;
+        if self.mins.last() == Some(&self.items.len()) {
+            self.mins.pop();
+        }
+        Some(value)
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        self.mins.last().map(|&index| &self.items[index])
+    }
+
+    // Pops every item, top first
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        Drain(self)
+    }
+}
+
+impl<T> MinStack<T> {
+    pub fn peek(&self) -> Option<&T> {
+        self.items.last()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.mins.clear();
+    }
+
+    // Bottom to top, like a Vec
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.items.iter()
+    }
+}
+
+impl<T: Ord> Default for MinStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MinStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.items.iter()).finish()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for MinStack<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut stack = MinStack::new();
+        stack.extend(iter);
+        stack
+    }
+}
+
+impl<T: Ord> Extend<T> for MinStack<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push(value);
+        }
+    }
+}
+
+impl<T> IntoIterator for MinStack<T> {
+    type Item = T;
+    type IntoIter = vec::IntoIter<T>;
+
+    fn into_iter(self) -> vec::IntoIter<T> {
+        self.items.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a MinStack<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.items.iter()
+    }
+}
+
+// Pops from the top, the order a stack hands its items out in
+pub struct Drain<'a, T: Ord>(&'a mut MinStack<T>);
+
+impl<T: Ord> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len(), Some(self.0.len()))
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for Drain<'_, T> {}
+impl<T: Ord> FusedIterator for Drain<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::model::{check_model, expect_eq, Model};
+    use property::{Arbitrary, Gen};
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Push(i32),
+        Pop,
+        Extend(Vec<i32>),
+        Clear,
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary(g: &mut Gen) -> Self {
+            // Small values, so equal minimums show up often
+            match g.below(20) {
+                0..=10 => Op::Push(g.range(-10, 10) as i32),
+                11..=17 => Op::Pop,
+                18 => Op::Extend((0..g.index(5)).map(|_| g.range(-10, 10) as i32).collect()),
+                _ => Op::Clear,
+            }
+        }
+    }
+
+    struct MinStackModel;
+
+    impl Model for MinStackModel {
+        type Item = i32;
+        type Op = Op;
+        type Subject = MinStack<i32>;
+        type Reference = Vec<i32>;
+
+        fn from_items(items: Vec<i32>) -> (MinStack<i32>, Vec<i32>) {
+            (items.iter().copied().collect(), items)
+        }
+
+        fn step(
+            op: &Op,
+            subject: &mut MinStack<i32>,
+            reference: &mut Vec<i32>,
+        ) -> Result<(), String> {
+            match op {
+                Op::Push(x) => {
+                    subject.push(*x);
+                    reference.push(*x);
+                }
+                Op::Pop => expect_eq("pop", subject.pop(), reference.pop())?,
+                Op::Extend(items) => {
+                    subject.extend(items.iter().copied());
+                    reference.extend(items.iter().copied());
+                }
+                Op::Clear => {
+                    subject.clear();
+                    reference.clear();
+                }
+            }
+            Ok(())
+        }
+
+        fn compare(subject: &MinStack<i32>, reference: &Vec<i32>) -> Result<(), String> {
+            expect_eq("len", subject.len(), reference.len())?;
+            expect_eq("peek", subject.peek(), reference.last())?;
+            expect_eq("min", subject.min(), reference.iter().min())?;
+            expect_eq(
+                "debug",
+                format!("{:?}", subject),
+                format!("{:?}", reference),
+            )?;
+            expect_eq(
+                "drain",
+                subject.clone().drain().collect::<Vec<_>>(),
+                reference.iter().rev().copied().collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_matches_vec() {
+        check_model::<MinStackModel>();
+    }
+
+    #[test]
+    fn test_min_follows_pops() {
+        let mut stack: MinStack<i32> = [5, 3, 7, 3, 1].into_iter().collect();
+        assert_eq!(stack.min(), Some(&1));
+        stack.pop();
+        assert_eq!(stack.min(), Some(&3));
+        stack.pop();
+        // The first 3 is still there
+        assert_eq!(stack.min(), Some(&3));
+        stack.pop();
+        stack.pop();
+        assert_eq!(stack.min(), Some(&5));
+        stack.pop();
+        assert_eq!(stack.min(), None);
+    }
+}
@@ -0,0 +1,642 @@
+// Sorted set in a skip list
+//
+// A skip list is a sorted linked list with extra "express lanes": every
+// node is on level 0, about a quarter of them also on level 1, a quarter
+// of those on level 2, and so on. A search runs along the top level until
+// the next step would overshoot, then drops a level, so it takes O(log n)
+// steps on average, like a balanced tree but without any rebalancing. The
+// levels come from a coin toss at insert time.
+//
+// The nodes live in a Vec and link to each other by index (NIL for the
+// end), which keeps the borrow checker happy without Rc or unsafe. Node 0
+// is a head sentinel that is on every level. Removed slots go on a free
+// list and are reused.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::iter::FusedIterator;
+use std::ops::{Bound, RangeBounds};
+
+use super::check_range;
+
+const NIL: usize = usize::MAX;
+const HEAD: usize = 0;
+const MAX_LEVEL: usize = 16;
+
+#[derive(Clone)]
+struct Node<T> {
+    // None for the head and for free slots
+    value: Option<T>,
+    // The next node on each level this node is on
+    next: Vec<usize>,
+    // The previous node on level 0, for walking backwards
+    prev: usize,
+}
+
+#[derive(Clone)]
+pub struct SkipList<T> {
+    nodes: Vec<Node<T>>,
+    free: Vec<usize>,
+    // Levels in use, at least 1
+    levels: usize,
+    // Last node, HEAD when empty
+    tail: usize,
+    len: usize,
+    rng: u64,
+}
+
+impl<T: Ord> SkipList<T> {
+    pub fn new() -> Self {
+        let head = Node {
+            value: None,
+            next: vec![NIL; MAX_LEVEL],
+            prev: HEAD,
+        };
+        // Different lists get different coin tosses; | 1 keeps xorshift off 0
+        let seed = RandomState::new().build_hasher().finish() | 1;
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            levels: 1,
+            tail: HEAD,
+            len: 0,
+            rng: seed,
+        }
+    }
+
+    // Returns false, leaving the list as it was, if value was already there
+    pub fn insert(&mut self, value: T) -> bool {
+        let update = self.search(|v| v < &value);
+        let found = self.nodes[update[0]].next[0];
+        if found != NIL && self.value(found) == &value {
+            return false;
+        }
+
+        let height = self.random_level();
+        if height > self.levels {
+            // update already holds HEAD for the new levels
+            self.levels = height;
+        }
+        let node = Node {
+            value: Some(value),
+            next: (0..height)
+                .map(|level| self.nodes[update[level]].next[level])
+                .collect(),
+            prev: update[0],
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for (level, &prev) in update.iter().enumerate().take(height) {
+            self.nodes[prev].next[level] = index;
+        }
+        self.relink_after(index);
+        self.len += 1;
+        true
+    }
+
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get(value).is_some()
+    }
+
+    pub fn get<Q>(&self, value: &Q) -> Option<&T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let found = self.first_not(|v| v.borrow() < value);
+        (found != NIL && self.value(found).borrow() == value).then(|| self.value(found))
+    }
+
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.take(value).is_some()
+    }
+
+    pub fn take<Q>(&mut self, value: &Q) -> Option<T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let update = self.search(|v| v.borrow() < value);
+        let found = self.nodes[update[0]].next[0];
+        if found == NIL || self.value(found).borrow() != value {
+            return None;
+        }
+        Some(self.unlink(&update, found))
+    }
+
+    pub fn pop_first(&mut self) -> Option<T> {
+        let first = self.nodes[HEAD].next[0];
+        // Nothing comes before the first node on any level
+        (first != NIL).then(|| self.unlink(&[HEAD; MAX_LEVEL], first))
+    }
+
+    pub fn pop_last(&mut self) -> Option<T> {
+        if self.tail == HEAD {
+            return None;
+        }
+        let last = self.value(self.tail);
+        let update = self.search(|v| v < last);
+        Some(self.unlink(&update, self.tail))
+    }
+
+    // Iterates over the values in range, in order
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, T>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        check_range(range.start_bound(), range.end_bound());
+        let front = self.first_not(|v| match range.start_bound() {
+            Bound::Included(start) => v.borrow() < start,
+            Bound::Excluded(start) => v.borrow() <= start,
+            Bound::Unbounded => false,
+        });
+        let after = self.first_not(|v| match range.end_bound() {
+            Bound::Included(end) => v.borrow() <= end,
+            Bound::Excluded(end) => v.borrow() < end,
+            Bound::Unbounded => true,
+        });
+        let back = if after == NIL {
+            self.tail
+        } else {
+            self.nodes[after].prev
+        };
+        Range {
+            list: self,
+            front,
+            back,
+            done: front == after,
+        }
+    }
+
+    // For each level, the last node for which `before` holds (or HEAD):
+    // the nodes whose links change when inserting or removing after them
+    fn search(&self, before: impl Fn(&T) -> bool) -> [usize; MAX_LEVEL] {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut node = HEAD;
+        for level in (0..self.levels).rev() {
+            loop {
+                let next = self.nodes[node].next[level];
+                if next == NIL || !before(self.value(next)) {
+                    break;
+                }
+                node = next;
+            }
+            update[level] = node;
+        }
+        update
+    }
+
+    // The first node for which `before` doesn't hold, or NIL
+    fn first_not(&self, before: impl Fn(&T) -> bool) -> usize {
+        let update = self.search(before);
+        self.nodes[update[0]].next[0]
+    }
+
+    fn unlink(&mut self, update: &[usize; MAX_LEVEL], index: usize) -> T {
+        let next = std::mem::take(&mut self.nodes[index].next);
+        for (level, &after) in next.iter().enumerate() {
+            self.nodes[update[level]].next[level] = after;
+        }
+        let prev = self.nodes[index].prev;
+        if next[0] == NIL {
+            self.tail = prev;
+        } else {
+            self.nodes[next[0]].prev = prev;
+        }
+        while self.levels > 1 && self.nodes[HEAD].next[self.levels - 1] == NIL {
+            self.levels -= 1;
+        }
+        self.free.push(index);
+        self.len -= 1;
+        self.nodes[index]
+            .value
+            .take()
+            .expect("linked nodes hold a value")
+    }
+
+    fn random_level(&mut self) -> usize {
+        let mut level = 1;
+        // Each extra level with probability 1/4
+        while level < MAX_LEVEL && self.next_random() & 3 == 0 {
+            level += 1;
+        }
+        level
+    }
+
+    // xorshift64: plenty for coin tosses
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    #[cfg(test)]
+    fn check_invariants(&self) -> Result<(), String> {
+        for level in 0..MAX_LEVEL {
+            let mut node = self.nodes[HEAD].next[level];
+            if level >= self.levels && node != NIL {
+                return Err(format!("level {} is in use but not counted", level));
+            }
+            let mut count = 0;
+            while node != NIL {
+                let next = self.nodes[node].next[level];
+                if next != NIL && self.value(node) >= self.value(next) {
+                    return Err(format!("level {} is out of order", level));
+                }
+                if level == 0 && next != NIL && self.nodes[next].prev != node {
+                    return Err("prev link is wrong".to_string());
+                }
+                node = next;
+                count += 1;
+            }
+            if level == 0 && count != self.len {
+                return Err(format!("{} nodes on level 0, len is {}", count, self.len));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T> SkipList<T> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        let first = self.nodes[HEAD].next[0];
+        (first != NIL).then(|| self.value(first))
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        (self.tail != HEAD).then(|| self.value(self.tail))
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.truncate(1);
+        self.nodes[HEAD].next = vec![NIL; MAX_LEVEL];
+        self.free.clear();
+        self.levels = 1;
+        self.tail = HEAD;
+        self.len = 0;
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            range: Range {
+                list: self,
+                front: self.nodes[HEAD].next[0],
+                back: self.tail,
+                done: self.len == 0,
+            },
+            len: self.len,
+        }
+    }
+
+    fn value(&self, index: usize) -> &T {
+        self.nodes[index]
+            .value
+            .as_ref()
+            .expect("linked nodes hold a value")
+    }
+
+    // Points the node after index (or the tail) back at it
+    fn relink_after(&mut self, index: usize) {
+        match self.nodes[index].next[0] {
+            NIL => self.tail = index,
+            next => self.nodes[next].prev = index,
+        }
+    }
+}
+
+impl<T: Ord> Default for SkipList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq> PartialEq for SkipList<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for SkipList<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for SkipList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Ord> FromIterator<T> for SkipList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = SkipList::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Ord> Extend<T> for SkipList<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+pub struct Range<'a, T> {
+    list: &'a SkipList<T>,
+    front: usize,
+    back: usize,
+    // Set once front and back have met
+    done: bool,
+}
+
+impl<'a, T> Iterator for Range<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.done {
+            return None;
+        }
+        let node = self.front;
+        if node == self.back {
+            self.done = true;
+        } else {
+            self.front = self.list.nodes[node].next[0];
+        }
+        Some(self.list.value(node))
+    }
+}
+
+impl<T> DoubleEndedIterator for Range<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let node = self.back;
+        if node == self.front {
+            self.done = true;
+        } else {
+            self.back = self.list.nodes[node].prev;
+        }
+        Some(self.list.value(node))
+    }
+}
+
+impl<T> FusedIterator for Range<'_, T> {}
+
+// A range over the whole list, which also knows its length
+pub struct Iter<'a, T> {
+    range: Range<'a, T>,
+    len: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let value = self.range.next()?;
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let value = self.range.next_back()?;
+        self.len -= 1;
+        Some(value)
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+impl<T> FusedIterator for Iter<'_, T> {}
+
+pub struct IntoIter<T: Ord>(SkipList<T>);
+
+impl<T: Ord> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.0.pop_first()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
+}
+
+impl<T: Ord> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.0.pop_last()
+    }
+}
+
+impl<T: Ord> ExactSizeIterator for IntoIter<T> {}
+impl<T: Ord> FusedIterator for IntoIter<T> {}
+
+impl<T: Ord> IntoIterator for SkipList<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SkipList<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::model::{check_model, expect_eq, key, key_range, Model};
+    use property::{Arbitrary, Gen};
+    use std::collections::BTreeSet;
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(i32),
+        Remove(i32),
+        Contains(i32),
+        PopFirst,
+        PopLast,
+        Range(Bound<i32>, Bound<i32>),
+        Extend(Vec<i32>),
+        Clear,
+    }
+
+    impl Arbitrary for Op {
+        fn arbitrary(g: &mut Gen) -> Self {
+            match g.below(24) {
+                0..=7 => Op::Insert(key(g)),
+                8..=12 => Op::Remove(key(g)),
+                13..=15 => Op::Contains(key(g)),
+                16 | 17 => Op::PopFirst,
+                18 | 19 => Op::PopLast,
+                20 | 21 => {
+                    let (start, end) = key_range(g);
+                    Op::Range(start, end)
+                }
+                22 => Op::Extend((0..g.index(10)).map(|_| key(g)).collect()),
+                _ => Op::Clear,
+            }
+        }
+    }
+
+    struct SkipListModel;
+
+    impl Model for SkipListModel {
+        type Item = i32;
+        type Op = Op;
+        type Subject = SkipList<i32>;
+        type Reference = BTreeSet<i32>;
+
+        fn from_items(items: Vec<i32>) -> (SkipList<i32>, BTreeSet<i32>) {
+            (items.iter().copied().collect(), items.into_iter().collect())
+        }
+
+        fn step(
+            op: &Op,
+            subject: &mut SkipList<i32>,
+            reference: &mut BTreeSet<i32>,
+        ) -> Result<(), String> {
+            match op {
+                Op::Insert(x) => expect_eq("insert", subject.insert(*x), reference.insert(*x)),
+                Op::Remove(x) => expect_eq("remove", subject.remove(x), reference.remove(x)),
+                Op::Contains(x) => {
+                    expect_eq("contains", subject.contains(x), reference.contains(x))
+                }
+                Op::PopFirst => expect_eq("pop_first", subject.pop_first(), reference.pop_first()),
+                Op::PopLast => expect_eq("pop_last", subject.pop_last(), reference.pop_last()),
+                Op::Range(start, end) => {
+                    let range = (*start, *end);
+                    expect_eq(
+                        "range",
+                        subject.range(range).collect::<Vec<_>>(),
+                        reference.range(range).collect(),
+                    )?;
+                    expect_eq(
+                        "range().rev()",
+                        subject.range(range).rev().collect::<Vec<_>>(),
+                        reference.range(range).rev().collect(),
+                    )
+                }
+                Op::Extend(items) => {
+                    subject.extend(items.iter().copied());
+                    reference.extend(items.iter().copied());
+                    Ok(())
+                }
+                Op::Clear => {
+                    subject.clear();
+                    reference.clear();
+                    Ok(())
+                }
+            }
+        }
+
+        fn compare(subject: &SkipList<i32>, reference: &BTreeSet<i32>) -> Result<(), String> {
+            subject.check_invariants()?;
+            expect_eq("len", subject.len(), reference.len())?;
+            expect_eq("first", subject.first(), reference.first())?;
+            expect_eq("last", subject.last(), reference.last())?;
+            expect_eq(
+                "debug",
+                format!("{:?}", subject),
+                format!("{:?}", reference),
+            )?;
+            expect_eq(
+                "iter().rev()",
+                subject.iter().rev().collect::<Vec<_>>(),
+                reference.iter().rev().collect(),
+            )?;
+            // Meeting in the middle
+            let mut iter = subject.iter();
+            let mut reference_iter = reference.iter();
+            loop {
+                let pair = (iter.next_back(), iter.next());
+                expect_eq(
+                    "next_back, next",
+                    pair,
+                    (reference_iter.next_back(), reference_iter.next()),
+                )?;
+                expect_eq("len()", iter.len(), reference_iter.len())?;
+                if pair.1.is_none() {
+                    break;
+                }
+            }
+            expect_eq(
+                "into_iter()",
+                subject.clone().into_iter().collect::<Vec<_>>(),
+                reference.iter().copied().collect(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_matches_btree_set() {
+        check_model::<SkipListModel>();
+    }
+
+    #[test]
+    fn test_range_and_borrowed_lookups() {
+        let words: SkipList<String> = ["pear", "apple", "fig", "kiwi", "apple"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(words.len(), 4);
+        assert!(words.contains("fig"));
+        let middle: Vec<&String> = words
+            .range::<str, _>((Bound::Included("b"), Bound::Excluded("pear")))
+            .collect();
+        assert_eq!(middle, vec!["fig", "kiwi"]);
+        assert_eq!(
+            format!("{:?}", words),
+            r#"{"apple", "fig", "kiwi", "pear"}"#
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "range start is greater than range end")]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_backwards_range_panics() {
+        let list: SkipList<i32> = (0..10).collect();
+        list.range(5..2);
+    }
+}
@@ -0,0 +1,15 @@
+// Option2 and Result2: std's Option and Result written out by hand
+//
+// Each combinator is a match on the two variants, which makes these a
+// readable reference for what the std versions do. The tests check them
+// against std on generated inputs.
+//
+// The ? operator needs the unstable Try trait, so it can't work on these
+// directly. Instead they convert to and from the std types with From (or
+// into_std), and `value.into_std()?` does the job.
+
+pub mod option2;
+pub mod result2;
+
+pub use option2::Option2;
+pub use result2::Result2;
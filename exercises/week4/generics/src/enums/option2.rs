@@ -0,0 +1,492 @@
+// Option2<T>: a value that may be missing
+//
+// The variants are declared None first, so that the derived Ord puts None
+// before any Some, as std does.
+
+use std::iter::FusedIterator;
+
+use super::Result2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Option2<T> {
+    #[default]
+    None,
+    Some(T),
+}
+
+impl<T> Option2<T> {
+    pub fn is_some(&self) -> bool {
+        matches!(self, Option2::Some(_))
+    }
+
+    pub fn is_none(&self) -> bool {
+        !self.is_some()
+    }
+
+    pub fn is_some_and(self, f: impl FnOnce(T) -> bool) -> bool {
+        match self {
+            Option2::Some(value) => f(value),
+            Option2::None => false,
+        }
+    }
+
+    pub fn as_ref(&self) -> Option2<&T> {
+        match self {
+            Option2::Some(value) => Option2::Some(value),
+            Option2::None => Option2::None,
+        }
+    }
+
+    pub fn as_mut(&mut self) -> Option2<&mut T> {
+        match self {
+            Option2::Some(value) => Option2::Some(value),
+            Option2::None => Option2::None,
+        }
+    }
+
+    pub fn expect(self, message: &str) -> T {
+        match self {
+            Option2::Some(value) => value,
+            Option2::None => panic!("{}", message),
+        }
+    }
+
+    pub fn unwrap(self) -> T {
+        self.expect("called `Option2::unwrap()` on a `None` value")
+    }
+
+    pub fn unwrap_or(self, default: T) -> T {
+        match self {
+            Option2::Some(value) => value,
+            Option2::None => default,
+        }
+    }
+
+    pub fn unwrap_or_else(self, f: impl FnOnce() -> T) -> T {
+        match self {
+            Option2::Some(value) => value,
+            Option2::None => f(),
+        }
+    }
+
+    pub fn unwrap_or_default(self) -> T
+    where
+        T: Default,
+    {
+        self.unwrap_or_else(T::default)
+    }
+
+    pub fn map<U, F>(self, f: F) -> Option2<U>
+    where
+        F: FnOnce(T) -> U,
+    {
+        match self {
+            Option2::Some(value) => Option2::Some(f(value)),
+            Option2::None => Option2::None,
+        }
+    }
+
+    pub fn map_or<U>(self, default: U, f: impl FnOnce(T) -> U) -> U {
+        match self {
+            Option2::Some(value) => f(value),
+            Option2::None => default,
+        }
+    }
+
+    pub fn map_or_else<U>(self, default: impl FnOnce() -> U, f: impl FnOnce(T) -> U) -> U {
+        match self {
+            Option2::Some(value) => f(value),
+            Option2::None => default(),
+        }
+    }
+
+    // Runs f on the value, for logging and the like, and passes self on
+    pub fn inspect(self, f: impl FnOnce(&T)) -> Self {
+        if let Option2::Some(value) = &self {
+            f(value);
+        }
+        self
+    }
+
+    pub fn ok_or<E>(self, err: E) -> Result2<T, E> {
+        match self {
+            Option2::Some(value) => Result2::Ok(value),
+            Option2::None => Result2::Err(err),
+        }
+    }
+
+    pub fn ok_or_else<E>(self, err: impl FnOnce() -> E) -> Result2<T, E> {
+        match self {
+            Option2::Some(value) => Result2::Ok(value),
+            Option2::None => Result2::Err(err()),
+        }
+    }
+
+    pub fn and<U>(self, other: Option2<U>) -> Option2<U> {
+        match self {
+            Option2::Some(_) => other,
+            Option2::None => Option2::None,
+        }
+    }
+
+    pub fn and_then<U>(self, f: impl FnOnce(T) -> Option2<U>) -> Option2<U> {
+        match self {
+            Option2::Some(value) => f(value),
+            Option2::None => Option2::None,
+        }
+    }
+
+    pub fn filter(self, predicate: impl FnOnce(&T) -> bool) -> Self {
+        match self {
+            Option2::Some(value) if predicate(&value) => Option2::Some(value),
+            _ => Option2::None,
+        }
+    }
+
+    pub fn or(self, other: Self) -> Self {
+        match self {
+            Option2::Some(_) => self,
+            Option2::None => other,
+        }
+    }
+
+    pub fn or_else(self, f: impl FnOnce() -> Self) -> Self {
+        match self {
+            Option2::Some(_) => self,
+            Option2::None => f(),
+        }
+    }
+
+    // Some if exactly one of the two is
+    pub fn xor(self, other: Self) -> Self {
+        match (self, other) {
+            (Option2::Some(value), Option2::None) | (Option2::None, Option2::Some(value)) => {
+                Option2::Some(value)
+            }
+            _ => Option2::None,
+        }
+    }
+
+    pub fn zip<U>(self, other: Option2<U>) -> Option2<(T, U)> {
+        match (self, other) {
+            (Option2::Some(a), Option2::Some(b)) => Option2::Some((a, b)),
+            _ => Option2::None,
+        }
+    }
+
+    pub fn take(&mut self) -> Self {
+        std::mem::replace(self, Option2::None)
+    }
+
+    pub fn replace(&mut self, value: T) -> Self {
+        std::mem::replace(self, Option2::Some(value))
+    }
+
+    pub fn insert(&mut self, value: T) -> &mut T {
+        *self = Option2::Some(value);
+        match self {
+            Option2::Some(value) => value,
+            Option2::None => unreachable!(),
+        }
+    }
+
+    pub fn get_or_insert_with(&mut self, f: impl FnOnce() -> T) -> &mut T {
+        if let Option2::None = self {
+            *self = Option2::Some(f());
+        }
+        match self {
+            Option2::Some(value) => value,
+            Option2::None => unreachable!(),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        IntoIter {
+            inner: self.as_ref(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IntoIter {
+            inner: self.as_mut(),
+        }
+    }
+
+    pub fn into_std(self) -> Option<T> {
+        self.into()
+    }
+}
+
+impl<T> Option2<Option2<T>> {
+    pub fn flatten(self) -> Option2<T> {
+        self.and_then(|inner| inner)
+    }
+}
+
+impl<T, U> Option2<(T, U)> {
+    pub fn unzip(self) -> (Option2<T>, Option2<U>) {
+        match self {
+            Option2::Some((a, b)) => (Option2::Some(a), Option2::Some(b)),
+            Option2::None => (Option2::None, Option2::None),
+        }
+    }
+}
+
+impl<T, E> Option2<Result2<T, E>> {
+    // Some(Err(e)) becomes Err(e); None becomes Ok(None)
+    pub fn transpose(self) -> Result2<Option2<T>, E> {
+        match self {
+            Option2::Some(Result2::Ok(value)) => Result2::Ok(Option2::Some(value)),
+            Option2::Some(Result2::Err(err)) => Result2::Err(err),
+            Option2::None => Result2::Ok(Option2::None),
+        }
+    }
+}
+
+impl<T: Copy> Option2<&T> {
+    pub fn copied(self) -> Option2<T> {
+        self.map(|value| *value)
+    }
+}
+
+impl<T: Clone> Option2<&T> {
+    pub fn cloned(self) -> Option2<T> {
+        self.map(T::clone)
+    }
+}
+
+impl<T> From<Option<T>> for Option2<T> {
+    fn from(option: Option<T>) -> Self {
+        match option {
+            Some(value) => Option2::Some(value),
+            None => Option2::None,
+        }
+    }
+}
+
+impl<T> From<Option2<T>> for Option<T> {
+    fn from(option: Option2<T>) -> Self {
+        match option {
+            Option2::Some(value) => Some(value),
+            Option2::None => None,
+        }
+    }
+}
+
+// Collecting stops at the first None, which becomes the result
+impl<A, V: FromIterator<A>> FromIterator<Option2<A>> for Option2<V> {
+    fn from_iter<I: IntoIterator<Item = Option2<A>>>(iter: I) -> Self {
+        let mut missing = false;
+        let collected = iter
+            .into_iter()
+            .map_while(|item| match item {
+                Option2::Some(value) => Some(value),
+                Option2::None => {
+                    missing = true;
+                    None
+                }
+            })
+            .collect();
+        if missing {
+            Option2::None
+        } else {
+            Option2::Some(collected)
+        }
+    }
+}
+
+// Yields the value, if there is one; iter and iter_mut yield references
+#[derive(Debug, Clone)]
+pub struct IntoIter<T> {
+    inner: Option2<T>,
+}
+
+pub type Iter<'a, T> = IntoIter<&'a T>;
+pub type IterMut<'a, T> = IntoIter<&'a mut T>;
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.inner.take().into_std()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = usize::from(self.inner.is_some());
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.next()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for Option2<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { inner: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Option2<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut Option2<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use property::quickcheck;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    // Each check gets a std Option and the same value as an Option2
+    fn both(option: Option<i32>) -> (Option<i32>, Option2<i32>) {
+        (option, option.into())
+    }
+
+    #[test]
+    // The lazy variants are what is being compared
+    #[allow(clippy::unnecessary_lazy_evaluations)]
+    fn test_matches_std_combinators() {
+        quickcheck(|(a, b, k): (Option<i32>, Option<i32>, i32)| {
+            let (a, a2) = both(a);
+            let (b, b2) = both(b);
+            let half = |x: i32| (x % 2 == 0).then_some(x / 2);
+            let half2 = |x: i32| Option2::from(half(x));
+            let above = |x: &i32| *x > k;
+
+            assert_eq!(a2.is_some(), a.is_some());
+            assert_eq!(a2.is_none(), a.is_none());
+            assert_eq!(a2.is_some_and(|x| x > k), a.is_some_and(|x| x > k));
+            assert_eq!(a2.unwrap_or(k), a.unwrap_or(k));
+            assert_eq!(
+                a2.unwrap_or_else(|| k.wrapping_neg()),
+                a.unwrap_or_else(|| k.wrapping_neg())
+            );
+            assert_eq!(a2.unwrap_or_default(), a.unwrap_or_default());
+            assert_eq!(
+                a2.map(|x| x.wrapping_mul(3)).into_std(),
+                a.map(|x| x.wrapping_mul(3))
+            );
+            assert_eq!(a2.map_or(k, |x| x ^ k), a.map_or(k, |x| x ^ k));
+            assert_eq!(
+                a2.map_or_else(|| k, |x| x ^ k),
+                a.map_or_else(|| k, |x| x ^ k)
+            );
+            assert_eq!(a2.ok_or(k).into_std(), a.ok_or(k));
+            assert_eq!(a2.ok_or_else(|| k).into_std(), a.ok_or_else(|| k));
+            assert_eq!(a2.and(b2).into_std(), a.and(b));
+            assert_eq!(a2.and_then(half2).into_std(), a.and_then(half));
+            assert_eq!(a2.filter(above).into_std(), a.filter(above));
+            assert_eq!(a2.or(b2).into_std(), a.or(b));
+            assert_eq!(a2.or_else(|| b2).into_std(), a.or_else(|| b));
+            assert_eq!(a2.xor(b2).into_std(), a.xor(b));
+            assert_eq!(a2.zip(b2).into_std(), a.zip(b));
+            let (left, right) = a2.zip(b2).unzip();
+            assert_eq!((left.into_std(), right.into_std()), a.zip(b).unzip());
+            assert_eq!(a2.iter().collect::<Vec<_>>(), a.iter().collect::<Vec<_>>());
+            assert_eq!(a2.into_iter().size_hint(), a.into_iter().size_hint());
+            assert_eq!(a2.as_ref().copied(), a2);
+            assert_eq!(a2.as_ref().cloned(), a2);
+
+            let mut inspected = None;
+            assert_eq!(a2.inspect(|x| inspected = Some(*x)), a2);
+            assert_eq!(inspected, a);
+        });
+    }
+
+    #[test]
+    fn test_matches_std_nesting_and_mutation() {
+        quickcheck(|(a, b, k): (Option<Option<i32>>, Option<i32>, i32)| {
+            let a2: Option2<Option2<i32>> = a.map(Option2::from).into();
+            assert_eq!(a2.flatten().into_std(), a.flatten());
+
+            let as_result = |x: i32| if x >= 0 { Ok(x) } else { Err(x) };
+            let nested = b.map(as_result);
+            let nested2: Option2<Result2<i32, i32>> = b.map(|x| as_result(x).into()).into();
+            let transposed = nested2.transpose().map(Option2::into_std).into_std();
+            assert_eq!(transposed, nested.transpose());
+
+            let (mut b, mut b2) = both(b);
+            if let Some(x) = b2.as_mut().into_std() {
+                *x = x.wrapping_add(k);
+            }
+            for x in &mut b2 {
+                *x = x.wrapping_mul(2);
+            }
+            b = b.map(|x| x.wrapping_add(k).wrapping_mul(2));
+            assert_eq!(b2.into_std(), b);
+            assert_eq!(b2.replace(k).into_std(), b.replace(k));
+            assert_eq!(b2.take().into_std(), b.take());
+            assert_eq!(
+                *b2.get_or_insert_with(|| k.wrapping_add(1)),
+                *b.get_or_insert_with(|| k.wrapping_add(1))
+            );
+            assert_eq!(*b2.insert(k), *b.insert(k));
+        });
+    }
+
+    #[test]
+    fn test_matches_std_ordering_and_collect() {
+        quickcheck(
+            |(a, b, items): (Option<i32>, Option<i32>, Vec<Option<i32>>)| {
+                let (a2, b2) = (Option2::from(a), Option2::from(b));
+                assert_eq!(a2.cmp(&b2), a.cmp(&b));
+                assert_eq!(a2 == b2, a == b);
+
+                let hash = |value: &dyn Fn(&mut DefaultHasher)| {
+                    let mut hasher = DefaultHasher::new();
+                    value(&mut hasher);
+                    hasher.finish()
+                };
+                // Equal values hash equally
+                if a == b {
+                    assert_eq!(hash(&|h| a2.hash(h)), hash(&|h| b2.hash(h)));
+                }
+
+                let collected: Option2<Vec<i32>> =
+                    items.iter().map(|&x| Option2::from(x)).collect();
+                let expected: Option<Vec<i32>> = items.iter().copied().collect();
+                assert_eq!(collected.into_std(), expected);
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "called `Option2::unwrap()` on a `None` value")]
+    fn test_unwrap_none_panics() {
+        Option2::<i32>::None.unwrap();
+    }
+
+    #[test]
+    fn test_question_mark_through_std() {
+        fn first_even(values: &[i32]) -> Option<i32> {
+            let found: Option2<&i32> = values.iter().find(|x| *x % 2 == 0).into();
+            let value = found.copied().into_std()?;
+            Some(value * 10)
+        }
+        assert_eq!(first_even(&[1, 4, 6]), Some(40));
+        assert_eq!(first_even(&[1, 3]), None);
+    }
+}
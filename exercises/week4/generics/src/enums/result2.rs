@@ -0,0 +1,424 @@
+// Result2<T, E>: a value or the error that stopped it
+//
+// Ok is declared first, so the derived Ord puts every Ok before any Err,
+// as std does.
+
+use std::fmt::Debug;
+
+use super::option2::IntoIter;
+use super::Option2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Result2<T, E> {
+    Ok(T),
+    Err(E),
+}
+
+impl<T, E> Result2<T, E> {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Result2::Ok(_))
+    }
+
+    pub fn is_err(&self) -> bool {
+        !self.is_ok()
+    }
+
+    pub fn is_ok_and(self, f: impl FnOnce(T) -> bool) -> bool {
+        match self {
+            Result2::Ok(value) => f(value),
+            Result2::Err(_) => false,
+        }
+    }
+
+    pub fn is_err_and(self, f: impl FnOnce(E) -> bool) -> bool {
+        match self {
+            Result2::Ok(_) => false,
+            Result2::Err(err) => f(err),
+        }
+    }
+
+    pub fn ok(self) -> Option2<T> {
+        match self {
+            Result2::Ok(value) => Option2::Some(value),
+            Result2::Err(_) => Option2::None,
+        }
+    }
+
+    pub fn err(self) -> Option2<E> {
+        match self {
+            Result2::Ok(_) => Option2::None,
+            Result2::Err(err) => Option2::Some(err),
+        }
+    }
+
+    pub fn as_ref(&self) -> Result2<&T, &E> {
+        match self {
+            Result2::Ok(value) => Result2::Ok(value),
+            Result2::Err(err) => Result2::Err(err),
+        }
+    }
+
+    pub fn as_mut(&mut self) -> Result2<&mut T, &mut E> {
+        match self {
+            Result2::Ok(value) => Result2::Ok(value),
+            Result2::Err(err) => Result2::Err(err),
+        }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Result2<U, E> {
+        match self {
+            Result2::Ok(value) => Result2::Ok(f(value)),
+            Result2::Err(err) => Result2::Err(err),
+        }
+    }
+
+    pub fn map_err<F>(self, f: impl FnOnce(E) -> F) -> Result2<T, F> {
+        match self {
+            Result2::Ok(value) => Result2::Ok(value),
+            Result2::Err(err) => Result2::Err(f(err)),
+        }
+    }
+
+    pub fn map_or<U>(self, default: U, f: impl FnOnce(T) -> U) -> U {
+        match self {
+            Result2::Ok(value) => f(value),
+            Result2::Err(_) => default,
+        }
+    }
+
+    pub fn map_or_else<U>(self, default: impl FnOnce(E) -> U, f: impl FnOnce(T) -> U) -> U {
+        match self {
+            Result2::Ok(value) => f(value),
+            Result2::Err(err) => default(err),
+        }
+    }
+
+    pub fn inspect(self, f: impl FnOnce(&T)) -> Self {
+        if let Result2::Ok(value) = &self {
+            f(value);
+        }
+        self
+    }
+
+    pub fn inspect_err(self, f: impl FnOnce(&E)) -> Self {
+        if let Result2::Err(err) = &self {
+            f(err);
+        }
+        self
+    }
+
+    pub fn and<U>(self, other: Result2<U, E>) -> Result2<U, E> {
+        match self {
+            Result2::Ok(_) => other,
+            Result2::Err(err) => Result2::Err(err),
+        }
+    }
+
+    pub fn and_then<U>(self, f: impl FnOnce(T) -> Result2<U, E>) -> Result2<U, E> {
+        match self {
+            Result2::Ok(value) => f(value),
+            Result2::Err(err) => Result2::Err(err),
+        }
+    }
+
+    pub fn or<F>(self, other: Result2<T, F>) -> Result2<T, F> {
+        match self {
+            Result2::Ok(value) => Result2::Ok(value),
+            Result2::Err(_) => other,
+        }
+    }
+
+    pub fn or_else<F>(self, f: impl FnOnce(E) -> Result2<T, F>) -> Result2<T, F> {
+        match self {
+            Result2::Ok(value) => Result2::Ok(value),
+            Result2::Err(err) => f(err),
+        }
+    }
+
+    pub fn expect(self, message: &str) -> T
+    where
+        E: Debug,
+    {
+        match self {
+            Result2::Ok(value) => value,
+            Result2::Err(err) => panic!("{}: {:?}", message, err),
+        }
+    }
+
+    pub fn unwrap(self) -> T
+    where
+        E: Debug,
+    {
+        self.expect("called `Result2::unwrap()` on an `Err` value")
+    }
+
+    pub fn expect_err(self, message: &str) -> E
+    where
+        T: Debug,
+    {
+        match self {
+            Result2::Ok(value) => panic!("{}: {:?}", message, value),
+            Result2::Err(err) => err,
+        }
+    }
+
+    pub fn unwrap_err(self) -> E
+    where
+        T: Debug,
+    {
+        self.expect_err("called `Result2::unwrap_err()` on an `Ok` value")
+    }
+
+    pub fn unwrap_or(self, default: T) -> T {
+        match self {
+            Result2::Ok(value) => value,
+            Result2::Err(_) => default,
+        }
+    }
+
+    pub fn unwrap_or_else(self, f: impl FnOnce(E) -> T) -> T {
+        match self {
+            Result2::Ok(value) => value,
+            Result2::Err(err) => f(err),
+        }
+    }
+
+    pub fn unwrap_or_default(self) -> T
+    where
+        T: Default,
+    {
+        self.unwrap_or_else(|_| T::default())
+    }
+
+    // The Ok value, if any
+    pub fn iter(&self) -> IntoIter<&T> {
+        self.as_ref().ok().into_iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IntoIter<&mut T> {
+        self.as_mut().ok().into_iter()
+    }
+
+    pub fn into_std(self) -> Result<T, E> {
+        self.into()
+    }
+}
+
+impl<T, E> Result2<Result2<T, E>, E> {
+    // Still unstable on std's Result; the same as and_then with identity
+    pub fn flatten(self) -> Result2<T, E> {
+        self.and_then(|inner| inner)
+    }
+}
+
+impl<T, E> Result2<Option2<T>, E> {
+    // Ok(None) becomes None; Err(e) becomes Some(Err(e))
+    pub fn transpose(self) -> Option2<Result2<T, E>> {
+        match self {
+            Result2::Ok(Option2::Some(value)) => Option2::Some(Result2::Ok(value)),
+            Result2::Ok(Option2::None) => Option2::None,
+            Result2::Err(err) => Option2::Some(Result2::Err(err)),
+        }
+    }
+}
+
+impl<T: Copy, E> Result2<&T, E> {
+    pub fn copied(self) -> Result2<T, E> {
+        self.map(|value| *value)
+    }
+}
+
+impl<T: Clone, E> Result2<&T, E> {
+    pub fn cloned(self) -> Result2<T, E> {
+        self.map(T::clone)
+    }
+}
+
+impl<T, E> From<Result<T, E>> for Result2<T, E> {
+    fn from(result: Result<T, E>) -> Self {
+        match result {
+            Ok(value) => Result2::Ok(value),
+            Err(err) => Result2::Err(err),
+        }
+    }
+}
+
+impl<T, E> From<Result2<T, E>> for Result<T, E> {
+    fn from(result: Result2<T, E>) -> Self {
+        match result {
+            Result2::Ok(value) => Ok(value),
+            Result2::Err(err) => Err(err),
+        }
+    }
+}
+
+// Collecting stops at the first Err, which becomes the result
+impl<A, E, V: FromIterator<A>> FromIterator<Result2<A, E>> for Result2<V, E> {
+    fn from_iter<I: IntoIterator<Item = Result2<A, E>>>(iter: I) -> Self {
+        let mut error = None;
+        let collected = iter
+            .into_iter()
+            .map_while(|item| match item {
+                Result2::Ok(value) => Some(value),
+                Result2::Err(err) => {
+                    error = Some(err);
+                    None
+                }
+            })
+            .collect();
+        match error {
+            Some(err) => Result2::Err(err),
+            None => Result2::Ok(collected),
+        }
+    }
+}
+
+impl<T, E> IntoIterator for Result2<T, E> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        self.ok().into_iter()
+    }
+}
+
+impl<'a, T, E> IntoIterator for &'a Result2<T, E> {
+    type Item = &'a T;
+    type IntoIter = IntoIter<&'a T>;
+
+    fn into_iter(self) -> IntoIter<&'a T> {
+        self.iter()
+    }
+}
+
+impl<'a, T, E> IntoIterator for &'a mut Result2<T, E> {
+    type Item = &'a mut T;
+    type IntoIter = IntoIter<&'a mut T>;
+
+    fn into_iter(self) -> IntoIter<&'a mut T> {
+        self.iter_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use property::{quickcheck, Arbitrary, Gen};
+
+    // A std Result generated from the parts property knows how to make
+    #[derive(Debug, Clone)]
+    struct AnyResult(Result<i32, u8>);
+
+    impl Arbitrary for AnyResult {
+        fn arbitrary(g: &mut Gen) -> Self {
+            AnyResult(if g.ratio(2, 3) {
+                Ok(i32::arbitrary(g))
+            } else {
+                Err(u8::arbitrary(g))
+            })
+        }
+    }
+
+    #[test]
+    fn test_matches_std_combinators() {
+        quickcheck(
+            |(AnyResult(a), AnyResult(b), k): (AnyResult, AnyResult, i32)| {
+                let (a2, b2) = (Result2::from(a), Result2::from(b));
+                let half = |x: i32| if x % 2 == 0 { Ok(x / 2) } else { Err(x as u8) };
+                let half2 = |x: i32| Result2::from(half(x));
+                let recover = |e: u8| if e < 128 { Ok(e as i32) } else { Err(e as i64) };
+                let recover2 = |e: u8| Result2::from(recover(e));
+
+                assert_eq!(a2.is_ok(), a.is_ok());
+                assert_eq!(a2.is_err(), a.is_err());
+                assert_eq!(a2.is_ok_and(|x| x > k), a.is_ok_and(|x| x > k));
+                assert_eq!(a2.is_err_and(|e| e > 100), a.is_err_and(|e| e > 100));
+                assert_eq!(a2.ok().into_std(), a.ok());
+                assert_eq!(a2.err().into_std(), a.err());
+                assert_eq!(a2.map(|x| x ^ k).into_std(), a.map(|x| x ^ k));
+                assert_eq!(
+                    a2.map_err(|e| (e as i32).wrapping_sub(k)).into_std(),
+                    a.map_err(|e| (e as i32).wrapping_sub(k))
+                );
+                assert_eq!(a2.map_or(k, |x| x ^ k), a.map_or(k, |x| x ^ k));
+                assert_eq!(
+                    a2.map_or_else(|e| e as i32, |x| x ^ k),
+                    a.map_or_else(|e| e as i32, |x| x ^ k)
+                );
+                assert_eq!(a2.and(b2).into_std(), a.and(b));
+                assert_eq!(a2.and_then(half2).into_std(), a.and_then(half));
+                assert_eq!(a2.or(b2).into_std(), a.or(b));
+                assert_eq!(a2.or_else(recover2).into_std(), a.or_else(recover));
+                assert_eq!(a2.unwrap_or(k), a.unwrap_or(k));
+                assert_eq!(
+                    a2.unwrap_or_else(|e| e as i32),
+                    a.unwrap_or_else(|e| e as i32)
+                );
+                assert_eq!(a2.unwrap_or_default(), a.unwrap_or_default());
+                assert_eq!(a2.iter().collect::<Vec<_>>(), a.iter().collect::<Vec<_>>());
+                let by_ref: Result2<&i32, u8> = a2.as_ref().map_err(|e| *e);
+                assert_eq!(by_ref.copied().into_std(), a);
+                assert_eq!(by_ref.cloned().into_std(), a);
+                assert_eq!(a2.cmp(&b2), a.cmp(&b));
+
+                let mut a2 = a2;
+                let mut a = a;
+                for x in &mut a2 {
+                    *x = x.wrapping_add(k);
+                }
+                if let Result2::Err(e) = a2.as_mut() {
+                    *e = e.wrapping_mul(3);
+                }
+                a = a.map(|x| x.wrapping_add(k)).map_err(|e| e.wrapping_mul(3));
+                assert_eq!(a2.into_std(), a);
+            },
+        );
+    }
+
+    #[test]
+    fn test_matches_std_nesting_and_collect() {
+        quickcheck(|(a, items): (Option<i32>, Vec<(bool, i32)>)| {
+            // Err for odd numbers, so some lists fail part way through
+            let parse = |x: i32| if x % 2 == 0 { Ok(x) } else { Err(x) };
+            let nested: Result<Option<i32>, i32> = a.map_or(Ok(None), |x| parse(x).map(Some));
+            let nested2: Result2<Option2<i32>, i32> = Result2::from(nested).map(Option2::from);
+            let transposed = nested2.transpose().map(Result2::into_std).into_std();
+            assert_eq!(transposed, nested.transpose());
+
+            let wrapped: Result2<Result2<i32, i32>, i32> = match a {
+                Some(x) => Result2::Ok(parse(x).into()),
+                None => Result2::Err(-1),
+            };
+            let expected = a.ok_or(-1).and_then(parse);
+            assert_eq!(wrapped.flatten().into_std(), expected);
+
+            let values = items
+                .iter()
+                .map(|&(odd, x)| if odd { x | 1 } else { x & !1 });
+            let collected: Result2<Vec<i32>, i32> =
+                values.clone().map(|x| parse(x).into()).collect();
+            let expected: Result<Vec<i32>, i32> = values.map(parse).collect();
+            assert_eq!(collected.into_std(), expected);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "called `Result2::unwrap()` on an `Err` value: \"bad input\"")]
+    fn test_unwrap_err_value_panics() {
+        Result2::<i32, &str>::Err("bad input").unwrap();
+    }
+
+    #[test]
+    fn test_question_mark_through_std() {
+        fn total(inputs: &[&str]) -> Result<i32, std::num::ParseIntError> {
+            let mut sum = 0;
+            for input in inputs {
+                let parsed: Result2<i32, _> = input.parse().into();
+                sum += parsed.into_std()?;
+            }
+            Ok(sum)
+        }
+        assert_eq!(total(&["1", "2", "3"]), Ok(6));
+        assert!(total(&["1", "two"]).is_err());
+    }
+}
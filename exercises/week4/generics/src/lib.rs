@@ -0,0 +1,3 @@
+// Library root file - exposes modules for integration testing
+pub mod collections;
+pub mod enums;
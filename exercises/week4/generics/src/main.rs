@@ -4,6 +4,9 @@
 use std::fmt::Display;
 use std::cmp::PartialOrd;
 
+use generics::collections::{BTree, IndexedHeap, MinStack, RingDeque, SkipList};
+use generics::enums::{Option2, Result2};
+
 // TODO 1: Generic functions
 fn largest<T: PartialOrd>(list: &[T]) -> &T {
     let mut largest = &list[0];
@@ -68,34 +71,8 @@ impl<T, U> MixedPoint<T, U> {
 }
 
 // TODO 4: Generic enums
-enum Option2<T> {
-    Some(T),
-    None,
-}
-
-enum Result2<T, E> {
-    Ok(T),
-    Err(E),
-}
-
-impl<T> Option2<T> {
-    fn unwrap_or(self, default: T) -> T {
-        match self {
-            Option2::Some(value) => value,
-            Option2::None => default,
-        }
-    }
-    
-    fn map<U, F>(self, f: F) -> Option2<U>
-    where
-        F: FnOnce(T) -> U,
-    {
-        match self {
-            Option2::Some(value) => Option2::Some(f(value)),
-            Option2::None => Option2::None,
-        }
-    }
-}
+// Option2<T> and Result2<T, E> live in the library (src/enums.rs), with the
+// full set of combinators that std's Option and Result have
 
 // TODO 5: Bounded generics
 fn print_and_compare<T: Display + PartialOrd>(x: T, y: T) {
@@ -332,6 +309,43 @@ impl Distance<Miles> {
     }
 }
 
+// Tour of the collections in the library (src/collections.rs)
+fn collections_examples() {
+    println!("\n--- Collections Beyond Stack ---");
+    
+    let mut deque: RingDeque<i32> = (1..=3).collect();
+    deque.push_front(0);
+    deque.push_back(4);
+    println!("RingDeque: {:?} (front {:?}, back {:?})", deque, deque.front(), deque.back());
+    
+    let mut min_stack: MinStack<i32> = [5, 2, 8, 1].into_iter().collect();
+    println!("MinStack: {:?}, min = {:?}", min_stack, min_stack.min());
+    min_stack.pop();
+    println!("After pop: min = {:?}", min_stack.min());
+    
+    // Shortest distances found so far, lowered as better paths turn up
+    let mut heap = IndexedHeap::new();
+    let far = heap.push((9, "C"));
+    heap.push((4, "B"));
+    heap.decrease_key(far, (3, "C")).unwrap();
+    print!("IndexedHeap pops:");
+    while let Some((distance, node)) = heap.pop() {
+        print!(" {}={}", node, distance);
+    }
+    println!();
+    
+    let primes: SkipList<u32> = [13, 2, 7, 3, 11, 5, 2].into_iter().collect();
+    println!("SkipList: {:?}", primes);
+    println!("Primes in 4..12: {:?}", primes.range(4..12).collect::<Vec<_>>());
+    
+    let mut ages: BTree<&str, u32> = BTree::new();
+    ages.extend([("carol", 41), ("alice", 30), ("dave", 25), ("bob", 35)]);
+    ages.insert("alice", 31);
+    println!("BTree: {:?}", ages);
+    let b_to_c: Vec<_> = ages.range("b".."d").collect();
+    println!("Names from b to d: {:?}", b_to_c);
+}
+
 fn main() {
     println!("=== Generics in Rust ===\n");
     
@@ -379,6 +393,14 @@ fn main() {
     let doubled = Option2::Some(5).map(|x| x * 2);
     println!("Doubled: {}", doubled.unwrap_or(0));
     
+    let parsed: Result2<i32, _> = "42".parse().into();
+    let halved = parsed
+        .map_err(|e| e.to_string())
+        .and_then(|n| Option2::Some(n).filter(|n| n % 2 == 0).ok_or("odd".to_string()))
+        .map(|n| n / 2);
+    println!("Parsed and halved: {:?}", halved);
+    println!("Zipped: {:?}", some_number.zip(Option2::Some('x')).into_std());
+    
     // TODO 20: Bounded generics
     println!("\n--- Bounded Generics ---");
     print_and_compare(10, 20);
@@ -401,6 +423,8 @@ fn main() {
         println!("Popped: {}", item);
     }
     
+    collections_examples();
+    
     // TODO 22: String stack using type alias
     println!("\n--- Type Aliases ---");
     let mut string_stack: StringStack = Stack::new();
@@ -0,0 +1,635 @@
+// Combinators: functions that take closures and return new ones
+//
+// - compose(f, g) is f(g(x)); pipe(f, g) is g(f(x)), reading left to right
+// - curry turns a two-argument function into a chain of one-argument ones,
+//   the general version of create_adder in main.rs
+// - memoize caches results in a HashMap; memoize_lru bounds the cache with
+//   the LRU Cache from the cache crate; memoize_recursive lets the function
+//   call its own memoized self, which is what makes fib(90) instant
+// - memoize_sync is shared between threads by reference and runs the
+//   function once per argument, even when threads ask for it at the same time
+// - retry calls a fallible operation until it succeeds, sleeping between
+//   attempts as a Backoff says
+//
+// Memoized functions must be pure: a cached result is returned without
+// calling the function again.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+
+use cache::Cache;
+
+// f after g
+pub fn compose<A, B, C, F, G>(f: F, g: G) -> impl Fn(A) -> C
+where
+    F: Fn(B) -> C,
+    G: Fn(A) -> B,
+{
+    move |x| f(g(x))
+}
+
+// f, then g
+pub fn pipe<A, B, C, F, G>(f: F, g: G) -> impl Fn(A) -> C
+where
+    F: Fn(A) -> B,
+    G: Fn(B) -> C,
+{
+    move |x| g(f(x))
+}
+
+// curry(f)(a)(b) == f(a, b). The inner function is boxed because a closure
+// can't return `impl Fn`, and it shares f with the outer one through an Rc
+pub fn curry<A, B, C, F>(f: F) -> impl Fn(A) -> Box<dyn Fn(B) -> C>
+where
+    A: Clone + 'static,
+    F: Fn(A, B) -> C + 'static,
+{
+    let f = Rc::new(f);
+    move |a| {
+        let f = Rc::clone(&f);
+        Box::new(move |b| f(a.clone(), b))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+enum Store<A, R> {
+    Unbounded(HashMap<A, R>),
+    Lru(Cache<A, R>),
+}
+
+impl<A: Hash + Eq + Clone, R: Clone> Store<A, R> {
+    fn get(&mut self, arg: &A) -> Option<R> {
+        match self {
+            Store::Unbounded(map) => map.get(arg).cloned(),
+            Store::Lru(cache) => cache.get(arg).cloned(),
+        }
+    }
+
+    fn insert(&mut self, arg: A, result: R) {
+        match self {
+            Store::Unbounded(map) => {
+                map.insert(arg, result);
+            }
+            Store::Lru(cache) => {
+                cache.insert(arg, result);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Store::Unbounded(map) => map.len(),
+            Store::Lru(cache) => cache.len(),
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Store::Unbounded(map) => map.clear(),
+            Store::Lru(cache) => cache.clear(),
+        }
+    }
+}
+
+pub struct Memoized<A, R, F> {
+    f: F,
+    store: Store<A, R>,
+    stats: MemoStats,
+}
+
+pub fn memoize<A, R, F>(f: F) -> Memoized<A, R, F>
+where
+    A: Hash + Eq + Clone,
+    R: Clone,
+    F: FnMut(A) -> R,
+{
+    Memoized {
+        f,
+        store: Store::Unbounded(HashMap::new()),
+        stats: MemoStats::default(),
+    }
+}
+
+// Keeps only the `capacity` most recently used results. Panics if capacity
+// is 0
+pub fn memoize_lru<A, R, F>(capacity: usize, f: F) -> Memoized<A, R, F>
+where
+    A: Hash + Eq + Clone,
+    R: Clone,
+    F: FnMut(A) -> R,
+{
+    assert!(capacity > 0, "memoize_lru capacity must be at least 1");
+    Memoized {
+        f,
+        store: Store::Lru(Cache::new(capacity)),
+        stats: MemoStats::default(),
+    }
+}
+
+impl<A, R, F> Memoized<A, R, F>
+where
+    A: Hash + Eq + Clone,
+    R: Clone,
+    F: FnMut(A) -> R,
+{
+    pub fn call(&mut self, arg: A) -> R {
+        if let Some(result) = self.store.get(&arg) {
+            self.stats.hits += 1;
+            return result;
+        }
+        self.stats.misses += 1;
+        let result = (self.f)(arg.clone());
+        self.store.insert(arg, result.clone());
+        result
+    }
+
+    // As a plain closure, to pass where an FnMut is expected
+    pub fn as_fn_mut(&mut self) -> impl FnMut(A) -> R + '_ {
+        move |arg| self.call(arg)
+    }
+
+    // Number of cached results
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.store.clear();
+    }
+
+    pub fn stats(&self) -> MemoStats {
+        self.stats
+    }
+}
+
+impl<A, R, F> fmt::Debug for Memoized<A, R, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Memoized")
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+// For functions that recurse: f gets a handle to its memoized self as the
+// first argument, and calls through it are cached too
+//
+//     let mut fib = memoize_recursive(|fib, n: u64| if n < 2 { n } else { fib(n - 1) + fib(n - 2) });
+pub fn memoize_recursive<A, R, F>(f: F) -> MemoizedRecursive<A, R, F>
+where
+    A: Hash + Eq + Clone,
+    R: Clone,
+    F: Fn(&mut dyn FnMut(A) -> R, A) -> R,
+{
+    MemoizedRecursive {
+        f,
+        cache: HashMap::new(),
+        stats: MemoStats::default(),
+    }
+}
+
+pub struct MemoizedRecursive<A, R, F> {
+    f: F,
+    cache: HashMap<A, R>,
+    stats: MemoStats,
+}
+
+impl<A, R, F> MemoizedRecursive<A, R, F>
+where
+    A: Hash + Eq + Clone,
+    R: Clone,
+    F: Fn(&mut dyn FnMut(A) -> R, A) -> R,
+{
+    pub fn call(&mut self, arg: A) -> R {
+        // f is borrowed for the whole call while the cache keeps changing,
+        // so the two are passed separately
+        fn go<A, R, F>(f: &F, cache: &mut HashMap<A, R>, stats: &mut MemoStats, arg: A) -> R
+        where
+            A: Hash + Eq + Clone,
+            R: Clone,
+            F: Fn(&mut dyn FnMut(A) -> R, A) -> R,
+        {
+            if let Some(result) = cache.get(&arg) {
+                stats.hits += 1;
+                return result.clone();
+            }
+            stats.misses += 1;
+            let result = f(&mut |inner| go(f, cache, stats, inner), arg.clone());
+            cache.insert(arg, result.clone());
+            result
+        }
+        go(&self.f, &mut self.cache, &mut self.stats, arg)
+    }
+
+    pub fn stats(&self) -> MemoStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+}
+
+// Thread-safe memoization. Every argument gets a OnceLock: the first caller
+// runs f while later callers for the same argument wait for its result,
+// and callers for other arguments are not held up at all. (SyncCache's
+// get_or_load may run the loader twice on a race; this never does.)
+pub struct SyncMemoized<A, R, F> {
+    f: F,
+    cells: Mutex<HashMap<A, Arc<OnceLock<R>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+pub fn memoize_sync<A, R, F>(f: F) -> SyncMemoized<A, R, F>
+where
+    A: Hash + Eq + Clone,
+    R: Clone,
+    F: Fn(A) -> R,
+{
+    SyncMemoized {
+        f,
+        cells: Mutex::new(HashMap::new()),
+        hits: AtomicU64::new(0),
+        misses: AtomicU64::new(0),
+    }
+}
+
+impl<A, R, F> SyncMemoized<A, R, F>
+where
+    A: Hash + Eq + Clone,
+    R: Clone,
+    F: Fn(A) -> R,
+{
+    pub fn call(&self, arg: A) -> R {
+        let cell = {
+            let mut cells = self.cells.lock().unwrap_or_else(|e| e.into_inner());
+            Arc::clone(cells.entry(arg.clone()).or_default())
+        };
+        let mut computed = false;
+        let result = cell.get_or_init(|| {
+            computed = true;
+            (self.f)(arg)
+        });
+        let counter = if computed { &self.misses } else { &self.hits };
+        counter.fetch_add(1, Ordering::Relaxed);
+        result.clone()
+    }
+
+    pub fn stats(&self) -> MemoStats {
+        MemoStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        let cells = self.cells.lock().unwrap_or_else(|e| e.into_inner());
+        cells.values().filter(|cell| cell.get().is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<A, R, F> fmt::Debug for SyncMemoized<A, R, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SyncMemoized")
+            .field("hits", &self.hits.load(Ordering::Relaxed))
+            .field("misses", &self.misses.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+// How long to wait between attempts: initial, initial * factor,
+// initial * factor^2, ... capped at max_delay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial: Duration,
+    factor: u32,
+    max_delay: Duration,
+    max_attempts: u32,
+}
+
+impl Backoff {
+    // Doubling delays, at most 5 attempts
+    pub fn exponential(initial: Duration) -> Self {
+        Backoff {
+            initial,
+            factor: 2,
+            max_delay: Duration::from_secs(60),
+            max_attempts: 5,
+        }
+    }
+
+    pub fn fixed(delay: Duration) -> Self {
+        Backoff::exponential(delay).factor(1)
+    }
+
+    pub fn factor(mut self, factor: u32) -> Self {
+        self.factor = factor.max(1);
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    // Including the first one, so 1 means no retries
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    // The wait after the given failed attempt (starting at 0)
+    pub fn delay(&self, failed_attempt: u32) -> Duration {
+        self.factor
+            .checked_pow(failed_attempt)
+            .and_then(|scale| self.initial.checked_mul(scale))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+
+    // Every wait a retry loop can make, in order
+    pub fn delays(&self) -> impl Iterator<Item = Duration> + '_ {
+        (0..self.max_attempts - 1).map(move |attempt| self.delay(attempt))
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::exponential(Duration::from_millis(100))
+    }
+}
+
+// Every attempt failed; holds the last error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryError<E> {
+    pub attempts: u32,
+    pub last_error: E,
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "gave up after {} attempts: {}",
+            self.attempts, self.last_error
+        )
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for RetryError<E> {}
+
+// Calls op with the attempt number (starting at 0) until it returns Ok or
+// the backoff runs out of attempts, sleeping in between
+pub fn retry<T, E, F>(backoff: &Backoff, op: F) -> Result<T, RetryError<E>>
+where
+    F: FnMut(u32) -> Result<T, E>,
+{
+    retry_with_sleep(backoff, thread::sleep, op)
+}
+
+// retry with a custom sleep, e.g. one that only records the delays in tests
+pub fn retry_with_sleep<T, E, F, S>(
+    backoff: &Backoff,
+    mut sleep: S,
+    mut op: F,
+) -> Result<T, RetryError<E>>
+where
+    F: FnMut(u32) -> Result<T, E>,
+    S: FnMut(Duration),
+{
+    let mut attempt = 0;
+    loop {
+        match op(attempt) {
+            Ok(value) => return Ok(value),
+            Err(last_error) if attempt + 1 >= backoff.max_attempts => {
+                return Err(RetryError {
+                    attempts: attempt + 1,
+                    last_error,
+                });
+            }
+            Err(_) => {
+                sleep(backoff.delay(attempt));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::sync::Barrier;
+
+    #[test]
+    fn test_compose_pipe_and_curry() {
+        let add_one = |x: i32| x + 1;
+        let double = |x: i32| x * 2;
+        assert_eq!(compose(add_one, double)(5), 11);
+        assert_eq!(pipe(add_one, double)(5), 12);
+
+        let describe = pipe(pipe(add_one, double), |x: i32| format!("<{}>", x));
+        assert_eq!(describe(1), "<4>");
+
+        let add = curry(|a: i32, b: i32| a + b);
+        let add_five = add(5);
+        assert_eq!(add_five(3), 8);
+        assert_eq!(add(10)(-10), 0);
+        let greet = curry(|greeting: String, name: &str| format!("{}, {}!", greeting, name));
+        let hello = greet("Hello".to_string());
+        assert_eq!(hello("Rust"), "Hello, Rust!");
+        assert_eq!(hello("world"), "Hello, world!");
+    }
+
+    #[test]
+    fn test_memoize_calls_once_per_argument() {
+        let calls = Cell::new(0);
+        let mut square = memoize(|x: u64| {
+            calls.set(calls.get() + 1);
+            x * x
+        });
+        for x in [3, 4, 3, 3, 4, 5] {
+            assert_eq!(square.call(x), x * x);
+        }
+        assert_eq!(calls.get(), 3);
+        assert_eq!(square.stats(), MemoStats { hits: 3, misses: 3 });
+        assert_eq!(square.len(), 3);
+
+        square.clear();
+        square.call(3);
+        assert_eq!(calls.get(), 4);
+
+        let lengths: Vec<usize> = ["a", "bb", "a"]
+            .iter()
+            .map(|s| s.to_string())
+            .map(memoize(|s: String| s.len()).as_fn_mut())
+            .collect();
+        assert_eq!(lengths, vec![1, 2, 1]);
+    }
+
+    #[test]
+    fn test_memoize_lru_evicts_least_recently_used() {
+        let calls = Cell::new(0);
+        let mut negate = memoize_lru(2, |x: i32| {
+            calls.set(calls.get() + 1);
+            -x
+        });
+        negate.call(1);
+        negate.call(2);
+        negate.call(1); // hit, so 2 is now the least recently used
+        negate.call(3); // evicts 2
+        assert_eq!(negate.len(), 2);
+        assert_eq!(calls.get(), 3);
+
+        negate.call(1);
+        assert_eq!(calls.get(), 3);
+        negate.call(2);
+        assert_eq!(calls.get(), 4);
+        assert_eq!(negate.stats(), MemoStats { hits: 2, misses: 4 });
+    }
+
+    #[test]
+    #[should_panic(expected = "memoize_lru capacity must be at least 1")]
+    fn test_memoize_lru_rejects_zero_capacity() {
+        memoize_lru(0, |x: i32| x);
+    }
+    #[test]
+    fn test_memoize_recursive_fibonacci() {
+        let calls = Cell::new(0u64);
+        let mut fib = memoize_recursive(|fib, n: u64| {
+            calls.set(calls.get() + 1);
+            if n < 2 {
+                n
+            } else {
+                fib(n - 1) + fib(n - 2)
+            }
+        });
+        // Without memoization this would take about 10^18 calls
+        assert_eq!(fib.call(90), 2_880_067_194_370_816_120);
+        assert_eq!(calls.get(), 91);
+        assert_eq!(fib.len(), 91);
+
+        fib.call(50);
+        assert_eq!(calls.get(), 91);
+    }
+
+    #[test]
+    fn test_memoize_sync_runs_once_under_contention() {
+        let calls = AtomicU64::new(0);
+        let slow_square = memoize_sync(|x: u64| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            x * x
+        });
+        let barrier = Barrier::new(8);
+        thread::scope(|s| {
+            for t in 0..8u64 {
+                let (slow_square, barrier) = (&slow_square, &barrier);
+                s.spawn(move || {
+                    barrier.wait();
+                    assert_eq!(slow_square.call(7), 49);
+                    assert_eq!(slow_square.call(t % 2), (t % 2) * (t % 2));
+                });
+            }
+        });
+        // 7, 0 and 1, each computed by exactly one thread
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            slow_square.stats(),
+            MemoStats {
+                hits: 13,
+                misses: 3
+            }
+        );
+        assert_eq!(slow_square.len(), 3);
+    }
+
+    #[test]
+    fn test_retry_backs_off_then_gives_up() {
+        let backoff = Backoff::exponential(Duration::from_millis(10))
+            .max_delay(Duration::from_millis(50))
+            .max_attempts(5);
+        let delays: Vec<u64> = backoff.delays().map(|d| d.as_millis() as u64).collect();
+        assert_eq!(delays, vec![10, 20, 40, 50]);
+
+        // Succeeds on the third attempt
+        let mut slept = Vec::new();
+        let result = retry_with_sleep(
+            &backoff,
+            |d| slept.push(d),
+            |attempt| {
+                if attempt < 2 {
+                    Err(format!("attempt {} failed", attempt))
+                } else {
+                    Ok(attempt)
+                }
+            },
+        );
+        assert_eq!(result, Ok(2));
+        assert_eq!(
+            slept,
+            vec![Duration::from_millis(10), Duration::from_millis(20)]
+        );
+
+        let calls = Cell::new(0);
+        let result: Result<(), _> = retry_with_sleep(
+            &backoff,
+            |_| {},
+            |_| {
+                calls.set(calls.get() + 1);
+                Err("down")
+            },
+        );
+        let error = result.unwrap_err();
+        assert_eq!(calls.get(), 5);
+        assert_eq!(error.to_string(), "gave up after 5 attempts: down");
+
+        assert_eq!(
+            Backoff::fixed(Duration::from_secs(1)).delay(30),
+            Duration::from_secs(1)
+        );
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_retry_sleeps_for_real() {
+        let backoff = Backoff::fixed(Duration::from_millis(5)).max_attempts(3);
+        let start = std::time::Instant::now();
+        let result: Result<u32, RetryError<&str>> = retry(&backoff, |attempt| {
+            if attempt == 2 {
+                Ok(attempt)
+            } else {
+                Err("busy")
+            }
+        });
+        assert_eq!(result, Ok(2));
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+}
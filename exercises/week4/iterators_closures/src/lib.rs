@@ -0,0 +1,4 @@
+// Library root file - exposes modules for integration testing
+pub mod combinators;
+pub mod matrix;
+pub mod parallel;
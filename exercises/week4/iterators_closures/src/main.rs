@@ -1,7 +1,18 @@
 // Week 4: Iterators and Closures
 // Learn about functional programming features in Rust
 
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use bench::Suite;
+use iter_ext::IterExt;
+use iterators_closures::combinators::{
+    compose, curry, memoize, memoize_lru, memoize_recursive, memoize_sync, pipe, retry, Backoff,
+};
+use iterators_closures::matrix::{Matrix, DEFAULT_BLOCK};
+use iterators_closures::parallel::{available_threads, default_chunk_size, ParallelSlice};
 
 // TODO 1: Basic closures
 fn basic_closures() {
@@ -98,6 +109,64 @@ fn create_counter() -> impl FnMut() -> i32 {
     }
 }
 
+// The same ideas made reusable (src/combinators.rs): build functions out of
+// functions, and cache or retry them
+fn combinator_examples() {
+    println!("\n--- Combinators ---");
+    
+    let add_one = |x: i32| x + 1;
+    let square = |x: i32| x * x;
+    println!("compose(add_one, square)(3) = {}", compose(add_one, square)(3));
+    println!("pipe(add_one, square)(3) = {}", pipe(add_one, square)(3));
+    
+    // create_adder is curry applied to addition
+    let add = curry(|a: i32, b: i32| a + b);
+    let add_five = add(5);
+    println!("curry(add)(5)(3) = {}", add_five(3));
+    
+    let calls = Cell::new(0);
+    let mut slow_len = memoize(|word: String| {
+        calls.set(calls.get() + 1);
+        word.chars().count()
+    });
+    for word in ["closure", "iterator", "closure", "closure"] {
+        slow_len.call(word.to_string());
+    }
+    println!("4 lookups, {} calls, stats {:?}", calls.get(), slow_len.stats());
+    
+    let mut recent = memoize_lru(2, |x: u64| x.pow(3));
+    for x in [1, 2, 1, 3, 2] {
+        recent.call(x);
+    }
+    println!("LRU-bounded memo holds {} results, stats {:?}", recent.len(), recent.stats());
+    
+    let mut fib = memoize_recursive(|fib, n: u64| if n < 2 { n } else { fib(n - 1) + fib(n - 2) });
+    println!("fib(80) = {} ({} distinct calls)", fib.call(80), fib.stats().misses);
+    
+    let shared = memoize_sync(|n: u64| (1..=n).product::<u64>());
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| shared.call(20));
+        }
+    });
+    println!("20! = {} computed once for 4 threads: {:?}", shared.call(20), shared.stats());
+    
+    let backoff = Backoff::exponential(Duration::from_millis(1)).max_attempts(4);
+    println!("Backoff delays: {:?}", backoff.delays().collect::<Vec<_>>());
+    let result = retry(&backoff, |attempt| {
+        if attempt < 2 {
+            Err(format!("attempt {} timed out", attempt))
+        } else {
+            Ok(format!("connected on attempt {}", attempt))
+        }
+    });
+    println!("Flaky operation: {:?}", result);
+    let result: Result<(), _> = retry(&backoff, |_| Err("service unavailable"));
+    if let Err(e) = result {
+        println!("Always failing: {}", e);
+    }
+}
+
 // TODO 5: Basic iterator usage
 fn basic_iterators() {
     println!("\n--- Basic Iterators ---");
@@ -227,57 +296,108 @@ impl Iterator for Counter {
     }
 }
 
-// TODO 9: Iterator for custom data structure
-struct Matrix {
-    data: Vec<Vec<i32>>,
+// Counter composes with the adapters from the iter_ext crate like any other
+// iterator: they come from an extension trait implemented for every Iterator
+fn iterator_toolkit_examples() {
+    println!("\n--- Iterator Toolkit (iter_ext) ---");
+    
+    let chunks: Vec<Vec<usize>> = Counter::new(10).chunks(4).collect();
+    println!("Chunks of 4: {:?}", chunks);
+    
+    let moving_sums: Vec<usize> = Counter::new(6).windows(3).map(|w| w.iter().sum()).collect();
+    println!("Moving sums over windows of 3: {:?}", moving_sums);
+    
+    let mixed: Vec<usize> = Counter::new(3).interleave(Counter::new(6).map(|x| x * 100)).collect();
+    println!("Interleaved: {:?}", mixed);
+    
+    let evens = Counter::new(10).filter(|x| x % 2 == 0);
+    let squares = Counter::new(4).map(|x| x * x);
+    let merged: Vec<usize> = evens.merge_sorted(squares).collect();
+    println!("Evens merged with squares: {:?}", merged);
+    
+    // Every closure has its own type, so the sources come from one closure
+    let multiples = [3, 5, 7].into_iter().map(|n| Counter::new(5).map(move |x| x * n));
+    let all: Vec<usize> = multiples.kmerge().dedup().collect();
+    println!("Multiples of 3, 5 and 7, merged without repeats: {:?}", all);
+    
+    let readings = [20, 21, 21, 25, 26, 26, 26, 19, 19];
+    let runs: Vec<(bool, Vec<i32>)> = readings.iter().copied().group_by_key(|&t| t >= 25).collect();
+    println!("Runs above/below 25: {:?}", runs);
+    let changes: Vec<i32> = readings.iter().copied().dedup_by(|a, b| (a - b).abs() < 2).collect();
+    println!("Readings that moved by 2 or more: {:?}", changes);
+    
+    let grid: Vec<(usize, char)> = Counter::new(2).cartesian_product("xyz".chars()).collect();
+    println!("Cartesian product: {:?}", grid);
+    
+    // The adapters report how much is left, so collect can allocate once
+    let pairs = (0..3).cartesian_product(0..4);
+    println!("Pairs size_hint: {:?}", pairs.size_hint());
 }
 
-impl Matrix {
-    fn new(rows: usize, cols: usize) -> Self {
-        let mut data = Vec::new();
-        for i in 0..rows {
-            let mut row = Vec::new();
-            for j in 0..cols {
-                row.push((i * cols + j) as i32);
-            }
-            data.push(row);
-        }
-        Matrix { data }
+// TODO 9: Iterator for custom data structure
+// Matrix lives in the library (src/matrix.rs): contiguous storage with row,
+// column and element iterators, operators and linear algebra
+fn matrix_examples() {
+    println!("\n--- Matrix ---");
+    
+    let m = Matrix::sequential(2, 3);
+    print!("{}", m);
+    let row_sums: Vec<i32> = m.row_iter().map(|row| row.iter().sum()).collect();
+    let column_sums: Vec<i32> = m.column_iter().map(|column| column.sum()).collect();
+    println!("Row sums: {:?}, column sums: {:?}", row_sums, column_sums);
+    let largest = m.indexed_iter().max_by_key(|&(_, &value)| value);
+    println!("Largest element at: {:?}", largest);
+    
+    // Operators take references so the operands can be reused
+    let t = m.transpose();
+    println!("m * m^T:\n{}", &m * &t);
+    println!("m^T * m + I:\n{}", &(&t * &m) + &Matrix::identity(3));
+    match m.checked_mul(&m) {
+        Ok(product) => println!("m * m:\n{}", product),
+        Err(e) => println!("m * m: {}", e),
     }
     
-    fn iter(&self) -> MatrixIterator {
-        MatrixIterator {
-            matrix: self,
-            row: 0,
-            col: 0,
-        }
+    // Linear algebra on floats
+    let a = Matrix::from_rows(vec![
+        vec![2.0, 1.0, 1.0],
+        vec![4.0, -6.0, 0.0],
+        vec![-2.0, 7.0, 2.0],
+    ])
+    .unwrap();
+    let lu = a.lu().unwrap();
+    println!("det(A) = {:.2}", lu.determinant());
+    println!("A = P^T L U with L:\n{:.3}U:\n{:.3}", lu.lower(), lu.upper());
+    match lu.solve(&[5.0, -2.0, 9.0]) {
+        Ok(x) => println!("A x = [5, -2, 9] gives x = {:?}", x),
+        Err(e) => println!("Could not solve: {}", e),
     }
-}
-
-struct MatrixIterator<'a> {
-    matrix: &'a Matrix,
-    row: usize,
-    col: usize,
-}
-
-impl<'a> Iterator for MatrixIterator<'a> {
-    type Item = &'a i32;
-    
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.row >= self.matrix.data.len() {
-            return None;
-        }
-        
-        if self.col >= self.matrix.data[self.row].len() {
-            self.row += 1;
-            self.col = 0;
-            return self.next();
-        }
-        
-        let item = &self.matrix.data[self.row][self.col];
-        self.col += 1;
-        Some(item)
+    let inverse = a.inverse().unwrap();
+    println!("A^-1:\n{:.4}", inverse);
+    println!("A * A^-1 == I: {}", (&a * &inverse).approx_eq(&Matrix::identity(3), 1e-12));
+    
+    let singular = Matrix::from_rows(vec![vec![1.0, 2.0], vec![2.0, 4.0]]).unwrap();
+    match singular.inverse() {
+        Ok(inverse) => println!("Unexpected inverse:\n{}", inverse),
+        Err(e) => println!("Inverting [[1, 2], [2, 4]]: {}", e),
     }
+    
+    // Naive and cache-blocked multiply give the same result; the blocked
+    // one only ever walks rows, so it stays in cache as n grows
+    let n = 192;
+    let a = Matrix::from_fn(n, n, |r, c| ((r * 31 + c * 17) % 101) as f64 / 101.0);
+    let b = Matrix::from_fn(n, n, |r, c| ((r * 13 + c * 7) % 89) as f64 / 89.0);
+    println!("Results match: {}", a.mul_naive(&b).approx_eq(&a.mul_blocked(&b, DEFAULT_BLOCK), 1e-9));
+    
+    let mut suite = Suite::new().samples(10);
+    suite
+        .bench("naive multiply (192x192)", || a.mul_naive(&b))
+        .bench("blocked multiply (192x192, 32)", || a.mul_blocked(&b, 32))
+        .bench("blocked multiply (192x192, 64)", || a.mul_blocked(&b, 64))
+        .bench("transpose (192x192)", || a.transpose());
+    let report = suite.run();
+    println!("{}", report);
+    
+    report.compare_with_saved("matrix");
 }
 
 // TODO 10: Functional programming style
@@ -328,33 +448,112 @@ fn functional_programming_examples() {
 }
 
 // TODO 11: Performance comparison
+// Each style is benchmarked on the same data. The sums are i64: the
+// squares of the even numbers below a million overflow an i32.
 fn performance_comparison() {
     println!("\n--- Performance Comparison ---");
     
-    let large_vec: Vec<i32> = (0..1000000).collect();
+    let large_vec: Vec<i64> = (0..1000000).collect();
     
     // Imperative style
-    let start = std::time::Instant::now();
-    let mut sum = 0;
-    for &item in &large_vec {
-        if item % 2 == 0 {
-            sum += item * item;
+    let imperative = || {
+        let mut sum = 0;
+        for &item in &large_vec {
+            if item % 2 == 0 {
+                sum += item * item;
+            }
         }
-    }
-    let imperative_time = start.elapsed();
-    println!("Imperative sum: {} (time: {:?})", sum, imperative_time);
+        sum
+    };
     
     // Functional style
-    let start = std::time::Instant::now();
-    let functional_sum: i32 = large_vec
-        .iter()
-        .filter(|&&x| x % 2 == 0)
-        .map(|&x| x * x)
-        .sum();
-    let functional_time = start.elapsed();
-    println!("Functional sum: {} (time: {:?})", functional_sum, functional_time);
+    let functional = || -> i64 {
+        large_vec
+            .iter()
+            .filter(|&&x| x % 2 == 0)
+            .map(|&x| x * x)
+            .sum()
+    };
+    
+    // Same pipeline as a single fold
+    let folded = || {
+        large_vec
+            .iter()
+            .fold(0, |sum, &x| if x % 2 == 0 { sum + x * x } else { sum })
+    };
     
-    println!("Results match: {}", sum == functional_sum);
+    let sum = imperative();
+    println!("Imperative sum: {}", sum);
+    println!("Results match: {}", sum == functional() && sum == folded());
+    
+    let mut suite = Suite::new().samples(20);
+    suite
+        .bench("imperative loop", imperative)
+        .bench("filter/map/sum", functional)
+        .bench("fold", folded);
+    let report = suite.run();
+    println!("{}", report);
+    
+    report.compare_with_saved("iterators_closures");
+}
+
+// Parallel versions of the same pipelines (src/parallel.rs), spread over
+// scoped threads; the results match the sequential ones exactly
+fn parallel_examples() {
+    println!("\n--- Parallel Iterators ---");
+    
+    let threads = available_threads();
+    println!("Available parallelism: {} threads", threads);
+    
+    let numbers: Vec<i64> = (0..2_000_000).collect();
+    let chunk_sums = numbers.par_chunks(500_000).map(|chunk| chunk.iter().sum::<i64>());
+    println!("Chunk sums: {:?}", chunk_sums);
+    
+    let squares = numbers.par_map(|&x| x * x);
+    let evens = numbers.par_filter(|&x| x % 2 == 0);
+    let total = numbers.par_reduce(|a, b| a + b);
+    println!("par_map matches: {}", squares == numbers.iter().map(|&x| x * x).collect::<Vec<_>>());
+    println!("par_filter matches: {}", evens == numbers.iter().copied().filter(|&x| x % 2 == 0).collect::<Vec<_>>());
+    println!("par_reduce: {:?} (sequential: {})", total, numbers.iter().sum::<i64>());
+    
+    // Matrix multiply: every output row is computed exactly as mul_naive
+    // does, so even floating point results are bit-identical
+    let n = 256;
+    let a = Matrix::from_fn(n, n, |r, c| ((r * 31 + c * 17) % 101) as f64 / 101.0);
+    let b = Matrix::from_fn(n, n, |r, c| ((r * 13 + c * 7) % 89) as f64 / 89.0);
+    println!("par_mul == mul_naive: {}", a.par_mul(&b) == a.mul_naive(&b));
+    
+    // Collatz steps: uneven work per element
+    let collatz = |&start: &i64| {
+        let (mut x, mut steps) = (start + 1, 0u32);
+        while x != 1 {
+            x = if x % 2 == 0 { x / 2 } else { 3 * x + 1 };
+            steps += 1;
+        }
+        steps
+    };
+    let seeds = &numbers[..200_000];
+    
+    let mut suite = Suite::new().samples(10);
+    suite
+        .bench("collatz steps (map)", || seeds.iter().map(collatz).collect::<Vec<_>>())
+        .bench("collatz steps (par_map)", || seeds.par_map(collatz))
+        .bench("even squares (filter + sum)", || {
+            numbers.iter().filter(|&&x| x % 2 == 0).map(|&x| x * x).sum::<i64>()
+        })
+        .bench("even squares (par_chunks)", || {
+            numbers
+                .par_chunks(default_chunk_size(numbers.len(), threads))
+                .map(|chunk| chunk.iter().filter(|&&x| x % 2 == 0).map(|&x| x * x).sum::<i64>())
+                .into_iter()
+                .sum::<i64>()
+        })
+        .bench("multiply 256x256 (blocked)", || a.mul_blocked(&b, DEFAULT_BLOCK))
+        .bench("multiply 256x256 (par_mul)", || a.par_mul(&b));
+    let report = suite.run();
+    println!("{}", report);
+    
+    report.compare_with_saved("parallel");
 }
 
 // TODO 12: Complex closure examples
@@ -421,6 +620,8 @@ fn main() {
     println!("Count: {}", counter_closure());
     println!("Count: {}", counter_closure());
     
+    combinator_examples();
+    
     basic_iterators();
     iterator_adaptors();
     consuming_adaptors();
@@ -431,12 +632,16 @@ fn main() {
     let collected: Vec<usize> = counter.collect();
     println!("Custom counter: {:?}", collected);
     
-    let matrix = Matrix::new(3, 3);
+    let matrix = Matrix::sequential(3, 3);
     let matrix_values: Vec<&i32> = matrix.iter().collect();
     println!("Matrix values: {:?}", matrix_values);
     
+    iterator_toolkit_examples();
+    matrix_examples();
+    
     functional_programming_examples();
     performance_comparison();
+    parallel_examples();
     complex_closure_examples();
     
     println!("\n=== All iterator and closure examples completed! ===");
@@ -0,0 +1,751 @@
+// Dense matrices with contiguous storage
+//
+// The Matrix in main.rs started as a Vec<Vec<i32>>; this one keeps all
+// elements in a single Vec<T> in row-major order, so element (r, c) lives at
+// r * cols + c. Rows are plain slices, columns are strided iterators over the
+// same buffer, and no element is ever copied just to be looked at.
+//
+// Arithmetic works for any T: Scalar (the integer and float primitives).
+// The operators panic when the dimensions do not fit, like indexing out of
+// bounds; the checked_* methods return a MatrixError instead.
+//
+// Multiplication comes in two flavours with the same result:
+// - mul_naive: the textbook triple loop, walking down a column of the right
+//   matrix for every element, which misses the cache once the matrix no
+//   longer fits in it
+// - mul_blocked: works on square tiles small enough to stay in cache and
+//   only ever walks rows; this is what the * operator uses
+//
+// LU decomposition, determinants and inverses for f64 matrices live in lu.rs.
+
+pub mod lu;
+
+use std::error::Error;
+use std::fmt;
+use std::iter::StepBy;
+use std::ops::{Add, Index, IndexMut, Mul, Sub};
+use std::slice;
+
+pub use lu::Lu;
+
+// Tile size used by the * operator
+pub const DEFAULT_BLOCK: usize = 64;
+
+// Numbers a matrix can do arithmetic with
+pub trait Scalar:
+    Copy + PartialEq + fmt::Debug + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+}
+
+macro_rules! impl_scalar {
+    ($($t:ty => $zero:expr, $one:expr);* $(;)?) => {
+        $(
+            impl Scalar for $t {
+                const ZERO: Self = $zero;
+                const ONE: Self = $one;
+            }
+        )*
+    };
+}
+
+impl_scalar! {
+    i32 => 0, 1;
+    i64 => 0, 1;
+    u32 => 0, 1;
+    u64 => 0, 1;
+    usize => 0, 1;
+    f32 => 0.0, 1.0;
+    f64 => 0.0, 1.0;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixError {
+    // from_vec was given the wrong number of elements
+    WrongLength {
+        expected: usize,
+        found: usize,
+    },
+    // from_rows was given rows of different lengths
+    RaggedRows {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    // The shapes do not fit the operation
+    DimensionMismatch {
+        op: &'static str,
+        left: (usize, usize),
+        right: (usize, usize),
+    },
+    // The operation needs a square matrix
+    NotSquare {
+        rows: usize,
+        cols: usize,
+    },
+    // The matrix has no inverse
+    Singular,
+}
+
+impl fmt::Display for MatrixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatrixError::WrongLength { expected, found } => {
+                write!(f, "expected {} elements, found {}", expected, found)
+            }
+            MatrixError::RaggedRows {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} has {} elements, but the first row has {}",
+                row, found, expected
+            ),
+            MatrixError::DimensionMismatch { op, left, right } => write!(
+                f,
+                "cannot {} a {}x{} matrix and a {}x{} matrix",
+                op, left.0, left.1, right.0, right.1
+            ),
+            MatrixError::NotSquare { rows, cols } => {
+                write!(f, "expected a square matrix, found {}x{}", rows, cols)
+            }
+            MatrixError::Singular => write!(f, "the matrix is singular"),
+        }
+    }
+}
+
+impl Error for MatrixError {}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Matrix<T> {
+    rows: usize,
+    cols: usize,
+    data: Vec<T>,
+}
+
+impl<T> Matrix<T> {
+    // Builds a matrix by calling f(row, col) for every element, row by row
+    pub fn from_fn<F>(rows: usize, cols: usize, mut f: F) -> Self
+    where
+        F: FnMut(usize, usize) -> T,
+    {
+        let data = (0..rows)
+            .flat_map(|r| (0..cols).map(move |c| (r, c)))
+            .map(|(r, c)| f(r, c))
+            .collect();
+        Matrix { rows, cols, data }
+    }
+
+    // Takes ownership of a row-major buffer
+    pub fn from_vec(rows: usize, cols: usize, data: Vec<T>) -> Result<Self, MatrixError> {
+        let expected = rows * cols;
+        if data.len() != expected {
+            return Err(MatrixError::WrongLength {
+                expected,
+                found: data.len(),
+            });
+        }
+        Ok(Matrix { rows, cols, data })
+    }
+
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Self, MatrixError> {
+        let cols = rows.first().map_or(0, Vec::len);
+        let row_count = rows.len();
+        let mut data = Vec::with_capacity(row_count * cols);
+        for (index, row) in rows.into_iter().enumerate() {
+            if row.len() != cols {
+                return Err(MatrixError::RaggedRows {
+                    row: index,
+                    expected: cols,
+                    found: row.len(),
+                });
+            }
+            data.extend(row);
+        }
+        Ok(Matrix {
+            rows: row_count,
+            cols,
+            data,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    // (rows, cols)
+    pub fn shape(&self) -> (usize, usize) {
+        (self.rows, self.cols)
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<&T> {
+        if row < self.rows && col < self.cols {
+            self.data.get(row * self.cols + col)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, row: usize, col: usize) -> Option<&mut T> {
+        if row < self.rows && col < self.cols {
+            self.data.get_mut(row * self.cols + col)
+        } else {
+            None
+        }
+    }
+
+    pub fn row(&self, row: usize) -> &[T] {
+        assert!(
+            row < self.rows,
+            "row {} out of range for {} rows",
+            row,
+            self.rows
+        );
+        &self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    pub fn row_mut(&mut self, row: usize) -> &mut [T] {
+        assert!(
+            row < self.rows,
+            "row {} out of range for {} rows",
+            row,
+            self.rows
+        );
+        &mut self.data[row * self.cols..(row + 1) * self.cols]
+    }
+
+    // The elements of one column, top to bottom
+    pub fn column(&self, col: usize) -> Column<'_, T> {
+        assert!(
+            col < self.cols,
+            "column {} out of range for {} columns",
+            col,
+            self.cols
+        );
+        // With no rows the buffer is empty, so there is nothing to skip
+        self.data
+            .get(col..)
+            .unwrap_or(&[])
+            .iter()
+            .step_by(self.cols)
+    }
+
+    // Every row as a slice, top to bottom
+    pub fn row_iter(&self) -> Rows<'_, T> {
+        Rows {
+            data: &self.data,
+            cols: self.cols,
+            remaining: self.rows,
+        }
+    }
+
+    pub fn row_iter_mut(&mut self) -> RowsMut<'_, T> {
+        RowsMut {
+            data: &mut self.data,
+            cols: self.cols,
+            remaining: self.rows,
+        }
+    }
+
+    // Every column as an iterator, left to right
+    pub fn column_iter(&self) -> Columns<'_, T> {
+        Columns {
+            matrix: self,
+            next: 0,
+        }
+    }
+
+    // Every element in row-major order
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        self.data.iter_mut()
+    }
+
+    // Every element with its (row, col) position
+    pub fn indexed_iter(&self) -> impl Iterator<Item = ((usize, usize), &T)> + '_ {
+        let cols = self.cols;
+        self.data
+            .iter()
+            .enumerate()
+            .map(move |(i, value)| ((i / cols, i % cols), value))
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        &mut self.data
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> Matrix<U> {
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().map(f).collect(),
+        }
+    }
+
+    pub fn swap_rows(&mut self, a: usize, b: usize) {
+        assert!(
+            a < self.rows && b < self.rows,
+            "rows {} and {} out of range for {} rows",
+            a,
+            b,
+            self.rows
+        );
+        if a == b {
+            return;
+        }
+        let (low, high) = (a.min(b), a.max(b));
+        let (top, bottom) = self.data.split_at_mut(high * self.cols);
+        top[low * self.cols..(low + 1) * self.cols].swap_with_slice(&mut bottom[..self.cols]);
+    }
+}
+
+impl<T: Clone> Matrix<T> {
+    pub fn filled(rows: usize, cols: usize, value: T) -> Self {
+        Matrix {
+            rows,
+            cols,
+            data: vec![value; rows * cols],
+        }
+    }
+
+    pub fn transpose(&self) -> Self {
+        Matrix::from_fn(self.cols, self.rows, |r, c| self[(c, r)].clone())
+    }
+}
+
+impl Matrix<i32> {
+    // 0, 1, 2, ... in row-major order, like the old Matrix::new in main.rs
+    pub fn sequential(rows: usize, cols: usize) -> Self {
+        Matrix::from_fn(rows, cols, |r, c| (r * cols + c) as i32)
+    }
+}
+
+impl<T: Scalar> Matrix<T> {
+    pub fn zeros(rows: usize, cols: usize) -> Self {
+        Matrix::filled(rows, cols, T::ZERO)
+    }
+
+    pub fn identity(size: usize) -> Self {
+        Matrix::from_fn(size, size, |r, c| if r == c { T::ONE } else { T::ZERO })
+    }
+
+    pub fn scale(&self, factor: T) -> Self {
+        self.map(|&value| value * factor)
+    }
+
+    pub fn checked_add(&self, other: &Self) -> Result<Self, MatrixError> {
+        self.zip_with(other, "add", |a, b| a + b)
+    }
+
+    pub fn checked_sub(&self, other: &Self) -> Result<Self, MatrixError> {
+        self.zip_with(other, "subtract", |a, b| a - b)
+    }
+
+    pub fn checked_mul(&self, other: &Self) -> Result<Self, MatrixError> {
+        self.check_mul(other)?;
+        Ok(self.mul_blocked(other, DEFAULT_BLOCK))
+    }
+
+    // The textbook i-j-k loop: each element is the dot product of a row of
+    // self and a column of other
+    pub fn mul_naive(&self, other: &Self) -> Self {
+        self.expect_mul(other);
+        let mut out = Matrix::zeros(self.rows, other.cols);
+        for i in 0..self.rows {
+            for j in 0..other.cols {
+                let mut sum = T::ZERO;
+                for k in 0..self.cols {
+                    sum = sum + self.data[i * self.cols + k] * other.data[k * other.cols + j];
+                }
+                out.data[i * other.cols + j] = sum;
+            }
+        }
+        out
+    }
+
+    // Multiplies block x block tiles at a time. Within a tile the loops run
+    // i-k-j, so the innermost loop walks a row of other and a row of the
+    // output, both contiguous.
+    pub fn mul_blocked(&self, other: &Self, block: usize) -> Self {
+        assert!(block > 0, "block size must be positive");
+        self.expect_mul(other);
+        let (n, m, p) = (self.rows, self.cols, other.cols);
+        let mut out = Matrix::zeros(n, p);
+        for i0 in (0..n).step_by(block) {
+            let i_end = (i0 + block).min(n);
+            for k0 in (0..m).step_by(block) {
+                let k_end = (k0 + block).min(m);
+                for j0 in (0..p).step_by(block) {
+                    let j_end = (j0 + block).min(p);
+                    for i in i0..i_end {
+                        let out_row = &mut out.data[i * p + j0..i * p + j_end];
+                        for k in k0..k_end {
+                            let a = self.data[i * m + k];
+                            let other_row = &other.data[k * p + j0..k * p + j_end];
+                            for (o, &b) in out_row.iter_mut().zip(other_row) {
+                                *o = *o + a * b;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn zip_with<F>(&self, other: &Self, op: &'static str, f: F) -> Result<Self, MatrixError>
+    where
+        F: Fn(T, T) -> T,
+    {
+        if self.shape() != other.shape() {
+            return Err(MatrixError::DimensionMismatch {
+                op,
+                left: self.shape(),
+                right: other.shape(),
+            });
+        }
+        let data = self
+            .data
+            .iter()
+            .zip(&other.data)
+            .map(|(&a, &b)| f(a, b))
+            .collect();
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data,
+        })
+    }
+
+    pub(crate) fn check_mul(&self, other: &Self) -> Result<(), MatrixError> {
+        if self.cols == other.rows {
+            Ok(())
+        } else {
+            Err(MatrixError::DimensionMismatch {
+                op: "multiply",
+                left: self.shape(),
+                right: other.shape(),
+            })
+        }
+    }
+
+    fn expect_mul(&self, other: &Self) {
+        if let Err(e) = self.check_mul(other) {
+            panic!("{}", e);
+        }
+    }
+}
+
+impl Matrix<f64> {
+    // Whether every element is within tolerance of the other's
+    pub fn approx_eq(&self, other: &Self, tolerance: f64) -> bool {
+        self.shape() == other.shape()
+            && self
+                .data
+                .iter()
+                .zip(&other.data)
+                .all(|(a, b)| (a - b).abs() <= tolerance)
+    }
+}
+
+impl<T: Scalar> Default for Matrix<T> {
+    fn default() -> Self {
+        Matrix::zeros(0, 0)
+    }
+}
+
+impl<T> Index<(usize, usize)> for Matrix<T> {
+    type Output = T;
+
+    fn index(&self, (row, col): (usize, usize)) -> &T {
+        let (rows, cols) = self.shape();
+        self.get(row, col).unwrap_or_else(|| {
+            panic!(
+                "index ({}, {}) out of range for a {}x{} matrix",
+                row, col, rows, cols
+            )
+        })
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Matrix<T> {
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut T {
+        let (rows, cols) = self.shape();
+        self.get_mut(row, col).unwrap_or_else(|| {
+            panic!(
+                "index ({}, {}) out of range for a {}x{} matrix",
+                row, col, rows, cols
+            )
+        })
+    }
+}
+
+// The operators are implemented for references, and for owned matrices by
+// borrowing them, so both a + b and &a + &b work
+macro_rules! impl_op {
+    ($trait:ident, $method:ident, $checked:ident) => {
+        impl<T: Scalar> $trait<&Matrix<T>> for &Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(self, other: &Matrix<T>) -> Matrix<T> {
+                self.$checked(other).unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+
+        impl<T: Scalar> $trait<Matrix<T>> for Matrix<T> {
+            type Output = Matrix<T>;
+
+            fn $method(self, other: Matrix<T>) -> Matrix<T> {
+                (&self).$method(&other)
+            }
+        }
+    };
+}
+
+impl_op!(Add, add, checked_add);
+impl_op!(Sub, sub, checked_sub);
+impl_op!(Mul, mul, checked_mul);
+
+impl<T: fmt::Debug> fmt::Debug for Matrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries((0..self.rows).map(|r| &self.data[r * self.cols..(r + 1) * self.cols]))
+            .finish()
+    }
+}
+
+// One row per line, with columns right-aligned
+impl<T: fmt::Display> fmt::Display for Matrix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cells: Vec<String> = self
+            .data
+            .iter()
+            .map(|value| match f.precision() {
+                Some(precision) => format!("{:.*}", precision, value),
+                None => value.to_string(),
+            })
+            .collect();
+        let width = cells.iter().map(String::len).max().unwrap_or(0);
+        for r in 0..self.rows {
+            let row = &cells[r * self.cols..(r + 1) * self.cols];
+            let line: Vec<String> = row
+                .iter()
+                .map(|cell| format!("{:>w$}", cell, w = width))
+                .collect();
+            writeln!(f, "[{}]", line.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Matrix<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> slice::Iter<'a, T> {
+        self.iter()
+    }
+}
+
+pub type Column<'a, T> = StepBy<slice::Iter<'a, T>>;
+
+pub struct Rows<'a, T> {
+    data: &'a [T],
+    cols: usize,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Rows<'a, T> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<&'a [T]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (row, rest) = self.data.split_at(self.cols);
+        self.data = rest;
+        self.remaining -= 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Rows<'_, T> {}
+
+pub struct RowsMut<'a, T> {
+    data: &'a mut [T],
+    cols: usize,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for RowsMut<'a, T> {
+    type Item = &'a mut [T];
+
+    fn next(&mut self) -> Option<&'a mut [T]> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let data = std::mem::take(&mut self.data);
+        let (row, rest) = data.split_at_mut(self.cols);
+        self.data = rest;
+        self.remaining -= 1;
+        Some(row)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for RowsMut<'_, T> {}
+
+pub struct Columns<'a, T> {
+    matrix: &'a Matrix<T>,
+    next: usize,
+}
+
+impl<'a, T> Iterator for Columns<'a, T> {
+    type Item = Column<'a, T>;
+
+    fn next(&mut self) -> Option<Column<'a, T>> {
+        if self.next >= self.matrix.cols {
+            return None;
+        }
+        let column = self.matrix.column(self.next);
+        self.next += 1;
+        Some(column)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.matrix.cols - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for Columns<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_columns_and_elements() {
+        let mut m = Matrix::sequential(2, 3);
+        assert_eq!(m.shape(), (2, 3));
+        assert_eq!(
+            m.iter().copied().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            m.row_iter().collect::<Vec<_>>(),
+            vec![&[0, 1, 2][..], &[3, 4, 5][..]]
+        );
+        let columns: Vec<Vec<i32>> = m.column_iter().map(|c| c.copied().collect()).collect();
+        assert_eq!(columns, vec![vec![0, 3], vec![1, 4], vec![2, 5]]);
+        assert_eq!(m.indexed_iter().nth(4), Some(((1, 1), &4)));
+
+        m[(1, 2)] = 50;
+        for row in m.row_iter_mut() {
+            row[0] *= 10;
+        }
+        m.swap_rows(0, 1);
+        assert_eq!(format!("{:?}", m), "[[30, 4, 50], [0, 1, 2]]");
+        assert_eq!(m.get(2, 0), None);
+        assert_eq!(m.transpose().shape(), (3, 2));
+        assert_eq!(m.transpose()[(2, 0)], 50);
+    }
+
+    #[test]
+    fn test_constructors_check_shapes() {
+        assert_eq!(
+            Matrix::from_vec(2, 2, vec![1, 2, 3]),
+            Err(MatrixError::WrongLength {
+                expected: 4,
+                found: 3
+            })
+        );
+        assert_eq!(
+            Matrix::from_rows(vec![vec![1, 2], vec![3]]),
+            Err(MatrixError::RaggedRows {
+                row: 1,
+                expected: 2,
+                found: 1
+            })
+        );
+        let m = Matrix::from_rows(vec![vec![1, 2], vec![3, 4]]).unwrap();
+        assert_eq!(m, Matrix::from_vec(2, 2, vec![1, 2, 3, 4]).unwrap());
+
+        // Empty shapes still iterate correctly
+        let wide: Matrix<i32> = Matrix::zeros(0, 3);
+        assert_eq!(wide.row_iter().count(), 0);
+        assert!(wide.column_iter().all(|mut c| c.next().is_none()));
+    }
+
+    #[test]
+    fn test_arithmetic_operators() {
+        let a = Matrix::from_rows(vec![vec![1, 2], vec![3, 4]]).unwrap();
+        let b = Matrix::from_rows(vec![vec![5, 6], vec![7, 8]]).unwrap();
+        assert_eq!((&a + &b).into_vec(), vec![6, 8, 10, 12]);
+        assert_eq!((&b - &a).into_vec(), vec![4, 4, 4, 4]);
+        assert_eq!((&a * &b).into_vec(), vec![19, 22, 43, 50]);
+        assert_eq!(&a * &Matrix::identity(2), a);
+        assert_eq!(a.scale(3).into_vec(), vec![3, 6, 9, 12]);
+
+        let column = Matrix::from_vec(2, 1, vec![1, 1]).unwrap();
+        assert_eq!((a.clone() * column.clone()).into_vec(), vec![3, 7]);
+        assert_eq!(
+            column.checked_mul(&a),
+            Err(MatrixError::DimensionMismatch {
+                op: "multiply",
+                left: (2, 1),
+                right: (2, 2)
+            })
+        );
+        assert!(a.checked_add(&column).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot add a 2x2 matrix and a 1x2 matrix")]
+    fn test_mismatched_add_panics() {
+        let _ = Matrix::<i32>::zeros(2, 2) + Matrix::zeros(1, 2);
+    }
+
+    #[test]
+    fn test_blocked_multiply_matches_naive() {
+        // Shapes that are not multiples of the block size
+        let a = Matrix::from_fn(37, 23, |r, c| (r * 7 + c * 3) as i64 % 11 - 5);
+        let b = Matrix::from_fn(23, 41, |r, c| (r * 5 + c) as i64 % 13 - 6);
+        let expected = a.mul_naive(&b);
+        for block in [1, 4, 8, 64] {
+            assert_eq!(a.mul_blocked(&b, block), expected, "block size {}", block);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+// LU decomposition with partial pivoting
+//
+// Factors a square matrix A into P * A = L * U, where P reorders the rows,
+// L is lower triangular with ones on the diagonal and U is upper
+// triangular. L and U are stored together in one matrix (L below the
+// diagonal, U on and above it), and P as the list of original row indices.
+//
+// Once factored, the determinant is the product of U's diagonal (with a sign
+// flip per row swap), and solving A x = b is a forward and a backward
+// substitution. The inverse solves for each column of the identity.
+//
+// Pivoting picks the largest remaining value in each column, which keeps
+// rounding errors small. A pivot that is tiny compared to the matrix's
+// largest value counts as zero, so the matrix is reported as singular.
+
+use super::{Matrix, MatrixError};
+
+#[derive(Debug, Clone)]
+pub struct Lu {
+    factors: Matrix<f64>,
+    permutation: Vec<usize>,
+    swaps: usize,
+    singular: bool,
+}
+
+impl Lu {
+    pub fn new(matrix: &Matrix<f64>) -> Result<Self, MatrixError> {
+        let (rows, cols) = matrix.shape();
+        if rows != cols {
+            return Err(MatrixError::NotSquare { rows, cols });
+        }
+        let n = rows;
+        let largest = matrix
+            .iter()
+            .fold(0.0_f64, |max, value| max.max(value.abs()));
+        let tolerance = largest * n as f64 * f64::EPSILON;
+
+        let mut factors = matrix.clone();
+        let mut permutation: Vec<usize> = (0..n).collect();
+        let mut swaps = 0;
+        let mut singular = false;
+
+        for k in 0..n {
+            let pivot_row = (k..n)
+                .max_by(|&a, &b| factors[(a, k)].abs().total_cmp(&factors[(b, k)].abs()))
+                .unwrap_or(k);
+            if pivot_row != k {
+                factors.swap_rows(k, pivot_row);
+                permutation.swap(k, pivot_row);
+                swaps += 1;
+            }
+
+            let pivot = factors[(k, k)];
+            if pivot.abs() <= tolerance {
+                // Nothing to eliminate with; the rest of the column is
+                // (near) zero already
+                singular = true;
+                continue;
+            }
+            for i in k + 1..n {
+                let factor = factors[(i, k)] / pivot;
+                factors[(i, k)] = factor;
+                for j in k + 1..n {
+                    let update = factor * factors[(k, j)];
+                    factors[(i, j)] -= update;
+                }
+            }
+        }
+
+        Ok(Lu {
+            factors,
+            permutation,
+            swaps,
+            singular,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.factors.rows()
+    }
+
+    pub fn is_singular(&self) -> bool {
+        self.singular
+    }
+
+    pub fn determinant(&self) -> f64 {
+        if self.singular {
+            return 0.0;
+        }
+        let sign = if self.swaps % 2 == 0 { 1.0 } else { -1.0 };
+        (0..self.size())
+            .map(|i| self.factors[(i, i)])
+            .product::<f64>()
+            * sign
+    }
+
+    // L, with ones on the diagonal
+    pub fn lower(&self) -> Matrix<f64> {
+        Matrix::from_fn(self.size(), self.size(), |r, c| match r.cmp(&c) {
+            std::cmp::Ordering::Greater => self.factors[(r, c)],
+            std::cmp::Ordering::Equal => 1.0,
+            std::cmp::Ordering::Less => 0.0,
+        })
+    }
+
+    pub fn upper(&self) -> Matrix<f64> {
+        Matrix::from_fn(self.size(), self.size(), |r, c| {
+            if r <= c {
+                self.factors[(r, c)]
+            } else {
+                0.0
+            }
+        })
+    }
+
+    // P as a matrix, so that P * A == L * U
+    pub fn permutation(&self) -> Matrix<f64> {
+        Matrix::from_fn(self.size(), self.size(), |r, c| {
+            if self.permutation[r] == c {
+                1.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    // Solves A x = b
+    pub fn solve(&self, b: &[f64]) -> Result<Vec<f64>, MatrixError> {
+        let n = self.size();
+        if b.len() != n {
+            return Err(MatrixError::DimensionMismatch {
+                op: "solve",
+                left: (n, n),
+                right: (b.len(), 1),
+            });
+        }
+        if self.singular {
+            return Err(MatrixError::Singular);
+        }
+
+        // L y = P b, top to bottom
+        let mut x: Vec<f64> = self.permutation.iter().map(|&row| b[row]).collect();
+        for i in 0..n {
+            let row = self.factors.row(i);
+            let sum: f64 = row[..i].iter().zip(&x[..i]).map(|(l, y)| l * y).sum();
+            x[i] -= sum;
+        }
+        // U x = y, bottom to top
+        for i in (0..n).rev() {
+            let row = self.factors.row(i);
+            let sum: f64 = row[i + 1..]
+                .iter()
+                .zip(&x[i + 1..])
+                .map(|(u, x)| u * x)
+                .sum();
+            x[i] = (x[i] - sum) / row[i];
+        }
+        Ok(x)
+    }
+
+    pub fn inverse(&self) -> Result<Matrix<f64>, MatrixError> {
+        if self.singular {
+            return Err(MatrixError::Singular);
+        }
+        let n = self.size();
+        let mut inverse = Matrix::zeros(n, n);
+        let mut unit = vec![0.0; n];
+        for c in 0..n {
+            unit[c] = 1.0;
+            let column = self.solve(&unit)?;
+            unit[c] = 0.0;
+            for (r, value) in column.into_iter().enumerate() {
+                inverse[(r, c)] = value;
+            }
+        }
+        Ok(inverse)
+    }
+}
+
+impl Matrix<f64> {
+    pub fn lu(&self) -> Result<Lu, MatrixError> {
+        Lu::new(self)
+    }
+
+    pub fn determinant(&self) -> Result<f64, MatrixError> {
+        Ok(self.lu()?.determinant())
+    }
+
+    pub fn inverse(&self) -> Result<Matrix<f64>, MatrixError> {
+        self.lu()?.inverse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matrix(rows: Vec<Vec<f64>>) -> Matrix<f64> {
+        Matrix::from_rows(rows).unwrap()
+    }
+
+    #[test]
+    fn test_factors_multiply_back() {
+        // The first pivot is zero, so this needs a row swap
+        let a = matrix(vec![
+            vec![0.0, 2.0, 1.0],
+            vec![1.0, 1.0, 0.0],
+            vec![3.0, 0.0, 1.0],
+        ]);
+        let lu = a.lu().unwrap();
+        assert!(!lu.is_singular());
+        assert!((&lu.permutation() * &a).approx_eq(&(&lu.lower() * &lu.upper()), 1e-12));
+        assert!((lu.determinant() - (-5.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_solve_and_inverse() {
+        let a = matrix(vec![
+            vec![4.0, -2.0, 1.0],
+            vec![-2.0, 4.0, -2.0],
+            vec![1.0, -2.0, 4.0],
+        ]);
+        let x = a.lu().unwrap().solve(&[11.0, -16.0, 17.0]).unwrap();
+        let expected = [1.0, -2.0, 3.0];
+        assert!(x.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-12));
+
+        let inverse = a.inverse().unwrap();
+        assert!((&a * &inverse).approx_eq(&Matrix::identity(3), 1e-12));
+        assert!((&inverse * &a).approx_eq(&Matrix::identity(3), 1e-12));
+        let det = a.determinant().unwrap();
+        assert!((inverse.determinant().unwrap() - 1.0 / det).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_singular_and_non_square() {
+        // The third row is the sum of the first two
+        let singular = matrix(vec![
+            vec![1.0, 2.0, 3.0],
+            vec![4.0, 5.0, 6.0],
+            vec![5.0, 7.0, 9.0],
+        ]);
+        assert_eq!(singular.determinant(), Ok(0.0));
+        assert_eq!(singular.inverse(), Err(MatrixError::Singular));
+        assert_eq!(
+            singular.lu().unwrap().solve(&[1.0, 2.0, 3.0]),
+            Err(MatrixError::Singular)
+        );
+
+        let wide = Matrix::<f64>::zeros(2, 3);
+        assert_eq!(
+            wide.determinant(),
+            Err(MatrixError::NotSquare { rows: 2, cols: 3 })
+        );
+        assert_eq!(Matrix::<f64>::identity(0).determinant(), Ok(1.0));
+    }
+}
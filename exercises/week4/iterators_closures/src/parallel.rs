@@ -0,0 +1,368 @@
+// Parallel iterator adapters on top of std::thread::scope
+//
+// par_chunks splits a slice into chunks and runs a closure on every chunk,
+// spreading the chunks over up to available_parallelism() scoped threads.
+// Scoped threads may borrow the slice and the closure, so nothing needs to
+// be 'static, Arc'd or cloned.
+//
+// Each thread gets a contiguous run of chunks, and the per-chunk results are
+// put back together in chunk order. That keeps every result identical to
+// the sequential version:
+// - par_map and par_filter return the same elements in the same order
+// - par_reduce combines chunk results left to right, so it matches a
+//   sequential reduce for any associative operation (for floats, only if
+//   the operation is exactly associative, which + and * are not)
+// - Matrix::par_mul computes every output row exactly as mul_naive does
+//
+// A panic in any chunk is propagated to the caller once all threads are
+// done. With one thread, or one chunk, everything runs on the calling
+// thread.
+
+use std::num::NonZeroUsize;
+use std::panic;
+use std::thread;
+
+use crate::matrix::{Matrix, Scalar};
+
+// How many chunks each thread gets by default, so that a slow chunk does not
+// leave the other threads idle for long
+const CHUNKS_PER_THREAD: usize = 4;
+
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+// A chunk size that gives every thread a few chunks to work on
+pub fn default_chunk_size(len: usize, threads: usize) -> usize {
+    let chunks = threads.max(1) * CHUNKS_PER_THREAD;
+    ((len + chunks - 1) / chunks).max(1)
+}
+
+// Runs f on every item on up to `threads` scoped threads and returns the
+// results in item order
+fn run<C, R, F>(items: Vec<C>, threads: usize, f: F) -> Vec<R>
+where
+    C: Send,
+    R: Send,
+    F: Fn(usize, C) -> R + Sync,
+{
+    let threads = threads.clamp(1, items.len().max(1));
+    if threads == 1 {
+        return items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| f(i, item))
+            .collect();
+    }
+
+    // Contiguous groups, the first ones one item larger when it doesn't
+    // divide evenly
+    let total = items.len();
+    let mut groups: Vec<Vec<(usize, C)>> = Vec::with_capacity(threads);
+    let mut items = items.into_iter().enumerate();
+    for g in 0..threads {
+        let size = total / threads + usize::from(g < total % threads);
+        groups.push(items.by_ref().take(size).collect());
+    }
+
+    let f = &f;
+    thread::scope(|s| {
+        let handles: Vec<_> = groups
+            .into_iter()
+            .map(|group| {
+                s.spawn(move || {
+                    group
+                        .into_iter()
+                        .map(|(i, item)| f(i, item))
+                        .collect::<Vec<R>>()
+                })
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(total);
+        for handle in handles {
+            match handle.join() {
+                Ok(group) => results.extend(group),
+                Err(payload) => panic::resume_unwind(payload),
+            }
+        }
+        results
+    })
+}
+
+pub struct ParChunks<'a, T> {
+    slice: &'a [T],
+    chunk_size: usize,
+    threads: usize,
+}
+
+impl<'a, T: Sync> ParChunks<'a, T> {
+    // Caps the number of threads (at least 1)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        (self.slice.len() + self.chunk_size - 1) / self.chunk_size
+    }
+
+    // One result per chunk, in chunk order
+    pub fn map<R, F>(self, f: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(&'a [T]) -> R + Sync,
+    {
+        let chunks: Vec<&'a [T]> = self.slice.chunks(self.chunk_size).collect();
+        run(chunks, self.threads, |_, chunk| f(chunk))
+    }
+
+    pub fn for_each<F>(self, f: F)
+    where
+        F: Fn(&'a [T]) + Sync,
+    {
+        self.map(f);
+    }
+}
+
+pub struct ParChunksMut<'a, T> {
+    slice: &'a mut [T],
+    chunk_size: usize,
+    threads: usize,
+}
+
+impl<'a, T: Send> ParChunksMut<'a, T> {
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // f gets each chunk's index along with the chunk, so it can tell where
+    // in the slice the chunk starts
+    pub fn for_each<F>(self, f: F)
+    where
+        F: Fn(usize, &'a mut [T]) + Sync,
+    {
+        let chunks: Vec<&'a mut [T]> = self.slice.chunks_mut(self.chunk_size).collect();
+        run(chunks, self.threads, f);
+    }
+}
+
+// Parallel versions of the usual slice pipelines
+pub trait ParallelSlice<T: Sync> {
+    fn par_chunks(&self, chunk_size: usize) -> ParChunks<'_, T>;
+
+    fn par_map<U, F>(&self, f: F) -> Vec<U>
+    where
+        U: Send,
+        F: Fn(&T) -> U + Sync;
+
+    fn par_filter<F>(&self, predicate: F) -> Vec<T>
+    where
+        T: Clone + Send,
+        F: Fn(&T) -> bool + Sync;
+
+    // None for an empty slice. `op` must be associative for the result to
+    // match a sequential reduce
+    fn par_reduce<F>(&self, op: F) -> Option<T>
+    where
+        T: Clone + Send,
+        F: Fn(T, T) -> T + Sync;
+}
+
+pub trait ParallelSliceMut<T: Send> {
+    fn par_chunks_mut(&mut self, chunk_size: usize) -> ParChunksMut<'_, T>;
+}
+
+impl<T: Sync> ParallelSlice<T> for [T] {
+    fn par_chunks(&self, chunk_size: usize) -> ParChunks<'_, T> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        ParChunks {
+            slice: self,
+            chunk_size,
+            threads: available_threads(),
+        }
+    }
+
+    fn par_map<U, F>(&self, f: F) -> Vec<U>
+    where
+        U: Send,
+        F: Fn(&T) -> U + Sync,
+    {
+        let chunk_size = default_chunk_size(self.len(), available_threads());
+        self.par_chunks(chunk_size)
+            .map(|chunk| chunk.iter().map(&f).collect::<Vec<U>>())
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    fn par_filter<F>(&self, predicate: F) -> Vec<T>
+    where
+        T: Clone + Send,
+        F: Fn(&T) -> bool + Sync,
+    {
+        let chunk_size = default_chunk_size(self.len(), available_threads());
+        self.par_chunks(chunk_size)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .filter(|item| predicate(item))
+                    .cloned()
+                    .collect::<Vec<T>>()
+            })
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    fn par_reduce<F>(&self, op: F) -> Option<T>
+    where
+        T: Clone + Send,
+        F: Fn(T, T) -> T + Sync,
+    {
+        let chunk_size = default_chunk_size(self.len(), available_threads());
+        self.par_chunks(chunk_size)
+            .map(|chunk| chunk.iter().cloned().reduce(&op))
+            .into_iter()
+            .flatten()
+            .reduce(&op)
+    }
+}
+
+impl<T: Send> ParallelSliceMut<T> for [T] {
+    fn par_chunks_mut(&mut self, chunk_size: usize) -> ParChunksMut<'_, T> {
+        assert!(chunk_size > 0, "chunk size must be positive");
+        ParChunksMut {
+            slice: self,
+            chunk_size,
+            threads: available_threads(),
+        }
+    }
+}
+
+impl<T: Scalar + Send + Sync> Matrix<T> {
+    // Same result as mul_naive and mul_blocked, with the output rows split
+    // between threads
+    pub fn par_mul(&self, other: &Self) -> Self {
+        self.par_mul_with_threads(other, available_threads())
+    }
+
+    pub fn par_mul_with_threads(&self, other: &Self, threads: usize) -> Self {
+        if let Err(e) = self.check_mul(other) {
+            panic!("{}", e);
+        }
+        let (n, m, p) = (self.rows(), self.cols(), other.cols());
+        let mut out = Matrix::zeros(n, p);
+        if out.is_empty() {
+            return out;
+        }
+
+        let rows_per_chunk = default_chunk_size(n, threads);
+        out.as_mut_slice()
+            .par_chunks_mut(rows_per_chunk * p)
+            .threads(threads)
+            .for_each(|chunk_index, out_rows| {
+                let first_row = chunk_index * rows_per_chunk;
+                for (offset, out_row) in out_rows.chunks_mut(p).enumerate() {
+                    let row = self.row(first_row + offset);
+                    // i-k-j order: each output element still adds up its
+                    // products for k = 0, 1, 2, ... like mul_naive
+                    for (k, &a) in row.iter().enumerate().take(m) {
+                        for (o, &b) in out_row.iter_mut().zip(other.row(k)) {
+                            *o = *o + a * b;
+                        }
+                    }
+                }
+            });
+        out
+    }
+
+    pub fn par_map<U, F>(&self, f: F) -> Matrix<U>
+    where
+        U: Send,
+        F: Fn(&T) -> U + Sync,
+    {
+        let data = self.as_slice().par_map(f);
+        Matrix::from_vec(self.rows(), self.cols(), data).expect("par_map keeps the length")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_chunks_run_on_several_threads_in_order() {
+        let data: Vec<u32> = (0..1000).collect();
+        let seen = Mutex::new(HashSet::new());
+        let sums = data.par_chunks(10).threads(4).map(|chunk| {
+            seen.lock().unwrap().insert(thread::current().id());
+            chunk.iter().sum::<u32>()
+        });
+        let expected: Vec<u32> = data.chunks(10).map(|c| c.iter().sum()).collect();
+        assert_eq!(sums, expected);
+        assert_eq!(seen.into_inner().unwrap().len(), 4);
+
+        // Uneven sizes and more threads than chunks
+        let sums = data[..25].par_chunks(10).threads(16).map(|c| c.len());
+        assert_eq!(sums, vec![10, 10, 5]);
+        assert!(Vec::<u32>::new().par_chunks(3).map(|c| c.len()).is_empty());
+    }
+
+    #[test]
+    fn test_map_filter_reduce_match_sequential() {
+        let data: Vec<i64> = (0..100_003).map(|i| (i * 7919) % 10_007 - 5_000).collect();
+        let squares: Vec<i64> = data.iter().map(|x| x * x).collect();
+        assert_eq!(data.par_map(|x| x * x), squares);
+
+        let positive: Vec<i64> = data.iter().copied().filter(|&x| x > 0).collect();
+        assert_eq!(data.par_filter(|&x| x > 0), positive);
+
+        assert_eq!(data.par_reduce(|a, b| a + b), Some(data.iter().sum()));
+        assert_eq!(data.par_reduce(i64::max), data.iter().copied().max());
+        assert_eq!(Vec::<i64>::new().par_reduce(|a, b| a + b), None);
+
+        let words: Vec<String> = ["a", "b", "c", "d", "e"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(words.par_reduce(|a, b| a + &b).as_deref(), Some("abcde"));
+    }
+
+    #[test]
+    fn test_chunks_mut_see_their_index() {
+        let mut data = vec![0usize; 103];
+        data.par_chunks_mut(10)
+            .threads(3)
+            .for_each(|index, chunk| chunk.iter_mut().for_each(|x| *x = index));
+        let expected: Vec<usize> = (0..103).map(|i| i / 10).collect();
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_par_mul_is_bit_identical() {
+        let a = Matrix::from_fn(67, 45, |r, c| ((r * 31 + c * 17) % 101) as f64 / 7.0 - 3.0);
+        let b = Matrix::from_fn(45, 29, |r, c| ((r * 13 + c * 7) % 89) as f64 / 3.0 - 11.0);
+        let expected = a.mul_naive(&b);
+        for threads in [1, 2, 3, 8] {
+            let product = a.par_mul_with_threads(&b, threads);
+            assert_eq!(product, expected, "{} threads", threads);
+        }
+        assert_eq!(a.mul_blocked(&b, 16), expected);
+        assert_eq!(a.par_map(|x| x * 2.0), a.scale(2.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "chunk 7")]
+    fn test_panics_reach_the_caller() {
+        let data: Vec<u32> = (0..100).collect();
+        data.par_chunks(10).threads(4).for_each(|chunk| {
+            if chunk[0] == 70 {
+                panic!("chunk 7");
+            }
+        });
+    }
+}
@@ -0,0 +1,4 @@
+// Library root file - exposes modules for integration testing
+pub mod persistent;
+pub mod shared_ptr;
+pub mod tree;
@@ -4,6 +4,15 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::ops::Deref;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+
+use bench::Suite;
+use graph::{dijkstra, strongly_connected_components, toposort, Dfs, Graph};
+use smart_pointers::persistent::{self, ArcVector, Map, Vector};
+use smart_pointers::shared_ptr::SharedPtr;
+use smart_pointers::tree::Tree;
 
 // TODO 1: Box<T> for heap allocation
 fn box_examples() {
@@ -131,6 +140,94 @@ fn rc_examples() {
     println!("b = {:?}", b);
 }
 
+// The same structural sharing for any element type, plus a vector and a
+// hash map that share everything a change doesn't touch (src/persistent.rs)
+fn persistent_examples() {
+    println!("\n--- Persistent Collections ---");
+    
+    let a: persistent::List<&str> = ["five", "ten"].into_iter().collect();
+    let mut b = a.clone();
+    b.push_front("three");
+    let mut c = a.clone();
+    c.push_front("four");
+    println!("a = {:?}, b = {:?}, c = {:?}", a, b, c);
+    println!("b and c share a's nodes: {}", b.tail().unwrap().ptr_eq(&a) && c.tail().unwrap().ptr_eq(&a));
+    
+    let v1: Vector<i32> = (0..1000).collect();
+    let mut v2 = v1.clone();
+    v2.set(500, -1);
+    v2.push_back(1000);
+    println!("v1[500] = {}, v2[500] = {}, lengths {} and {}", v1[500], v2[500], v1.len(), v2.len());
+    
+    let mut prices: Map<&str, u32> = Map::new();
+    prices.insert("apple", 3);
+    prices.insert("pear", 4);
+    let before_sale = prices.clone();
+    prices.insert("apple", 2);
+    prices.remove("pear");
+    println!("before sale: {:?}, after: {:?}", before_sale, prices);
+    
+    // Arc variants can be handed to other threads
+    let shared: ArcVector<u64> = (1..=100).collect();
+    let sums: Vec<u64> = thread::scope(|s| {
+        let handles: Vec<_> = (0..3)
+            .map(|t| {
+                let mut mine = shared.clone();
+                s.spawn(move || {
+                    mine.push_back(t);
+                    mine.iter().sum::<u64>()
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    println!("Per-thread sums over one shared vector: {:?}", sums);
+    
+    // Against std: persistent structures pay on every operation, and win
+    // when old versions have to be kept around
+    const N: usize = 10_000;
+    let std_vec: Vec<usize> = (0..N).collect();
+    let p_vec: Vector<usize> = (0..N).collect();
+    let std_map: HashMap<usize, usize> = (0..N).map(|i| (i, i)).collect();
+    let p_map: Map<usize, usize> = (0..N).map(|i| (i, i)).collect();
+    
+    let mut suite = Suite::new().warmup(Duration::from_millis(50)).samples(10);
+    suite
+        .bench("Vec push", || (0..N).collect::<Vec<_>>())
+        .bench("Vector push_back", || (0..N).collect::<Vector<_>>())
+        .bench("Vec iterate", || std_vec.iter().sum::<usize>())
+        .bench("Vector iterate", || p_vec.iter().sum::<usize>())
+        .bench("HashMap insert", || (0..N).map(|i| (i, i)).collect::<HashMap<_, _>>())
+        .bench("Map insert", || (0..N).map(|i| (i, i)).collect::<Map<_, _>>())
+        .bench("HashMap get", || (0..N).filter_map(|i| std_map.get(&i)).sum::<usize>())
+        .bench("Map get", || (0..N).filter_map(|i| p_map.get(&i)).sum::<usize>())
+        // Keep the old version, change one element in the new one
+        .bench("Vec snapshot + set", || {
+            let mut copy = std_vec.clone();
+            copy[N / 2] = 0;
+            copy
+        })
+        .bench("Vector snapshot + set", || {
+            let mut copy = p_vec.clone();
+            copy.set(N / 2, 0);
+            copy
+        })
+        .bench("HashMap snapshot + insert", || {
+            let mut copy = std_map.clone();
+            copy.insert(N, N);
+            copy
+        })
+        .bench("Map snapshot + insert", || {
+            let mut copy = p_map.clone();
+            copy.insert(N, N);
+            copy
+        });
+    let report = suite.run();
+    println!("{}", report);
+    
+    report.compare_with_saved("persistent");
+}
+
 // TODO 5: RefCell<T> for interior mutability
 fn refcell_examples() {
     println!("\n--- RefCell<T> Examples ---");
@@ -199,6 +296,43 @@ fn rc_refcell_examples() {
     println!("c after = {:?}", c);
 }
 
+// The cons lists above can share tails but never loop back: an Rc cycle
+// would leak. An arena graph (crates/graph) stores nodes in a Vec and links
+// them by index, so cycles and general graphs need no Rc at all.
+fn graph_examples() {
+    println!("\n--- Arena Graph ---");
+    
+    // The same sharing as List3: b and c both continue into a
+    let mut list: Graph<i32, ()> = Graph::new();
+    let a = list.add_node(5);
+    let b = list.add_node(3);
+    let c = list.add_node(4);
+    list.add_edge(b, a, ()).unwrap();
+    list.add_edge(c, a, ()).unwrap();
+    list[a] += 10;
+    println!("b -> {:?}", Dfs::new(&list, b).map(|n| list[n]).collect::<Vec<_>>());
+    println!("c -> {:?}", Dfs::new(&list, c).map(|n| list[n]).collect::<Vec<_>>());
+    
+    // Something no cons list can hold: a road map with a loop in it
+    let mut roads: Graph<&str, u32> = Graph::new();
+    let [home, shop, park, school] = ["home", "shop", "park", "school"].map(|place| roads.add_node(place));
+    for (from, to, minutes) in [(home, shop, 5), (shop, park, 3), (park, home, 4), (home, school, 12), (park, school, 2)] {
+        roads.add_edge(from, to, minutes).unwrap();
+    }
+    let paths = dijkstra(&roads, home, |road| *road.weight);
+    let route: Vec<_> = paths.path_to(school).unwrap().into_iter().map(|n| roads[n]).collect();
+    println!("Fastest way to school: {:?} ({} minutes)", route, paths.distance(school).unwrap());
+    println!("Topological order: {:?}", toposort(&roads).map_err(|cycle| cycle.to_string()));
+    for component in strongly_connected_components(&roads) {
+        println!("Mutually reachable: {:?}", component.iter().map(|&n| roads[n]).collect::<Vec<_>>());
+    }
+    
+    // Ids are generational, so a handle to a removed node is caught
+    roads.remove_node(shop);
+    let cafe = roads.add_node("cafe");
+    println!("Old shop id: {:?}, reused slot holds {:?}", roads.node(shop), roads.node(cafe));
+}
+
 // TODO 7: Weak references to prevent cycles
 use std::rc::Weak;
 
@@ -244,58 +378,47 @@ fn weak_references() {
              Rc::strong_count(&leaf), Rc::weak_count(&leaf));
 }
 
-// TODO 8: Custom smart pointer with multiple ownership
-#[derive(Debug)]
-struct SharedData<T> {
-    data: T,
-    ref_count: RefCell<usize>,
-}
-
-struct SharedPtr<T> {
-    inner: Rc<SharedData<T>>,
-}
-
-impl<T> SharedPtr<T> {
-    fn new(data: T) -> Self {
-        SharedPtr {
-            inner: Rc::new(SharedData {
-                data,
-                ref_count: RefCell::new(1),
-            }),
-        }
-    }
-    
-    fn get_ref_count(&self) -> usize {
-        *self.inner.ref_count.borrow()
-    }
-}
-
-impl<T> Clone for SharedPtr<T> {
-    fn clone(&self) -> Self {
-        *self.inner.ref_count.borrow_mut() += 1;
-        SharedPtr {
-            inner: Rc::clone(&self.inner),
-        }
+// The same Rc-down, Weak-up layout as Node, for any value type, with
+// operations that keep it a tree (see src/tree.rs)
+fn tree_examples() {
+    println!("\n--- Generic Tree ---");
+    
+    let tree = Tree::new("src");
+    let src = tree.root();
+    let main_rs = src.append("main.rs");
+    let util = src.append("util");
+    let parse = util.append("parse.rs");
+    let fmt = util.append("fmt.rs");
+    let tests = src.append("tests");
+    print!("{}", tree);
+    
+    fn names<'a>(nodes: impl IntoIterator<Item = smart_pointers::tree::Node<&'a str>>) -> Vec<&'a str> {
+        nodes.into_iter().map(|node| *node.value()).collect()
     }
+    println!("pre-order:   {:?}", names(tree.pre_order()));
+    println!("post-order:  {:?}", names(tree.post_order()));
+    println!("level-order: {:?}", names(tree.level_order()));
+    println!("path to root from parse.rs: {:?}", names(parse.path_to_root()));
+    println!("common ancestor of parse.rs and fmt.rs: {:?}", parse.lowest_common_ancestor(&fmt));
+    println!("common ancestor of parse.rs and main.rs: {:?}", parse.lowest_common_ancestor(&main_rs));
+    
+    // Moves that would make a node its own ancestor are refused
+    println!("Move util into util/parse.rs: {:?}", util.reparent(&parse));
+    fmt.reparent(&tests).unwrap();
+    let util = util.remove().unwrap();
+    println!("After moving fmt.rs to tests and removing util:");
+    print!("{}", tree);
+    
+    // A handle keeps its node and the subtree below it alive, but not the
+    // parent: parent links are Weak
+    drop(tree);
+    drop(src);
+    println!("main.rs after the tree is dropped: {:?}, parent {:?}", main_rs, main_rs.parent());
+    println!("Removed util subtree: {} nodes", util.len());
 }
 
-impl<T> Deref for SharedPtr<T> {
-    type Target = T;
-    
-    fn deref(&self) -> &Self::Target {
-        &self.inner.data
-    }
-}
-
-impl<T> Drop for SharedPtr<T> {
-    fn drop(&mut self) {
-        *self.inner.ref_count.borrow_mut() -= 1;
-        let count = *self.inner.ref_count.borrow();
-        if count == 0 {
-            println!("Last SharedPtr dropped!");
-        }
-    }
-}
+// TODO 8: Custom smart pointer with multiple ownership
+// SharedPtr and WeakPtr (see src/shared_ptr.rs) are a hand-rolled Rc<T>/Weak<T>
 
 // TODO 9: Cell<T> for Copy types
 use std::cell::Cell;
@@ -364,9 +487,12 @@ fn main() {
     mybox_examples();
     drop_examples();
     rc_examples();
+    persistent_examples();
     refcell_examples();
     rc_refcell_examples();
+    graph_examples();
     weak_references();
+    tree_examples();
     
     // TODO 11: Custom smart pointer usage
     println!("\n--- Custom Smart Pointer ---");
@@ -381,6 +507,17 @@ fn main() {
     
     println!("After ptr2 dropped: {}", ptr1.get_ref_count());
     
+    let weak = SharedPtr::downgrade(&ptr1);
+    println!("Weak upgrade while alive: {:?}", weak.upgrade());
+    
+    let mut ptr3 = ptr1.clone();
+    SharedPtr::make_mut(&mut ptr3).push_str(", world");
+    println!("ptr1: {}, ptr3 after make_mut: {}", *ptr1, *ptr3);
+    
+    drop(ptr1);
+    println!("Weak upgrade after last owner dropped: {:?}", weak.upgrade());
+    println!("ptr3 unwrapped: {:?}", SharedPtr::try_unwrap(ptr3));
+    
     cell_examples();
     pointer_comparison();
     
@@ -0,0 +1,139 @@
+// Persistent (immutable, structurally shared) collections
+//
+// - List: singly linked list, like List2 in main.rs but generic
+// - Vector: 32-way trie with a tail chunk, as in Clojure's vectors
+// - Map: hash array mapped trie (HAMT)
+//
+// Cloning any of them is O(1): the clone shares every node with the
+// original. Updates take &mut self and copy only the nodes on the path they
+// touch (Rc::make_mut), so the old versions stay intact and a collection
+// that is not shared is simply updated in place. To keep a version around,
+// clone it before changing it.
+//
+// Nodes are shared through Rc by default. The Arc variants (ArcList,
+// ArcVector, ArcMap) use Arc instead, so versions can be sent to and shared
+// between threads. The choice is a type parameter implementing PointerKind.
+
+pub mod list;
+pub mod map;
+pub mod vector;
+
+use std::ops::Deref;
+use std::rc::Rc;
+use std::sync::Arc;
+
+pub use list::List;
+pub use map::Map;
+pub use vector::Vector;
+
+pub type ArcList<T> = List<T, ArcKind>;
+pub type ArcVector<T> = Vector<T, ArcKind>;
+pub type ArcMap<K, V> = Map<K, V, ArcKind>;
+
+// A family of reference counted pointers: Rc or Arc
+pub trait PointerKind: 'static {
+    type Ptr<T>: Clone + Deref<Target = T>;
+
+    fn new<T>(value: T) -> Self::Ptr<T>;
+
+    // Clones the value first if the pointer is shared
+    fn make_mut<T: Clone>(ptr: &mut Self::Ptr<T>) -> &mut T;
+
+    fn try_unwrap<T>(ptr: Self::Ptr<T>) -> Result<T, Self::Ptr<T>>;
+
+    fn ptr_eq<T>(a: &Self::Ptr<T>, b: &Self::Ptr<T>) -> bool;
+}
+
+#[derive(Debug)]
+pub enum RcKind {}
+
+#[derive(Debug)]
+pub enum ArcKind {}
+
+impl PointerKind for RcKind {
+    type Ptr<T> = Rc<T>;
+
+    fn new<T>(value: T) -> Rc<T> {
+        Rc::new(value)
+    }
+
+    fn make_mut<T: Clone>(ptr: &mut Rc<T>) -> &mut T {
+        Rc::make_mut(ptr)
+    }
+
+    fn try_unwrap<T>(ptr: Rc<T>) -> Result<T, Rc<T>> {
+        Rc::try_unwrap(ptr)
+    }
+
+    fn ptr_eq<T>(a: &Rc<T>, b: &Rc<T>) -> bool {
+        Rc::ptr_eq(a, b)
+    }
+}
+
+impl PointerKind for ArcKind {
+    type Ptr<T> = Arc<T>;
+
+    fn new<T>(value: T) -> Arc<T> {
+        Arc::new(value)
+    }
+
+    fn make_mut<T: Clone>(ptr: &mut Arc<T>) -> &mut T {
+        Arc::make_mut(ptr)
+    }
+
+    fn try_unwrap<T>(ptr: Arc<T>) -> Result<T, Arc<T>> {
+        Arc::try_unwrap(ptr)
+    }
+
+    fn ptr_eq<T>(a: &Arc<T>, b: &Arc<T>) -> bool {
+        Arc::ptr_eq(a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    #[test]
+    fn test_arc_versions_are_shared_between_threads() {
+        let list: ArcList<i32> = (0..100).collect();
+        let vector: ArcVector<i32> = (0..100).collect();
+        let map: ArcMap<i32, i32> = (0..100).map(|i| (i, i * i)).collect();
+        assert_send_sync(&list);
+        assert_send_sync(&vector);
+        assert_send_sync(&map);
+
+        // Each thread builds its own version on top of the shared ones
+        let totals: Vec<i32> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|t| {
+                    let (mut list, mut vector, mut map) =
+                        (list.clone(), vector.clone(), map.clone());
+                    s.spawn(move || {
+                        list.push_front(t);
+                        vector.push_back(t);
+                        vector.set(0, t);
+                        map.insert(t, -1);
+                        list.iter().sum::<i32>()
+                            + vector.iter().sum::<i32>()
+                            + map.values().sum::<i32>()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let squares: i32 = (0..100).map(|i| i * i).sum();
+        for (t, total) in (0..4).zip(totals) {
+            let map_total = squares - t * t - 1;
+            assert_eq!(total, (4950 + t) + (4950 + t + t) + map_total);
+        }
+        // The originals are unchanged
+        assert_eq!(list.len(), 100);
+        assert_eq!(vector.get(0), Some(&0));
+        assert_eq!(map.get(&0), Some(&0));
+    }
+}
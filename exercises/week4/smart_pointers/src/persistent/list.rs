@@ -0,0 +1,234 @@
+// Persistent singly linked list
+//
+// Like List2::Cons(i32, Rc<List2>) in main.rs, but generic: a list is a
+// pointer to its first node, and lists that were cloned from each other
+// share every node they have in common. push_front and pop_front are O(1)
+// and never copy; pop_front on a shared node clones just that node's value.
+
+use super::{PointerKind, RcKind};
+use std::fmt;
+use std::iter::FromIterator;
+
+struct Node<T, P: PointerKind> {
+    value: T,
+    next: Option<P::Ptr<Node<T, P>>>,
+}
+
+pub struct List<T, P: PointerKind = RcKind> {
+    head: Option<P::Ptr<Node<T, P>>>,
+    len: usize,
+}
+
+impl<T, P: PointerKind> List<T, P> {
+    pub fn new() -> Self {
+        List { head: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.head.as_ref().map(|node| &node.value)
+    }
+
+    pub fn push_front(&mut self, value: T) {
+        let next = self.head.take();
+        self.head = Some(P::new(Node { value, next }));
+        self.len += 1;
+    }
+
+    // The list without its first element, sharing all of its nodes
+    pub fn tail(&self) -> Option<Self> {
+        let head = self.head.as_ref()?;
+        Some(List {
+            head: head.next.clone(),
+            len: self.len - 1,
+        })
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter {
+            next: self.head.as_deref(),
+            remaining: self.len,
+        }
+    }
+
+    // Whether the two lists are the same nodes, not just equal values
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (&self.head, &other.head) {
+            (Some(a), Some(b)) => P::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: Clone, P: PointerKind> List<T, P> {
+    pub fn pop_front(&mut self) -> Option<T> {
+        let head = self.head.take()?;
+        self.len -= 1;
+        match P::try_unwrap(head) {
+            Ok(node) => {
+                self.head = node.next;
+                Some(node.value)
+            }
+            // Another list still uses the node
+            Err(shared) => {
+                self.head = shared.next.clone();
+                Some(shared.value.clone())
+            }
+        }
+    }
+
+    // A new list in reverse order
+    pub fn reversed(&self) -> Self {
+        let mut reversed = List::new();
+        for value in self {
+            reversed.push_front(value.clone());
+        }
+        reversed
+    }
+}
+
+impl<T, P: PointerKind> Drop for List<T, P> {
+    fn drop(&mut self) {
+        // Free the nodes only this list holds one at a time, rather than
+        // recursing through `next`
+        let mut next = self.head.take();
+        while let Some(node) = next {
+            next = match P::try_unwrap(node) {
+                Ok(mut node) => node.next.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl<T, P: PointerKind> Clone for List<T, P> {
+    fn clone(&self) -> Self {
+        List {
+            head: self.head.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T, P: PointerKind> Default for List<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq, P: PointerKind> PartialEq for List<T, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && (self.ptr_eq(other) || self.iter().eq(other.iter()))
+    }
+}
+
+impl<T: Eq, P: PointerKind> Eq for List<T, P> {}
+
+impl<T: fmt::Debug, P: PointerKind> fmt::Debug for List<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// Keeps the iterator's order: the first item ends up at the front
+impl<T, P: PointerKind> FromIterator<T> for List<T, P> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let values: Vec<T> = iter.into_iter().collect();
+        let mut list = List::new();
+        for value in values.into_iter().rev() {
+            list.push_front(value);
+        }
+        list
+    }
+}
+
+pub struct Iter<'a, T, P: PointerKind> {
+    next: Option<&'a Node<T, P>>,
+    remaining: usize,
+}
+
+impl<'a, T, P: PointerKind> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.next?;
+        self.next = node.next.as_deref();
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, P: PointerKind> ExactSizeIterator for Iter<'_, T, P> {}
+
+impl<'a, T, P: PointerKind> IntoIterator for &'a List<T, P> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_versions_share_their_tails() {
+        let a: List<i32> = [5, 10].into_iter().collect();
+        let mut b = a.clone();
+        b.push_front(3);
+        let mut c = a.clone();
+        c.push_front(4);
+
+        assert_eq!(
+            format!("{:?} {:?} {:?}", a, b, c),
+            "[5, 10] [3, 5, 10] [4, 5, 10]"
+        );
+        assert!(b.tail().unwrap().ptr_eq(&a));
+        assert!(c.tail().unwrap().ptr_eq(&a));
+        // a's first node is held by a, b and c
+        assert_eq!(Rc::strong_count(a.head.as_ref().unwrap()), 3);
+        assert_eq!(b.iter().len(), 3);
+    }
+
+    #[test]
+    fn test_pop_front_leaves_other_versions_alone() {
+        let original: List<String> = ["x", "y", "z"].iter().map(|s| s.to_string()).collect();
+        let mut popped = original.clone();
+        assert_eq!(popped.pop_front().as_deref(), Some("x"));
+        assert_eq!(popped.pop_front().as_deref(), Some("y"));
+        assert_eq!(popped.front().map(String::as_str), Some("z"));
+        assert_eq!(popped.len(), 1);
+
+        assert_eq!(original.len(), 3);
+        assert_eq!(original.front().map(String::as_str), Some("x"));
+        assert_eq!(
+            original.reversed(),
+            ["z", "y", "x"].iter().map(|s| s.to_string()).collect()
+        );
+        assert_ne!(original, popped);
+    }
+
+    #[test]
+    fn test_long_lists_drop_without_recursion() {
+        let list: List<u32> = (0..200_000).collect();
+        let shared = list.tail().unwrap();
+        drop(list);
+        assert_eq!(shared.len(), 199_999);
+        assert_eq!(shared.front(), Some(&1));
+    }
+}
@@ -0,0 +1,538 @@
+// Persistent hash map: a hash array mapped trie (HAMT)
+//
+// Each level of the trie consumes five bits of the key's 64-bit hash. A
+// branch only stores the children that exist, packed into a Vec, plus a
+// 32-bit bitmap saying which of the 32 possible slots they are; the
+// position of slot i in the Vec is the number of bits set below bit i. Keys
+// whose whole hashes are equal end up together in a collision node.
+//
+// Lookups and updates visit O(log32 n) nodes, and an update copies only
+// those nodes if they are shared.
+
+use super::{PointerKind, RcKind};
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::FromIterator;
+
+const BITS: u32 = 5;
+const MASK: u64 = (1 << BITS) - 1;
+
+enum Entry<K, V, P: PointerKind> {
+    Leaf(u64, K, V),
+    Node(P::Ptr<Node<K, V, P>>),
+}
+
+enum Node<K, V, P: PointerKind> {
+    Branch {
+        bitmap: u32,
+        children: Vec<Entry<K, V, P>>,
+    },
+    Collision {
+        hash: u64,
+        entries: Vec<(K, V)>,
+    },
+}
+
+impl<K: Clone, V: Clone, P: PointerKind> Clone for Entry<K, V, P> {
+    fn clone(&self) -> Self {
+        match self {
+            Entry::Leaf(hash, key, value) => Entry::Leaf(*hash, key.clone(), value.clone()),
+            Entry::Node(node) => Entry::Node(node.clone()),
+        }
+    }
+}
+
+impl<K: Clone, V: Clone, P: PointerKind> Clone for Node<K, V, P> {
+    fn clone(&self) -> Self {
+        match self {
+            Node::Branch { bitmap, children } => Node::Branch {
+                bitmap: *bitmap,
+                children: children.clone(),
+            },
+            Node::Collision { hash, entries } => Node::Collision {
+                hash: *hash,
+                entries: entries.clone(),
+            },
+        }
+    }
+}
+
+// Which of a branch's 32 slots `hash` falls in at this depth
+fn slot(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) & MASK)
+}
+
+// Where that slot's entry sits in the packed children
+fn position(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+impl<K: Eq, V, P: PointerKind> Node<K, V, P> {
+    fn get<Q>(&self, hash: u64, mut shift: u32, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let mut node = self;
+        loop {
+            match node {
+                Node::Branch { bitmap, children } => {
+                    let bit = slot(hash, shift);
+                    if bitmap & bit == 0 {
+                        return None;
+                    }
+                    match &children[position(*bitmap, bit)] {
+                        Entry::Leaf(h, k, v) => {
+                            return (*h == hash && k.borrow() == key).then_some((k, v));
+                        }
+                        Entry::Node(child) => {
+                            node = child;
+                            shift += BITS;
+                        }
+                    }
+                }
+                Node::Collision { entries, .. } => {
+                    return entries
+                        .iter()
+                        .find(|(k, _)| k.borrow() == key)
+                        .map(|(k, v)| (k, v));
+                }
+            }
+        }
+    }
+}
+
+impl<K: Eq + Clone, V: Clone, P: PointerKind> Node<K, V, P> {
+    fn empty() -> Self {
+        Node::Branch {
+            bitmap: 0,
+            children: Vec::new(),
+        }
+    }
+
+    // Returns the value that was replaced, if any
+    fn insert(&mut self, hash: u64, shift: u32, key: K, value: V) -> Option<V> {
+        match self {
+            Node::Collision { hash: h, entries } if *h == hash => {
+                if let Some(entry) = entries.iter_mut().find(|(k, _)| *k == key) {
+                    return Some(std::mem::replace(&mut entry.1, value));
+                }
+                entries.push((key, value));
+                None
+            }
+            Node::Collision { hash: h, .. } => {
+                // A different hash reached this collision node: put a branch
+                // in its place, holding it, and insert into that
+                let bit = slot(*h, shift);
+                let collision = std::mem::replace(self, Node::empty());
+                *self = Node::Branch {
+                    bitmap: bit,
+                    children: vec![Entry::Node(P::new(collision))],
+                };
+                self.insert(hash, shift, key, value)
+            }
+            Node::Branch { bitmap, children } => {
+                let bit = slot(hash, shift);
+                let pos = position(*bitmap, bit);
+                if *bitmap & bit == 0 {
+                    *bitmap |= bit;
+                    children.insert(pos, Entry::Leaf(hash, key, value));
+                    return None;
+                }
+                match &mut children[pos] {
+                    Entry::Leaf(h, k, v) if *h == hash && *k == key => {
+                        Some(std::mem::replace(v, value))
+                    }
+                    Entry::Leaf(..) => {
+                        // Two keys share this slot: push both one level down
+                        let Entry::Leaf(h, k, v) = std::mem::replace(
+                            &mut children[pos],
+                            Entry::Node(P::new(Node::empty())),
+                        ) else {
+                            unreachable!()
+                        };
+                        let node = Node::pair(shift + BITS, (h, k, v), (hash, key, value));
+                        children[pos] = Entry::Node(P::new(node));
+                        None
+                    }
+                    Entry::Node(child) => P::make_mut(child).insert(hash, shift + BITS, key, value),
+                }
+            }
+        }
+    }
+
+    // A node holding two entries that clash above `shift`
+    fn pair(shift: u32, a: (u64, K, V), b: (u64, K, V)) -> Self {
+        if a.0 == b.0 || shift >= u64::BITS {
+            return Node::Collision {
+                hash: a.0,
+                entries: vec![(a.1, a.2), (b.1, b.2)],
+            };
+        }
+        let (bit_a, bit_b) = (slot(a.0, shift), slot(b.0, shift));
+        if bit_a == bit_b {
+            let child = Node::pair(shift + BITS, a, b);
+            return Node::Branch {
+                bitmap: bit_a,
+                children: vec![Entry::Node(P::new(child))],
+            };
+        }
+        let mut children = vec![Entry::Leaf(a.0, a.1, a.2), Entry::Leaf(b.0, b.1, b.2)];
+        if bit_b < bit_a {
+            children.swap(0, 1);
+        }
+        Node::Branch {
+            bitmap: bit_a | bit_b,
+            children,
+        }
+    }
+
+    fn remove<Q>(&mut self, hash: u64, shift: u32, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        match self {
+            Node::Collision { entries, .. } => {
+                let index = entries.iter().position(|(k, _)| k.borrow() == key)?;
+                Some(entries.swap_remove(index))
+            }
+            Node::Branch { bitmap, children } => {
+                let bit = slot(hash, shift);
+                if *bitmap & bit == 0 {
+                    return None;
+                }
+                let pos = position(*bitmap, bit);
+                let removed = match &mut children[pos] {
+                    Entry::Leaf(h, k, _) => {
+                        if *h != hash || <K as Borrow<Q>>::borrow(k) != key {
+                            return None;
+                        }
+                        *bitmap &= !bit;
+                        let Entry::Leaf(_, k, v) = children.remove(pos) else {
+                            unreachable!()
+                        };
+                        return Some((k, v));
+                    }
+                    Entry::Node(child) => P::make_mut(child).remove(hash, shift + BITS, key)?,
+                };
+                // Keep the trie compact: a child left empty goes, and one
+                // left holding a single entry is pulled up in its place
+                let Entry::Node(child) = &mut children[pos] else {
+                    unreachable!()
+                };
+                match P::make_mut(child).take_single() {
+                    Some(Some(entry)) => children[pos] = entry,
+                    Some(None) => {
+                        *bitmap &= !bit;
+                        children.remove(pos);
+                    }
+                    None => {}
+                }
+                Some(removed)
+            }
+        }
+    }
+
+    // If this node holds at most one key-value pair, takes it out as a leaf:
+    // Some(None) when empty, Some(Some(leaf)) when it held one pair
+    fn take_single(&mut self) -> Option<Option<Entry<K, V, P>>> {
+        match self {
+            Node::Branch { children, .. } if children.is_empty() => Some(None),
+            Node::Branch { children, .. }
+                if children.len() == 1 && matches!(children[0], Entry::Leaf(..)) =>
+            {
+                Some(children.pop())
+            }
+            Node::Collision { entries, .. } if entries.is_empty() => Some(None),
+            Node::Collision { hash, entries } if entries.len() == 1 => {
+                let (k, v) = entries.pop().unwrap();
+                Some(Some(Entry::Leaf(*hash, k, v)))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct Map<K, V, P: PointerKind = RcKind> {
+    root: P::Ptr<Node<K, V, P>>,
+    len: usize,
+    hasher: RandomState,
+}
+
+impl<K, V, P: PointerKind> Map<K, V, P> {
+    pub fn new() -> Self {
+        Map {
+            root: P::new(Node::Branch {
+                bitmap: 0,
+                children: Vec::new(),
+            }),
+            len: 0,
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        let mut iter = Iter {
+            stack: vec![],
+            collisions: [].iter(),
+            remaining: self.len,
+        };
+        match &*self.root {
+            Node::Branch { children, .. } => iter.stack.push(children.iter()),
+            Node::Collision { entries, .. } => iter.collisions = entries.iter(),
+        }
+        iter
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+impl<K: Hash + Eq, V, P: PointerKind> Map<K, V, P> {
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.root.get(self.hash(key), 0, key).map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, P: PointerKind> Map<K, V, P> {
+    // Returns the previous value for the key, if there was one
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let hash = self.hash(&key);
+        let old = P::make_mut(&mut self.root).insert(hash, 0, key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hash(key);
+        // Don't copy the path for a key that isn't there
+        self.root.get(hash, 0, key)?;
+        let (_, value) = P::make_mut(&mut self.root).remove(hash, 0, key)?;
+        self.len -= 1;
+        Some(value)
+    }
+}
+
+impl<K, V, P: PointerKind> Clone for Map<K, V, P> {
+    fn clone(&self) -> Self {
+        Map {
+            root: self.root.clone(),
+            len: self.len,
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl<K, V, P: PointerKind> Default for Map<K, V, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq, V: PartialEq, P: PointerKind> PartialEq for Map<K, V, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: Hash + Eq, V: Eq, P: PointerKind> Eq for Map<K, V, P> {}
+
+impl<K: fmt::Debug, V: fmt::Debug, P: PointerKind> fmt::Debug for Map<K, V, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, P: PointerKind> FromIterator<(K, V)> for Map<K, V, P> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Map::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, P: PointerKind> Extend<(K, V)> for Map<K, V, P> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+// Depth-first over the trie
+pub struct Iter<'a, K, V, P: PointerKind> {
+    // Entries still to visit, one slice per level
+    stack: Vec<std::slice::Iter<'a, Entry<K, V, P>>>,
+    collisions: std::slice::Iter<'a, (K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V, P: PointerKind> Iterator for Iter<'a, K, V, P> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            if let Some((k, v)) = self.collisions.next() {
+                self.remaining -= 1;
+                return Some((k, v));
+            }
+            match self.stack.last_mut()?.next() {
+                Some(Entry::Leaf(_, k, v)) => {
+                    self.remaining -= 1;
+                    return Some((k, v));
+                }
+                Some(Entry::Node(child)) => match &**child {
+                    Node::Branch { children, .. } => self.stack.push(children.iter()),
+                    Node::Collision { entries, .. } => self.collisions = entries.iter(),
+                },
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, P: PointerKind> ExactSizeIterator for Iter<'_, K, V, P> {}
+
+impl<'a, K, V, P: PointerKind> IntoIterator for &'a Map<K, V, P> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, P>;
+
+    fn into_iter(self) -> Iter<'a, K, V, P> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Hashes to one of four values, to force deep tries and collisions
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Clash(u32);
+
+    impl Hash for Clash {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            (self.0 % 4).hash(state);
+        }
+    }
+
+    #[test]
+    fn test_matches_hash_map() {
+        let mut map: Map<u32, u32> = Map::new();
+        let mut model = HashMap::new();
+        // A small LCG picks keys, so inserts, updates and removes mix
+        let mut seed = 12345u64;
+        for step in 0..20_000u32 {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let key = (seed >> 33) as u32 % 3000;
+            if step % 3 == 2 {
+                assert_eq!(map.remove(&key), model.remove(&key));
+            } else {
+                assert_eq!(map.insert(key, step), model.insert(key, step));
+            }
+            assert_eq!(map.len(), model.len());
+        }
+        for key in 0..3000 {
+            assert_eq!(map.get(&key), model.get(&key));
+        }
+        let mut pairs: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+        pairs.sort_unstable();
+        let mut expected: Vec<_> = model.into_iter().collect();
+        expected.sort_unstable();
+        assert_eq!(pairs, expected);
+        assert_eq!(map.iter().len(), map.len());
+    }
+
+    #[test]
+    fn test_colliding_hashes() {
+        let mut map: Map<Clash, u32> = (0..40).map(|i| (Clash(i), i)).collect();
+        assert_eq!(map.len(), 40);
+        assert!((0..40).all(|i| map.get(&Clash(i)) == Some(&i)));
+        assert_eq!(map.get(&Clash(40)), None);
+
+        for i in (0..40).step_by(2) {
+            assert_eq!(map.remove(&Clash(i)), Some(i));
+        }
+        assert_eq!(map.remove(&Clash(0)), None);
+        assert_eq!(map.insert(Clash(1), 100), Some(1));
+        let mut values: Vec<_> = map.values().copied().collect();
+        values.sort_unstable();
+        assert_eq!(values.len(), 20);
+        assert_eq!(values[19], 100);
+        // Emptying the map leaves a bare root
+        for i in (1..40).step_by(2) {
+            assert!(map.remove(&Clash(i)).is_some());
+        }
+        assert!(map.is_empty());
+        assert!(matches!(&*map.root, Node::Branch { children, .. } if children.is_empty()));
+    }
+
+    #[test]
+    fn test_old_versions_are_unchanged() {
+        let v1: Map<String, i32> = [("a", 1), ("b", 2)]
+            .map(|(k, v)| (k.to_string(), v))
+            .into_iter()
+            .collect();
+        let mut v2 = v1.clone();
+        v2.insert("c".to_string(), 3);
+        v2.remove("a");
+        v2.insert("b".to_string(), 0);
+
+        assert_eq!(v1.get("a"), Some(&1));
+        assert_eq!(v1.get("b"), Some(&2));
+        assert_eq!(v1.len(), 2);
+        assert_eq!(v2.get("a"), None);
+        assert_eq!(v2.get("c"), Some(&3));
+        assert_ne!(v1, v2);
+        assert_eq!(format!("{:?}", Map::<i32, i32>::new()), "{}");
+    }
+}
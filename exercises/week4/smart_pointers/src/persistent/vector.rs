@@ -0,0 +1,427 @@
+// Persistent vector: a 32-way trie of chunks plus a tail chunk
+//
+// Elements live in leaf chunks of 32. Index i is found by reading its bits
+// five at a time from the top, so a lookup visits at most
+// log32(len) nodes (7 for four billion elements). The last, partly filled
+// chunk is kept outside the trie as the tail, which makes push_back and
+// pop_back cheap: only when the tail fills up is it moved into the trie.
+//
+// Modifying an element copies the nodes on the path from the root to its
+// chunk if they are shared, and touches nothing else.
+
+use super::{PointerKind, RcKind};
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Index;
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+enum Node<T, P: PointerKind> {
+    Branch(Vec<P::Ptr<Node<T, P>>>),
+    Leaf(Vec<T>),
+}
+
+impl<T: Clone, P: PointerKind> Clone for Node<T, P> {
+    fn clone(&self) -> Self {
+        match self {
+            Node::Branch(children) => Node::Branch(children.clone()),
+            Node::Leaf(values) => Node::Leaf(values.clone()),
+        }
+    }
+}
+
+impl<T, P: PointerKind> Node<T, P> {
+    fn children(&self) -> &[P::Ptr<Node<T, P>>] {
+        match self {
+            Node::Branch(children) => children,
+            Node::Leaf(_) => unreachable!("leaves sit at level 0"),
+        }
+    }
+
+    fn values(&self) -> &[T] {
+        match self {
+            Node::Leaf(values) => values,
+            Node::Branch(_) => unreachable!("branches sit above level 0"),
+        }
+    }
+}
+
+impl<T: Clone, P: PointerKind> Node<T, P> {
+    fn children_mut(&mut self) -> &mut Vec<P::Ptr<Node<T, P>>> {
+        match self {
+            Node::Branch(children) => children,
+            Node::Leaf(_) => unreachable!("leaves sit at level 0"),
+        }
+    }
+}
+
+pub struct Vector<T, P: PointerKind = RcKind> {
+    len: usize,
+    // Bits to shift an index by to find its slot in the root
+    shift: usize,
+    root: P::Ptr<Node<T, P>>,
+    tail: P::Ptr<Vec<T>>,
+}
+
+impl<T, P: PointerKind> Vector<T, P> {
+    pub fn new() -> Self {
+        Vector {
+            len: 0,
+            shift: BITS,
+            root: P::new(Node::Branch(Vec::new())),
+            tail: P::new(Vec::new()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len {
+            return None;
+        }
+        Some(&self.chunk_for(index)[index & MASK])
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.len.checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter {
+            vector: self,
+            next_chunk: 0,
+            chunk: [].iter(),
+        }
+    }
+
+    // Index of the first element in the tail
+    fn tail_offset(&self) -> usize {
+        self.len - self.tail.len()
+    }
+
+    // The 32-element chunk holding `index`, which must be in range
+    fn chunk_for(&self, index: usize) -> &[T] {
+        if index >= self.tail_offset() {
+            return &self.tail;
+        }
+        let mut node = &*self.root;
+        let mut level = self.shift;
+        while level > 0 {
+            node = &node.children()[(index >> level) & MASK];
+            level -= BITS;
+        }
+        node.values()
+    }
+}
+
+impl<T: Clone, P: PointerKind> Vector<T, P> {
+    pub fn push_back(&mut self, value: T) {
+        if self.tail.len() == WIDTH {
+            self.push_tail();
+        }
+        P::make_mut(&mut self.tail).push(value);
+        self.len += 1;
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let value = P::make_mut(&mut self.tail).pop().unwrap();
+        self.len -= 1;
+        if self.tail.is_empty() && self.len > 0 {
+            self.pop_tail();
+        }
+        Some(value)
+    }
+
+    // Replaces the element at `index`, returning the old one
+    //
+    // Panics if `index` is out of bounds.
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        assert!(
+            index < self.len,
+            "index {} out of bounds for length {}",
+            index,
+            self.len
+        );
+        let slot = if index >= self.tail_offset() {
+            let offset = self.tail_offset();
+            &mut P::make_mut(&mut self.tail)[index - offset]
+        } else {
+            let mut node = P::make_mut(&mut self.root);
+            let mut level = self.shift;
+            while level > 0 {
+                let child = &mut node.children_mut()[(index >> level) & MASK];
+                node = P::make_mut(child);
+                level -= BITS;
+            }
+            match node {
+                Node::Leaf(values) => &mut values[index & MASK],
+                Node::Branch(_) => unreachable!("branches sit above level 0"),
+            }
+        };
+        std::mem::replace(slot, value)
+    }
+
+    // Moves the full tail into the trie as its rightmost leaf
+    fn push_tail(&mut self) {
+        // Index of the first element going into the trie
+        let index = self.len - WIDTH;
+        let tail = std::mem::replace(&mut self.tail, P::new(Vec::with_capacity(WIDTH)));
+        let leaf = P::new(Node::Leaf(
+            P::try_unwrap(tail).unwrap_or_else(|shared| (*shared).clone()),
+        ));
+        if index >> self.shift >= WIDTH {
+            // The trie is full at this height: grow a new root above it
+            let old_root = self.root.clone();
+            let path = Self::new_path(self.shift, leaf);
+            self.root = P::new(Node::Branch(vec![old_root, path]));
+            self.shift += BITS;
+            return;
+        }
+        let mut node = P::make_mut(&mut self.root);
+        let mut level = self.shift;
+        loop {
+            let slot = (index >> level) & MASK;
+            let children = node.children_mut();
+            if level == BITS {
+                children.push(leaf);
+                return;
+            }
+            if slot == children.len() {
+                children.push(Self::new_path(level - BITS, leaf));
+                return;
+            }
+            node = P::make_mut(&mut children[slot]);
+            level -= BITS;
+        }
+    }
+
+    // A chain of single-child branches from `level` down to the leaf
+    fn new_path(level: usize, leaf: P::Ptr<Node<T, P>>) -> P::Ptr<Node<T, P>> {
+        (0..level / BITS).fold(leaf, |node, _| P::new(Node::Branch(vec![node])))
+    }
+
+    // Takes the rightmost leaf back out of the trie to be the tail
+    fn pop_tail(&mut self) {
+        let index = self.len - 1;
+        let leaf = {
+            let mut node = P::make_mut(&mut self.root);
+            let mut level = self.shift;
+            loop {
+                let children = node.children_mut();
+                if level == BITS {
+                    break children.pop().unwrap();
+                }
+                let slot = (index >> level) & MASK;
+                node = P::make_mut(&mut children[slot]);
+                level -= BITS;
+            }
+        };
+        self.prune(index);
+        self.tail = P::new(match P::try_unwrap(leaf) {
+            Ok(Node::Leaf(values)) => values,
+            Ok(Node::Branch(_)) => unreachable!("leaves sit at level 0"),
+            Err(shared) => shared.values().to_vec(),
+        });
+        // Drop a root that only has one child left
+        while self.shift > BITS && self.root.children().len() == 1 {
+            self.root = self.root.children()[0].clone();
+            self.shift -= BITS;
+        }
+    }
+
+    // Removes branches on the path to `index` that were left empty
+    fn prune(&mut self, index: usize) {
+        fn prune_from<T: Clone, P: PointerKind>(node: &mut Node<T, P>, index: usize, level: usize) {
+            if level == BITS {
+                return;
+            }
+            let children = node.children_mut();
+            let slot = (index >> level) & MASK;
+            if slot >= children.len() {
+                return;
+            }
+            let child = P::make_mut(&mut children[slot]);
+            prune_from(child, index, level - BITS);
+            if child.children().is_empty() {
+                children.pop();
+            }
+        }
+        prune_from(P::make_mut(&mut self.root), index, self.shift);
+    }
+}
+
+impl<T, P: PointerKind> Clone for Vector<T, P> {
+    fn clone(&self) -> Self {
+        Vector {
+            len: self.len,
+            shift: self.shift,
+            root: self.root.clone(),
+            tail: self.tail.clone(),
+        }
+    }
+}
+
+impl<T, P: PointerKind> Default for Vector<T, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, P: PointerKind> Index<usize> for Vector<T, P> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        match self.get(index) {
+            Some(value) => value,
+            None => panic!("index {} out of bounds for length {}", index, self.len),
+        }
+    }
+}
+
+impl<T: PartialEq, P: PointerKind> PartialEq for Vector<T, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, P: PointerKind> Eq for Vector<T, P> {}
+
+impl<T: fmt::Debug, P: PointerKind> fmt::Debug for Vector<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Clone, P: PointerKind> FromIterator<T> for Vector<T, P> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vector = Vector::new();
+        vector.extend(iter);
+        vector
+    }
+}
+
+impl<T: Clone, P: PointerKind> Extend<T> for Vector<T, P> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for value in iter {
+            self.push_back(value);
+        }
+    }
+}
+
+// Walks one chunk at a time, so each element costs O(1) on average
+pub struct Iter<'a, T, P: PointerKind> {
+    vector: &'a Vector<T, P>,
+    // Index of the first element after `chunk`
+    next_chunk: usize,
+    chunk: std::slice::Iter<'a, T>,
+}
+
+impl<'a, T, P: PointerKind> Iterator for Iter<'a, T, P> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if let Some(value) = self.chunk.next() {
+            return Some(value);
+        }
+        if self.next_chunk >= self.vector.len {
+            return None;
+        }
+        self.chunk = self.vector.chunk_for(self.next_chunk).iter();
+        self.next_chunk += self.chunk.len();
+        self.chunk.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.vector.len - self.next_chunk + self.chunk.len();
+        (remaining, Some(remaining))
+    }
+}
+
+impl<T, P: PointerKind> ExactSizeIterator for Iter<'_, T, P> {}
+
+impl<'a, T, P: PointerKind> IntoIterator for &'a Vector<T, P> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, P>;
+
+    fn into_iter(self) -> Iter<'a, T, P> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_vec_through_pushes_sets_and_pops() {
+        // Crosses the one, two and three level boundaries both ways
+        let sizes = [0, 1, 31, 32, 33, 64, 65, 1024, 1056, 1057, 40_000];
+        let mut vector: Vector<usize> = Vector::new();
+        let mut model = Vec::new();
+        for &size in &sizes {
+            while vector.len() < size {
+                vector.push_back(vector.len());
+                model.push(model.len());
+            }
+            assert_eq!(vector.len(), model.len());
+            assert!(vector.iter().eq(model.iter()));
+            for i in (0..size).step_by(97) {
+                assert_eq!(vector.set(i, i * 2), model[i]);
+                model[i] = i * 2;
+            }
+            assert_eq!(vector.last(), model.last());
+        }
+        for &size in sizes.iter().rev() {
+            while vector.len() > size {
+                assert_eq!(vector.pop_back(), model.pop());
+            }
+            assert!(vector.iter().eq(model.iter()));
+            assert_eq!(vector.get(size), None);
+        }
+        assert_eq!(vector.pop_back(), None);
+        assert_eq!((vector.shift, vector.root.children().len()), (BITS, 0));
+    }
+
+    #[test]
+    fn test_old_versions_are_unchanged() {
+        let v1: Vector<i32> = (0..100).collect();
+        let mut v2 = v1.clone();
+        v2.set(5, -5);
+        v2.push_back(100);
+        let mut v3 = v2.clone();
+        for _ in 0..50 {
+            v3.pop_back();
+        }
+
+        assert_eq!((v1.len(), v1[5], v1.last()), (100, 5, Some(&99)));
+        assert_eq!((v2.len(), v2[5], v2.last()), (101, -5, Some(&100)));
+        assert_eq!((v3.len(), v3[5], v3.last()), (51, -5, Some(&50)));
+        // Untouched chunks are still shared
+        assert!(RcKind::ptr_eq(
+            &v1.root.children()[1],
+            &v2.root.children()[1]
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_set_out_of_bounds_panics() {
+        let mut vector: Vector<i32> = (0..3).collect();
+        vector.set(3, 0);
+    }
+}
@@ -0,0 +1,325 @@
+// Single-threaded reference counted pointer with weak references
+//
+// A hand-rolled Rc<T>: the data and both counts live in one heap allocation.
+// The strong pointers collectively hold one implicit weak reference, so the
+// allocation is freed once the last strong and the last weak pointer are gone.
+//
+// The unsafe code here is exercised by the tests below, which CI runs under
+// Miri (.github/workflows/miri.yml) to catch undefined behaviour. Locally:
+//   cargo +nightly miri test -p smart_pointers --lib shared_ptr
+
+use std::cell::Cell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+struct SharedData<T> {
+    strong: Cell<usize>,
+    weak: Cell<usize>,
+    data: ManuallyDrop<T>,
+}
+
+pub struct SharedPtr<T> {
+    ptr: NonNull<SharedData<T>>,
+    // Owns a SharedData<T>; also makes SharedPtr !Send and !Sync
+    _marker: PhantomData<SharedData<T>>,
+}
+
+pub struct WeakPtr<T> {
+    ptr: NonNull<SharedData<T>>,
+    _marker: PhantomData<SharedData<T>>,
+}
+
+impl<T> SharedPtr<T> {
+    pub fn new(data: T) -> Self {
+        let boxed = Box::new(SharedData {
+            strong: Cell::new(1),
+            weak: Cell::new(1),
+            data: ManuallyDrop::new(data),
+        });
+        SharedPtr::from_inner(NonNull::from(Box::leak(boxed)))
+    }
+
+    fn from_inner(ptr: NonNull<SharedData<T>>) -> Self {
+        SharedPtr {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    fn inner(&self) -> &SharedData<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+    pub fn weak_count(this: &Self) -> usize {
+        this.inner().weak.get() - 1
+    }
+
+    // Kept from the original exercise; same as strong_count
+    pub fn get_ref_count(&self) -> usize {
+        Self::strong_count(self)
+    }
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        &*this.inner().data as *const T
+    }
+
+    pub fn downgrade(this: &Self) -> WeakPtr<T> {
+        let weak = &this.inner().weak;
+        weak.set(weak.get() + 1);
+        WeakPtr {
+            ptr: this.ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    fn is_unique(&self) -> bool {
+        Self::strong_count(self) == 1 && Self::weak_count(self) == 0
+    }
+
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.is_unique() {
+            Some(unsafe { &mut (*this.ptr.as_ptr()).data })
+        } else {
+            None
+        }
+    }
+
+    // Clone-on-write: clones the data into a fresh allocation unless this is
+    // the only pointer to it. Outstanding WeakPtrs are disassociated.
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        if !this.is_unique() {
+            *this = SharedPtr::new((**this).clone());
+        }
+        unsafe { &mut (*this.ptr.as_ptr()).data }
+    }
+
+    // Returns the data if this is the only strong pointer, otherwise gives
+    // the pointer back
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if Self::strong_count(&this) != 1 {
+            return Err(this);
+        }
+        let this = ManuallyDrop::new(this);
+        this.inner().strong.set(0);
+        let data = unsafe { ptr::read(&*this.inner().data) };
+        // Release the implicit weak reference held by the strong pointers
+        drop(WeakPtr {
+            ptr: this.ptr,
+            _marker: PhantomData,
+        });
+        Ok(data)
+    }
+}
+
+impl<T> Clone for SharedPtr<T> {
+    fn clone(&self) -> Self {
+        let strong = &self.inner().strong;
+        strong.set(strong.get() + 1);
+        SharedPtr::from_inner(self.ptr)
+    }
+}
+
+impl<T> Deref for SharedPtr<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner().data
+    }
+}
+
+impl<T> Drop for SharedPtr<T> {
+    fn drop(&mut self) {
+        let strong = &self.inner().strong;
+        strong.set(strong.get() - 1);
+        if strong.get() == 0 {
+            unsafe {
+                ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).data);
+            }
+            drop(WeakPtr {
+                ptr: self.ptr,
+                _marker: PhantomData,
+            });
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SharedPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for SharedPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T> WeakPtr<T> {
+    fn inner(&self) -> &SharedData<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub fn upgrade(&self) -> Option<SharedPtr<T>> {
+        let strong = &self.inner().strong;
+        if strong.get() == 0 {
+            None
+        } else {
+            strong.set(strong.get() + 1);
+            Some(SharedPtr::from_inner(self.ptr))
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.inner().strong.get()
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<T> Clone for WeakPtr<T> {
+    fn clone(&self) -> Self {
+        let weak = &self.inner().weak;
+        weak.set(weak.get() + 1);
+        WeakPtr {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for WeakPtr<T> {
+    fn drop(&mut self) {
+        let weak = &self.inner().weak;
+        weak.set(weak.get() - 1);
+        if weak.get() == 0 {
+            // The data was dropped with the last strong pointer; ManuallyDrop
+            // keeps Box from dropping it a second time
+            unsafe {
+                drop(Box::from_raw(self.ptr.as_ptr()));
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for WeakPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(WeakPtr)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts how many times it has been dropped
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_clone_and_drop_counts() {
+        let drops = Cell::new(0);
+        let a = SharedPtr::new(DropCounter(&drops));
+        let b = a.clone();
+        assert_eq!(a.get_ref_count(), 2);
+        assert!(SharedPtr::ptr_eq(&a, &b));
+
+        drop(a);
+        assert_eq!(drops.get(), 0);
+        drop(b);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_weak_upgrade_and_downgrade() {
+        let strong = SharedPtr::new(String::from("hello"));
+        let weak = SharedPtr::downgrade(&strong);
+        assert_eq!(SharedPtr::weak_count(&strong), 1);
+        assert_eq!(*weak.upgrade().unwrap(), "hello");
+
+        drop(strong);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+    }
+
+    #[test]
+    fn test_data_dropped_before_last_weak() {
+        let drops = Cell::new(0);
+        let strong = SharedPtr::new(DropCounter(&drops));
+        let weak = SharedPtr::downgrade(&strong);
+        let weak2 = weak.clone();
+
+        drop(strong);
+        assert_eq!(drops.get(), 1);
+        assert!(weak.ptr_eq(&weak2));
+        drop(weak);
+        drop(weak2);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_get_mut_requires_uniqueness() {
+        let mut a = SharedPtr::new(1);
+        *SharedPtr::get_mut(&mut a).unwrap() += 1;
+        assert_eq!(*a, 2);
+
+        let b = a.clone();
+        assert!(SharedPtr::get_mut(&mut a).is_none());
+        drop(b);
+
+        let weak = SharedPtr::downgrade(&a);
+        assert!(SharedPtr::get_mut(&mut a).is_none());
+        drop(weak);
+        assert!(SharedPtr::get_mut(&mut a).is_some());
+    }
+
+    #[test]
+    fn test_make_mut_clones_on_write() {
+        let mut a = SharedPtr::new(vec![1, 2]);
+        let b = a.clone();
+        SharedPtr::make_mut(&mut a).push(3);
+        assert_eq!(*a, vec![1, 2, 3]);
+        assert_eq!(*b, vec![1, 2]);
+
+        let before = SharedPtr::as_ptr(&a);
+        SharedPtr::make_mut(&mut a).push(4);
+        assert_eq!(SharedPtr::as_ptr(&a), before);
+
+        let weak = SharedPtr::downgrade(&a);
+        SharedPtr::make_mut(&mut a).push(5);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_try_unwrap() {
+        let a = SharedPtr::new(String::from("only"));
+        let b = a.clone();
+        let a = SharedPtr::try_unwrap(a).unwrap_err();
+        drop(b);
+
+        let weak = SharedPtr::downgrade(&a);
+        assert_eq!(SharedPtr::try_unwrap(a).unwrap(), "only");
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,497 @@
+// Generic tree built from Rc and Weak
+//
+// The same layout as the Node in main.rs, for any T: a node owns its
+// children through Rc and points back at its parent through Weak, so the
+// reference counts never form a cycle and a tree is freed as soon as the
+// last handle to its root goes away.
+//
+// A Node<T> is a handle to one node. Handles are cheap to clone, keep their
+// node (and its subtree) alive, and compare equal when they refer to the
+// same node. Operations that move nodes check that the result is still a
+// tree: a node can never become its own ancestor.
+//
+// Dropping is iterative, so very deep trees do not overflow the stack.
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+struct NodeData<T> {
+    value: RefCell<T>,
+    parent: RefCell<Weak<NodeData<T>>>,
+    children: RefCell<Vec<Rc<NodeData<T>>>>,
+}
+
+impl<T> Drop for NodeData<T> {
+    fn drop(&mut self) {
+        // Unlink children we hold the last reference to before they drop,
+        // instead of recursing once per level
+        let mut stack = std::mem::take(self.children.get_mut());
+        while let Some(child) = stack.pop() {
+            if let Ok(mut data) = Rc::try_unwrap(child) {
+                stack.append(data.children.get_mut());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeError {
+    // The node is the root of its tree, so it has no parent to leave
+    IsRoot,
+    // The move would make a node its own ancestor
+    WouldCreateCycle,
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::IsRoot => write!(f, "the node is the root of its tree"),
+            TreeError::WouldCreateCycle => write!(f, "a node cannot become its own ancestor"),
+        }
+    }
+}
+
+impl Error for TreeError {}
+
+// attach failed because the node is inside the tree being attached; hands
+// the tree back
+pub struct AttachError<T>(pub Tree<T>);
+
+impl<T> fmt::Debug for AttachError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AttachError { .. }")
+    }
+}
+
+impl<T> fmt::Display for AttachError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a tree cannot be attached below one of its own nodes")
+    }
+}
+
+impl<T> Error for AttachError<T> {}
+
+pub struct Node<T>(Rc<NodeData<T>>);
+
+impl<T> Node<T> {
+    fn new(value: T) -> Self {
+        Node(Rc::new(NodeData {
+            value: RefCell::new(value),
+            parent: RefCell::new(Weak::new()),
+            children: RefCell::new(Vec::new()),
+        }))
+    }
+
+    pub fn value(&self) -> Ref<'_, T> {
+        self.0.value.borrow()
+    }
+
+    pub fn value_mut(&self) -> RefMut<'_, T> {
+        self.0.value.borrow_mut()
+    }
+
+    pub fn parent(&self) -> Option<Node<T>> {
+        self.0.parent.borrow().upgrade().map(Node)
+    }
+
+    pub fn children(&self) -> Vec<Node<T>> {
+        self.0.children.borrow().iter().cloned().map(Node).collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.parent().is_none()
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.0.children.borrow().is_empty()
+    }
+
+    // Number of edges up to the root
+    pub fn depth(&self) -> usize {
+        self.ancestors().count()
+    }
+
+    // Adds a new last child
+    pub fn append(&self, value: T) -> Node<T> {
+        let child = Node::new(value);
+        self.link(&child);
+        child
+    }
+
+    // Moves another tree in as the last child of this node
+    pub fn attach(&self, tree: Tree<T>) -> Result<Node<T>, AttachError<T>> {
+        // A handle into `tree` could be this very node
+        if self.root() == tree.root {
+            return Err(AttachError(tree));
+        }
+        self.link(&tree.root);
+        Ok(tree.root)
+    }
+
+    // Takes this node and its subtree out of the tree, as a tree of its own
+    pub fn remove(&self) -> Result<Tree<T>, TreeError> {
+        let parent = self.parent().ok_or(TreeError::IsRoot)?;
+        parent.unlink(self);
+        Ok(Tree { root: self.clone() })
+    }
+
+    // Moves this node and its subtree under `new_parent`, as its last child
+    pub fn reparent(&self, new_parent: &Node<T>) -> Result<(), TreeError> {
+        let parent = self.parent().ok_or(TreeError::IsRoot)?;
+        if new_parent.path_to_root().contains(self) {
+            return Err(TreeError::WouldCreateCycle);
+        }
+        parent.unlink(self);
+        new_parent.link(self);
+        Ok(())
+    }
+
+    // The topmost ancestor, or this node if it is a root
+    pub fn root(&self) -> Node<T> {
+        self.ancestors().last().unwrap_or_else(|| self.clone())
+    }
+
+    // Parent, grandparent, ... up to the root
+    pub fn ancestors(&self) -> Ancestors<T> {
+        Ancestors {
+            next: self.parent(),
+        }
+    }
+
+    // This node followed by its ancestors
+    pub fn path_to_root(&self) -> Vec<Node<T>> {
+        std::iter::once(self.clone())
+            .chain(self.ancestors())
+            .collect()
+    }
+
+    // Deepest node that has both as descendants (a node counts as its own
+    // descendant), or None if they are in different trees
+    pub fn lowest_common_ancestor(&self, other: &Node<T>) -> Option<Node<T>> {
+        let (mut a, mut b) = (self.clone(), other.clone());
+        let (mut depth_a, mut depth_b) = (a.depth(), b.depth());
+        while depth_a > depth_b {
+            a = a.parent()?;
+            depth_a -= 1;
+        }
+        while depth_b > depth_a {
+            b = b.parent()?;
+            depth_b -= 1;
+        }
+        while a != b {
+            a = a.parent()?;
+            b = b.parent()?;
+        }
+        Some(a)
+    }
+
+    // Node, then each child's subtree in order
+    pub fn pre_order(&self) -> PreOrder<T> {
+        PreOrder {
+            stack: vec![self.clone()],
+        }
+    }
+
+    // Each child's subtree in order, then the node
+    pub fn post_order(&self) -> PostOrder<T> {
+        PostOrder {
+            stack: vec![(self.clone(), false)],
+        }
+    }
+
+    // Breadth first: the node, its children, its grandchildren, ...
+    pub fn level_order(&self) -> LevelOrder<T> {
+        LevelOrder {
+            queue: VecDeque::from([self.clone()]),
+        }
+    }
+
+    fn link(&self, child: &Node<T>) {
+        *child.0.parent.borrow_mut() = Rc::downgrade(&self.0);
+        self.0.children.borrow_mut().push(Rc::clone(&child.0));
+    }
+
+    fn unlink(&self, child: &Node<T>) {
+        self.0
+            .children
+            .borrow_mut()
+            .retain(|c| !Rc::ptr_eq(c, &child.0));
+        *child.0.parent.borrow_mut() = Weak::new();
+    }
+}
+
+impl<T> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        Node(Rc::clone(&self.0))
+    }
+}
+
+// Handles are equal when they refer to the same node
+impl<T> PartialEq for Node<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Eq for Node<T> {}
+
+impl<T: fmt::Debug> fmt::Debug for Node<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Node").field(&*self.value()).finish()
+    }
+}
+
+pub struct Ancestors<T> {
+    next: Option<Node<T>>,
+}
+
+impl<T> Iterator for Ancestors<T> {
+    type Item = Node<T>;
+
+    fn next(&mut self) -> Option<Node<T>> {
+        let node = self.next.take()?;
+        self.next = node.parent();
+        Some(node)
+    }
+}
+
+pub struct PreOrder<T> {
+    stack: Vec<Node<T>>,
+}
+
+impl<T> Iterator for PreOrder<T> {
+    type Item = Node<T>;
+
+    fn next(&mut self) -> Option<Node<T>> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children().into_iter().rev());
+        Some(node)
+    }
+}
+
+pub struct PostOrder<T> {
+    // (node, whether its children have been pushed already)
+    stack: Vec<(Node<T>, bool)>,
+}
+
+impl<T> Iterator for PostOrder<T> {
+    type Item = Node<T>;
+
+    fn next(&mut self) -> Option<Node<T>> {
+        loop {
+            let (node, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some(node);
+            }
+            let children = node.children();
+            self.stack.push((node, true));
+            self.stack
+                .extend(children.into_iter().rev().map(|child| (child, false)));
+        }
+    }
+}
+
+pub struct LevelOrder<T> {
+    queue: VecDeque<Node<T>>,
+}
+
+impl<T> Iterator for LevelOrder<T> {
+    type Item = Node<T>;
+
+    fn next(&mut self) -> Option<Node<T>> {
+        let node = self.queue.pop_front()?;
+        self.queue.extend(node.children());
+        Some(node)
+    }
+}
+
+// Owns a root node. Dropping the tree frees every node that no other
+// handle is keeping alive.
+pub struct Tree<T> {
+    root: Node<T>,
+}
+
+impl<T> Tree<T> {
+    pub fn new(value: T) -> Self {
+        Tree {
+            root: Node::new(value),
+        }
+    }
+
+    pub fn root(&self) -> Node<T> {
+        self.root.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.pre_order().count()
+    }
+
+    // A tree always has its root
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn pre_order(&self) -> PreOrder<T> {
+        self.root.pre_order()
+    }
+
+    pub fn post_order(&self) -> PostOrder<T> {
+        self.root.post_order()
+    }
+
+    pub fn level_order(&self) -> LevelOrder<T> {
+        self.root.level_order()
+    }
+}
+
+// One node per line, indented by depth
+impl<T: fmt::Display> fmt::Display for Tree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let base = self.root.depth();
+        for node in self.pre_order() {
+            let indent = 2 * (node.depth() - base);
+            writeln!(f, "{:indent$}{}", "", node.value(), indent = indent)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Tree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tree").field("root", &self.root).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //        a
+    //      / | \
+    //     b  c  d
+    //    / \     \
+    //   e   f     g
+    fn sample() -> (Tree<char>, Vec<Node<char>>) {
+        let tree = Tree::new('a');
+        let a = tree.root();
+        let b = a.append('b');
+        let c = a.append('c');
+        let d = a.append('d');
+        let e = b.append('e');
+        let f = b.append('f');
+        let g = d.append('g');
+        (tree, vec![a, b, c, d, e, f, g])
+    }
+
+    fn values(nodes: impl Iterator<Item = Node<char>>) -> String {
+        nodes.map(|node| *node.value()).collect()
+    }
+
+    #[test]
+    fn test_traversals() {
+        let (tree, nodes) = sample();
+        assert_eq!(values(tree.pre_order()), "abefcdg");
+        assert_eq!(values(tree.post_order()), "efbcgda");
+        assert_eq!(values(tree.level_order()), "abcdefg");
+        assert_eq!(values(nodes[1].pre_order()), "bef");
+        assert_eq!(tree.len(), 7);
+        assert_eq!(tree.to_string(), "a\n  b\n    e\n    f\n  c\n  d\n    g\n");
+    }
+
+    #[test]
+    fn test_paths_and_common_ancestors() {
+        let (tree, nodes) = sample();
+        let [a, b, c, d, e, f, g] = <[Node<char>; 7]>::try_from(nodes).unwrap();
+        assert_eq!(values(e.path_to_root().into_iter()), "eba");
+        assert_eq!(e.depth(), 2);
+        assert_eq!(g.root(), a);
+
+        assert_eq!(e.lowest_common_ancestor(&f), Some(b.clone()));
+        assert_eq!(e.lowest_common_ancestor(&g), Some(a.clone()));
+        assert_eq!(b.lowest_common_ancestor(&f), Some(b.clone()));
+        assert_eq!(c.lowest_common_ancestor(&c), Some(c.clone()));
+
+        let other = Tree::new('x');
+        assert_eq!(d.lowest_common_ancestor(&other.root()), None);
+        drop(tree);
+    }
+
+    #[test]
+    fn test_remove_reparent_and_attach() {
+        let (tree, nodes) = sample();
+        let (a, b, d, e) = (&nodes[0], &nodes[1], &nodes[3], &nodes[4]);
+
+        b.reparent(d).unwrap();
+        assert_eq!(values(tree.pre_order()), "acdgbef");
+        assert_eq!(b.parent(), Some(d.clone()));
+
+        // A node cannot move under itself or its own descendants
+        assert_eq!(d.reparent(e), Err(TreeError::WouldCreateCycle));
+        assert_eq!(d.reparent(d), Err(TreeError::WouldCreateCycle));
+        assert_eq!(a.reparent(e), Err(TreeError::IsRoot));
+        assert_eq!(a.remove().err(), Some(TreeError::IsRoot));
+
+        let removed = d.remove().unwrap();
+        assert!(d.is_root());
+        assert_eq!(values(tree.pre_order()), "ac");
+        assert_eq!(values(removed.pre_order()), "dgbef");
+
+        // Nor can a tree be attached below one of its own nodes
+        let inner = removed.root().children()[0].clone();
+        let removed = inner.attach(removed).unwrap_err().0;
+        let d = a.attach(removed).unwrap();
+        assert_eq!(d.parent(), Some(a.clone()));
+        assert_eq!(values(tree.pre_order()), "acdgbef");
+    }
+
+    #[test]
+    fn test_dropping_the_tree_frees_every_node() {
+        let (tree, nodes) = sample();
+        let weak: Vec<_> = nodes.iter().map(|node| Rc::downgrade(&node.0)).collect();
+        // Each node is held by its parent and by our handle, the root only
+        // by the tree and our handle: parent links add nothing
+        assert_eq!(Rc::strong_count(&nodes[0].0), 2);
+        assert!(nodes[1..].iter().all(|node| Rc::strong_count(&node.0) == 2));
+
+        // Moving nodes around leaves no extra strong references behind
+        nodes[1].reparent(&nodes[6]).unwrap();
+        let removed = nodes[3].remove().unwrap();
+        nodes[0].attach(removed).unwrap();
+
+        drop(nodes);
+        assert!(weak.iter().all(|w| w.strong_count() == 1));
+        drop(tree);
+        assert!(weak.iter().all(|w| w.strong_count() == 0));
+    }
+
+    #[test]
+    fn test_handles_outlive_the_tree() {
+        let (tree, nodes) = sample();
+        let b = nodes[1].clone();
+        let weak_a = Rc::downgrade(&nodes[0].0);
+        drop(nodes);
+        drop(tree);
+        // b keeps its subtree alive, but not its parent
+        assert_eq!(weak_a.strong_count(), 0);
+        assert!(b.parent().is_none());
+        assert_eq!(values(b.pre_order()), "bef");
+        *b.value_mut() = 'B';
+        assert_eq!(*b.value(), 'B');
+    }
+
+    #[test]
+    fn test_deep_tree_drops_without_recursion() {
+        let tree = Tree::new(0);
+        let mut node = tree.root();
+        for i in 1..200_000 {
+            node = node.append(i);
+        }
+        let leaf = Rc::downgrade(&node.0);
+        drop(node);
+        drop(tree);
+        assert_eq!(leaf.strong_count(), 0);
+    }
+}
@@ -0,0 +1,413 @@
+// Atomically reference counted pointer with weak references
+//
+// Memory layout follows std::sync::Arc: one heap allocation holds the data
+// plus a strong and a weak count. All strong pointers together own one
+// implicit weak reference, so the allocation is freed only after the last
+// strong *and* the last weak pointer are gone.
+//
+// The unsafe code here is exercised by the tests below, which CI runs under
+// Miri (.github/workflows/miri.yml) to catch undefined behaviour. Locally:
+//   cargo +nightly miri test -p atomics --lib
+
+use std::fmt;
+use std::hint;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::process;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+
+// Refuse to let a count overflow into the "locked" sentinel (same as std)
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+// Value stored in the weak count while is_unique() inspects the strong count
+const WEAK_LOCKED: usize = usize::MAX;
+
+struct ArcInner<T> {
+    strong: AtomicUsize,
+    weak: AtomicUsize,
+    data: ManuallyDrop<T>,
+}
+
+pub struct SimpleArc<T> {
+    ptr: NonNull<ArcInner<T>>,
+}
+
+pub struct Weak<T> {
+    ptr: NonNull<ArcInner<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for SimpleArc<T> {}
+unsafe impl<T: Send + Sync> Sync for SimpleArc<T> {}
+unsafe impl<T: Send + Sync> Send for Weak<T> {}
+unsafe impl<T: Send + Sync> Sync for Weak<T> {}
+
+impl<T> SimpleArc<T> {
+    pub fn new(data: T) -> Self {
+        let boxed = Box::new(ArcInner {
+            strong: AtomicUsize::new(1),
+            weak: AtomicUsize::new(1),
+            data: ManuallyDrop::new(data),
+        });
+        SimpleArc {
+            ptr: NonNull::from(Box::leak(boxed)),
+        }
+    }
+
+    fn inner(&self) -> &ArcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.load(Ordering::Acquire)
+    }
+
+    pub fn weak_count(this: &Self) -> usize {
+        let weak = this.inner().weak.load(Ordering::Acquire);
+        if weak == WEAK_LOCKED {
+            // Someone is checking uniqueness, so there are no other weaks
+            0
+        } else {
+            weak - 1
+        }
+    }
+
+    // Kept from the original exercise; same as strong_count
+    pub fn get_ref_count(&self) -> usize {
+        Self::strong_count(self)
+    }
+
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    pub fn as_ptr(this: &Self) -> *const T {
+        &*this.inner().data as *const T
+    }
+
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        let weak = &this.inner().weak;
+        let mut current = weak.load(Ordering::Relaxed);
+        loop {
+            // Wait out a concurrent is_unique() check
+            if current == WEAK_LOCKED {
+                hint::spin_loop();
+                current = weak.load(Ordering::Relaxed);
+                continue;
+            }
+            if current > MAX_REFCOUNT {
+                process::abort();
+            }
+            match weak.compare_exchange_weak(current, current + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Weak { ptr: this.ptr },
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    // True if this is the only strong pointer and no weak pointers exist.
+    // The weak count is locked meanwhile so no Weak can upgrade behind our back.
+    fn is_unique(&mut self) -> bool {
+        let inner = self.inner();
+        if inner
+            .weak
+            .compare_exchange(1, WEAK_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            // Acquire pairs with the Release decrement in Drop so we see all
+            // writes made through pointers that have since been dropped
+            let unique = inner.strong.load(Ordering::Acquire) == 1;
+            inner.weak.store(1, Ordering::Release);
+            unique
+        } else {
+            false
+        }
+    }
+
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if this.is_unique() {
+            Some(unsafe { &mut (*this.ptr.as_ptr()).data })
+        } else {
+            None
+        }
+    }
+
+    // Clone-on-write: clones the data into a fresh allocation unless this is
+    // the only pointer to it. Outstanding Weak pointers are disassociated.
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        if !this.is_unique() {
+            *this = SimpleArc::new((**this).clone());
+        }
+        unsafe { &mut (*this.ptr.as_ptr()).data }
+    }
+
+    // Returns the data if this is the only strong pointer, otherwise gives
+    // the pointer back
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this
+            .inner()
+            .strong
+            .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(this);
+        }
+        atomic::fence(Ordering::Acquire);
+
+        let this = ManuallyDrop::new(this);
+        let data = unsafe { ptr::read(&*this.inner().data) };
+        // Release the implicit weak reference held by the strong pointers
+        drop(Weak { ptr: this.ptr });
+        Ok(data)
+    }
+}
+
+impl<T> Clone for SimpleArc<T> {
+    fn clone(&self) -> Self {
+        // Relaxed is enough: a new reference can only be created from an
+        // existing one, which already keeps the allocation alive
+        let old = self.inner().strong.fetch_add(1, Ordering::Relaxed);
+        if old > MAX_REFCOUNT {
+            process::abort();
+        }
+        SimpleArc { ptr: self.ptr }
+    }
+}
+
+impl<T> Deref for SimpleArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().data
+    }
+}
+
+impl<T> Drop for SimpleArc<T> {
+    fn drop(&mut self) {
+        // Release: our uses of the data happen before the final drop
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        // Acquire: the final drop sees every other thread's uses
+        atomic::fence(Ordering::Acquire);
+
+        unsafe {
+            ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).data);
+        }
+        drop(Weak { ptr: self.ptr });
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SimpleArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for SimpleArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T> Weak<T> {
+    fn inner(&self) -> &ArcInner<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    pub fn upgrade(&self) -> Option<SimpleArc<T>> {
+        let strong = &self.inner().strong;
+        let mut current = strong.load(Ordering::Relaxed);
+        loop {
+            // The data is already gone once the strong count hits zero
+            if current == 0 {
+                return None;
+            }
+            if current > MAX_REFCOUNT {
+                process::abort();
+            }
+            match strong.compare_exchange_weak(current, current + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(SimpleArc { ptr: self.ptr }),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.inner().strong.load(Ordering::Acquire)
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<T> Clone for Weak<T> {
+    fn clone(&self) -> Self {
+        let old = self.inner().weak.fetch_add(1, Ordering::Relaxed);
+        if old > MAX_REFCOUNT {
+            process::abort();
+        }
+        Weak { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for Weak<T> {
+    fn drop(&mut self) {
+        if self.inner().weak.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+
+        // The data was dropped with the last strong pointer; ManuallyDrop
+        // keeps Box from dropping it a second time
+        unsafe {
+            drop(Box::from_raw(self.ptr.as_ptr()));
+        }
+    }
+}
+
+impl<T> fmt::Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(Weak)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    // Counts how many times it has been dropped
+    struct DropCounter<'a>(&'a AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_clone_and_drop_counts() {
+        let drops = AtomicUsize::new(0);
+        let a = SimpleArc::new(DropCounter(&drops));
+        let b = a.clone();
+        assert_eq!(SimpleArc::strong_count(&a), 2);
+        assert!(SimpleArc::ptr_eq(&a, &b));
+
+        drop(a);
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+        drop(b);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_weak_upgrade_and_downgrade() {
+        let strong = SimpleArc::new(5);
+        let weak = SimpleArc::downgrade(&strong);
+        assert_eq!(SimpleArc::weak_count(&strong), 1);
+
+        let upgraded = weak.upgrade().unwrap();
+        assert_eq!(*upgraded, 5);
+        assert_eq!(SimpleArc::strong_count(&strong), 2);
+
+        drop(upgraded);
+        drop(strong);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+    }
+
+    #[test]
+    fn test_data_dropped_before_last_weak() {
+        let drops = AtomicUsize::new(0);
+        let strong = SimpleArc::new(DropCounter(&drops));
+        let weak = SimpleArc::downgrade(&strong);
+        let weak2 = weak.clone();
+
+        drop(strong);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+        assert!(weak.ptr_eq(&weak2));
+        drop(weak);
+        drop(weak2);
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_get_mut_requires_uniqueness() {
+        let mut a = SimpleArc::new(1);
+        *SimpleArc::get_mut(&mut a).unwrap() += 1;
+        assert_eq!(*a, 2);
+
+        let b = a.clone();
+        assert!(SimpleArc::get_mut(&mut a).is_none());
+        drop(b);
+
+        let weak = SimpleArc::downgrade(&a);
+        assert!(SimpleArc::get_mut(&mut a).is_none());
+        drop(weak);
+        assert!(SimpleArc::get_mut(&mut a).is_some());
+    }
+
+    #[test]
+    fn test_make_mut_clones_on_write() {
+        let mut a = SimpleArc::new(vec![1, 2]);
+        let b = a.clone();
+        SimpleArc::make_mut(&mut a).push(3);
+        assert_eq!(*a, vec![1, 2, 3]);
+        assert_eq!(*b, vec![1, 2]);
+        assert!(!SimpleArc::ptr_eq(&a, &b));
+
+        // Unique: mutated in place
+        let before = SimpleArc::as_ptr(&a);
+        SimpleArc::make_mut(&mut a).push(4);
+        assert_eq!(SimpleArc::as_ptr(&a), before);
+
+        // Only weak pointers left: they get disassociated
+        let weak = SimpleArc::downgrade(&a);
+        SimpleArc::make_mut(&mut a).push(5);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(*a, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_try_unwrap() {
+        let a = SimpleArc::new(String::from("only"));
+        let b = a.clone();
+        let a = SimpleArc::try_unwrap(a).unwrap_err();
+        drop(b);
+
+        let weak = SimpleArc::downgrade(&a);
+        assert_eq!(SimpleArc::try_unwrap(a).unwrap(), "only");
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_concurrent_clone_upgrade_and_drop() {
+        let drops = AtomicUsize::new(0);
+        let iterations = if cfg!(miri) { 20 } else { 1000 };
+        {
+            let shared = SimpleArc::new(DropCounter(&drops));
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    let local = shared.clone();
+                    s.spawn(move || {
+                        let weak = SimpleArc::downgrade(&local);
+                        for _ in 0..iterations {
+                            let extra = weak.upgrade().unwrap();
+                            let another = extra.clone();
+                            drop(extra);
+                            drop(another);
+                        }
+                    });
+                }
+            });
+            assert_eq!(SimpleArc::strong_count(&shared), 1);
+            assert_eq!(SimpleArc::weak_count(&shared), 0);
+        }
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+    }
+}
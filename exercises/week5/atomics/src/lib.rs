@@ -0,0 +1,4 @@
+// Library root file - exposes modules for integration testing
+pub mod spin;
+pub mod arc;
+pub mod stack;
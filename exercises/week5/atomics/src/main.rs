@@ -1,10 +1,15 @@
 // Week 5: Atomic Types and Lock-Free Programming
 // Learn about atomic operations and memory ordering
 
-use std::sync::atomic::{AtomicBool, AtomicUsize, AtomicI32, AtomicPtr, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, AtomicI32, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+
+use atomics::arc::SimpleArc;
+use atomics::spin::{SpinMutex, TicketMutex};
+use atomics::stack::LockFreeStack;
+use bench::Suite;
 
 // TODO 1: Basic atomic operations
 fn basic_atomic_operations() {
@@ -105,50 +110,18 @@ fn compare_and_swap() {
 }
 
 // TODO 4: Spinlock implementation
-struct Spinlock {
-    locked: AtomicBool,
-}
-
-impl Spinlock {
-    fn new() -> Self {
-        Spinlock {
-            locked: AtomicBool::new(false),
-        }
-    }
-    
-    fn lock(&self) {
-        while self.locked.compare_exchange_weak(
-            false,
-            true,
-            Ordering::Acquire,
-            Ordering::Relaxed
-        ).is_err() {
-            // Spin while locked
-            while self.locked.load(Ordering::Relaxed) {
-                thread::yield_now();
-            }
-        }
-    }
-    
-    fn unlock(&self) {
-        self.locked.store(false, Ordering::Release);
-    }
-}
-
+// SpinMutex (see src/spin.rs) owns the data it protects and unlocks when its
+// guard goes out of scope, so there is no way to forget the unlock.
 fn spinlock_example() {
-    let spinlock = Arc::new(Spinlock::new());
-    let counter = Arc::new(AtomicUsize::new(0));
+    let spinlock = Arc::new(SpinMutex::new(0));
     let mut handles = vec![];
     
     for i in 0..5 {
         let spinlock = Arc::clone(&spinlock);
-        let counter = Arc::clone(&counter);
         let handle = thread::spawn(move || {
             for _ in 0..100 {
-                spinlock.lock();
-                let current = counter.load(Ordering::Relaxed);
-                counter.store(current + 1, Ordering::Relaxed);
-                spinlock.unlock();
+                let mut counter = spinlock.lock().unwrap();
+                *counter += 1;
             }
             println!("Thread {} finished", i);
         });
@@ -159,73 +132,30 @@ fn spinlock_example() {
         handle.join().unwrap();
     }
     
-    println!("Spinlock protected counter: {}", counter.load(Ordering::Relaxed));
-}
-
-// TODO 5: Lock-free stack
-struct LockFreeStack<T> {
-    head: AtomicPtr<Node<T>>,
-}
-
-struct Node<T> {
-    data: T,
-    next: *mut Node<T>,
-}
-
-impl<T> LockFreeStack<T> {
-    fn new() -> Self {
-        LockFreeStack {
-            head: AtomicPtr::new(std::ptr::null_mut()),
-        }
-    }
+    println!("Spinlock protected counter: {}", *spinlock.lock().unwrap());
     
-    fn push(&self, data: T) {
-        let new_node = Box::into_raw(Box::new(Node {
-            data,
-            next: std::ptr::null_mut(),
-        }));
-        
-        loop {
-            let head = self.head.load(Ordering::Acquire);
-            unsafe {
-                (*new_node).next = head;
-            }
-            
-            if self.head.compare_exchange_weak(
-                head,
-                new_node,
-                Ordering::Release,
-                Ordering::Relaxed
-            ).is_ok() {
-                break;
-            }
-        }
+    // Ticket lock: waiters are served in the order they arrived
+    let ticket_lock = Arc::new(TicketMutex::new(Vec::new()));
+    let mut handles = vec![];
+    
+    for i in 0..3 {
+        let ticket_lock = Arc::clone(&ticket_lock);
+        let handle = thread::spawn(move || {
+            ticket_lock.lock().unwrap().push(i);
+        });
+        handles.push(handle);
     }
     
-    fn pop(&self) -> Option<T> {
-        loop {
-            let head = self.head.load(Ordering::Acquire);
-            if head.is_null() {
-                return None;
-            }
-            
-            let next = unsafe { (*head).next };
-            
-            if self.head.compare_exchange_weak(
-                head,
-                next,
-                Ordering::Release,
-                Ordering::Relaxed
-            ).is_ok() {
-                let data = unsafe { Box::from_raw(head).data };
-                return Some(data);
-            }
-        }
+    for handle in handles {
+        handle.join().unwrap();
     }
+    
+    println!("Ticket lock arrival order: {:?}", *ticket_lock.lock().unwrap());
 }
 
-// Note: This implementation has ABA problem and memory leaks
-// In production, use hazard pointers or epoch-based reclamation
+// TODO 5: Lock-free stack
+// Popped nodes are freed through hazard pointers (see src/stack.rs), so any
+// number of threads can push and pop at once
 fn lock_free_stack_example() {
     let stack = Arc::new(LockFreeStack::new());
     let mut handles = vec![];
@@ -296,96 +226,108 @@ fn atomic_flags_coordination() {
 }
 
 // TODO 7: Performance comparison: Atomic vs Mutex
+// One benchmark iteration is a whole contended run: THREADS threads sharing
+// INCREMENTS increments of one counter. The bench crate repeats each run
+// many times, so the table shows a distribution rather than one noisy number.
 fn performance_comparison() {
-    const ITERATIONS: usize = 1_000_000;
+    const INCREMENTS: usize = 100_000;
     const THREADS: usize = 4;
     
-    // Atomic performance test
-    let atomic_counter = Arc::new(AtomicUsize::new(0));
-    let start = Instant::now();
-    
-    let mut handles = vec![];
-    for _ in 0..THREADS {
-        let counter = Arc::clone(&atomic_counter);
-        let handle = thread::spawn(move || {
-            for _ in 0..ITERATIONS / THREADS {
-                counter.fetch_add(1, Ordering::Relaxed);
-            }
-        });
-        handles.push(handle);
-    }
-    
-    for handle in handles {
-        handle.join().unwrap();
-    }
-    
-    let atomic_time = start.elapsed();
-    println!("Atomic counter time: {:?}, result: {}", 
-             atomic_time, atomic_counter.load(Ordering::Relaxed));
-    
-    // Mutex performance test
-    let mutex_counter = Arc::new(std::sync::Mutex::new(0));
-    let start = Instant::now();
-    
-    let mut handles = vec![];
-    for _ in 0..THREADS {
-        let counter = Arc::clone(&mutex_counter);
-        let handle = thread::spawn(move || {
-            for _ in 0..ITERATIONS / THREADS {
-                let mut num = counter.lock().unwrap();
-                *num += 1;
-            }
-        });
-        handles.push(handle);
-    }
-    
-    for handle in handles {
-        handle.join().unwrap();
+    let atomic_counter = AtomicUsize::new(0);
+    let mutex_counter = std::sync::Mutex::new(0);
+    let spin_counter = SpinMutex::new(0);
+    let hybrid_counter = SpinMutex::hybrid(0);
+    // Strict FIFO hand-off hurts badly once threads outnumber cores: the next
+    // ticket holder may be descheduled while everyone else waits for it
+    let ticket_counter = TicketMutex::new(0);
+    
+    let mut suite = Suite::new().warmup(Duration::from_millis(50)).samples(15);
+    suite
+        .bench("AtomicUsize", || contended_increments(THREADS, INCREMENTS / THREADS, || {
+            atomic_counter.fetch_add(1, Ordering::Relaxed);
+        }))
+        .bench("std Mutex", || contended_increments(THREADS, INCREMENTS / THREADS, || {
+            *mutex_counter.lock().unwrap() += 1;
+        }))
+        .bench("SpinMutex", || contended_increments(THREADS, INCREMENTS / THREADS, || {
+            *spin_counter.lock().unwrap() += 1;
+        }))
+        .bench("SpinMutex (spin-then-park)", || contended_increments(THREADS, INCREMENTS / THREADS, || {
+            *hybrid_counter.lock().unwrap() += 1;
+        }))
+        .bench("TicketMutex", || contended_increments(THREADS, INCREMENTS / THREADS, || {
+            *ticket_counter.lock().unwrap() += 1;
+        }));
+    let report = suite.run();
+    println!("{} increments on {} threads per iteration:", INCREMENTS, THREADS);
+    println!("{}", report);
+    
+    // Every run must add exactly INCREMENTS, whatever the lock
+    let totals = [
+        atomic_counter.load(Ordering::Relaxed),
+        *mutex_counter.lock().unwrap(),
+        *spin_counter.lock().unwrap(),
+        *hybrid_counter.lock().unwrap(),
+        *ticket_counter.lock().unwrap(),
+    ];
+    println!("No lost increments: {}", totals.iter().all(|total| total % INCREMENTS == 0));
+    
+    let mutex_median = report.get("std Mutex").unwrap().stats.median;
+    for result in report.results.iter().filter(|result| result.name != "std Mutex") {
+        println!("{} vs std Mutex: {:.2}x", result.name, mutex_median / result.stats.median);
     }
     
-    let mutex_time = start.elapsed();
-    println!("Mutex counter time: {:?}, result: {}", 
-             mutex_time, *mutex_counter.lock().unwrap());
-    
-    println!("Atomic was {:.2}x faster", 
-             mutex_time.as_nanos() as f64 / atomic_time.as_nanos() as f64);
-}
-
-// TODO 8: Atomic reference counting simulation
-struct SimpleArc<T> {
-    data: *const T,
-    ref_count: AtomicUsize,
+    report.compare_with_saved("atomics");
 }
 
-impl<T> SimpleArc<T> {
-    fn new(data: T) -> Self {
-        let boxed = Box::new(data);
-        SimpleArc {
-            data: Box::into_raw(boxed),
-            ref_count: AtomicUsize::new(1),
-        }
-    }
-    
-    fn clone(&self) -> Self {
-        self.ref_count.fetch_add(1, Ordering::Relaxed);
-        SimpleArc {
-            data: self.data,
-            ref_count: AtomicUsize::new(0), // This is wrong but simplified for demo
+// Runs `increment` `per_thread` times on each of `threads` threads
+fn contended_increments<F>(threads: usize, per_thread: usize, increment: F)
+where
+    F: Fn() + Sync,
+{
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..per_thread {
+                    increment();
+                }
+            });
         }
-    }
-    
-    fn get_ref_count(&self) -> usize {
-        self.ref_count.load(Ordering::Relaxed)
-    }
+    });
 }
 
-// Note: This is a simplified example - real Arc is much more complex
+// TODO 8: Atomic reference counting simulation
+// SimpleArc (see src/arc.rs) keeps its strong and weak counts in the shared
+// allocation, next to the data, just like std::sync::Arc.
 fn atomic_reference_counting() {
     let data = SimpleArc::new(42);
     println!("Initial ref count: {}", data.get_ref_count());
     
     let data_clone = data.clone();
     println!("After clone ref count: {}", data.get_ref_count());
+    
+    // Clones can be shared across threads
+    let handle = thread::spawn(move || {
+        println!("Thread sees: {}, ref count: {}", *data_clone, data_clone.get_ref_count());
+    });
+    handle.join().unwrap();
+    println!("After thread finished ref count: {}", data.get_ref_count());
+    
+    // Weak references don't keep the data alive
+    let weak = SimpleArc::downgrade(&data);
+    println!("Weak count: {}, upgrade: {:?}", SimpleArc::weak_count(&data), weak.upgrade());
+    
+    // Clone-on-write
+    let mut config = SimpleArc::new(vec!["a", "b"]);
+    let snapshot = config.clone();
+    SimpleArc::make_mut(&mut config).push("c");
+    println!("Modified: {:?}, snapshot: {:?}", config, snapshot);
+    
+    drop(snapshot);
+    println!("Unwrapped: {:?}", SimpleArc::try_unwrap(config));
+    
+    drop(data);
+    println!("After last strong pointer dropped, upgrade: {:?}", weak.upgrade());
 }
 
 fn main() {
@@ -0,0 +1,696 @@
+// Spin-based locks built on atomics
+//
+// - SpinMutex<T>: test-and-test-and-set lock with exponential backoff and an
+//   optional spin-then-park mode for long critical sections
+// - TicketMutex<T>: FIFO-fair ticket lock
+//
+// Both hand out RAII guards that deref to the protected data and poison the
+// lock when the holder panics, mirroring std::sync::Mutex.
+
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::hint;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LockResult, Mutex, PoisonError, TryLockError, TryLockResult};
+use std::thread::{self, Thread};
+
+// Exponential backoff for contended spin loops.
+//
+// The first few rounds busy-wait with spin_loop hints (2, 4, 8, ... iterations),
+// after that each round yields the time slice to the OS scheduler.
+pub struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    const SPIN_LIMIT: u32 = 6;
+    const YIELD_LIMIT: u32 = 10;
+
+    pub fn new() -> Self {
+        Backoff { step: 0 }
+    }
+
+    // Busy-wait only; use when the wait is expected to be very short
+    pub fn spin(&mut self) {
+        for _ in 0..1u32 << self.step.min(Self::SPIN_LIMIT) {
+            hint::spin_loop();
+        }
+        if self.step <= Self::SPIN_LIMIT {
+            self.step += 1;
+        }
+    }
+
+    // Busy-wait at first, then start yielding to other threads
+    pub fn snooze(&mut self) {
+        if self.step <= Self::SPIN_LIMIT {
+            for _ in 0..1u32 << self.step {
+                hint::spin_loop();
+            }
+        } else {
+            thread::yield_now();
+        }
+        if self.step <= Self::YIELD_LIMIT {
+            self.step += 1;
+        }
+    }
+
+    // True once backing off further is pointless and the caller should block
+    pub fn is_completed(&self) -> bool {
+        self.step > Self::YIELD_LIMIT
+    }
+
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Poison flag shared by both lock types
+struct Poison {
+    failed: AtomicBool,
+}
+
+impl Poison {
+    fn new() -> Self {
+        Poison {
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    fn get(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    fn clear(&self) {
+        self.failed.store(false, Ordering::Relaxed);
+    }
+
+    fn result<G>(&self, guard: G) -> LockResult<G> {
+        if self.get() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    // Called on unlock; `panicking` is whether the thread was already
+    // panicking when it took the lock
+    fn done(&self, panicking: bool) {
+        if !panicking && thread::panicking() {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+// How a SpinMutex waits for the lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpinMode {
+    // Spin with backoff, yielding once spinning stops paying off
+    Spin,
+    // Spin with backoff for a while, then park the thread until unlock
+    Hybrid,
+}
+
+pub struct SpinMutex<T: ?Sized> {
+    locked: AtomicBool,
+    poison: Poison,
+    mode: SpinMode,
+    parked: AtomicUsize,
+    waiters: Mutex<VecDeque<Thread>>,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for SpinMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for SpinMutex<T> {}
+
+/// A guard gives out `&T` to every thread it is shared with, so it is only
+/// `Sync` when `T` is:
+///
+/// ```compile_fail
+/// use atomics::spin::SpinMutex;
+/// use std::cell::Cell;
+///
+/// fn is_sync<S: Sync>(_: &S) {}
+///
+/// let mutex = SpinMutex::new(Cell::new(0));
+/// is_sync(&mutex.lock().unwrap());
+/// ```
+///
+/// It must also be dropped on the thread that locked, so it is never `Send`:
+///
+/// ```compile_fail
+/// use atomics::spin::SpinMutex;
+///
+/// fn is_send<S: Send>(_: &S) {}
+///
+/// let mutex = SpinMutex::new(0);
+/// is_send(&mutex.lock().unwrap());
+/// ```
+pub struct SpinGuard<'a, T: ?Sized> {
+    mutex: &'a SpinMutex<T>,
+    panicking: bool,
+    // Raw pointers are !Send
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for SpinGuard<'_, T> {}
+
+impl<T> SpinMutex<T> {
+    pub fn new(data: T) -> Self {
+        Self::with_mode(data, SpinMode::Spin)
+    }
+
+    pub fn hybrid(data: T) -> Self {
+        Self::with_mode(data, SpinMode::Hybrid)
+    }
+
+    pub fn with_mode(data: T, mode: SpinMode) -> Self {
+        SpinMutex {
+            locked: AtomicBool::new(false),
+            poison: Poison::new(),
+            mode,
+            parked: AtomicUsize::new(0),
+            waiters: Mutex::new(VecDeque::new()),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let data = self.data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl<T: ?Sized> SpinMutex<T> {
+    pub fn lock(&self) -> LockResult<SpinGuard<'_, T>> {
+        if !self.try_acquire() {
+            self.lock_contended();
+        }
+        self.poison.result(SpinGuard {
+            mutex: self,
+            panicking: thread::panicking(),
+            _not_send: PhantomData,
+        })
+    }
+
+    pub fn try_lock(&self) -> TryLockResult<SpinGuard<'_, T>> {
+        if self.try_acquire() {
+            Ok(self.poison.result(SpinGuard {
+                mutex: self,
+                panicking: thread::panicking(),
+                _not_send: PhantomData,
+            })?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    pub fn mode(&self) -> SpinMode {
+        self.mode
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    // No locking needed: the &mut borrow proves nobody else holds a guard
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let data = self.data.get_mut();
+        if self.poison.get() {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn lock_contended(&self) {
+        let mut backoff = Backoff::new();
+        loop {
+            // Test-and-test-and-set: only attempt the CAS when the lock looks free
+            if !self.locked.load(Ordering::Relaxed) && self.try_acquire() {
+                return;
+            }
+            if self.mode == SpinMode::Hybrid && backoff.is_completed() {
+                self.park();
+                backoff.reset();
+            } else {
+                backoff.snooze();
+            }
+        }
+    }
+
+    fn park(&self) {
+        let me = thread::current();
+        {
+            let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+            waiters.push_back(me.clone());
+            self.parked.fetch_add(1, Ordering::SeqCst);
+        }
+
+        // Re-check after registering: pairs with the SeqCst store/load in
+        // unlock() so either we see the lock free or the unlocker sees us
+        if self.locked.load(Ordering::SeqCst) {
+            thread::park();
+        }
+
+        // Deregister if nobody popped us (early return or spurious wakeup)
+        let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(pos) = waiters.iter().position(|t| t.id() == me.id()) {
+            waiters.remove(pos);
+            self.parked.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    fn unlock(&self) {
+        match self.mode {
+            SpinMode::Spin => self.locked.store(false, Ordering::Release),
+            SpinMode::Hybrid => {
+                self.locked.store(false, Ordering::SeqCst);
+                if self.parked.load(Ordering::SeqCst) > 0 {
+                    self.unpark_one();
+                }
+            }
+        }
+    }
+
+    fn unpark_one(&self) {
+        let mut waiters = self.waiters.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(waiter) = waiters.pop_front() {
+            self.parked.fetch_sub(1, Ordering::SeqCst);
+            waiter.unpark();
+        }
+    }
+}
+
+impl<T: Default> Default for SpinMutex<T> {
+    fn default() -> Self {
+        SpinMutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SpinMutex");
+        match self.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(err)) => d.field("data", &&**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned())
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+impl<T: ?Sized> Deref for SpinGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for SpinGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for SpinGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.poison.done(self.panicking);
+        self.mutex.unlock();
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+// Ticket lock: every locker takes a number and waits until it is served,
+// so the lock is handed out in strict FIFO order.
+pub struct TicketMutex<T: ?Sized> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    poison: Poison,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for TicketMutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for TicketMutex<T> {}
+
+/// Same rules as `SpinGuard`: `Sync` only when `T` is, and never `Send`.
+///
+/// ```compile_fail
+/// use atomics::spin::TicketMutex;
+/// use std::cell::Cell;
+///
+/// fn is_sync<S: Sync>(_: &S) {}
+///
+/// let mutex = TicketMutex::new(Cell::new(0));
+/// is_sync(&mutex.lock().unwrap());
+/// ```
+///
+/// ```compile_fail
+/// use atomics::spin::TicketMutex;
+///
+/// fn is_send<S: Send>(_: &S) {}
+///
+/// let mutex = TicketMutex::new(0);
+/// is_send(&mutex.lock().unwrap());
+/// ```
+pub struct TicketGuard<'a, T: ?Sized> {
+    mutex: &'a TicketMutex<T>,
+    panicking: bool,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for TicketGuard<'_, T> {}
+
+impl<T> TicketMutex<T> {
+    pub fn new(data: T) -> Self {
+        TicketMutex {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            poison: Poison::new(),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let poisoned = self.poison.get();
+        let data = self.data.into_inner();
+        if poisoned {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl<T: ?Sized> TicketMutex<T> {
+    pub fn lock(&self) -> LockResult<TicketGuard<'_, T>> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        let mut backoff = Backoff::new();
+        loop {
+            let serving = self.now_serving.load(Ordering::Acquire);
+            if serving == ticket {
+                break;
+            }
+            // Only the next in line needs to watch closely; the rest yield
+            if ticket.wrapping_sub(serving) > 1 {
+                thread::yield_now();
+            } else {
+                backoff.snooze();
+            }
+        }
+        self.poison.result(TicketGuard {
+            mutex: self,
+            panicking: thread::panicking(),
+            _not_send: PhantomData,
+        })
+    }
+
+    pub fn try_lock(&self) -> TryLockResult<TicketGuard<'_, T>> {
+        // Only take a ticket if it would be served immediately
+        let serving = self.now_serving.load(Ordering::Acquire);
+        if self
+            .next_ticket
+            .compare_exchange(serving, serving.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Ok(self.poison.result(TicketGuard {
+                mutex: self,
+                panicking: thread::panicking(),
+                _not_send: PhantomData,
+            })?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    // Number of threads holding or waiting for the lock
+    pub fn queue_len(&self) -> usize {
+        let next = self.next_ticket.load(Ordering::Relaxed);
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        next.wrapping_sub(serving)
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.poison.get()
+    }
+
+    pub fn clear_poison(&self) {
+        self.poison.clear();
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        let data = self.data.get_mut();
+        if self.poison.get() {
+            Err(PoisonError::new(data))
+        } else {
+            Ok(data)
+        }
+    }
+}
+
+impl<T: Default> Default for TicketMutex<T> {
+    fn default() -> Self {
+        TicketMutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TicketMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("TicketMutex");
+        match self.try_lock() {
+            Ok(guard) => d.field("data", &&*guard),
+            Err(TryLockError::Poisoned(err)) => d.field("data", &&**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("data", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned())
+            .field("queue_len", &self.queue_len())
+            .finish()
+    }
+}
+
+impl<T: ?Sized> Deref for TicketGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for TicketGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for TicketGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.poison.done(self.panicking);
+        self.mutex.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TicketGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn hammer<L, F>(lock: Arc<L>, threads: usize, iterations: usize, bump: F)
+    where
+        L: Send + Sync + 'static,
+        F: Fn(&L) + Send + Sync + Copy + 'static,
+    {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let lock = Arc::clone(&lock);
+                thread::spawn(move || {
+                    for _ in 0..iterations {
+                        bump(&lock);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_guard_derefs_to_data() {
+        let mutex = SpinMutex::new(vec![1, 2]);
+        mutex.lock().unwrap().push(3);
+        assert_eq!(*mutex.lock().unwrap(), vec![1, 2, 3]);
+        assert!(!mutex.is_locked());
+        assert_eq!(mutex.into_inner().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_try_lock_would_block() {
+        let mutex = SpinMutex::new(0);
+        let guard = mutex.lock().unwrap();
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(guard);
+        assert!(mutex.try_lock().is_ok());
+
+        let ticket = TicketMutex::new(0);
+        let guard = ticket.lock().unwrap();
+        assert!(matches!(ticket.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(guard);
+        assert!(ticket.try_lock().is_ok());
+        assert_eq!(ticket.queue_len(), 0);
+    }
+
+    #[test]
+    fn test_spin_counter_under_contention() {
+        let mutex = Arc::new(SpinMutex::new(0usize));
+        hammer(Arc::clone(&mutex), 4, 1000, |m| *m.lock().unwrap() += 1);
+        assert_eq!(*mutex.lock().unwrap(), 4000);
+    }
+
+    #[test]
+    fn test_hybrid_counter_under_contention() {
+        let mutex = Arc::new(SpinMutex::hybrid(0usize));
+        hammer(Arc::clone(&mutex), 8, 500, |m| {
+            let mut guard = m.lock().unwrap();
+            *guard += 1;
+            if *guard % 100 == 0 {
+                // Long critical section so waiters run out of spins and park
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        assert_eq!(*mutex.lock().unwrap(), 4000);
+        assert_eq!(mutex.parked.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_hybrid_parks_and_wakes() {
+        let mutex = Arc::new(SpinMutex::hybrid(0));
+        let guard = mutex.lock().unwrap();
+
+        let waiter = {
+            let mutex = Arc::clone(&mutex);
+            thread::spawn(move || *mutex.lock().unwrap() += 1)
+        };
+        while mutex.parked.load(Ordering::SeqCst) == 0 {
+            thread::yield_now();
+        }
+        drop(guard);
+
+        waiter.join().unwrap();
+        assert_eq!(*mutex.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_ticket_counter_under_contention() {
+        let mutex = Arc::new(TicketMutex::new(0usize));
+        hammer(Arc::clone(&mutex), 4, 1000, |m| *m.lock().unwrap() += 1);
+        assert_eq!(*mutex.lock().unwrap(), 4000);
+    }
+
+    #[test]
+    fn test_ticket_lock_is_fifo() {
+        let mutex = Arc::new(TicketMutex::new(Vec::new()));
+        let guard = mutex.lock().unwrap();
+
+        let mut handles = vec![];
+        for i in 0..5 {
+            let m = Arc::clone(&mutex);
+            handles.push(thread::spawn(move || m.lock().unwrap().push(i)));
+            // Wait until thread i has taken its ticket before spawning the next
+            while mutex.queue_len() != i + 2 {
+                thread::yield_now();
+            }
+        }
+        drop(guard);
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*mutex.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_panic_poisons_lock() {
+        let mutex = Arc::new(SpinMutex::new(1));
+        let m = Arc::clone(&mutex);
+        let result = thread::spawn(move || {
+            let _guard = m.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned());
+        assert!(!mutex.is_locked());
+
+        let recovered = mutex.lock().unwrap_or_else(PoisonError::into_inner);
+        assert_eq!(*recovered, 1);
+        drop(recovered);
+
+        mutex.clear_poison();
+        assert!(mutex.lock().is_ok());
+    }
+
+    #[test]
+    fn test_ticket_panic_poisons_lock() {
+        let mutex = Arc::new(TicketMutex::new(1));
+        let m = Arc::clone(&mutex);
+        let _ = thread::spawn(move || {
+            let _guard = m.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(mutex.is_poisoned());
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::Poisoned(_))));
+    }
+
+    #[test]
+    fn test_backoff_completes() {
+        let mut backoff = Backoff::new();
+        while !backoff.is_completed() {
+            backoff.snooze();
+        }
+        backoff.reset();
+        assert!(!backoff.is_completed());
+    }
+}
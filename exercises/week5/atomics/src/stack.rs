@@ -0,0 +1,367 @@
+// Treiber stack: a lock-free linked list where push and pop swing the head
+// pointer with compare_exchange
+//
+// The hard part is freeing popped nodes. A pop reads head.next before its
+// compare_exchange, and by then another pop may have taken and freed that
+// node; if the address is then reused for a new node, the compare_exchange
+// can even succeed with a stale next (the ABA problem).
+//
+// Hazard pointers fix both. Before touching a node, a popper publishes its
+// address in a hazard slot and checks that the node is still the head. A
+// popped node is retired instead of freed, and retired nodes are only freed
+// once no slot points at them. Since a node is never freed while a popper
+// can still read it, its address can't be reused under it either.
+
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+pub struct LockFreeStack<T> {
+    head: AtomicPtr<Node<T>>,
+    // One slot per pop in progress; slots are reused, and freed with the stack
+    hazards: AtomicPtr<Hazard<T>>,
+    // Popped nodes that may still be protected, linked through retired_next
+    retired: AtomicPtr<Node<T>>,
+    // The stack owns T values (for Send/Sync and drop checking)
+    _marker: PhantomData<T>,
+}
+
+// Pushed and popped values move between threads, and pop only needs &self
+unsafe impl<T: Send> Send for LockFreeStack<T> {}
+unsafe impl<T: Send> Sync for LockFreeStack<T> {}
+
+struct Node<T> {
+    // Moved out by pop; the node itself is freed later without dropping it
+    data: ManuallyDrop<T>,
+    // Never changes once the node is pushed
+    next: *mut Node<T>,
+    retired_next: *mut Node<T>,
+}
+
+struct Hazard<T> {
+    protected: AtomicPtr<Node<T>>,
+    in_use: AtomicBool,
+    next: *mut Hazard<T>,
+}
+
+// Claims a hazard slot for the length of one pop
+struct HazardGuard<'a, T> {
+    hazard: &'a Hazard<T>,
+}
+
+impl<T> Drop for HazardGuard<'_, T> {
+    fn drop(&mut self) {
+        self.hazard
+            .protected
+            .store(ptr::null_mut(), Ordering::Release);
+        self.hazard.in_use.store(false, Ordering::Release);
+    }
+}
+
+impl<T> LockFreeStack<T> {
+    pub fn new() -> Self {
+        LockFreeStack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            hazards: AtomicPtr::new(ptr::null_mut()),
+            retired: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn push(&self, data: T) {
+        let new_node = Box::into_raw(Box::new(Node {
+            data: ManuallyDrop::new(data),
+            next: ptr::null_mut(),
+            retired_next: ptr::null_mut(),
+        }));
+
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            unsafe {
+                (*new_node).next = head;
+            }
+
+            if self
+                .head
+                .compare_exchange_weak(head, new_node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = self.claim_hazard();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            if head.is_null() {
+                return None;
+            }
+
+            // Publish the hazard, then check head again: if it is unchanged,
+            // head wasn't popped before the hazard became visible, so any
+            // later scan will see the hazard and leave the node alone. SeqCst
+            // orders this store and load against the scan in retire().
+            guard.hazard.protected.store(head, Ordering::SeqCst);
+            if self.head.load(Ordering::SeqCst) != head {
+                continue;
+            }
+
+            let next = unsafe { (*head).next };
+
+            if self
+                .head
+                .compare_exchange_weak(head, next, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok()
+            {
+                // Only this thread got the node, so only it takes the data;
+                // other poppers may still read `next`, which is left alone
+                let data = unsafe { ptr::read(ptr::addr_of!((*head).data)) };
+                drop(guard);
+                self.retire(head);
+                return Some(ManuallyDrop::into_inner(data));
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+
+    // Reuses a free slot, or adds a new one when every slot is busy
+    fn claim_hazard(&self) -> HazardGuard<'_, T> {
+        let mut current = self.hazards.load(Ordering::Acquire);
+        while !current.is_null() {
+            let hazard = unsafe { &*current };
+            if hazard
+                .in_use
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return HazardGuard { hazard };
+            }
+            current = hazard.next;
+        }
+
+        let new_hazard = Box::into_raw(Box::new(Hazard {
+            protected: AtomicPtr::new(ptr::null_mut()),
+            in_use: AtomicBool::new(true),
+            next: ptr::null_mut(),
+        }));
+        loop {
+            let head = self.hazards.load(Ordering::Acquire);
+            unsafe {
+                (*new_hazard).next = head;
+            }
+            if self
+                .hazards
+                .compare_exchange_weak(head, new_hazard, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return HazardGuard {
+                    hazard: unsafe { &*new_hazard },
+                };
+            }
+        }
+    }
+
+    // Adds a popped node to the retired list, then frees every retired node
+    // that no hazard points at. Nodes still protected go back on the list
+    // for a later pop (or drop) to free.
+    fn retire(&self, node: *mut Node<T>) {
+        self.push_retired(node);
+
+        // Take the whole list, so no other scan can free these nodes too
+        let mut current = self.retired.swap(ptr::null_mut(), Ordering::Acquire);
+        if current.is_null() {
+            return;
+        }
+        let protected = self.protected();
+        while !current.is_null() {
+            let next = unsafe { (*current).retired_next };
+            if protected.contains(&current) {
+                self.push_retired(current);
+            } else {
+                drop(unsafe { Box::from_raw(current) });
+            }
+            current = next;
+        }
+    }
+
+    fn push_retired(&self, node: *mut Node<T>) {
+        loop {
+            let head = self.retired.load(Ordering::Relaxed);
+            unsafe {
+                (*node).retired_next = head;
+            }
+            if self
+                .retired
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    fn protected(&self) -> Vec<*mut Node<T>> {
+        let mut protected = Vec::new();
+        let mut current = self.hazards.load(Ordering::Acquire);
+        while !current.is_null() {
+            let hazard = unsafe { &*current };
+            let node = hazard.protected.load(Ordering::SeqCst);
+            if !node.is_null() {
+                protected.push(node);
+            }
+            current = hazard.next;
+        }
+        protected
+    }
+}
+
+impl<T> Default for LockFreeStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// &mut self means no pop is running, so nothing is protected any more
+impl<T> Drop for LockFreeStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { ManuallyDrop::drop(&mut boxed.data) };
+            node = boxed.next;
+        }
+
+        // Their data was already moved out by pop
+        let mut node = *self.retired.get_mut();
+        while !node.is_null() {
+            let boxed = unsafe { Box::from_raw(node) };
+            node = boxed.retired_next;
+        }
+
+        let mut hazard = *self.hazards.get_mut();
+        while !hazard.is_null() {
+            let boxed = unsafe { Box::from_raw(hazard) };
+            hazard = boxed.next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linearizability::{check, ContainerOp, Recorder, StackModel};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_lifo_order() {
+        let stack = LockFreeStack::new();
+        for value in 1..=3 {
+            stack.push(value);
+        }
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(2));
+        stack.push(4);
+        assert_eq!(stack.pop(), Some(4));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+    }
+
+    #[test]
+    fn test_is_linearizable() {
+        // Every thread both pushes and pops, so pops race with each other
+        // as well as with pushes
+        let rounds = if cfg!(miri) { 1 } else { 5 };
+        for _ in 0..rounds {
+            let stack = LockFreeStack::new();
+            let recorder = Recorder::new();
+            thread::scope(|s| {
+                for t in 0..4 {
+                    let (stack, recorder) = (&stack, &recorder);
+                    s.spawn(move || {
+                        for i in 0..24 {
+                            if i % 3 == 2 {
+                                recorder.record(t, ContainerOp::Pop, || stack.pop());
+                            } else {
+                                let value = t * 100 + i;
+                                recorder.record(t, ContainerOp::Push(value), || {
+                                    stack.push(value);
+                                    None
+                                });
+                            }
+                        }
+                    });
+                }
+            });
+            let history = recorder.into_history();
+            if let Err(counterexample) = check(&StackModel::new(), &history) {
+                panic!("{}", counterexample);
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_pops_take_each_value_once() {
+        let per_thread = if cfg!(miri) { 50 } else { 2000 };
+        let stack = LockFreeStack::new();
+        for value in 0..4 * per_thread {
+            stack.push(value);
+        }
+        let mut popped: Vec<usize> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut mine = Vec::new();
+                        while let Some(value) = stack.pop() {
+                            mine.push(value);
+                            // Keep pushes in the mix so freed addresses get reused
+                            if value != usize::MAX && value % 3 == 0 {
+                                stack.push(usize::MAX);
+                            }
+                        }
+                        mine
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        popped.retain(|&value| value != usize::MAX);
+        popped.sort_unstable();
+        assert_eq!(popped, (0..4 * per_thread).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drop_frees_remaining_values() {
+        struct Counted(Arc<AtomicUsize>);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let stack = LockFreeStack::new();
+        for _ in 0..10 {
+            stack.push(Counted(Arc::clone(&drops)));
+        }
+        drop(stack.pop());
+        drop(stack.pop());
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+
+        // Eight still in the stack, none dropped twice
+        drop(stack);
+        assert_eq!(drops.load(Ordering::Relaxed), 10);
+    }
+}
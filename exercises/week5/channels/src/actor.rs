@@ -0,0 +1,556 @@
+// Actors: state owned by one thread, reached only through messages
+//
+// An Actor handles one message at a time from its mailbox, so its state
+// needs no locks. Other threads hold an ActorRef and either
+//
+// - tell: queue a message and carry on, or
+// - ask: queue a message with a one-shot reply channel and wait for the
+//   value handle() returns.
+//
+// Actors started through Props can be supervised: if handle() panics, the
+// broken instance is thrown away and a fresh one is built from the factory,
+// unless it has already been restarted too often recently, in which case
+// it stops for good. The asker of the message that panicked gets
+// AskError::NoReply; the rest of the mailbox is kept.
+//
+// Stopping is graceful: ActorHandle::stop queues a stop request behind the
+// messages already sent, waits for them to be handled and returns how the
+// actor ended. An actor also stops when every ActorRef to it is gone.
+// Whatever is still in the mailbox when it stops (behind a stop request,
+// ctx.stop() or a panic it gave up on) is dropped, so asks among it get
+// AskError::NoReply.
+
+use crate::mpmc::{self, Receiver, RecvTimeoutError, Sender};
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub trait Actor: Send + 'static {
+    type Message: Send + 'static;
+    type Reply: Send + 'static;
+
+    fn handle(&mut self, message: Self::Message, ctx: &mut Context) -> Self::Reply;
+
+    // Called before the first message, and again after every restart
+    fn started(&mut self, _ctx: &mut Context) {}
+
+    // Called when the actor stops, but not for an instance that panicked
+    fn stopped(&mut self) {}
+}
+
+// Passed to the actor's callbacks
+#[derive(Debug, Default)]
+pub struct Context {
+    restarts: usize,
+    stopping: bool,
+}
+
+impl Context {
+    // Stop once the current message has been handled
+    pub fn stop(&mut self) {
+        self.stopping = true;
+    }
+
+    pub fn restarts(&self) -> usize {
+        self.restarts
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AskError {
+    // The actor had already stopped
+    Stopped,
+    // The actor took the message but gave no reply: it panicked handling
+    // it, or stopped before reaching it
+    NoReply,
+    Timeout,
+}
+
+impl fmt::Display for AskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AskError::Stopped => write!(f, "the actor has stopped"),
+            AskError::NoReply => write!(f, "the actor did not reply"),
+            AskError::Timeout => write!(f, "timed out waiting for a reply"),
+        }
+    }
+}
+
+impl std::error::Error for AskError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExitReason {
+    // Stopped by ActorHandle::stop, ActorRef::stop or Context::stop
+    Stopped,
+    // Every ActorRef was dropped
+    Unreferenced,
+    // Panicked more often than its supervision allows; holds the last
+    // panic message
+    Panicked(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exit {
+    pub reason: ExitReason,
+    // Messages handled, including those that panicked
+    pub processed: u64,
+    pub restarts: usize,
+}
+
+enum Envelope<A: Actor> {
+    Tell(A::Message),
+    Ask(A::Message, Sender<A::Reply>),
+    Stop,
+}
+
+pub struct ActorRef<A: Actor> {
+    mailbox: Sender<Envelope<A>>,
+}
+
+impl<A: Actor> ActorRef<A> {
+    // Hands the message back if the actor has stopped
+    pub fn tell(&self, message: A::Message) -> Result<(), A::Message> {
+        self.mailbox
+            .send(Envelope::Tell(message))
+            .map_err(|e| match e.0 {
+                Envelope::Tell(message) => message,
+                _ => unreachable!("sent a Tell"),
+            })
+    }
+
+    pub fn ask(&self, message: A::Message) -> Result<A::Reply, AskError> {
+        let reply = self.send_ask(message)?;
+        reply.recv().map_err(|_| AskError::NoReply)
+    }
+
+    pub fn ask_timeout(
+        &self,
+        message: A::Message,
+        timeout: Duration,
+    ) -> Result<A::Reply, AskError> {
+        let reply = self.send_ask(message)?;
+        reply.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => AskError::Timeout,
+            RecvTimeoutError::Disconnected => AskError::NoReply,
+        })
+    }
+
+    fn send_ask(&self, message: A::Message) -> Result<Receiver<A::Reply>, AskError> {
+        let (reply_tx, reply_rx) = mpmc::bounded(1);
+        self.mailbox
+            .send(Envelope::Ask(message, reply_tx))
+            .map_err(|_| AskError::Stopped)?;
+        Ok(reply_rx)
+    }
+
+    // Asks the actor to stop after the messages already queued; returns
+    // false if it had already stopped
+    pub fn stop(&self) -> bool {
+        self.mailbox.send(Envelope::Stop).is_ok()
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.mailbox.is_disconnected()
+    }
+
+    // Messages waiting in the mailbox
+    pub fn queued(&self) -> usize {
+        self.mailbox.len()
+    }
+}
+
+impl<A: Actor> Clone for ActorRef<A> {
+    fn clone(&self) -> Self {
+        ActorRef {
+            mailbox: self.mailbox.clone(),
+        }
+    }
+}
+
+// Owns the actor's thread; dropping it lets the actor run on detached
+pub struct ActorHandle<A: Actor> {
+    actor: ActorRef<A>,
+    thread: JoinHandle<Exit>,
+}
+
+impl<A: Actor> ActorHandle<A> {
+    pub fn actor_ref(&self) -> ActorRef<A> {
+        self.actor.clone()
+    }
+
+    // Graceful stop: the actor handles what is already in its mailbox,
+    // then stops
+    pub fn stop(self) -> Exit {
+        self.actor.stop();
+        self.join()
+    }
+
+    // Waits for the actor to stop by itself or lose its last reference
+    pub fn join(self) -> Exit {
+        drop(self.actor);
+        match self.thread.join() {
+            Ok(exit) => exit,
+            // started() or stopped() panicked; those are not supervised
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }
+}
+
+impl<A: Actor> std::ops::Deref for ActorHandle<A> {
+    type Target = ActorRef<A>;
+
+    fn deref(&self) -> &ActorRef<A> {
+        &self.actor
+    }
+}
+
+// How to start an actor: a factory for fresh instances, the mailbox size
+// and how many restarts to allow
+pub struct Props<A: Actor> {
+    factory: Box<dyn FnMut() -> Option<A> + Send>,
+    mailbox: Option<usize>,
+    max_restarts: usize,
+    within: Duration,
+}
+
+impl<A: Actor> Props<A> {
+    pub fn new<F>(mut factory: F) -> Self
+    where
+        F: FnMut() -> A + Send + 'static,
+    {
+        Props {
+            factory: Box::new(move || Some(factory())),
+            mailbox: None,
+            max_restarts: 0,
+            within: Duration::ZERO,
+        }
+    }
+
+    // A bounded mailbox makes tell and ask wait while it is full
+    pub fn mailbox(mut self, capacity: usize) -> Self {
+        self.mailbox = Some(capacity);
+        self
+    }
+
+    // Allow up to `max_restarts` restarts in any window of `within`
+    pub fn restart(mut self, max_restarts: usize, within: Duration) -> Self {
+        self.max_restarts = max_restarts;
+        self.within = within;
+        self
+    }
+
+    pub fn spawn(self) -> ActorHandle<A> {
+        let (tx, rx) = match self.mailbox {
+            Some(capacity) => mpmc::bounded(capacity),
+            None => mpmc::unbounded(),
+        };
+        let thread = thread::spawn(move || self.run(rx));
+        ActorHandle {
+            actor: ActorRef { mailbox: tx },
+            thread,
+        }
+    }
+
+    fn run(mut self, mailbox: Receiver<Envelope<A>>) -> Exit {
+        let exit = self.serve(&mailbox);
+        // Dropping an Ask's reply sender tells its asker no answer is
+        // coming. Anything sent after this is dropped with the mailbox.
+        while let Ok(envelope) = mailbox.try_recv() {
+            drop(envelope);
+        }
+        exit
+    }
+
+    fn serve(&mut self, mailbox: &Receiver<Envelope<A>>) -> Exit {
+        let mut actor = (self.factory)().expect("the factory builds the first instance");
+        let mut ctx = Context::default();
+        let mut processed = 0;
+        let mut recent_restarts = VecDeque::new();
+        actor.started(&mut ctx);
+
+        let reason = loop {
+            if ctx.stopping {
+                break ExitReason::Stopped;
+            }
+            let (message, reply) = match mailbox.recv() {
+                Ok(Envelope::Tell(message)) => (message, None),
+                Ok(Envelope::Ask(message, reply)) => (message, Some(reply)),
+                Ok(Envelope::Stop) => break ExitReason::Stopped,
+                Err(_) => break ExitReason::Unreferenced,
+            };
+            processed += 1;
+            match panic::catch_unwind(AssertUnwindSafe(|| actor.handle(message, &mut ctx))) {
+                Ok(value) => {
+                    if let Some(reply) = reply {
+                        // The asker may have given up waiting
+                        let _ = reply.send(value);
+                    }
+                }
+                Err(payload) => {
+                    // Dropping the reply sender tells the asker there is
+                    // no answer coming
+                    drop(reply);
+                    let now = Instant::now();
+                    recent_restarts.retain(|&at| now.duration_since(at) < self.within);
+                    let fresh = if recent_restarts.len() < self.max_restarts {
+                        (self.factory)()
+                    } else {
+                        None
+                    };
+                    match fresh {
+                        Some(fresh) => {
+                            recent_restarts.push_back(now);
+                            actor = fresh;
+                            ctx.restarts += 1;
+                            actor.started(&mut ctx);
+                        }
+                        None => {
+                            // The broken instance is not asked to clean up
+                            return Exit {
+                                reason: ExitReason::Panicked(panic_message(&*payload)),
+                                processed,
+                                restarts: ctx.restarts,
+                            };
+                        }
+                    }
+                }
+            }
+        };
+        actor.stopped();
+        Exit {
+            reason,
+            processed,
+            restarts: ctx.restarts,
+        }
+    }
+}
+
+// Starts an actor with an unbounded mailbox and no restarts
+pub fn spawn<A: Actor>(actor: A) -> ActorHandle<A> {
+    let mut actor = Some(actor);
+    Props {
+        factory: Box::new(move || actor.take()),
+        mailbox: None,
+        max_restarts: 0,
+        within: Duration::ZERO,
+    }
+    .spawn()
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Barrier};
+
+    struct Counter {
+        count: i64,
+        stopped: Arc<AtomicBool>,
+    }
+
+    enum CounterMsg {
+        Add(i64),
+        Get,
+        Divide(i64),
+        Quit,
+    }
+
+    impl Actor for Counter {
+        type Message = CounterMsg;
+        type Reply = i64;
+
+        fn handle(&mut self, message: CounterMsg, ctx: &mut Context) -> i64 {
+            match message {
+                CounterMsg::Add(n) => self.count += n,
+                CounterMsg::Get => {}
+                CounterMsg::Divide(n) => self.count /= n,
+                CounterMsg::Quit => ctx.stop(),
+            }
+            self.count
+        }
+
+        fn stopped(&mut self) {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn counter(start: i64) -> (Counter, Arc<AtomicBool>) {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let counter = Counter {
+            count: start,
+            stopped: Arc::clone(&stopped),
+        };
+        (counter, stopped)
+    }
+
+    #[test]
+    fn test_tell_and_ask() {
+        let (actor, stopped) = counter(0);
+        let handle = spawn(actor);
+        let actors: Vec<_> = (0..4).map(|_| handle.actor_ref()).collect();
+        thread::scope(|s| {
+            for actor in &actors {
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        actor.tell(CounterMsg::Add(1)).ok().unwrap();
+                    }
+                });
+            }
+        });
+        // The mailbox is FIFO, so the ask sees every earlier tell
+        assert_eq!(handle.ask(CounterMsg::Get), Ok(400));
+        assert_eq!(handle.ask(CounterMsg::Add(5)), Ok(405));
+
+        let exit = handle.stop();
+        assert_eq!(exit.reason, ExitReason::Stopped);
+        assert_eq!(exit.processed, 402);
+        assert!(stopped.load(Ordering::SeqCst));
+        assert_eq!(actors[0].ask(CounterMsg::Get), Err(AskError::Stopped));
+        assert!(actors[0].tell(CounterMsg::Add(1)).is_err());
+        assert!(actors[0].is_stopped());
+    }
+
+    #[test]
+    fn test_stop_waits_for_queued_messages() {
+        let (actor, _) = counter(0);
+        let handle = spawn(actor);
+        let actor = handle.actor_ref();
+        for _ in 0..50 {
+            actor.tell(CounterMsg::Add(2)).ok().unwrap();
+        }
+        let exit = handle.stop();
+        assert_eq!(exit.processed, 50);
+        assert_eq!(actor.ask(CounterMsg::Get), Err(AskError::Stopped));
+    }
+
+    #[test]
+    fn test_supervisor_restarts_with_fresh_state() {
+        let builds = Arc::new(AtomicUsize::new(0));
+        let handle = {
+            let builds = Arc::clone(&builds);
+            Props::new(move || {
+                builds.fetch_add(1, Ordering::SeqCst);
+                counter(10).0
+            })
+            .restart(3, Duration::from_secs(60))
+            .spawn()
+        };
+        // The panic message is printed; the test checks what happens after
+        assert_eq!(handle.ask(CounterMsg::Add(5)), Ok(15));
+        assert_eq!(handle.ask(CounterMsg::Divide(0)), Err(AskError::NoReply));
+        // Restarted from the factory, so the 5 is gone
+        assert_eq!(handle.ask(CounterMsg::Get), Ok(10));
+        let exit = handle.stop();
+        assert_eq!((exit.reason, exit.restarts), (ExitReason::Stopped, 1));
+        assert_eq!(builds.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_supervisor_gives_up_after_too_many_restarts() {
+        let handle = Props::new(|| counter(1).0)
+            .restart(2, Duration::from_secs(60))
+            .spawn();
+        let actor = handle.actor_ref();
+        for _ in 0..3 {
+            assert_eq!(actor.ask(CounterMsg::Divide(0)), Err(AskError::NoReply));
+        }
+        drop(actor);
+        let exit = handle.join();
+        assert_eq!(exit.restarts, 2);
+        assert_eq!(
+            exit.reason,
+            ExitReason::Panicked("attempt to divide by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn test_actor_stops_itself_or_when_unreferenced() {
+        let (actor, stopped) = counter(0);
+        let handle = spawn(actor);
+        assert_eq!(handle.ask(CounterMsg::Quit), Ok(0));
+        assert_eq!(handle.join().reason, ExitReason::Stopped);
+        assert!(stopped.load(Ordering::SeqCst));
+
+        let (actor, stopped) = counter(0);
+        let handle = spawn(actor);
+        let actor = handle.actor_ref();
+        let ready = Arc::new(Barrier::new(2));
+        let user = {
+            let ready = Arc::clone(&ready);
+            thread::spawn(move || {
+                actor.tell(CounterMsg::Add(1)).ok().unwrap();
+                ready.wait();
+            })
+        };
+        ready.wait();
+        user.join().unwrap();
+        let exit = handle.join();
+        assert_eq!((exit.reason, exit.processed), (ExitReason::Unreferenced, 1));
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_ask_behind_a_stop_gets_no_reply() {
+        struct Gate(Arc<Barrier>);
+        impl Actor for Gate {
+            type Message = bool;
+            type Reply = ();
+
+            fn handle(&mut self, quit: bool, ctx: &mut Context) {
+                if quit {
+                    self.0.wait();
+                    ctx.stop();
+                }
+            }
+        }
+        let gate = Arc::new(Barrier::new(2));
+        let handle = spawn(Gate(Arc::clone(&gate)));
+        handle.tell(true).ok().unwrap();
+        // Hold the actor at the gate until the ask is queued behind it
+        while handle.queued() > 0 {
+            thread::yield_now();
+        }
+        let asker = {
+            let actor = handle.actor_ref();
+            thread::spawn(move || actor.ask(false))
+        };
+        while handle.queued() == 0 {
+            thread::yield_now();
+        }
+        gate.wait();
+        assert_eq!(asker.join().unwrap(), Err(AskError::NoReply));
+        assert_eq!(handle.join().reason, ExitReason::Stopped);
+    }
+
+    #[test]
+    fn test_ask_timeout() {
+        struct Sleeper;
+        impl Actor for Sleeper {
+            type Message = u64;
+            type Reply = ();
+
+            fn handle(&mut self, millis: u64, _: &mut Context) {
+                thread::sleep(Duration::from_millis(millis));
+            }
+        }
+        let handle = Props::new(|| Sleeper).mailbox(1).spawn();
+        assert_eq!(
+            handle.ask_timeout(50, Duration::from_millis(5)),
+            Err(AskError::Timeout)
+        );
+        assert_eq!(handle.ask_timeout(0, Duration::from_secs(5)), Ok(()));
+        handle.stop();
+    }
+}
@@ -0,0 +1,5 @@
+// Library root file - exposes modules for integration testing
+pub mod actor;
+pub mod mpmc;
+pub mod pipeline;
+pub mod select;
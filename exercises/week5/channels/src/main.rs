@@ -5,6 +5,11 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
+use channels::actor::{Actor, Context, Props};
+use channels::mpmc;
+use channels::pipeline::{Pipeline, Stage};
+use channels::select;
+
 // TODO 1: Basic channel usage
 fn basic_channel() {
     let (tx, rx) = mpsc::channel();
@@ -193,35 +198,91 @@ fn worker_pool() {
     }
 }
 
-// TODO 8: Bidirectional communication
-fn bidirectional_communication() {
-    let (tx1, rx1) = mpsc::channel();
-    let (tx2, rx2) = mpsc::channel();
+// TODO 8: Request/response with actors
+// Rather than wiring a request channel and a response channel by hand, the
+// worker is an actor (see src/actor.rs): `ask` sends a message with its own
+// one-shot reply channel and waits for the answer, `tell` just queues it.
+struct Worker {
+    jobs_done: usize,
+}
+
+enum WorkerMsg {
+    Process(String),
+    Log(String),
+    Fail,
+}
+
+impl Actor for Worker {
+    type Message = WorkerMsg;
+    type Reply = Option<String>;
     
-    thread::spawn(move || {
-        // Receive request
-        let request = rx1.recv().unwrap();
-        println!("Worker received request: {}", request);
-        
-        // Process and send response
-        let response = format!("Processed: {}", request);
-        tx2.send(response).unwrap();
+    fn started(&mut self, ctx: &mut Context) {
+        if ctx.restarts() > 0 {
+            println!("Worker restarted (restart #{})", ctx.restarts());
+        }
+    }
+    
+    fn handle(&mut self, message: WorkerMsg, _ctx: &mut Context) -> Option<String> {
+        match message {
+            WorkerMsg::Process(request) => {
+                println!("Worker received request: {}", request);
+                self.jobs_done += 1;
+                Some(format!("Processed: {} (job #{})", request, self.jobs_done))
+            }
+            WorkerMsg::Log(line) => {
+                println!("Worker log: {}", line);
+                None
+            }
+            WorkerMsg::Fail => panic!("worker hit a bad request"),
+        }
+    }
+    
+    fn stopped(&mut self) {
+        println!("Worker stopped after {} jobs", self.jobs_done);
+    }
+}
+
+fn actor_pattern() {
+    // Restart the worker from scratch if it panics, at most 3 times a second
+    let worker = Props::new(|| Worker { jobs_done: 0 })
+        .restart(3, Duration::from_secs(1))
+        .spawn();
+    
+    // Request/response
+    let response = worker.ask(WorkerMsg::Process("Do some work".to_string()));
+    println!("Main received response: {:?}", response);
+    
+    // Fire and forget, from several threads at once
+    thread::scope(|s| {
+        for id in 0..2 {
+            let worker = worker.actor_ref();
+            s.spawn(move || {
+                let _ = worker.tell(WorkerMsg::Log(format!("hello from thread {}", id)));
+            });
+        }
     });
     
-    // Send request
-    tx1.send("Do some work".to_string()).unwrap();
+    // A panic loses the worker's state but not the worker; the default
+    // hook prints the panic before the supervisor restarts it
+    println!("Asking the worker to fail: {:?}", worker.ask(WorkerMsg::Fail).unwrap_err());
+    let response = worker.ask(WorkerMsg::Process("More work".to_string()));
+    println!("Main received response: {:?}", response);
     
-    // Receive response
-    let response = rx2.recv().unwrap();
-    println!("Main received response: {}", response);
+    // Graceful stop: queued messages are handled first
+    let _ = worker.tell(WorkerMsg::Log("last message".to_string()));
+    let exit = worker.stop();
+    println!("Worker exit: {:?}", exit);
 }
 
 // TODO 9: Fan-out pattern
+// An mpsc Receiver cannot be cloned, so it only ever has one consumer. An
+// mpmc Receiver can: the clones share one queue and each message goes to
+// exactly one of them.
 fn fan_out_pattern() {
-    let (tx, rx) = mpsc::channel();
+    let (tx, rx) = mpmc::unbounded();
     
     // Multiple receivers (fan-out)
-    let receivers = vec![rx];
+    let receivers = vec![rx.clone(), rx.clone(), rx];
     
     // Producer
     thread::spawn(move || {
@@ -230,45 +291,111 @@ fn fan_out_pattern() {
         }
     });
     
-    // Process messages
-    for rx in receivers {
-        thread::spawn(move || {
-            while let Ok(msg) = rx.recv() {
-                println!("Processor received: {}", msg);
+    // Each processor stops when the producer hangs up and the queue is empty
+    let mut processors = vec![];
+    for (id, rx) in receivers.into_iter().enumerate() {
+        processors.push(thread::spawn(move || {
+            let mut processed = 0;
+            for msg in rx {
+                println!("Processor {} received: {}", id, msg);
+                processed += 1;
             }
-        });
+            processed
+        }));
     }
     
-    thread::sleep(Duration::from_millis(100));
+    let total: usize = processors.into_iter().map(|p| p.join().unwrap()).sum();
+    println!("Processed {} messages", total);
 }
 
 // TODO 10: Pipeline pattern
+// The Pipeline builder (see src/pipeline.rs) wires the stages together with
+// bounded channels, runs each stage on its own worker threads and returns
+// once everything has shut down, with per-stage metrics.
 fn pipeline_pattern() {
-    // Stage 1: Generate numbers
-    let (tx1, rx1) = mpsc::channel();
+    let report = Pipeline::new(1..=20u8)
+        // Stage 1: Square numbers, failing once the square no longer fits
+        .stage(Stage::new("square", |n: u8| {
+            n.checked_mul(n).ok_or(format!("{}² does not fit in a u8", n))
+        }).workers(2).buffer(4))
+        // Stage 2: Describe them
+        .stage(Stage::new("describe", |square: u8| {
+            thread::sleep(Duration::from_millis(2));
+            Ok(format!("square {}", square))
+        }))
+        // Stage 3: Print results on this thread
+        .for_each(|result| match result {
+            Ok(description) => println!("Final result: {}", description),
+            Err(e) => println!("Error: {}", e),
+        });
+    
+    println!("{}", report);
+}
+
+// TODO 11: Select over several channels
+// select! waits on several operations at once and runs the branch of the
+// first one that can go ahead; see src/select.rs.
+fn select_pattern() {
+    let (numbers_tx, mut numbers) = mpmc::unbounded();
+    let (words_tx, mut words) = mpmc::unbounded();
+    
     thread::spawn(move || {
-        for i in 1..=5 {
-            tx1.send(i).unwrap();
+        for i in 0..3 {
+            numbers_tx.send(i).unwrap();
+            thread::sleep(Duration::from_millis(30));
         }
     });
-    
-    // Stage 2: Square numbers
-    let (tx2, rx2) = mpsc::channel();
     thread::spawn(move || {
-        for num in rx1 {
-            let squared = num * num;
-            tx2.send(squared).unwrap();
+        for word in ["alpha", "beta"] {
+            words_tx.send(word).unwrap();
+            thread::sleep(Duration::from_millis(50));
         }
     });
     
-    // Stage 3: Print results
-    thread::spawn(move || {
-        for result in rx2 {
-            println!("Final result: {}", result);
+    // A hung-up channel is always ready, so swap in one that never is
+    let mut open = 2;
+    while open > 0 {
+        select! {
+            recv(numbers) -> msg => match msg {
+                Ok(n) => println!("Number: {}", n),
+                Err(_) => {
+                    println!("Numbers producer hung up");
+                    numbers = mpmc::never();
+                    open -= 1;
+                }
+            },
+            recv(words) -> msg => match msg {
+                Ok(word) => println!("Word: {}", word),
+                Err(_) => {
+                    println!("Words producer hung up");
+                    words = mpmc::never();
+                    open -= 1;
+                }
+            },
+            timeout(Duration::from_secs(1)) => {
+                println!("Nothing for a second, giving up");
+                break;
+            }
         }
-    });
+    }
     
-    thread::sleep(Duration::from_millis(100));
+    // Sends can be selected too: give up instead of blocking on a full channel
+    let (tx, rx) = mpmc::bounded(1);
+    for i in 0..2 {
+        select! {
+            send(tx, i) -> res => println!("Sent {}: {:?}", i, res),
+            timeout(Duration::from_millis(50)) => println!("Channel full, dropped {}", i),
+        }
+    }
+    
+    // The sender notices when the last receiver is gone
+    drop(rx);
+    select! {
+        send(tx, 2) -> res => match res {
+            Ok(()) => println!("Sent 2"),
+            Err(mpmc::SendError(value)) => println!("No receivers left, got {} back", value),
+        },
+    }
 }
 
 fn main() {
@@ -295,8 +422,8 @@ fn main() {
     println!("\n--- Worker Pool ---");
     worker_pool();
     
-    println!("\n--- Bidirectional Communication ---");
-    bidirectional_communication();
+    println!("\n--- Actors (request/response) ---");
+    actor_pattern();
     
     println!("\n--- Fan-out Pattern ---");
     fan_out_pattern();
@@ -304,5 +431,8 @@ fn main() {
     println!("\n--- Pipeline Pattern ---");
     pipeline_pattern();
     
+    println!("\n--- Select ---");
+    select_pattern();
+    
     println!("\n=== All channel examples completed! ===");
 }
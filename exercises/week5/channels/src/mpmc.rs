@@ -0,0 +1,670 @@
+// Multi-producer, multi-consumer channels
+//
+// Like std::sync::mpsc, but the Receiver can be cloned too: every clone
+// takes messages from the same queue and each message is delivered to
+// exactly one of them. Channels are either unbounded or bounded, where a
+// full channel makes senders wait.
+//
+// Both sides can tell when the other side is gone. Once every Sender has
+// been dropped, receivers get the messages still queued and then
+// RecvError; once every Receiver has been dropped, the queued messages
+// are dropped too (nobody can receive them) and send hands the value back
+// in a SendError.
+//
+// Channels also notify any select (see select.rs) that is waiting on them,
+// so one thread can wait on several channels at once.
+
+use crate::select::Signal;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendTimeoutError<T> {
+    Timeout(T),
+    Disconnected(T),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    Timeout,
+    Disconnected,
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "sending on a channel with no receivers")
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "sending on a full channel"),
+            TrySendError::Disconnected(_) => write!(f, "sending on a channel with no receivers"),
+        }
+    }
+}
+
+impl<T> fmt::Display for SendTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendTimeoutError::Timeout(_) => write!(f, "timed out waiting for room in the channel"),
+            SendTimeoutError::Disconnected(_) => {
+                write!(f, "sending on a channel with no receivers")
+            }
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "receiving on an empty channel with no senders")
+    }
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "receiving on an empty channel"),
+            TryRecvError::Disconnected => {
+                write!(f, "receiving on an empty channel with no senders")
+            }
+        }
+    }
+}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => write!(f, "timed out waiting for a message"),
+            RecvTimeoutError::Disconnected => {
+                write!(f, "receiving on an empty channel with no senders")
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+impl<T: fmt::Debug> std::error::Error for TrySendError<T> {}
+impl<T: fmt::Debug> std::error::Error for SendTimeoutError<T> {}
+impl std::error::Error for RecvError {}
+impl std::error::Error for TryRecvError {}
+impl std::error::Error for RecvTimeoutError {}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: Option<usize>,
+    senders: usize,
+    receivers: usize,
+    // Selects currently waiting on this channel
+    watchers: Vec<Arc<Signal>>,
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.queue.len() >= capacity)
+    }
+
+    fn notify_watchers(&self) {
+        for signal in &self.watchers {
+            signal.notify();
+        }
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    // Receivers wait here for messages, senders for room
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn new(capacity: Option<usize>, senders: usize) -> Arc<Self> {
+        Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                capacity,
+                senders,
+                receivers: 1,
+                watchers: Vec::new(),
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap()
+    }
+
+    fn watch(&self, signal: &Arc<Signal>) {
+        self.lock().watchers.push(Arc::clone(signal));
+    }
+
+    fn unwatch(&self, signal: &Arc<Signal>) {
+        self.lock()
+            .watchers
+            .retain(|watcher| !Arc::ptr_eq(watcher, signal));
+    }
+}
+
+// Waits on `condvar` until notified or until `deadline`; None means the
+// deadline has already passed
+fn wait_until<'a, T>(
+    condvar: &Condvar,
+    state: MutexGuard<'a, State<T>>,
+    deadline: Option<Instant>,
+) -> Option<MutexGuard<'a, State<T>>> {
+    match deadline {
+        None => Some(condvar.wait(state).unwrap()),
+        Some(deadline) => {
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            Some(condvar.wait_timeout(state, deadline - now).unwrap().0)
+        }
+    }
+}
+
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Shared::new(None, 1);
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+// A channel that holds at most `capacity` messages
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(
+        capacity > 0,
+        "a bounded channel needs room for at least one message"
+    );
+    let shared = Shared::new(Some(capacity), 1);
+    (
+        Sender {
+            shared: Arc::clone(&shared),
+        },
+        Receiver { shared },
+    )
+}
+
+// A receiver that never gets a message and never disconnects. Swapping it
+// in for a receiver that has disconnected switches that select branch off.
+pub fn never<T>() -> Receiver<T> {
+    // One sender is counted but none exists, so none can ever be dropped
+    Receiver {
+        shared: Shared::new(None, 1),
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    // Waits while the channel is full
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.send_until(value, None).map_err(|e| match e {
+            SendTimeoutError::Timeout(value) | SendTimeoutError::Disconnected(value) => {
+                SendError(value)
+            }
+        })
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 {
+            return Err(TrySendError::Disconnected(value));
+        }
+        if state.is_full() {
+            return Err(TrySendError::Full(value));
+        }
+        self.push(&mut state, value);
+        Ok(())
+    }
+
+    pub fn send_timeout(&self, value: T, timeout: Duration) -> Result<(), SendTimeoutError<T>> {
+        self.send_until(value, Instant::now().checked_add(timeout))
+    }
+
+    fn send_until(&self, value: T, deadline: Option<Instant>) -> Result<(), SendTimeoutError<T>> {
+        let mut state = self.shared.lock();
+        loop {
+            if state.receivers == 0 {
+                return Err(SendTimeoutError::Disconnected(value));
+            }
+            if !state.is_full() {
+                break;
+            }
+            state = match wait_until(&self.shared.not_full, state, deadline) {
+                Some(state) => state,
+                None => return Err(SendTimeoutError::Timeout(value)),
+            };
+        }
+        self.push(&mut state, value);
+        Ok(())
+    }
+
+    fn push(&self, state: &mut State<T>, value: T) {
+        state.queue.push_back(value);
+        self.shared.not_empty.notify_one();
+        state.notify_watchers();
+    }
+
+    // True once every receiver has been dropped
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.lock().receivers == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.shared.lock().is_full()
+    }
+
+    // None for unbounded channels
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.lock().capacity
+    }
+
+    // A send would not block: there is room, or it would fail right away
+    pub(crate) fn is_ready(&self) -> bool {
+        let state = self.shared.lock();
+        state.receivers == 0 || !state.is_full()
+    }
+
+    pub(crate) fn watch(&self, signal: &Arc<Signal>) {
+        self.shared.watch(signal);
+    }
+
+    pub(crate) fn unwatch(&self, signal: &Arc<Signal>) {
+        self.shared.unwatch(signal);
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.not_empty.notify_all();
+            state.notify_watchers();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    // Waits for a message; fails only when the channel is empty and every
+    // sender has been dropped
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.recv_until(None).map_err(|_| RecvError)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.pop(&mut state) {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.recv_until(Instant::now().checked_add(timeout))
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(value) = self.pop(&mut state) {
+                return Ok(value);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            state = match wait_until(&self.shared.not_empty, state, deadline) {
+                Some(state) => state,
+                None => return Err(RecvTimeoutError::Timeout),
+            };
+        }
+    }
+
+    fn pop(&self, state: &mut State<T>) -> Option<T> {
+        let value = state.queue.pop_front()?;
+        self.shared.not_full.notify_one();
+        state.notify_watchers();
+        Some(value)
+    }
+
+    // Blocks for each message until the channel disconnects
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    // Only the messages that are already queued
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { receiver: self }
+    }
+
+    // True once every sender has been dropped, even if messages are left
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.lock().senders == 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.shared.lock().capacity
+    }
+
+    // A recv would not block: a message is waiting, or it would fail
+    pub(crate) fn is_ready(&self) -> bool {
+        let state = self.shared.lock();
+        !state.queue.is_empty() || state.senders == 0
+    }
+
+    pub(crate) fn watch(&self, signal: &Arc<Signal>) {
+        self.shared.watch(signal);
+    }
+
+    pub(crate) fn unwatch(&self, signal: &Arc<Signal>) {
+        self.shared.unwatch(signal);
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            self.shared.not_full.notify_all();
+            state.notify_watchers();
+            // Dropped after unlocking, since a message's Drop may use the
+            // channel (an actor's ask carries its reply Sender, for one)
+            let queue = std::mem::take(&mut state.queue);
+            drop(state);
+            drop(queue);
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+pub struct TryIter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_messages_arrive_in_order_until_disconnect() {
+        let (tx, rx) = unbounded();
+        for i in 0..3 {
+            tx.send(i).unwrap();
+        }
+        assert!(!rx.is_disconnected());
+        drop(tx);
+        // Queued messages are still delivered after the senders are gone
+        assert!(rx.is_disconnected());
+        assert_eq!(rx.iter().collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(rx.recv(), Err(RecvError));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn test_bounded_channel_applies_backpressure() {
+        let (tx, rx) = bounded(2);
+        assert_eq!(tx.capacity(), Some(2));
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert!(tx.is_full());
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(
+            tx.send_timeout(3, Duration::from_millis(10)),
+            Err(SendTimeoutError::Timeout(3))
+        );
+
+        // A blocked sender continues once a receiver makes room
+        let sender = thread::spawn(move || {
+            tx.send(3).unwrap();
+            tx.send(4).unwrap();
+        });
+        let received: Vec<i32> = rx.iter().collect();
+        sender.join().unwrap();
+        assert_eq!(received, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_sender_detects_dropped_receivers() {
+        let (tx, rx) = bounded(1);
+        let rx2 = rx.clone();
+        drop(rx);
+        tx.send(1).unwrap();
+        drop(rx2);
+        assert!(tx.is_disconnected());
+        assert_eq!(tx.send(2), Err(SendError(2)));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Disconnected(3)));
+
+        // A sender blocked on a full channel is woken up too
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+        let sender = thread::spawn(move || tx.send(2));
+        thread::sleep(Duration::from_millis(20));
+        drop(rx);
+        assert_eq!(sender.join().unwrap(), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_queued_messages_dropped_with_last_receiver() {
+        let (tx, rx) = unbounded();
+        let value = Arc::new(());
+        tx.send(Arc::clone(&value)).unwrap();
+        let rx2 = rx.clone();
+        drop(rx);
+        assert_eq!(Arc::strong_count(&value), 2);
+        drop(rx2);
+        // Gone while the sender is still alive
+        assert_eq!(Arc::strong_count(&value), 1);
+        assert!(tx.is_disconnected());
+    }
+
+    #[test]
+    fn test_every_message_reaches_exactly_one_receiver() {
+        let (tx, rx) = bounded(8);
+        let producers: Vec<_> = (0..4)
+            .map(|p| {
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    for i in 0..500 {
+                        tx.send(p * 1000 + i).unwrap();
+                    }
+                })
+            })
+            .collect();
+        drop(tx);
+        let consumers: Vec<_> = (0..4)
+            .map(|_| {
+                let rx = rx.clone();
+                thread::spawn(move || rx.into_iter().collect::<Vec<_>>())
+            })
+            .collect();
+        drop(rx);
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut received: Vec<i32> = consumers
+            .into_iter()
+            .flat_map(|consumer| consumer.join().unwrap())
+            .collect();
+        received.sort_unstable();
+        let expected: Vec<i32> = (0..4)
+            .flat_map(|p| (0..500).map(move |i| p * 1000 + i))
+            .collect();
+        assert_eq!(received, expected);
+    }
+
+    #[test]
+    fn test_recv_timeout_and_never() {
+        let (tx, rx) = unbounded();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            tx.send("late").unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("late"));
+        sender.join().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+
+        let never = never::<i32>();
+        assert_eq!(never.try_recv(), Err(TryRecvError::Empty));
+        assert!(!never.is_disconnected());
+    }
+
+    #[test]
+    fn test_timeout_too_long_waits_without_deadline() {
+        let (tx, rx) = bounded(1);
+        tx.send_timeout(1, Duration::MAX).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::MAX), Ok(1));
+
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            tx.send(2).unwrap();
+        });
+        assert_eq!(rx.recv_timeout(Duration::MAX), Ok(2));
+        sender.join().unwrap();
+        assert_eq!(
+            rx.recv_timeout(Duration::MAX),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+}
@@ -0,0 +1,526 @@
+// Multi-stage pipelines over bounded channels
+//
+// A Pipeline starts from a source iterator and adds stages one at a time.
+// Each stage is a closure T -> Result<U, E> run by its own pool of worker
+// threads, which read from a bounded mpmc channel and write to the next
+// one:
+//
+//     let report = Pipeline::new(1..=100)
+//         .stage(Stage::new("square", |x: u64| Ok::<_, String>(x * x)).workers(4))
+//         .stage(Stage::new("format", |x| Ok(x.to_string())).buffer(8))
+//         .for_each(|item| println!("{:?}", item));
+//
+// - Backpressure: a full buffer blocks the stage that feeds it, so a slow
+//   stage slows everything before it instead of letting queues grow.
+// - Errors: an Err is tagged with the stage that produced it and passed
+//   along untouched by later stages, so it reaches the end of the pipeline.
+// - Shutdown: when the source runs out, each stage finishes once its input
+//   is drained and disconnects from the next one. A sink that stops early
+//   drops its receiver; the failed sends then stop every stage upstream.
+// - Metrics: each stage counts items, errors and busy time, and samples
+//   how many items are waiting in its input queue.
+//
+// With more than one worker a stage may reorder items.
+
+use crate::mpmc::{self, Receiver, Sender};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const DEFAULT_BUFFER: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageError<E> {
+    pub stage: String,
+    pub error: E,
+}
+
+impl<E: fmt::Display> fmt::Display for StageError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stage '{}' failed: {}", self.stage, self.error)
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for StageError<E> {}
+
+type Item<T, E> = Result<T, StageError<E>>;
+
+pub struct Stage<T, U, E> {
+    name: String,
+    workers: usize,
+    buffer: usize,
+    work: Arc<dyn Fn(T) -> Result<U, E> + Send + Sync>,
+}
+
+impl<T, U, E> Stage<T, U, E> {
+    pub fn new<F>(name: &str, work: F) -> Self
+    where
+        F: Fn(T) -> Result<U, E> + Send + Sync + 'static,
+    {
+        Stage {
+            name: name.to_string(),
+            workers: 1,
+            buffer: DEFAULT_BUFFER,
+            work: Arc::new(work),
+        }
+    }
+
+    // Number of threads running the closure; at least one
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // Capacity of the queue this stage writes to; at least one
+    pub fn buffer(mut self, buffer: usize) -> Self {
+        self.buffer = buffer.max(1);
+        self
+    }
+}
+
+#[derive(Default)]
+struct Metrics {
+    processed: AtomicU64,
+    errors: AtomicU64,
+    busy_nanos: AtomicU64,
+    depth_samples: AtomicU64,
+    depth_total: AtomicU64,
+    max_depth: AtomicU64,
+    // Set by the last worker of the stage to finish
+    finished: Mutex<Option<Instant>>,
+}
+
+impl Metrics {
+    fn sample_depth(&self, depth: usize) {
+        self.depth_samples.fetch_add(1, Ordering::Relaxed);
+        self.depth_total.fetch_add(depth as u64, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth as u64, Ordering::Relaxed);
+    }
+
+    fn finish(&self) {
+        let mut finished = self.finished.lock().unwrap();
+        let now = Instant::now();
+        if finished.map_or(true, |at| at < now) {
+            *finished = Some(now);
+        }
+    }
+}
+
+struct StageInfo {
+    name: String,
+    workers: usize,
+    // Capacity of the input queue
+    buffer: usize,
+    metrics: Arc<Metrics>,
+}
+
+pub struct Pipeline<T, E> {
+    output: Receiver<Item<T, E>>,
+    output_buffer: usize,
+    stages: Vec<StageInfo>,
+    threads: Vec<JoinHandle<()>>,
+    produced: Arc<AtomicU64>,
+    start: Instant,
+}
+
+impl<T: Send + 'static, E: Send + 'static> Pipeline<T, E> {
+    pub fn new<I>(source: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        Self::with_buffer(source, DEFAULT_BUFFER)
+    }
+
+    // `buffer` is the capacity of the queue after the source
+    pub fn with_buffer<I>(source: I, buffer: usize) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        let buffer = buffer.max(1);
+        let (tx, rx) = mpmc::bounded(buffer);
+        let produced = Arc::new(AtomicU64::new(0));
+        let source = source.into_iter();
+        let thread = {
+            let produced = Arc::clone(&produced);
+            thread::spawn(move || {
+                for item in source {
+                    if tx.send(Ok(item)).is_err() {
+                        // Everything downstream is gone
+                        break;
+                    }
+                    produced.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+        };
+        Pipeline {
+            output: rx,
+            output_buffer: buffer,
+            stages: Vec::new(),
+            threads: vec![thread],
+            produced,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn stage<U: Send + 'static>(mut self, stage: Stage<T, U, E>) -> Pipeline<U, E> {
+        let metrics = Arc::new(Metrics::default());
+        let (tx, rx) = mpmc::bounded(stage.buffer);
+        let input = self.output;
+        for _ in 0..stage.workers {
+            let (input, output) = (input.clone(), tx.clone());
+            let (work, metrics) = (Arc::clone(&stage.work), Arc::clone(&metrics));
+            let name = stage.name.clone();
+            self.threads.push(thread::spawn(move || {
+                run_worker(&name, &input, &output, &*work, &metrics);
+                metrics.finish();
+            }));
+        }
+
+        self.stages.push(StageInfo {
+            name: stage.name,
+            workers: stage.workers,
+            buffer: self.output_buffer,
+            metrics,
+        });
+        Pipeline {
+            output: rx,
+            output_buffer: stage.buffer,
+            stages: self.stages,
+            threads: self.threads,
+            produced: self.produced,
+            start: self.start,
+        }
+    }
+
+    // Feeds every item, in the order they arrive, to `sink` on the calling
+    // thread and returns once the whole pipeline has shut down
+    pub fn for_each<F>(self, mut sink: F) -> Report
+    where
+        F: FnMut(Item<T, E>),
+    {
+        self.drive(|item| {
+            sink(item);
+            true
+        })
+    }
+
+    // Collects the results, stopping at the first error. Stopping early
+    // shuts the rest of the pipeline down.
+    pub fn collect(self) -> (Result<Vec<T>, StageError<E>>, Report) {
+        let mut items = Vec::new();
+        let mut failure = None;
+        let report = self.drive(|item| match item {
+            Ok(item) => {
+                items.push(item);
+                true
+            }
+            Err(e) => {
+                failure = Some(e);
+                false
+            }
+        });
+        (failure.map_or(Ok(items), Err), report)
+    }
+
+    // `sink` returns false to stop early
+    fn drive<F>(self, mut sink: F) -> Report
+    where
+        F: FnMut(Item<T, E>) -> bool,
+    {
+        let metrics = Metrics::default();
+        for item in &self.output {
+            metrics.sample_depth(self.output.len());
+            let started = Instant::now();
+            let failed = item.is_err();
+            let go_on = sink(item);
+            metrics
+                .busy_nanos
+                .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+            let counter = if failed {
+                &metrics.errors
+            } else {
+                &metrics.processed
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+            if !go_on {
+                break;
+            }
+        }
+        metrics.finish();
+        drop(self.output);
+
+        let mut panic = None;
+        for thread in self.threads {
+            if let Err(payload) = thread.join() {
+                panic.get_or_insert(payload);
+            }
+        }
+        if let Some(payload) = panic {
+            std::panic::resume_unwind(payload);
+        }
+
+        let elapsed = self.start.elapsed();
+        let sink = StageInfo {
+            name: "sink".to_string(),
+            workers: 1,
+            buffer: self.output_buffer,
+            metrics: Arc::new(metrics),
+        };
+        Report {
+            produced: self.produced.load(Ordering::Relaxed),
+            elapsed,
+            stages: self
+                .stages
+                .iter()
+                .chain(std::iter::once(&sink))
+                .map(|stage| stage.report(self.start))
+                .collect(),
+        }
+    }
+}
+
+fn run_worker<T, U, E>(
+    name: &str,
+    input: &Receiver<Item<T, E>>,
+    output: &Sender<Item<U, E>>,
+    work: &(dyn Fn(T) -> Result<U, E> + Send + Sync),
+    metrics: &Metrics,
+) {
+    for item in input {
+        metrics.sample_depth(input.len());
+        let result = match item {
+            Ok(value) => {
+                let started = Instant::now();
+                let result = work(value);
+                metrics
+                    .busy_nanos
+                    .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+                match result {
+                    Ok(value) => {
+                        metrics.processed.fetch_add(1, Ordering::Relaxed);
+                        Ok(value)
+                    }
+                    Err(error) => {
+                        metrics.errors.fetch_add(1, Ordering::Relaxed);
+                        Err(StageError {
+                            stage: name.to_string(),
+                            error,
+                        })
+                    }
+                }
+            }
+            // Failed upstream: pass it on
+            Err(e) => Err(e),
+        };
+        if output.send(result).is_err() {
+            break;
+        }
+    }
+}
+
+impl StageInfo {
+    fn report(&self, start: Instant) -> StageReport {
+        let metrics = &self.metrics;
+        let samples = metrics.depth_samples.load(Ordering::Relaxed);
+        let finished = metrics
+            .finished
+            .lock()
+            .unwrap()
+            .unwrap_or_else(Instant::now);
+        StageReport {
+            name: self.name.clone(),
+            workers: self.workers,
+            buffer: self.buffer,
+            processed: metrics.processed.load(Ordering::Relaxed),
+            errors: metrics.errors.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(metrics.busy_nanos.load(Ordering::Relaxed)),
+            elapsed: finished.saturating_duration_since(start),
+            mean_depth: if samples == 0 {
+                0.0
+            } else {
+                metrics.depth_total.load(Ordering::Relaxed) as f64 / samples as f64
+            },
+            max_depth: metrics.max_depth.load(Ordering::Relaxed) as usize,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageReport {
+    pub name: String,
+    pub workers: usize,
+    // Capacity of the input queue
+    pub buffer: usize,
+    pub processed: u64,
+    pub errors: u64,
+    // Time spent inside the stage's closure, summed over its workers
+    pub busy: Duration,
+    // From the start of the pipeline until the stage's last worker stopped
+    pub elapsed: Duration,
+    // Items waiting in the input queue, sampled at every receive
+    pub mean_depth: f64,
+    pub max_depth: usize,
+}
+
+impl StageReport {
+    // Items handled per second, errors included
+    pub fn throughput(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            0.0
+        } else {
+            (self.processed + self.errors) as f64 / seconds
+        }
+    }
+
+    // Share of the workers' time spent in the closure rather than waiting
+    pub fn utilization(&self) -> f64 {
+        let available = self.elapsed.as_secs_f64() * self.workers as f64;
+        if available == 0.0 {
+            0.0
+        } else {
+            (self.busy.as_secs_f64() / available).min(1.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    // Items the source sent into the pipeline
+    pub produced: u64,
+    pub elapsed: Duration,
+    // One per stage, followed by the sink
+    pub stages: Vec<StageReport>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} items from the source in {:?}",
+            self.produced, self.elapsed
+        )?;
+        write!(
+            f,
+            "{:<12} {:>7} {:>9} {:>6} {:>10} {:>6} {:>11}",
+            "stage", "workers", "processed", "errors", "items/s", "busy", "queue (max)"
+        )?;
+        for stage in &self.stages {
+            write!(
+                f,
+                "\n{:<12} {:>7} {:>9} {:>6} {:>10.0} {:>5.0}% {:>5.1} ({}/{})",
+                stage.name,
+                stage.workers,
+                stage.processed,
+                stage.errors,
+                stage.throughput(),
+                stage.utilization() * 100.0,
+                stage.mean_depth,
+                stage.max_depth,
+                stage.buffer
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_items_flow_through_every_stage() {
+        let (result, report) = Pipeline::new(1..=100u64)
+            .stage(Stage::new("square", |x: u64| Ok::<_, String>(x * x)).workers(3))
+            .stage(Stage::new("halve", |x: u64| Ok(x / 2)).buffer(4))
+            .collect();
+        let mut items = result.unwrap();
+        items.sort_unstable();
+        let expected: Vec<u64> = (1..=100).map(|x| x * x / 2).collect();
+        assert_eq!(items, expected);
+
+        assert_eq!(report.produced, 100);
+        let names: Vec<_> = report.stages.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["square", "halve", "sink"]);
+        for stage in &report.stages {
+            assert_eq!((stage.processed, stage.errors), (100, 0));
+            assert!(stage.max_depth <= stage.buffer);
+        }
+        assert_eq!(report.stages[0].workers, 3);
+        // halve writes into a queue of 4, which is the sink's input
+        assert_eq!(report.stages[2].buffer, 4);
+    }
+
+    #[test]
+    fn test_errors_reach_the_end_tagged_with_their_stage() {
+        let mut results = Vec::new();
+        let report = Pipeline::new(vec!["1", "x", "3"])
+            .stage(Stage::new("parse", |s: &str| s.parse::<i32>()))
+            .stage(Stage::new("double", |n: i32| Ok(n * 2)))
+            .for_each(|item| results.push(item));
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(2));
+        let error = results[1].as_ref().unwrap_err();
+        assert_eq!(error.stage, "parse");
+        assert_eq!(
+            error.to_string(),
+            "stage 'parse' failed: invalid digit found in string"
+        );
+        assert_eq!(results[2], Ok(6));
+
+        // The failed item skipped the later stage
+        assert_eq!(
+            (report.stages[0].processed, report.stages[0].errors),
+            (2, 1)
+        );
+        assert_eq!(
+            (report.stages[1].processed, report.stages[1].errors),
+            (2, 0)
+        );
+        assert_eq!(
+            (report.stages[2].processed, report.stages[2].errors),
+            (2, 1)
+        );
+    }
+
+    #[test]
+    fn test_stopping_early_shuts_down_an_endless_source() {
+        let (result, report) = Pipeline::with_buffer(0u64.., 2)
+            .stage(
+                Stage::new("check", |x: u64| if x < 50 { Ok(x) } else { Err(x) })
+                    .workers(2)
+                    .buffer(2),
+            )
+            .collect();
+        // Either worker may hit the first bad item
+        let error = result.unwrap_err();
+        assert_eq!(error.stage, "check");
+        assert!(error.error >= 50);
+        // Only a bounded number of items got in before everything stopped
+        assert!(report.produced < 1000);
+    }
+
+    #[test]
+    fn test_backpressure_bounds_queue_depth() {
+        let report = Pipeline::with_buffer(0..200, 3)
+            .stage(
+                Stage::new("slow", |x: i32| {
+                    thread::sleep(Duration::from_micros(200));
+                    Ok::<_, ()>(x)
+                })
+                .buffer(3),
+            )
+            .for_each(drop);
+        let slow = &report.stages[0];
+        assert_eq!(slow.processed, 200);
+        assert!(slow.max_depth <= 3);
+        assert!(slow.busy >= Duration::from_millis(40));
+        assert!(slow.utilization() > 0.0);
+        assert!(report.to_string().contains("slow"));
+    }
+}
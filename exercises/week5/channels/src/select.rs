@@ -0,0 +1,472 @@
+// Waiting on several channels at once
+//
+// select! blocks until one of its operations can go ahead, performs it and
+// runs that branch:
+//
+//     select! {
+//         recv(numbers) -> msg => println!("number {:?}", msg),
+//         recv(words) -> msg => println!("word {:?}", msg),
+//         send(results, 42) -> res => println!("sent: {:?}", res.is_ok()),
+//         timeout(Duration::from_millis(100)) => println!("nothing ready"),
+//     }
+//
+// A recv branch gets Result<T, RecvError> and a send branch
+// Result<(), SendError<T>>, so disconnection shows up as an Err. A
+// disconnected channel is always ready; replace it with mpmc::never() to
+// switch its branch off. The last branch may be `timeout(duration)` or
+// `default` (do not wait at all). When several operations are ready, one
+// is picked in turn so that no channel starves the others.
+//
+// The value of a send branch is evaluated up front and dropped if another
+// branch wins. Bodies run outside of select's own loop, so break, continue
+// and return in them refer to the surrounding code.
+//
+// Underneath is Select, which only reports which operation is ready. Every
+// channel it waits on notifies a shared Signal whenever its state changes.
+
+use crate::mpmc::{Receiver, RecvError, SendError, Sender, TryRecvError, TrySendError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+pub(crate) struct Signal {
+    fired: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Signal {
+    pub(crate) fn notify(&self) {
+        *self.fired.lock().unwrap() = true;
+        self.cond.notify_one();
+    }
+
+    // Returns false if the deadline passed without a notification
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        let mut fired = self.fired.lock().unwrap();
+        while !*fired {
+            fired = match deadline {
+                None => self.cond.wait(fired).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.cond.wait_timeout(fired, deadline - now).unwrap().0
+                }
+            };
+        }
+        *fired = false;
+        true
+    }
+}
+
+// The part of a channel end that Select needs, without its message type
+trait Watch {
+    fn is_ready(&self) -> bool;
+    fn watch(&self, signal: &Arc<Signal>);
+    fn unwatch(&self, signal: &Arc<Signal>);
+}
+
+impl<T> Watch for Receiver<T> {
+    fn is_ready(&self) -> bool {
+        Receiver::is_ready(self)
+    }
+
+    fn watch(&self, signal: &Arc<Signal>) {
+        Receiver::watch(self, signal)
+    }
+
+    fn unwatch(&self, signal: &Arc<Signal>) {
+        Receiver::unwatch(self, signal)
+    }
+}
+
+impl<T> Watch for Sender<T> {
+    fn is_ready(&self) -> bool {
+        Sender::is_ready(self)
+    }
+
+    fn watch(&self, signal: &Arc<Signal>) {
+        Sender::watch(self, signal)
+    }
+
+    fn unwatch(&self, signal: &Arc<Signal>) {
+        Sender::unwatch(self, signal)
+    }
+}
+
+// Rotates the first operation looked at between selects
+static NEXT_START: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Default)]
+pub struct Select<'a> {
+    handles: Vec<&'a dyn Watch>,
+}
+
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select {
+            handles: Vec::new(),
+        }
+    }
+
+    // Adds a receive operation and returns its index
+    pub fn recv<T>(&mut self, receiver: &'a Receiver<T>) -> usize {
+        self.handles.push(receiver);
+        self.handles.len() - 1
+    }
+
+    // Adds a send operation and returns its index
+    pub fn send<T>(&mut self, sender: &'a Sender<T>) -> usize {
+        self.handles.push(sender);
+        self.handles.len() - 1
+    }
+
+    // Index of a ready operation, without waiting
+    pub fn try_ready(&self) -> Option<usize> {
+        let len = self.handles.len();
+        if len == 0 {
+            return None;
+        }
+        let start = NEXT_START.fetch_add(1, Ordering::Relaxed);
+        (0..len)
+            .map(|offset| (start + offset) % len)
+            .find(|&i| self.handles[i].is_ready())
+    }
+
+    // Waits until an operation is ready and returns its index. Readiness
+    // is only a hint: with several receivers or senders on a channel,
+    // another thread may take the message or the free slot first.
+    pub fn ready(&self) -> usize {
+        self.ready_until(None)
+            .expect("waiting without a deadline only returns when ready")
+    }
+
+    // A timeout too long to represent as an Instant means no deadline
+    pub fn ready_timeout(&self, timeout: Duration) -> Option<usize> {
+        self.ready_until(Instant::now().checked_add(timeout))
+    }
+
+    // Panics if there is neither an operation nor a deadline, since that
+    // would wait forever
+    pub fn ready_until(&self, deadline: Option<Instant>) -> Option<usize> {
+        if let Some(ready) = self.try_ready() {
+            return Some(ready);
+        }
+        if self.handles.is_empty() {
+            let deadline = deadline.expect("select with no operations and no timeout");
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            return None;
+        }
+
+        // Watch first and check again, so that a change in between is not
+        // missed: it will have fired the signal
+        let signal = Arc::new(Signal::default());
+        for handle in &self.handles {
+            handle.watch(&signal);
+        }
+        let ready = loop {
+            if let Some(ready) = self.try_ready() {
+                break Some(ready);
+            }
+            if !signal.wait(deadline) {
+                break self.try_ready();
+            }
+        };
+        for handle in &self.handles {
+            handle.unwatch(&signal);
+        }
+        ready
+    }
+}
+
+// How select! completes an operation that Select reported ready. Returns
+// false if another thread got there first; select! then waits again.
+pub trait Attempt {
+    type Value;
+    type Output;
+
+    fn attempt(&self, value: &mut Self::Value, output: &mut Option<Self::Output>) -> bool;
+}
+
+impl<T> Attempt for Receiver<T> {
+    type Value = ();
+    type Output = Result<T, RecvError>;
+
+    fn attempt(&self, _: &mut (), output: &mut Option<Self::Output>) -> bool {
+        match self.try_recv() {
+            Ok(value) => *output = Some(Ok(value)),
+            Err(TryRecvError::Disconnected) => *output = Some(Err(RecvError)),
+            Err(TryRecvError::Empty) => return false,
+        }
+        true
+    }
+}
+
+impl<T> Attempt for Sender<T> {
+    type Value = Option<T>;
+    type Output = Result<(), SendError<T>>;
+
+    fn attempt(&self, value: &mut Option<T>, output: &mut Option<Self::Output>) -> bool {
+        let message = value.take().expect("a send branch only completes once");
+        match self.try_send(message) {
+            Ok(()) => *output = Some(Ok(())),
+            Err(TrySendError::Disconnected(message)) => *output = Some(Err(SendError(message))),
+            Err(TrySendError::Full(message)) => {
+                *value = Some(message);
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[macro_export]
+macro_rules! select {
+    ($($input:tt)+) => {{
+        let mut __select = $crate::select::Select::new();
+        $crate::__select_internal!(@parse __select [] $($input)+)
+    }};
+}
+
+// The branches are parsed one at a time. Each recursion level declares the
+// state for one branch; macro hygiene keeps the names of different levels
+// apart, and the tokens passed down let the last level refer to all of them.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __select_internal {
+    (@parse $sel:ident [$($done:tt)*] , $($rest:tt)*) => {
+        $crate::__select_internal!(@parse $sel [$($done)*] $($rest)*)
+    };
+    (@parse $sel:ident [$($done:tt)*] recv($rx:expr) -> $pat:pat => $body:block $($rest:tt)*) => {
+        $crate::__select_internal!(@recv $sel [$($done)*] ($rx) ($pat) ($body) $($rest)*)
+    };
+    (@parse $sel:ident [$($done:tt)*] recv($rx:expr) -> $pat:pat => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__select_internal!(@recv $sel [$($done)*] ($rx) ($pat) ($body) $($($rest)*)?)
+    };
+    (@parse $sel:ident [$($done:tt)*] send($tx:expr, $value:expr) -> $pat:pat => $body:block $($rest:tt)*) => {
+        $crate::__select_internal!(@send $sel [$($done)*] ($tx) ($value) ($pat) ($body) $($rest)*)
+    };
+    (@parse $sel:ident [$($done:tt)*] send($tx:expr, $value:expr) -> $pat:pat => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__select_internal!(@send $sel [$($done)*] ($tx) ($value) ($pat) ($body) $($($rest)*)?)
+    };
+    (@parse $sel:ident [$($done:tt)*] timeout($timeout:expr) => $body:expr $(,)?) => {
+        $crate::__select_internal!(@run $sel [$($done)*]
+            (::std::time::Instant::now().checked_add($timeout)) ($body))
+    };
+    (@parse $sel:ident [$($done:tt)*] default => $body:expr $(,)?) => {
+        $crate::__select_internal!(@run $sel [$($done)*]
+            (::std::option::Option::Some(::std::time::Instant::now())) ($body))
+    };
+    (@parse $sel:ident [$($done:tt)*]) => {
+        $crate::__select_internal!(@run $sel [$($done)*]
+            (::std::option::Option::None) (::std::unreachable!()))
+    };
+
+    (@recv $sel:ident [$($done:tt)*] ($rx:expr) ($pat:pat) ($body:expr) $($rest:tt)*) => {{
+        let __channel = &$rx;
+        let __index = $sel.recv(__channel);
+        let mut __value = ();
+        let mut __output = ::std::option::Option::None;
+        $crate::__select_internal!(@parse $sel
+            [$($done)* (__channel __index __value __output ($pat) ($body))] $($rest)*)
+    }};
+    (@send $sel:ident [$($done:tt)*] ($tx:expr) ($value:expr) ($pat:pat) ($body:expr) $($rest:tt)*) => {{
+        let __channel = &$tx;
+        let __index = $sel.send(__channel);
+        let mut __value = ::std::option::Option::Some($value);
+        let mut __output = ::std::option::Option::None;
+        $crate::__select_internal!(@parse $sel
+            [$($done)* (__channel __index __value __output ($pat) ($body))] $($rest)*)
+    }};
+
+    (@run $sel:ident
+        [$(($channel:ident $index:ident $value:ident $output:ident ($pat:pat) ($body:expr)))*]
+        ($deadline:expr) ($fallback:expr)) => {{
+        let __deadline = $deadline;
+        while let ::std::option::Option::Some(__ready) = $sel.ready_until(__deadline) {
+            if false $(|| (__ready == $index
+                && $crate::select::Attempt::attempt($channel, &mut $value, &mut $output)))*
+            {
+                break;
+            }
+        }
+        // Release the channels before running user code, which may replace them
+        ::std::mem::drop($sel);
+        $(
+            if let ::std::option::Option::Some(__result) = $output {
+                let $pat = __result;
+                $body
+            } else
+        )* {
+            $fallback
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mpmc;
+
+    #[test]
+    fn test_ready_operation_is_taken() {
+        let (tx1, rx1) = mpmc::unbounded::<i32>();
+        let (tx2, rx2) = mpmc::unbounded();
+        tx2.send("two").unwrap();
+        let picked = crate::select! {
+            recv(rx1) -> msg => format!("rx1 {:?}", msg),
+            recv(rx2) -> msg => format!("rx2 {:?}", msg),
+        };
+        assert_eq!(picked, "rx2 Ok(\"two\")");
+        assert!(rx2.is_empty());
+        drop(tx1);
+    }
+
+    #[test]
+    fn test_timeout_and_default() {
+        let (_tx, rx) = mpmc::unbounded::<i32>();
+        let start = Instant::now();
+        let result = crate::select! {
+            recv(rx) -> msg => msg.ok(),
+            timeout(Duration::from_millis(20)) => None,
+        };
+        assert_eq!(result, None);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let result = crate::select! {
+            recv(rx) -> _msg => "message",
+            default => "nothing",
+        };
+        assert_eq!(result, "nothing");
+    }
+
+    #[test]
+    fn test_timeout_too_long_waits_without_deadline() {
+        let (tx, rx) = mpmc::unbounded();
+        tx.send(1).unwrap();
+        let result = crate::select! {
+            recv(rx) -> msg => msg.ok(),
+            timeout(Duration::MAX) => None,
+        };
+        assert_eq!(result, Some(1));
+
+        let mut sel = Select::new();
+        sel.recv(&rx);
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx.send(2).unwrap();
+        });
+        assert_eq!(sel.ready_timeout(Duration::MAX), Some(0));
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_waits_for_a_message_from_another_thread() {
+        let (_tx1, rx1) = mpmc::unbounded::<i32>();
+        let (tx2, rx2) = mpmc::bounded(1);
+        let sender = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            tx2.send(7).unwrap();
+        });
+        let received = crate::select! {
+            recv(rx1) -> msg => msg.unwrap(),
+            recv(rx2) -> msg => msg.unwrap() * 10,
+        };
+        assert_eq!(received, 70);
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_send_branch_and_disconnection() {
+        let (tx, rx) = mpmc::bounded(1);
+        tx.send(1).unwrap();
+        // Full, so the send has to wait for the timeout
+        let sent = crate::select! {
+            send(tx, 2) -> res => res.is_ok(),
+            timeout(Duration::from_millis(10)) => false,
+        };
+        assert!(!sent);
+        assert_eq!(rx.recv(), Ok(1));
+        let sent = crate::select! {
+            send(tx, 2) -> res => res.is_ok(),
+            default => false,
+        };
+        assert!(sent);
+
+        // A dropped receiver makes the send branch fail right away
+        drop(rx);
+        let returned = crate::select! {
+            send(tx, 3) -> res => res.unwrap_err().0,
+        };
+        assert_eq!(returned, 3);
+    }
+
+    #[test]
+    fn test_multiplexing_until_every_channel_disconnects() {
+        let (numbers_tx, mut numbers) = mpmc::unbounded();
+        let (words_tx, mut words) = mpmc::unbounded();
+        let producers = [
+            thread::spawn(move || {
+                for i in 0..50 {
+                    numbers_tx.send(i).unwrap();
+                }
+            }),
+            thread::spawn(move || {
+                for word in ["a", "b", "c"] {
+                    words_tx.send(word).unwrap();
+                }
+            }),
+        ];
+
+        let (mut sum, mut seen, mut open) = (0, Vec::new(), 2);
+        loop {
+            crate::select! {
+                recv(numbers) -> msg => match msg {
+                    Ok(n) => sum += n,
+                    Err(_) => {
+                        numbers = mpmc::never();
+                        open -= 1;
+                    }
+                },
+                recv(words) -> msg => {
+                    match msg {
+                        Ok(word) => seen.push(word),
+                        Err(_) => {
+                            words = mpmc::never();
+                            open -= 1;
+                        }
+                    }
+                }
+            }
+            if open == 0 {
+                break;
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert_eq!(sum, (0..50).sum::<i32>());
+        assert_eq!(seen, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_select_reports_ready_index() {
+        let (tx1, rx1) = mpmc::unbounded::<i32>();
+        let (tx2, rx2) = mpmc::bounded::<i32>(1);
+        tx2.send(0).unwrap();
+        let mut select = Select::new();
+        let recv = select.recv(&rx1);
+        let send = select.send(&tx2);
+        assert_eq!(select.try_ready(), None);
+        assert_eq!(select.ready_timeout(Duration::from_millis(5)), None);
+
+        tx1.send(1).unwrap();
+        assert_eq!(select.ready(), recv);
+        assert_eq!(rx1.recv(), Ok(1));
+        assert_eq!(rx2.recv(), Ok(0));
+        assert_eq!(select.ready(), send);
+    }
+}
@@ -0,0 +1,80 @@
+// Clocks for code that waits or measures time
+//
+// Rate limiters and the priority queue's aging read time through a Clock
+// rather than Instant, so tests can swap in a ManualClock and drive time
+// by hand instead of sleeping.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Source of time
+pub trait Clock: Send + Sync {
+    // Time elapsed since some fixed starting point
+    fn now(&self) -> Duration;
+
+    // Block the caller for `duration` of this clock's time
+    fn sleep(&self, duration: Duration);
+}
+
+// Wall clock time, measured from when the clock was created
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+// Clock that only moves when told to. Clones share the same time, and
+// sleeping simply advances it, so blocking code stays deterministic.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.nanos.fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
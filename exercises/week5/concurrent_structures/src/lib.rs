@@ -0,0 +1,5 @@
+// Library root file - exposes modules for integration testing
+pub mod clock;
+pub mod mpmc_queue;
+pub mod priority_queue;
+pub mod rate_limit;
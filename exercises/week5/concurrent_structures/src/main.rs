@@ -6,6 +6,13 @@ use std::thread;
 use std::time::{Duration, Instant};
 use std::collections::VecDeque;
 
+use concurrent_structures::mpmc_queue::MPMCQueue;
+use concurrent_structures::priority_queue::ThreadSafePriorityQueue;
+use concurrent_structures::rate_limit::{
+    FixedWindow, Gcra, KeyedRateLimiter, LeakyBucket, RateLimiter, SlidingWindowLog, TokenBucket,
+};
+use linearizability::{check, ContainerOp, PriorityQueueModel, QueueModel, Recorder};
+
 // TODO 1: Thread-safe counter with multiple operations
 struct ThreadSafeCounter {
     value: Mutex<i64>,
@@ -184,52 +191,6 @@ fn producer_consumer_example() {
 }
 
 // TODO 3: Thread-safe priority queue
-use std::collections::BinaryHeap;
-use std::cmp::Reverse;
-
-struct ThreadSafePriorityQueue<T: Ord> {
-    heap: Mutex<BinaryHeap<T>>,
-    not_empty: Condvar,
-}
-
-impl<T: Ord> ThreadSafePriorityQueue<T> {
-    fn new() -> Self {
-        ThreadSafePriorityQueue {
-            heap: Mutex::new(BinaryHeap::new()),
-            not_empty: Condvar::new(),
-        }
-    }
-    
-    fn push(&self, item: T) {
-        let mut heap = self.heap.lock().unwrap();
-        let was_empty = heap.is_empty();
-        heap.push(item);
-        
-        if was_empty {
-            self.not_empty.notify_one();
-        }
-    }
-    
-    fn pop(&self) -> Option<T> {
-        let mut heap = self.heap.lock().unwrap();
-        heap.pop()
-    }
-    
-    fn pop_blocking(&self) -> T {
-        let mut heap = self.heap.lock().unwrap();
-        
-        while heap.is_empty() {
-            heap = self.not_empty.wait(heap).unwrap();
-        }
-        
-        heap.pop().unwrap()
-    }
-    
-    fn len(&self) -> usize {
-        self.heap.lock().unwrap().len()
-    }
-}
-
 fn priority_queue_example() {
     let pq = Arc::new(ThreadSafePriorityQueue::new());
     let mut handles = vec![];
@@ -261,6 +222,24 @@ fn priority_queue_example() {
         handle.join().unwrap();
     }
     consumer.join().unwrap();
+    
+    // Scheduling: (priority, task) pairs, with handles to reprioritise or
+    // cancel tasks that are still queued
+    let scheduler = ThreadSafePriorityQueue::with_aging(Duration::from_millis(50));
+    scheduler.push((1, "write report"));
+    let backup = scheduler.push((2, "backup"));
+    let cleanup = scheduler.push((3, "cleanup"));
+    scheduler.push((3, "deploy"));
+    scheduler.update(backup, (5, "backup"));
+    scheduler.remove(cleanup);
+    while let Some((priority, task)) = scheduler.pop_timeout(Duration::from_millis(10)) {
+        println!("Scheduler: running {} (priority {})", task, priority);
+        if task == "backup" {
+            // Long enough for "write report" to age past the new urgent work
+            thread::sleep(Duration::from_millis(60));
+            scheduler.push((9, "urgent fix"));
+        }
+    }
 }
 
 // TODO 4: Work-stealing queue simulation
@@ -353,62 +332,18 @@ fn work_stealing_example() {
 }
 
 // TODO 5: Rate limiter
-struct RateLimiter {
-    tokens: Mutex<f64>,
-    capacity: f64,
-    refill_rate: f64, // tokens per second
-    last_refill: Mutex<Instant>,
-}
-
-impl RateLimiter {
-    fn new(capacity: f64, refill_rate: f64) -> Self {
-        RateLimiter {
-            tokens: Mutex::new(capacity),
-            capacity,
-            refill_rate,
-            last_refill: Mutex::new(Instant::now()),
-        }
-    }
-    
-    fn try_acquire(&self, tokens: f64) -> bool {
-        let mut token_count = self.tokens.lock().unwrap();
-        let mut last_refill = self.last_refill.lock().unwrap();
-        
-        // Refill tokens based on elapsed time
-        let now = Instant::now();
-        let elapsed = now.duration_since(*last_refill).as_secs_f64();
-        let new_tokens = elapsed * self.refill_rate;
-        
-        *token_count = (*token_count + new_tokens).min(self.capacity);
-        *last_refill = now;
-        
-        // Try to consume tokens
-        if *token_count >= tokens {
-            *token_count -= tokens;
-            true
-        } else {
-            false
-        }
-    }
-    
-    fn get_available_tokens(&self) -> f64 {
-        let token_count = self.tokens.lock().unwrap();
-        *token_count
-    }
-}
-
+// The limiters live in src/rate_limit.rs behind the RateLimiter trait
 fn rate_limiter_example() {
-    let limiter = Arc::new(RateLimiter::new(10.0, 2.0)); // 10 capacity, 2 tokens/sec
+    let limiter = Arc::new(TokenBucket::new(10, 2.0)); // 10 capacity, 2 tokens/sec
     let mut handles = vec![];
     
     for i in 0..5 {
         let limiter = Arc::clone(&limiter);
         let handle = thread::spawn(move || {
             for j in 0..5 {
-                if limiter.try_acquire(1.0) {
-                    println!("Thread {} - Request {} ALLOWED", i, j);
-                } else {
-                    println!("Thread {} - Request {} DENIED (rate limited)", i, j);
+                match limiter.try_acquire_n(1) {
+                    Ok(()) => println!("Thread {} - Request {} ALLOWED", i, j),
+                    Err(e) => println!("Thread {} - Request {} DENIED ({})", i, j, e),
                 }
                 thread::sleep(Duration::from_millis(200));
             }
@@ -419,47 +354,39 @@ fn rate_limiter_example() {
     for handle in handles {
         handle.join().unwrap();
     }
-}
-
-// TODO 6: Multi-producer, multi-consumer queue
-struct MPMCQueue<T> {
-    queue: Mutex<VecDeque<T>>,
-    not_empty: Condvar,
-}
-
-impl<T> MPMCQueue<T> {
-    fn new() -> Self {
-        MPMCQueue {
-            queue: Mutex::new(VecDeque::new()),
-            not_empty: Condvar::new(),
-        }
-    }
     
-    fn enqueue(&self, item: T) {
-        let mut queue = self.queue.lock().unwrap();
-        let was_empty = queue.is_empty();
-        queue.push_back(item);
-        
-        if was_empty {
-            self.not_empty.notify_all(); // Notify all waiting consumers
-        }
+    // Same workload against each algorithm, all allowing ~5 requests per 100ms
+    let window = Duration::from_millis(100);
+    let limiters: Vec<(&str, Box<dyn RateLimiter>)> = vec![
+        ("Token bucket", Box::new(TokenBucket::new(5, 50.0))),
+        ("Leaky bucket", Box::new(LeakyBucket::new(5, 50.0))),
+        ("Fixed window", Box::new(FixedWindow::new(5, window))),
+        ("Sliding window log", Box::new(SlidingWindowLog::new(5, window))),
+        ("GCRA", Box::new(Gcra::new(5, window, 5))),
+    ];
+    
+    for (name, limiter) in &limiters {
+        let allowed = (0..20).filter(|_| limiter.try_acquire()).count();
+        println!("{}: {} of 20 burst requests allowed", name, allowed);
     }
     
-    fn dequeue(&self) -> Option<T> {
-        self.queue.lock().unwrap().pop_front()
+    // Blocking acquire sleeps until a token is available
+    let start = Instant::now();
+    let limiter = TokenBucket::new(1, 20.0);
+    for _ in 0..5 {
+        limiter.acquire().unwrap();
     }
+    println!("5 blocking acquires at 20/sec took {:?}", start.elapsed());
     
-    fn dequeue_blocking(&self) -> T {
-        let mut queue = self.queue.lock().unwrap();
-        
-        while queue.is_empty() {
-            queue = self.not_empty.wait(queue).unwrap();
-        }
-        
-        queue.pop_front().unwrap()
+    // Per-client limiting
+    let per_client = KeyedRateLimiter::new(|| TokenBucket::new(3, 1.0));
+    for client in ["alice", "bob", "alice", "alice", "alice", "bob"] {
+        let allowed = per_client.try_acquire(&client.to_string());
+        println!("Client {}: {}", client, if allowed { "ALLOWED" } else { "DENIED" });
     }
 }
 
+// TODO 6: Multi-producer, multi-consumer queue
 fn mpmc_queue_example() {
     let queue = Arc::new(MPMCQueue::new());
     let mut handles = vec![];
@@ -496,6 +423,80 @@ fn mpmc_queue_example() {
     }
 }
 
+// TODO 7: Checking linearizability
+// Record every call and return during a concurrent run, then search for a
+// sequential order that matches both real time and a simple model
+fn linearizability_example() {
+    let queue = MPMCQueue::new();
+    let pq = ThreadSafePriorityQueue::new();
+    let queue_history = Recorder::new();
+    let pq_history = Recorder::new();
+    
+    thread::scope(|s| {
+        for t in 0..4 {
+            let (queue, pq) = (&queue, &pq);
+            let (queue_history, pq_history) = (&queue_history, &pq_history);
+            s.spawn(move || {
+                for i in 0..25 {
+                    let value = t * 100 + i;
+                    if (t + i) % 3 == 0 {
+                        queue_history.record(t, ContainerOp::Pop, || queue.dequeue());
+                        pq_history.record(t, ContainerOp::Pop, || pq.pop());
+                    } else {
+                        queue_history.record(t, ContainerOp::Push(value), || {
+                            queue.enqueue(value);
+                            None
+                        });
+                        pq_history.record(t, ContainerOp::Push(value), || {
+                            pq.push(value);
+                            None
+                        });
+                    }
+                }
+            });
+        }
+    });
+    
+    let queue_history = queue_history.into_history();
+    match check(&QueueModel::new(), &queue_history) {
+        Ok(order) => println!("MPMCQueue: linearizable ({} operations)", order.len()),
+        Err(counterexample) => println!("MPMCQueue: {}", counterexample),
+    }
+    let pq_history = pq_history.into_history();
+    match check(&PriorityQueueModel::new(), &pq_history) {
+        Ok(order) => println!("ThreadSafePriorityQueue: linearizable ({} operations)", order.len()),
+        Err(counterexample) => println!("ThreadSafePriorityQueue: {}", counterexample),
+    }
+    
+    // The work-stealing queue is deliberately not FIFO: the owner pops its
+    // newest item while thieves take the oldest. The checker finds it and
+    // shrinks the run down to the few operations that show it.
+    let ws_queue = WorkStealingQueue::new();
+    let ws_history = Recorder::new();
+    thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..10 {
+                ws_history.record(0, ContainerOp::Push(i), || {
+                    ws_queue.push_local(i);
+                    None
+                });
+            }
+            for _ in 0..5 {
+                ws_history.record(0, ContainerOp::Pop, || ws_queue.pop_local());
+            }
+        });
+        s.spawn(|| {
+            for _ in 0..5 {
+                ws_history.record(1, ContainerOp::Pop, || ws_queue.steal());
+            }
+        });
+    });
+    match check(&QueueModel::new(), &ws_history.into_history()) {
+        Ok(_) => println!("WorkStealingQueue as a FIFO queue: linearizable"),
+        Err(counterexample) => print!("WorkStealingQueue as a FIFO queue: {}", counterexample),
+    }
+}
+
 fn main() {
     println!("=== Concurrent Data Structures ===\n");
     
@@ -517,5 +518,8 @@ fn main() {
     println!("\n--- MPMC Queue ---");
     mpmc_queue_example();
     
+    println!("\n--- Linearizability Checking ---");
+    linearizability_example();
+    
     println!("\n=== All concurrent structure examples completed! ===");
 }
@@ -0,0 +1,99 @@
+// Multi-producer, multi-consumer FIFO queue: a VecDeque behind a Mutex,
+// with a Condvar so consumers can block until something is enqueued
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+pub struct MPMCQueue<T> {
+    queue: Mutex<VecDeque<T>>,
+    not_empty: Condvar,
+}
+
+impl<T> MPMCQueue<T> {
+    pub fn new() -> Self {
+        MPMCQueue {
+            queue: Mutex::new(VecDeque::new()),
+            not_empty: Condvar::new(),
+        }
+    }
+
+    pub fn enqueue(&self, item: T) {
+        let mut queue = self.queue.lock().unwrap();
+        let was_empty = queue.is_empty();
+        queue.push_back(item);
+
+        if was_empty {
+            self.not_empty.notify_all(); // Notify all waiting consumers
+        }
+    }
+
+    pub fn dequeue(&self) -> Option<T> {
+        self.queue.lock().unwrap().pop_front()
+    }
+
+    pub fn dequeue_blocking(&self) -> T {
+        let mut queue = self.queue.lock().unwrap();
+
+        while queue.is_empty() {
+            queue = self.not_empty.wait(queue).unwrap();
+        }
+
+        queue.pop_front().unwrap()
+    }
+}
+
+impl<T> Default for MPMCQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use linearizability::{check, ContainerOp, QueueModel, Recorder};
+    use std::thread;
+
+    #[test]
+    fn test_fifo_order() {
+        let queue = MPMCQueue::new();
+        for item in ["a", "b", "c"] {
+            queue.enqueue(item);
+        }
+        assert_eq!(queue.dequeue_blocking(), "a");
+        assert_eq!(queue.dequeue(), Some("b"));
+        assert_eq!(queue.dequeue(), Some("c"));
+        assert_eq!(queue.dequeue(), None);
+    }
+
+    #[test]
+    fn test_is_linearizable() {
+        for _ in 0..5 {
+            let queue = MPMCQueue::new();
+            let recorder = Recorder::new();
+            thread::scope(|s| {
+                // Two producers and two consumers
+                for t in 0..4 {
+                    let (queue, recorder) = (&queue, &recorder);
+                    s.spawn(move || {
+                        for i in 0..30 {
+                            if t < 2 {
+                                let value = t * 100 + i;
+                                recorder.record(t, ContainerOp::Push(value), || {
+                                    queue.enqueue(value);
+                                    None
+                                });
+                            } else {
+                                recorder.record(t, ContainerOp::Pop, || queue.dequeue());
+                            }
+                        }
+                    });
+                }
+            });
+            let history = recorder.into_history();
+            if let Err(counterexample) = check(&QueueModel::new(), &history) {
+                panic!("{}", counterexample);
+            }
+        }
+    }
+}
@@ -0,0 +1,462 @@
+// Thread-safe priority queue for scheduling work between threads
+//
+// - Largest item first; equal items come out in the order they were pushed
+// - push returns a Handle, which can later change the item's priority
+//   (decrease-key and increase-key) or take it back out with remove
+// - Consumers can block with pop_blocking or pop_timeout. Every push wakes
+//   a waiter, not just the push onto an empty queue, so a burst of pushes
+//   wakes as many blocked consumers as there are new items
+// - Optional aging: once an item has waited `max_wait` it jumps ahead of
+//   everything that has not, oldest first, so low priorities cannot starve
+//
+// The heap is a Vec with a map from handle to position, so an item can be
+// found and re-sifted in O(log n) without scanning. Handles are sequence
+// numbers from a counter, which also gives the FIFO tie-break and the
+// arrival order used for aging.
+
+use crate::clock::{Clock, SystemClock};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// Refers to one pushed item until it is popped or removed. Handles are
+// never reused, so a stale one simply finds nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Handle(u64);
+
+struct Entry<T> {
+    item: T,
+    seq: u64,
+    enqueued: Duration,
+}
+
+impl<T: Ord> Entry<T> {
+    // Whether self should be popped before other
+    fn before(&self, other: &Entry<T>) -> bool {
+        match self.item.cmp(&other.item) {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => self.seq < other.seq,
+        }
+    }
+}
+
+struct State<T> {
+    heap: Vec<Entry<T>>,
+    // Handle -> index in heap
+    positions: HashMap<u64, usize>,
+    // Handles in push order, for aging
+    arrivals: BTreeSet<u64>,
+    next_seq: u64,
+    // Consumers currently blocked in pop_blocking or pop_timeout
+    waiting: usize,
+}
+
+impl<T: Ord> State<T> {
+    fn push(&mut self, item: T, now: Duration) -> Handle {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.heap.push(Entry {
+            item,
+            seq,
+            enqueued: now,
+        });
+        self.positions.insert(seq, self.heap.len() - 1);
+        self.arrivals.insert(seq);
+        self.sift_up(self.heap.len() - 1);
+        Handle(seq)
+    }
+
+    fn remove_at(&mut self, index: usize) -> T {
+        let last = self.heap.len() - 1;
+        self.swap(index, last);
+        let entry = self.heap.pop().unwrap();
+        self.positions.remove(&entry.seq);
+        self.arrivals.remove(&entry.seq);
+        if index < self.heap.len() {
+            self.resift(index);
+        }
+        entry.item
+    }
+
+    fn resift(&mut self, index: usize) {
+        let index = self.sift_up(index);
+        self.sift_down(index);
+    }
+
+    fn sift_up(&mut self, mut index: usize) -> usize {
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if !self.heap[index].before(&self.heap[parent]) {
+                break;
+            }
+            self.swap(index, parent);
+            index = parent;
+        }
+        index
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        loop {
+            let mut first = index;
+            for child in [2 * index + 1, 2 * index + 2] {
+                if child < self.heap.len() && self.heap[child].before(&self.heap[first]) {
+                    first = child;
+                }
+            }
+            if first == index {
+                return;
+            }
+            self.swap(index, first);
+            index = first;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.positions.insert(self.heap[a].seq, a);
+        self.positions.insert(self.heap[b].seq, b);
+    }
+}
+
+pub struct ThreadSafePriorityQueue<T: Ord, C: Clock = SystemClock> {
+    state: Mutex<State<T>>,
+    not_empty: Condvar,
+    max_wait: Option<Duration>,
+    clock: C,
+}
+
+impl<T: Ord> ThreadSafePriorityQueue<T> {
+    pub fn new() -> Self {
+        Self::with_clock(None, SystemClock::new())
+    }
+
+    // Items that have waited `max_wait` are served before all others
+    pub fn with_aging(max_wait: Duration) -> Self {
+        Self::with_clock(Some(max_wait), SystemClock::new())
+    }
+}
+
+impl<T: Ord, C: Clock> ThreadSafePriorityQueue<T, C> {
+    // The clock only drives aging; blocking pops time out on real time
+    pub fn with_clock(max_wait: Option<Duration>, clock: C) -> Self {
+        ThreadSafePriorityQueue {
+            state: Mutex::new(State {
+                heap: Vec::new(),
+                positions: HashMap::new(),
+                arrivals: BTreeSet::new(),
+                next_seq: 0,
+                waiting: 0,
+            }),
+            not_empty: Condvar::new(),
+            max_wait,
+            clock,
+        }
+    }
+
+    pub fn push(&self, item: T) -> Handle {
+        let mut state = self.state.lock().unwrap();
+        let handle = state.push(item, self.clock.now());
+        // One new item, one waiter to wake. Notifying only when the queue
+        // was empty loses wake-ups when several consumers are blocked.
+        if state.waiting > 0 {
+            self.not_empty.notify_one();
+        }
+        handle
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        self.take(&mut state)
+    }
+
+    pub fn pop_blocking(&self) -> T {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = self.take(&mut state) {
+                return item;
+            }
+            state.waiting += 1;
+            state = self.not_empty.wait(state).unwrap();
+            state.waiting -= 1;
+        }
+    }
+
+    // None if nothing arrived within `timeout`
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        // A timeout too long to represent as an Instant never runs out
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return Some(self.pop_blocking());
+        };
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = self.take(&mut state) {
+                return Some(item);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            state.waiting += 1;
+            state = self
+                .not_empty
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+            state.waiting -= 1;
+        }
+    }
+
+    // Replaces the item (and so its priority), returning the old one, or
+    // None if the handle's item has already left the queue. The item keeps
+    // its place among equals and its waiting time for aging.
+    pub fn update(&self, handle: Handle, item: T) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        let index = *state.positions.get(&handle.0)?;
+        let old = std::mem::replace(&mut state.heap[index].item, item);
+        state.resift(index);
+        Some(old)
+    }
+
+    pub fn remove(&self, handle: Handle) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        let index = *state.positions.get(&handle.0)?;
+        Some(state.remove_at(index))
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.state.lock().unwrap().positions.contains_key(&handle.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().heap.is_empty()
+    }
+
+    fn take(&self, state: &mut MutexGuard<'_, State<T>>) -> Option<T> {
+        if state.heap.is_empty() {
+            return None;
+        }
+        if let Some(max_wait) = self.max_wait {
+            let oldest = *state.arrivals.iter().next().unwrap();
+            let index = state.positions[&oldest];
+            if self.clock.now().saturating_sub(state.heap[index].enqueued) >= max_wait {
+                return Some(state.remove_at(index));
+            }
+        }
+        Some(state.remove_at(0))
+    }
+}
+
+impl<T: Ord> Default for ThreadSafePriorityQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use linearizability::{check, ContainerOp, PriorityQueueModel, Recorder};
+    use std::sync::Barrier;
+    use std::thread;
+
+    // Ordered by priority alone, so jobs with equal priority tie
+    #[derive(Debug)]
+    struct Job {
+        priority: u8,
+        name: &'static str,
+    }
+
+    impl PartialEq for Job {
+        fn eq(&self, other: &Self) -> bool {
+            self.priority == other.priority
+        }
+    }
+
+    impl Eq for Job {}
+
+    impl PartialOrd for Job {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Job {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.priority.cmp(&other.priority)
+        }
+    }
+
+    fn job(priority: u8, name: &'static str) -> Job {
+        Job { priority, name }
+    }
+
+    fn drain_names<C: Clock>(pq: &ThreadSafePriorityQueue<Job, C>) -> Vec<&'static str> {
+        std::iter::from_fn(|| pq.pop())
+            .map(|job| job.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_pops_highest_priority_first() {
+        let pq = ThreadSafePriorityQueue::new();
+        for priority in [3, 9, 1, 7] {
+            pq.push(priority);
+        }
+        assert_eq!(pq.len(), 4);
+        let popped: Vec<_> = std::iter::from_fn(|| pq.pop()).collect();
+        assert_eq!(popped, [9, 7, 3, 1]);
+        assert!(pq.is_empty());
+    }
+
+    #[test]
+    fn test_equal_priorities_are_fifo() {
+        let pq = ThreadSafePriorityQueue::new();
+        for (priority, name) in [(1, "a"), (5, "b"), (1, "c"), (5, "d"), (1, "e"), (5, "f")] {
+            pq.push(job(priority, name));
+        }
+        assert_eq!(drain_names(&pq), ["b", "d", "f", "a", "c", "e"]);
+    }
+
+    #[test]
+    fn test_update_and_remove_by_handle() {
+        let pq = ThreadSafePriorityQueue::new();
+        let a = pq.push(job(5, "a"));
+        let b = pq.push(job(3, "b"));
+        let c = pq.push(job(1, "c"));
+        let d = pq.push(job(3, "d"));
+
+        // Decrease a below everything, raise c to the top
+        assert_eq!(pq.update(a, job(0, "a")).unwrap().priority, 5);
+        assert_eq!(pq.update(c, job(9, "c")).unwrap().priority, 1);
+        // An update to an equal priority keeps b ahead of d
+        pq.update(b, job(3, "b"));
+        assert_eq!(pq.remove(d).unwrap().name, "d");
+        assert!(!pq.contains(d));
+        assert_eq!(pq.remove(d), None);
+
+        assert_eq!(drain_names(&pq), ["c", "b", "a"]);
+        assert_eq!(pq.update(a, job(7, "a")), None);
+    }
+
+    #[test]
+    fn test_aging_prevents_starvation() {
+        let clock = ManualClock::new();
+        let pq =
+            ThreadSafePriorityQueue::with_clock(Some(Duration::from_millis(100)), clock.clone());
+        pq.push(job(1, "low"));
+        pq.push(job(1, "low-later"));
+
+        // A steady stream of urgent work would starve the low jobs forever
+        let mut served = vec![];
+        for _ in 0..6 {
+            clock.advance(Duration::from_millis(30));
+            pq.push(job(9, "urgent"));
+            served.push(pq.pop().unwrap().name);
+        }
+        // Served strictly by priority until "low" had waited 100ms; the
+        // next pop at 120ms takes it, then both aged jobs go oldest first
+        assert_eq!(
+            served,
+            ["urgent", "urgent", "urgent", "low", "low-later", "urgent"]
+        );
+        assert_eq!(pq.len(), 2);
+    }
+
+    #[test]
+    fn test_pop_timeout() {
+        let pq = ThreadSafePriorityQueue::new();
+        let start = Instant::now();
+        assert_eq!(pq.pop_timeout(Duration::from_millis(20)), None);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                pq.push(42);
+            });
+            assert_eq!(pq.pop_timeout(Duration::from_secs(10)), Some(42));
+        });
+
+        // Too long for an Instant, so it waits like pop_blocking
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                pq.push(7);
+            });
+            assert_eq!(pq.pop_timeout(Duration::MAX), Some(7));
+        });
+    }
+
+    #[test]
+    fn test_no_blocked_consumer_misses_a_wakeup() {
+        const CONSUMERS: usize = 8;
+        for _ in 0..20 {
+            let pq = ThreadSafePriorityQueue::new();
+            let start = Barrier::new(CONSUMERS + 1);
+            thread::scope(|s| {
+                let consumers: Vec<_> = (0..CONSUMERS)
+                    .map(|_| {
+                        let (pq, start) = (&pq, &start);
+                        s.spawn(move || {
+                            start.wait();
+                            // Long enough that a missed wake-up fails the
+                            // test instead of being papered over
+                            pq.pop_timeout(Duration::from_secs(10))
+                        })
+                    })
+                    .collect();
+                start.wait();
+                // Wait until every consumer is blocked, then push a burst
+                while pq.state.lock().unwrap().waiting < CONSUMERS {
+                    thread::yield_now();
+                }
+                for i in 0..CONSUMERS {
+                    pq.push(i);
+                }
+                let mut got: Vec<_> = consumers
+                    .into_iter()
+                    .map(|c| c.join().unwrap().expect("a consumer missed its wake-up"))
+                    .collect();
+                got.sort_unstable();
+                assert_eq!(got, (0..CONSUMERS).collect::<Vec<_>>());
+            });
+        }
+    }
+
+    #[test]
+    fn test_is_linearizable() {
+        for round in 0..5 {
+            let pq = ThreadSafePriorityQueue::new();
+            let recorder = Recorder::new();
+            thread::scope(|s| {
+                for t in 0..4 {
+                    let (pq, recorder) = (&pq, &recorder);
+                    s.spawn(move || {
+                        for i in 0..20 {
+                            // Distinct values, interleaved across threads
+                            let value = (i * 37 + t * 11 + round) % 97 * 4 + t;
+                            recorder.record(t, ContainerOp::Push(value), || {
+                                pq.push(value);
+                                None
+                            });
+                            if i % 2 == 1 {
+                                recorder.record(t, ContainerOp::Pop, || pq.pop());
+                            }
+                        }
+                    });
+                }
+            });
+            let history = recorder.into_history();
+            if let Err(counterexample) = check(&PriorityQueueModel::new(), &history) {
+                panic!("{}", counterexample);
+            }
+        }
+    }
+}
@@ -0,0 +1,717 @@
+// Rate limiting algorithms behind a common RateLimiter trait
+//
+// - TokenBucket: lock-free, bursts up to `capacity`, refills continuously
+// - LeakyBucket: a meter that fills per request and drains at a fixed rate
+// - FixedWindow: lock-free counter that resets at each window boundary
+// - SlidingWindowLog: exact limit over any window, one timestamp per permit
+// - Gcra: lock-free generic cell rate algorithm (theoretical arrival time)
+//
+// Every limiter reads time through a Clock (see crate::clock), so tests
+// can swap in a ManualClock and drive time by hand instead of sleeping.
+
+use crate::clock::{Clock, SystemClock};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitError {
+    // Not enough capacity right now; try again after this long
+    RetryAfter(Duration),
+    // The request can never succeed because it is larger than the limit
+    ExceedsCapacity { requested: u32, capacity: u32 },
+}
+
+impl fmt::Display for RateLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RateLimitError::RetryAfter(wait) => write!(f, "rate limited, retry after {:?}", wait),
+            RateLimitError::ExceedsCapacity { requested, capacity } => write!(
+                f,
+                "requested {} permits but the limiter only allows {}",
+                requested, capacity
+            ),
+        }
+    }
+}
+
+impl Error for RateLimitError {}
+
+pub trait RateLimiter: Send + Sync {
+    // Take `permits` without blocking
+    fn try_acquire_n(&self, permits: u32) -> Result<(), RateLimitError>;
+
+    // The clock used for refills and for sleeping in acquire()
+    fn clock(&self) -> &dyn Clock;
+
+    fn try_acquire(&self) -> bool {
+        self.try_acquire_n(1).is_ok()
+    }
+
+    // Take `permits`, sleeping until they become available. Only fails if
+    // the request can never be satisfied.
+    fn acquire_n(&self, permits: u32) -> Result<(), RateLimitError> {
+        loop {
+            match self.try_acquire_n(permits) {
+                Err(RateLimitError::RetryAfter(wait)) => self.clock().sleep(wait),
+                result => return result,
+            }
+        }
+    }
+
+    fn acquire(&self) -> Result<(), RateLimitError> {
+        self.acquire_n(1)
+    }
+}
+
+fn nanos(duration: Duration) -> i64 {
+    duration.as_nanos() as i64
+}
+
+fn retry_after(nanos: i64) -> RateLimitError {
+    RateLimitError::RetryAfter(Duration::from_nanos(nanos.max(1) as u64))
+}
+
+fn check_capacity(requested: u32, capacity: u32) -> Result<(), RateLimitError> {
+    if requested > capacity {
+        Err(RateLimitError::ExceedsCapacity {
+            requested,
+            capacity,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+// Token bucket holding up to `capacity` tokens, refilled at `refill_rate`
+// tokens per second.
+//
+// Instead of (tokens, last_refill) behind two locks we store a single
+// timestamp: the moment the bucket was last empty. The current level is
+// min(capacity, (now - empty_at) / token_interval), and taking n tokens moves
+// empty_at forward by n intervals, so one CAS updates the whole state.
+pub struct TokenBucket<C: Clock = SystemClock> {
+    capacity: u32,
+    token_interval: i64,
+    empty_at: AtomicI64,
+    clock: C,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, refill_rate: f64) -> Self {
+        Self::with_clock(capacity, refill_rate, SystemClock::new())
+    }
+}
+
+impl<C: Clock> TokenBucket<C> {
+    // Starts full
+    pub fn with_clock(capacity: u32, refill_rate: f64, clock: C) -> Self {
+        assert!(refill_rate > 0.0, "refill rate must be positive");
+        let token_interval = (1e9 / refill_rate).round().max(1.0) as i64;
+        let now = nanos(clock.now());
+        TokenBucket {
+            capacity,
+            token_interval,
+            empty_at: AtomicI64::new(now - capacity as i64 * token_interval),
+            clock,
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    // Current number of tokens, including partially refilled ones
+    pub fn available(&self) -> f64 {
+        let now = nanos(self.clock.now());
+        let empty_at = self.empty_at.load(Ordering::Acquire);
+        ((now - empty_at) as f64 / self.token_interval as f64).min(self.capacity as f64)
+    }
+}
+
+impl<C: Clock> RateLimiter for TokenBucket<C> {
+    fn try_acquire_n(&self, permits: u32) -> Result<(), RateLimitError> {
+        check_capacity(permits, self.capacity)?;
+        let cost = permits as i64 * self.token_interval;
+        let full_since = self.capacity as i64 * self.token_interval;
+        let mut current = self.empty_at.load(Ordering::Acquire);
+        loop {
+            let now = nanos(self.clock.now());
+            // Tokens beyond capacity are lost, so never look further back
+            let base = current.max(now - full_since);
+            let next = base + cost;
+            if next > now {
+                return Err(retry_after(next - now));
+            }
+            match self
+                .empty_at
+                .compare_exchange_weak(current, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(()),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
+
+// Leaky bucket used as a meter: each request pours `permits` into a bucket of
+// size `capacity` which leaks `leak_rate` units per second. Requests that
+// would overflow the bucket are rejected.
+pub struct LeakyBucket<C: Clock = SystemClock> {
+    capacity: u32,
+    leak_rate: f64,
+    state: Mutex<LeakyState>,
+    clock: C,
+}
+
+struct LeakyState {
+    level: f64,
+    last_leak: Duration,
+}
+
+impl LeakyBucket {
+    pub fn new(capacity: u32, leak_rate: f64) -> Self {
+        Self::with_clock(capacity, leak_rate, SystemClock::new())
+    }
+}
+
+impl<C: Clock> LeakyBucket<C> {
+    // Starts empty
+    pub fn with_clock(capacity: u32, leak_rate: f64, clock: C) -> Self {
+        assert!(leak_rate > 0.0, "leak rate must be positive");
+        let now = clock.now();
+        LeakyBucket {
+            capacity,
+            leak_rate,
+            state: Mutex::new(LeakyState {
+                level: 0.0,
+                last_leak: now,
+            }),
+            clock,
+        }
+    }
+
+    pub fn level(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        self.leak(&mut state);
+        state.level
+    }
+
+    fn leak(&self, state: &mut LeakyState) {
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(state.last_leak).as_secs_f64();
+        state.level = (state.level - elapsed * self.leak_rate).max(0.0);
+        state.last_leak = now;
+    }
+}
+
+impl<C: Clock> RateLimiter for LeakyBucket<C> {
+    fn try_acquire_n(&self, permits: u32) -> Result<(), RateLimitError> {
+        check_capacity(permits, self.capacity)?;
+        let mut state = self.state.lock().unwrap();
+        self.leak(&mut state);
+
+        let overflow = state.level + permits as f64 - self.capacity as f64;
+        if overflow > 1e-9 {
+            let wait = Duration::from_secs_f64(overflow / self.leak_rate);
+            return Err(RateLimitError::RetryAfter(wait.max(Duration::from_nanos(1))));
+        }
+        state.level += permits as f64;
+        Ok(())
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
+
+// At most `limit` permits per window of length `window`. The counter resets
+// at each window boundary, so up to 2 * limit permits can pass around one.
+//
+// State is packed into one word: high 32 bits window number, low 32 bits
+// permits used in that window.
+pub struct FixedWindow<C: Clock = SystemClock> {
+    limit: u32,
+    window: Duration,
+    state: AtomicU64,
+    clock: C,
+}
+
+impl FixedWindow {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self::with_clock(limit, window, SystemClock::new())
+    }
+}
+
+impl<C: Clock> FixedWindow<C> {
+    pub fn with_clock(limit: u32, window: Duration, clock: C) -> Self {
+        assert!(!window.is_zero(), "window must be non-zero");
+        FixedWindow {
+            limit,
+            window,
+            state: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    fn window_number(&self, now: Duration) -> u64 {
+        (now.as_nanos() / self.window.as_nanos()) as u64
+    }
+}
+
+impl<C: Clock> RateLimiter for FixedWindow<C> {
+    fn try_acquire_n(&self, permits: u32) -> Result<(), RateLimitError> {
+        check_capacity(permits, self.limit)?;
+        let mut current = self.state.load(Ordering::Acquire);
+        loop {
+            let now = self.clock.now();
+            let window = self.window_number(now) as u32;
+            let (stored_window, used) = ((current >> 32) as u32, current as u32);
+            let used = if stored_window == window { used } else { 0 };
+
+            // A sum past u32::MAX is over any limit too
+            let total = match used.checked_add(permits) {
+                Some(total) if total <= self.limit => total,
+                _ => {
+                    let window_end = self.window.as_nanos() * (self.window_number(now) as u128 + 1);
+                    return Err(retry_after((window_end - now.as_nanos()) as i64));
+                }
+            };
+            let next = ((window as u64) << 32) | total as u64;
+            match self
+                .state
+                .compare_exchange_weak(current, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(()),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
+
+// At most `limit` permits in any trailing window of length `window`.
+// Exact, at the cost of remembering when each grant in the window happened.
+pub struct SlidingWindowLog<C: Clock = SystemClock> {
+    limit: u32,
+    window: Duration,
+    log: Mutex<GrantLog>,
+    clock: C,
+}
+
+// Grants oldest first as (time, permits) runs, so a bulk request takes one
+// entry however many permits it asks for; `total` is the sum of the runs
+#[derive(Default)]
+struct GrantLog {
+    runs: VecDeque<(Duration, u32)>,
+    total: u64,
+}
+
+impl SlidingWindowLog {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self::with_clock(limit, window, SystemClock::new())
+    }
+}
+
+impl<C: Clock> SlidingWindowLog<C> {
+    pub fn with_clock(limit: u32, window: Duration, clock: C) -> Self {
+        SlidingWindowLog {
+            limit,
+            window,
+            log: Mutex::new(GrantLog::default()),
+            clock,
+        }
+    }
+
+    // Permits granted within the current window
+    pub fn in_window(&self) -> usize {
+        let mut log = self.log.lock().unwrap();
+        self.expire(&mut log, self.clock.now());
+        log.total as usize
+    }
+
+    fn expire(&self, log: &mut GrantLog, now: Duration) {
+        while let Some(&(oldest, permits)) = log.runs.front() {
+            if oldest + self.window <= now {
+                log.runs.pop_front();
+                log.total -= permits as u64;
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl<C: Clock> RateLimiter for SlidingWindowLog<C> {
+    fn try_acquire_n(&self, permits: u32) -> Result<(), RateLimitError> {
+        check_capacity(permits, self.limit)?;
+        let now = self.clock.now();
+        let mut log = self.log.lock().unwrap();
+        self.expire(&mut log, now);
+
+        let excess = (log.total + permits as u64).saturating_sub(self.limit as u64);
+        if excess > 0 {
+            // Wait until enough of the oldest runs have aged out
+            let mut freed = 0;
+            for &(granted_at, run) in &log.runs {
+                freed += run as u64;
+                if freed >= excess {
+                    return Err(retry_after(nanos(granted_at + self.window - now)));
+                }
+            }
+            unreachable!("permits never exceed the limit on their own");
+        }
+        match log.runs.back_mut() {
+            Some((last, run)) if *last == now => *run += permits,
+            _ => log.runs.push_back((now, permits)),
+        }
+        log.total += permits as u64;
+        Ok(())
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
+
+// Generic cell rate algorithm: `rate` permits per `period`, with bursts of up
+// to `burst` permits.
+//
+// Tracks the theoretical arrival time (TAT) of the next permit if traffic
+// flowed at exactly the configured rate. A request is allowed as long as it
+// does not push the TAT more than `burst` emission intervals into the future.
+pub struct Gcra<C: Clock = SystemClock> {
+    emission_interval: i64,
+    burst: u32,
+    tat: AtomicI64,
+    clock: C,
+}
+
+impl Gcra {
+    pub fn new(rate: u32, period: Duration, burst: u32) -> Self {
+        Self::with_clock(rate, period, burst, SystemClock::new())
+    }
+}
+
+impl<C: Clock> Gcra<C> {
+    pub fn with_clock(rate: u32, period: Duration, burst: u32, clock: C) -> Self {
+        assert!(rate > 0, "rate must be positive");
+        assert!(burst > 0, "burst must be positive");
+        let now = nanos(clock.now());
+        Gcra {
+            emission_interval: (nanos(period) / rate as i64).max(1),
+            burst,
+            tat: AtomicI64::new(now),
+            clock,
+        }
+    }
+}
+
+impl<C: Clock> RateLimiter for Gcra<C> {
+    fn try_acquire_n(&self, permits: u32) -> Result<(), RateLimitError> {
+        check_capacity(permits, self.burst)?;
+        let tolerance = self.burst as i64 * self.emission_interval;
+        let mut current = self.tat.load(Ordering::Acquire);
+        loop {
+            let now = nanos(self.clock.now());
+            let new_tat = current.max(now) + permits as i64 * self.emission_interval;
+            let allow_at = new_tat - tolerance;
+            if allow_at > now {
+                return Err(retry_after(allow_at - now));
+            }
+            match self
+                .tat
+                .compare_exchange_weak(current, new_tat, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(()),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        &self.clock
+    }
+}
+
+// One limiter per key (for example per client id), created on first use
+pub struct KeyedRateLimiter<K, L> {
+    limiters: RwLock<HashMap<K, Arc<L>>>,
+    factory: Box<dyn Fn() -> L + Send + Sync>,
+}
+
+impl<K, L> KeyedRateLimiter<K, L>
+where
+    K: Eq + Hash + Clone,
+    L: RateLimiter,
+{
+    pub fn new<F>(factory: F) -> Self
+    where
+        F: Fn() -> L + Send + Sync + 'static,
+    {
+        KeyedRateLimiter {
+            limiters: RwLock::new(HashMap::new()),
+            factory: Box::new(factory),
+        }
+    }
+
+    pub fn limiter(&self, key: &K) -> Arc<L> {
+        if let Some(limiter) = self.limiters.read().unwrap().get(key) {
+            return Arc::clone(limiter);
+        }
+        let mut limiters = self.limiters.write().unwrap();
+        Arc::clone(
+            limiters
+                .entry(key.clone())
+                .or_insert_with(|| Arc::new((self.factory)())),
+        )
+    }
+
+    pub fn try_acquire_n(&self, key: &K, permits: u32) -> Result<(), RateLimitError> {
+        self.limiter(key).try_acquire_n(permits)
+    }
+
+    pub fn try_acquire(&self, key: &K) -> bool {
+        self.limiter(key).try_acquire()
+    }
+
+    pub fn acquire_n(&self, key: &K, permits: u32) -> Result<(), RateLimitError> {
+        self.limiter(key).acquire_n(permits)
+    }
+
+    pub fn acquire(&self, key: &K) -> Result<(), RateLimitError> {
+        self.acquire_n(key, 1)
+    }
+
+    // Forget a key, e.g. when a client disconnects
+    pub fn remove(&self, key: &K) -> bool {
+        self.limiters.write().unwrap().remove(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.limiters.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::thread;
+    use std::time::Instant;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // Counts how many single permits pass at the current instant
+    fn drain(limiter: &dyn RateLimiter) -> usize {
+        let mut granted = 0;
+        while limiter.try_acquire() {
+            granted += 1;
+        }
+        granted
+    }
+
+    #[test]
+    fn test_token_bucket_burst_then_refill() {
+        let clock = ManualClock::new();
+        let bucket = TokenBucket::with_clock(5, 10.0, clock.clone());
+        assert_eq!(drain(&bucket), 5);
+        assert_eq!(bucket.try_acquire_n(1), Err(RateLimitError::RetryAfter(ms(100))));
+
+        clock.advance(ms(250));
+        assert!((bucket.available() - 2.5).abs() < 1e-9);
+        assert_eq!(drain(&bucket), 2);
+
+        // Never refills past capacity
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(drain(&bucket), 5);
+    }
+
+    #[test]
+    fn test_leaky_bucket_drains_at_rate() {
+        let clock = ManualClock::new();
+        let bucket = LeakyBucket::with_clock(4, 2.0, clock.clone());
+        assert!(bucket.try_acquire_n(3).is_ok());
+        assert_eq!(bucket.try_acquire_n(2), Err(RateLimitError::RetryAfter(ms(500))));
+
+        clock.advance(ms(500));
+        assert!(bucket.try_acquire_n(2).is_ok());
+        assert!((bucket.level() - 4.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fixed_window_resets_at_boundary() {
+        let clock = ManualClock::new();
+        let limiter = FixedWindow::with_clock(3, Duration::from_secs(1), clock.clone());
+        clock.advance(ms(900));
+        assert_eq!(drain(&limiter), 3);
+        assert_eq!(limiter.try_acquire_n(1), Err(RateLimitError::RetryAfter(ms(100))));
+
+        clock.advance(ms(100));
+        assert_eq!(drain(&limiter), 3);
+    }
+
+    #[test]
+    fn test_fixed_window_near_u32_max() {
+        let clock = ManualClock::new();
+        let limiter = FixedWindow::with_clock(u32::MAX, Duration::from_secs(1), clock.clone());
+        assert!(limiter.try_acquire_n(u32::MAX - 1).is_ok());
+        assert_eq!(
+            limiter.try_acquire_n(u32::MAX),
+            Err(RateLimitError::RetryAfter(Duration::from_secs(1)))
+        );
+        assert!(limiter.try_acquire());
+        assert!(!limiter.try_acquire());
+    }
+
+    #[test]
+    fn test_sliding_window_log_bulk_near_u32_max() {
+        // One entry per grant, not per permit, so this stays small
+        let clock = ManualClock::new();
+        let limiter = SlidingWindowLog::with_clock(u32::MAX, Duration::from_secs(1), clock.clone());
+        assert!(limiter.try_acquire_n(u32::MAX - 1).is_ok());
+        assert!(limiter.try_acquire());
+        assert_eq!(limiter.in_window(), u32::MAX as usize);
+        assert_eq!(limiter.try_acquire_n(1), Err(RateLimitError::RetryAfter(ms(1000))));
+
+        clock.advance(ms(1000));
+        assert_eq!(limiter.in_window(), 0);
+        assert!(limiter.try_acquire_n(u32::MAX).is_ok());
+    }
+
+    #[test]
+    fn test_sliding_window_log_is_exact() {
+        let clock = ManualClock::new();
+        let limiter = SlidingWindowLog::with_clock(3, Duration::from_secs(1), clock.clone());
+        assert!(limiter.try_acquire());
+        clock.advance(ms(400));
+        assert!(limiter.try_acquire_n(2).is_ok());
+        assert_eq!(limiter.try_acquire_n(1), Err(RateLimitError::RetryAfter(ms(600))));
+
+        // The first permit ages out, the other two are still in the window
+        clock.advance(ms(600));
+        assert_eq!(limiter.in_window(), 2);
+        assert_eq!(drain(&limiter), 1);
+        assert_eq!(limiter.try_acquire_n(2), Err(RateLimitError::RetryAfter(ms(400))));
+    }
+
+    #[test]
+    fn test_gcra_spaces_requests() {
+        let clock = ManualClock::new();
+        let limiter = Gcra::with_clock(10, Duration::from_secs(1), 3, clock.clone());
+        assert_eq!(drain(&limiter), 3);
+        assert_eq!(limiter.try_acquire_n(1), Err(RateLimitError::RetryAfter(ms(100))));
+
+        clock.advance(ms(100));
+        assert_eq!(drain(&limiter), 1);
+        clock.advance(ms(1000));
+        assert_eq!(drain(&limiter), 3);
+    }
+
+    #[test]
+    fn test_request_larger_than_capacity_fails_fast() {
+        let clock = ManualClock::new();
+        let limiters: Vec<Box<dyn RateLimiter>> = vec![
+            Box::new(TokenBucket::with_clock(2, 1.0, clock.clone())),
+            Box::new(LeakyBucket::with_clock(2, 1.0, clock.clone())),
+            Box::new(FixedWindow::with_clock(2, ms(10), clock.clone())),
+            Box::new(SlidingWindowLog::with_clock(2, ms(10), clock.clone())),
+            Box::new(Gcra::with_clock(1, ms(10), 2, clock.clone())),
+        ];
+        for limiter in &limiters {
+            assert_eq!(
+                limiter.acquire_n(3),
+                Err(RateLimitError::ExceedsCapacity {
+                    requested: 3,
+                    capacity: 2
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_blocking_acquire_sleeps_on_clock() {
+        let clock = ManualClock::new();
+        let bucket = TokenBucket::with_clock(2, 4.0, clock.clone());
+        for _ in 0..6 {
+            bucket.acquire().unwrap();
+        }
+        // Two from the initial burst, four more at 250ms each
+        assert_eq!(clock.now(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_blocking_acquire_with_system_clock() {
+        let bucket = TokenBucket::new(1, 100.0);
+        let start = Instant::now();
+        for _ in 0..4 {
+            bucket.acquire().unwrap();
+        }
+        assert!(start.elapsed() >= ms(25));
+    }
+
+    #[test]
+    fn test_keyed_limiter_isolates_clients() {
+        let clock = ManualClock::new();
+        let limiter = {
+            let clock = clock.clone();
+            KeyedRateLimiter::new(move || TokenBucket::with_clock(2, 1.0, clock.clone()))
+        };
+        let (alice, bob) = ("alice".to_string(), "bob".to_string());
+        assert!(limiter.try_acquire(&alice));
+        assert!(limiter.try_acquire(&alice));
+        assert!(!limiter.try_acquire(&alice));
+        assert!(limiter.try_acquire(&bob));
+        assert_eq!(limiter.len(), 2);
+
+        assert!(limiter.remove(&alice));
+        assert!(limiter.try_acquire(&alice));
+    }
+
+    #[test]
+    fn test_lock_free_limiters_never_over_grant() {
+        let clock = ManualClock::new();
+        let limiters: Vec<Arc<dyn RateLimiter>> = vec![
+            Arc::new(TokenBucket::with_clock(100, 1.0, clock.clone())),
+            Arc::new(FixedWindow::with_clock(100, Duration::from_secs(60), clock.clone())),
+            Arc::new(Gcra::with_clock(1, Duration::from_secs(1), 100, clock.clone())),
+        ];
+        for limiter in limiters {
+            let granted = Arc::new(AtomicU64::new(0));
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let limiter = Arc::clone(&limiter);
+                    let granted = Arc::clone(&granted);
+                    thread::spawn(move || {
+                        for _ in 0..50 {
+                            if limiter.try_acquire() {
+                                granted.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(granted.load(Ordering::Relaxed), 100);
+        }
+    }
+}
@@ -0,0 +1,561 @@
+// Concurrent hash map with sharded locks
+//
+// Keys are spread over N independent RwLock<HashMap> shards by hash, so a
+// write only blocks readers of the same shard instead of the whole map (as
+// with a single RwLock<HashMap>). Each shard grows and rehashes on its own,
+// which also keeps a resize from stalling every other thread.
+
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+
+pub struct ConcurrentMap<K, V, S = RandomState> {
+    shards: Box<[RwLock<HashMap<K, V, S>>]>,
+    hasher: S,
+}
+
+// Read access to a value; keeps its shard read-locked while alive
+pub struct Ref<'a, K, V, S> {
+    _guard: RwLockReadGuard<'a, HashMap<K, V, S>>,
+    value: *const V,
+}
+
+// Write access to a value; keeps its shard write-locked while alive
+pub struct RefMut<'a, K, V, S> {
+    _guard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+    value: *mut V,
+}
+
+// A slot in the map, obtained with ConcurrentMap::entry. Holds the shard's
+// write lock, so the check-then-insert is atomic.
+pub struct Entry<'a, K, V, S> {
+    guard: RwLockWriteGuard<'a, HashMap<K, V, S>>,
+    key: K,
+}
+
+fn default_shard_count() -> usize {
+    let cpus = thread::available_parallelism().map_or(4, |n| n.get());
+    (cpus * 4).next_power_of_two()
+}
+
+impl<K: Eq + Hash, V> ConcurrentMap<K, V> {
+    pub fn new() -> Self {
+        Self::with_shards(default_shard_count())
+    }
+
+    pub fn with_shards(shards: usize) -> Self {
+        Self::with_shards_and_hasher(shards, RandomState::new())
+    }
+}
+
+impl<K: Eq + Hash, V> Default for ConcurrentMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> ConcurrentMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone,
+{
+    pub fn with_shards_and_hasher(shards: usize, hasher: S) -> Self {
+        assert!(shards > 0, "need at least one shard");
+        ConcurrentMap {
+            shards: (0..shards)
+                .map(|_| RwLock::new(HashMap::with_hasher(hasher.clone())))
+                .collect(),
+            hasher,
+        }
+    }
+
+    fn shard_index<Q>(&self, key: &Q) -> usize
+    where
+        Q: Hash + ?Sized,
+    {
+        // The shard's own HashMap uses the low bits of the same hash, so
+        // pick the shard from the high bits to keep the two independent
+        let mut state = self.hasher.build_hasher();
+        key.hash(&mut state);
+        let hash = state.finish();
+        ((hash >> 32) as usize) % self.shards.len()
+    }
+
+    fn read_shard<Q>(&self, key: &Q) -> RwLockReadGuard<'_, HashMap<K, V, S>>
+    where
+        Q: Hash + ?Sized,
+    {
+        self.shards[self.shard_index(key)].read().unwrap()
+    }
+
+    fn write_shard<Q>(&self, key: &Q) -> RwLockWriteGuard<'_, HashMap<K, V, S>>
+    where
+        Q: Hash + ?Sized,
+    {
+        self.shards[self.shard_index(key)].write().unwrap()
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Option<V> {
+        self.write_shard(&key).insert(key, value)
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.write_shard(key).remove(key)
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.read_shard(key).get(key).cloned()
+    }
+
+    // Borrow a value without cloning it. The shard stays read-locked until
+    // the returned Ref is dropped, so don't hold on to it.
+    pub fn get_ref<Q>(&self, key: &Q) -> Option<Ref<'_, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let guard = self.read_shard(key);
+        let value = guard.get(key)? as *const V;
+        Some(Ref {
+            _guard: guard,
+            value,
+        })
+    }
+
+    pub fn get_mut<Q>(&self, key: &Q) -> Option<RefMut<'_, K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut guard = self.write_shard(key);
+        let value = guard.get_mut(key)? as *mut V;
+        Some(RefMut {
+            _guard: guard,
+            value,
+        })
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.read_shard(key).contains_key(key)
+    }
+
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, S> {
+        Entry {
+            guard: self.write_shard(&key),
+            key,
+        }
+    }
+
+    // Returns the value for `key`, computing and inserting it first if it is
+    // missing. `f` runs at most once per missing key, even under contention.
+    //
+    // That guarantee comes from running `f` with the key's shard
+    // write-locked: every other access to the shard waits for `f`, so keep
+    // it short, and never touch the map from inside it (that deadlocks).
+    pub fn compute_if_absent<F>(&self, key: K, f: F) -> V
+    where
+        F: FnOnce(&K) -> V,
+        V: Clone,
+    {
+        // Fast path: most calls hit, and only need a read lock
+        if let Some(value) = self.read_shard(&key).get(&key) {
+            return value.clone();
+        }
+        let mut shard = self.write_shard(&key);
+        if let Some(value) = shard.get(&key) {
+            return value.clone();
+        }
+        let value = f(&key);
+        shard.insert(key, value.clone());
+        value
+    }
+
+    // Replaces the value for `key` with `f(key, value)`, or removes it if
+    // `f` returns None. Returns the new value. Like compute_if_absent, `f`
+    // runs under the shard's write lock.
+    pub fn compute_if_present<Q, F>(&self, key: &Q, f: F) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&Q, &V) -> Option<V>,
+        V: Clone,
+    {
+        let mut shard = self.write_shard(key);
+        let updated = f(key, shard.get(key)?);
+        match &updated {
+            Some(value) => *shard.get_mut(key)? = value.clone(),
+            None => {
+                shard.remove(key);
+            }
+        }
+        updated
+    }
+
+    // Not atomic across shards: concurrent writers may change it mid-count
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|s| s.read().unwrap().is_empty())
+    }
+
+    // Number of entries per shard, handy for checking key distribution
+    pub fn shard_lens(&self) -> Vec<usize> {
+        self.shards.iter().map(|s| s.read().unwrap().len()).collect()
+    }
+
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap().clear();
+        }
+    }
+
+    pub fn retain<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        for shard in self.shards.iter() {
+            shard.write().unwrap().retain(|k, v| f(k, v));
+        }
+    }
+
+    // Point-in-time copy of the whole map. All shards are read-locked (in
+    // index order, so this cannot deadlock with other snapshots) before any
+    // is copied, so no write can land between shards.
+    pub fn snapshot(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let guards: Vec<_> = self.shards.iter().map(|s| s.read().unwrap()).collect();
+        guards
+            .iter()
+            .flat_map(|shard| shard.iter().map(|(k, v)| (k.clone(), v.clone())))
+            .collect()
+    }
+
+    pub fn iter_snapshot(&self) -> std::vec::IntoIter<(K, V)>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.snapshot().into_iter()
+    }
+
+    // Visits every entry, locking one shard at a time. Cheaper than a
+    // snapshot but sees each shard at a different moment.
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&K, &V),
+    {
+        for shard in self.shards.iter() {
+            for (k, v) in shard.read().unwrap().iter() {
+                f(k, v);
+            }
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shards.iter().map(|s| s.read().unwrap().capacity()).sum()
+    }
+
+    // Pre-sizes every shard for `additional` more entries spread evenly
+    pub fn reserve(&self, additional: usize) {
+        let per_shard = (additional + self.shards.len() - 1) / self.shards.len();
+        for shard in self.shards.iter() {
+            shard.write().unwrap().reserve(per_shard);
+        }
+    }
+
+    pub fn shrink_to_fit(&self) {
+        for shard in self.shards.iter() {
+            shard.write().unwrap().shrink_to_fit();
+        }
+    }
+
+    // Changes the number of shards, rehashing every entry. Takes &mut self,
+    // so no locking is needed: nobody else can be using the map.
+    pub fn reshard(&mut self, shards: usize) {
+        assert!(shards > 0, "need at least one shard");
+        let old = std::mem::replace(
+            &mut self.shards,
+            (0..shards)
+                .map(|_| RwLock::new(HashMap::with_hasher(self.hasher.clone())))
+                .collect(),
+        );
+        for shard in old.into_vec() {
+            for (key, value) in shard.into_inner().unwrap() {
+                let index = self.shard_index(&key);
+                self.shards[index].get_mut().unwrap().insert(key, value);
+            }
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for ConcurrentMap<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher + Clone + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let map = Self::with_shards_and_hasher(default_shard_count(), S::default());
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl<K, V, S> fmt::Debug for ConcurrentMap<K, V, S>
+where
+    K: Eq + Hash + Clone + fmt::Debug,
+    V: Clone + fmt::Debug,
+    S: BuildHasher + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.snapshot()).finish()
+    }
+}
+
+impl<'a, K: Hash + Eq, V, S: BuildHasher> Entry<'a, K, V, S> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn or_insert(self, default: V) -> RefMut<'a, K, V, S> {
+        self.or_insert_with(|| default)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(mut self, f: F) -> RefMut<'a, K, V, S> {
+        // The value lives in the HashMap's heap storage, not in the guard,
+        // so the pointer stays valid when the guard moves into RefMut
+        let value = self.guard.entry(self.key).or_insert_with(f) as *mut V;
+        RefMut {
+            _guard: self.guard,
+            value,
+        }
+    }
+
+    pub fn or_default(self) -> RefMut<'a, K, V, S>
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let Some(value) = self.guard.get_mut(&self.key) {
+            f(value);
+        }
+        self
+    }
+}
+
+impl<K, V, S> Deref for Ref<'_, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        // Valid while the shard is read-locked by _guard
+        unsafe { &*self.value }
+    }
+}
+
+impl<K, V, S> Deref for RefMut<'_, K, V, S> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        unsafe { &*self.value }
+    }
+}
+
+impl<K, V, S> DerefMut for RefMut<'_, K, V, S> {
+    fn deref_mut(&mut self) -> &mut V {
+        // Valid and exclusive while the shard is write-locked by _guard
+        unsafe { &mut *self.value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_basic_operations() {
+        let map = ConcurrentMap::with_shards(4);
+        assert_eq!(map.insert("a".to_string(), 1), None);
+        assert_eq!(map.insert("a".to_string(), 2), Some(1));
+        map.insert("b".to_string(), 3);
+
+        assert_eq!(map.get("a"), Some(2));
+        assert_eq!(*map.get_ref("b").unwrap(), 3);
+        assert!(map.contains_key("b"));
+        assert_eq!(map.len(), 2);
+
+        *map.get_mut("b").unwrap() += 10;
+        assert_eq!(map.remove("b"), Some(13));
+        assert_eq!(map.get("b"), None);
+
+        map.clear();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_entry_api() {
+        let map: ConcurrentMap<&str, Vec<i32>> = ConcurrentMap::with_shards(2);
+        map.entry("x").or_insert_with(Vec::new).push(1);
+        map.entry("x").or_insert_with(Vec::new).push(2);
+        map.entry("x").and_modify(|v| v.push(3)).or_default();
+        map.entry("y").and_modify(|v| v.push(99)).or_default();
+        assert_eq!(map.get("x"), Some(vec![1, 2, 3]));
+        assert_eq!(map.get("y"), Some(vec![]));
+        assert_eq!(*map.entry("z").or_insert(vec![7]), vec![7]);
+    }
+
+    #[test]
+    fn test_compute_if_absent_runs_once_under_contention() {
+        let map = Arc::new(ConcurrentMap::with_shards(8));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let map = Arc::clone(&map);
+                let calls = Arc::clone(&calls);
+                thread::spawn(move || {
+                    for key in 0..100 {
+                        let value = map.compute_if_absent(key, |k| {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            k * 2
+                        });
+                        assert_eq!(value, key * 2);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 100);
+        assert_eq!(map.len(), 100);
+    }
+
+    #[test]
+    fn test_compute_if_present() {
+        let map = ConcurrentMap::with_shards(2);
+        map.insert("hits", 1);
+        assert_eq!(map.compute_if_present("hits", |_, v| Some(v + 1)), Some(2));
+        assert_eq!(map.compute_if_present("misses", |_, v| Some(v + 1)), None);
+        assert_eq!(map.compute_if_present("hits", |_, _| None), None);
+        assert!(!map.contains_key("hits"));
+    }
+
+    #[test]
+    fn test_concurrent_entry_increments() {
+        let map = Arc::new(ConcurrentMap::with_shards(4));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let map = Arc::clone(&map);
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        *map.entry(i % 10).or_insert(0) += 1;
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let total: i32 = map.iter_snapshot().map(|(_, count)| count).sum();
+        assert_eq!(total, 4000);
+        map.for_each(|_, count| assert_eq!(*count, 400));
+    }
+
+    #[test]
+    fn test_snapshot_is_consistent() {
+        // The writer keeps moving one unit from key 0 to key 1 while holding
+        // both shards, so every snapshot must see a total of exactly 100
+        let map = Arc::new(ConcurrentMap::with_shards(16));
+        map.insert(0, 100);
+        map.insert(1, 0);
+
+        let writer = {
+            let map = Arc::clone(&map);
+            thread::spawn(move || {
+                let (from, to) = (map.shard_index(&0), map.shard_index(&1));
+                for _ in 0..2000 {
+                    if from == to {
+                        let mut shard = map.shards[from].write().unwrap();
+                        *shard.get_mut(&0).unwrap() -= 1;
+                        *shard.get_mut(&1).unwrap() += 1;
+                    } else {
+                        // Same index order as snapshot() to avoid deadlock
+                        let first = map.shards[from.min(to)].write().unwrap();
+                        let second = map.shards[from.max(to)].write().unwrap();
+                        let (mut src, mut dst) = if from < to {
+                            (first, second)
+                        } else {
+                            (second, first)
+                        };
+                        *src.get_mut(&0).unwrap() -= 1;
+                        *dst.get_mut(&1).unwrap() += 1;
+                    }
+                }
+            })
+        };
+        for _ in 0..500 {
+            let total: i32 = map.snapshot().iter().map(|(_, v)| *v).sum();
+            assert_eq!(total, 100);
+        }
+        writer.join().unwrap();
+        assert_eq!(map.get(&1), Some(2000));
+    }
+
+    #[test]
+    fn test_reshard_keeps_entries() {
+        let mut map: ConcurrentMap<i32, i32> = (0..1000).map(|i| (i, i * i)).collect();
+        map.reshard(3);
+        assert_eq!(map.shard_count(), 3);
+        assert_eq!(map.len(), 1000);
+        assert!(map.shard_lens().iter().all(|&n| n > 0));
+        for i in 0..1000 {
+            assert_eq!(map.get(&i), Some(i * i));
+        }
+    }
+
+    #[test]
+    fn test_reserve_and_retain() {
+        let map = ConcurrentMap::with_shards(4);
+        map.reserve(1000);
+        assert!(map.capacity() >= 1000);
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        map.retain(|k, _| k % 2 == 0);
+        assert_eq!(map.len(), 50);
+        map.shrink_to_fit();
+        assert!(map.capacity() >= 50);
+    }
+}
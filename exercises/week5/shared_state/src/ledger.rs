@@ -0,0 +1,552 @@
+// Multi-account bank ledger
+//
+// Amounts are whole cents (i64), so no rounding creeps in the way it does
+// with f64 dollars. Each account has its own Mutex; a transfer locks both
+// accounts, always lower id first, so two opposite transfers can't each
+// hold one lock while waiting for the other. Every successful operation
+// is appended to a journal that can only be read, never edited.
+//
+// Balances are never negative, and deposits are refused once the money in
+// the whole ledger would pass i64::MAX cents, so any sum of balances (a
+// total, or a balance rebuilt from the journal) fits in a Cents.
+//
+// Lock order everywhere: accounts by ascending id, then the supply, then the
+// journal.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Sub};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cents(pub i64);
+
+impl Cents {
+    pub const ZERO: Cents = Cents(0);
+
+    // Panics if the amount doesn't fit in i64 cents
+    pub fn from_dollars(dollars: i64) -> Cents {
+        match dollars.checked_mul(100) {
+            Some(cents) => Cents(cents),
+            None => panic!("{} dollars is too large an amount of cents", dollars),
+        }
+    }
+
+    pub fn checked_add(self, other: Cents) -> Option<Cents> {
+        self.0.checked_add(other.0).map(Cents)
+    }
+
+    pub fn checked_sub(self, other: Cents) -> Option<Cents> {
+        self.0.checked_sub(other.0).map(Cents)
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 > 0
+    }
+}
+
+impl fmt::Display for Cents {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}${}.{:02}", sign, abs / 100, abs % 100)
+    }
+}
+
+impl Add for Cents {
+    type Output = Cents;
+
+    fn add(self, other: Cents) -> Cents {
+        Cents(self.0 + other.0)
+    }
+}
+
+impl Sub for Cents {
+    type Output = Cents;
+
+    fn sub(self, other: Cents) -> Cents {
+        Cents(self.0 - other.0)
+    }
+}
+
+impl Sum for Cents {
+    fn sum<I: Iterator<Item = Cents>>(iter: I) -> Cents {
+        iter.fold(Cents::ZERO, Add::add)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AccountId(usize);
+
+impl fmt::Display for AccountId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LedgerError {
+    UnknownAccount(AccountId),
+    InvalidAmount(Cents),
+    SameAccount(AccountId),
+    InsufficientFunds {
+        account: AccountId,
+        balance: Cents,
+        requested: Cents,
+    },
+    Overflow(AccountId),
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerError::UnknownAccount(id) => write!(f, "unknown account {}", id),
+            LedgerError::InvalidAmount(amount) => {
+                write!(f, "amount must be positive, got {}", amount)
+            }
+            LedgerError::SameAccount(id) => write!(f, "cannot transfer from {} to itself", id),
+            LedgerError::InsufficientFunds {
+                account,
+                balance,
+                requested,
+            } => write!(
+                f,
+                "account {} has {} but {} was requested",
+                account, balance, requested
+            ),
+            LedgerError::Overflow(id) => {
+                write!(f, "money added to account {} would overflow the ledger's total", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LedgerError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+    Open(AccountId),
+    Deposit(AccountId),
+    Withdrawal(AccountId),
+    Transfer { from: AccountId, to: AccountId },
+}
+
+// One journal entry. The journal only hands out copies, so entries can't be
+// changed once written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transaction {
+    pub id: u64,
+    pub kind: TransactionKind,
+    pub amount: Cents,
+}
+
+impl Transaction {
+    pub fn involves(&self, account: AccountId) -> bool {
+        match self.kind {
+            TransactionKind::Open(id) | TransactionKind::Deposit(id) | TransactionKind::Withdrawal(id) => {
+                id == account
+            }
+            TransactionKind::Transfer { from, to } => from == account || to == account,
+        }
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            TransactionKind::Open(id) => write!(f, "[{}] open {} with {}", self.id, id, self.amount),
+            TransactionKind::Deposit(id) => write!(f, "[{}] deposit {} into {}", self.id, self.amount, id),
+            TransactionKind::Withdrawal(id) => {
+                write!(f, "[{}] withdraw {} from {}", self.id, self.amount, id)
+            }
+            TransactionKind::Transfer { from, to } => {
+                write!(f, "[{}] transfer {} from {} to {}", self.id, self.amount, from, to)
+            }
+        }
+    }
+}
+
+struct Account {
+    owner: String,
+    balance: Mutex<Cents>,
+}
+
+#[derive(Default)]
+pub struct Ledger {
+    // Accounts are never removed, so an AccountId is just an index
+    accounts: RwLock<Vec<Arc<Account>>>,
+    // Sum of all balances; only deposits and withdrawals change it
+    supply: Mutex<Cents>,
+    journal: Mutex<Vec<Transaction>>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Ledger::default()
+    }
+
+    fn account(&self, id: AccountId) -> Result<Arc<Account>, LedgerError> {
+        self.accounts
+            .read()
+            .unwrap()
+            .get(id.0)
+            .cloned()
+            .ok_or(LedgerError::UnknownAccount(id))
+    }
+
+    fn check_amount(amount: Cents) -> Result<(), LedgerError> {
+        if amount.is_positive() {
+            Ok(())
+        } else {
+            Err(LedgerError::InvalidAmount(amount))
+        }
+    }
+
+    // Adds newly deposited money to the supply, or fails if the ledger
+    // would hold more than i64::MAX cents in total
+    fn add_supply(&self, id: AccountId, amount: Cents) -> Result<(), LedgerError> {
+        let mut supply = self.supply.lock().unwrap();
+        *supply = supply.checked_add(amount).ok_or(LedgerError::Overflow(id))?;
+        Ok(())
+    }
+
+    // Must be called with the affected accounts still locked, so the journal
+    // order matches the order in which balances changed
+    fn record(&self, kind: TransactionKind, amount: Cents) -> u64 {
+        let mut journal = self.journal.lock().unwrap();
+        let id = journal.len() as u64;
+        journal.push(Transaction { id, kind, amount });
+        id
+    }
+
+    pub fn open_account(&self, owner: &str, initial: Cents) -> Result<AccountId, LedgerError> {
+        if initial < Cents::ZERO {
+            return Err(LedgerError::InvalidAmount(initial));
+        }
+        let mut accounts = self.accounts.write().unwrap();
+        let id = AccountId(accounts.len());
+        self.add_supply(id, initial)?;
+        accounts.push(Arc::new(Account {
+            owner: owner.to_string(),
+            balance: Mutex::new(initial),
+        }));
+        // Nobody can use the account before the write lock is released, so
+        // the Open entry always comes first in its history
+        self.record(TransactionKind::Open(id), initial);
+        Ok(id)
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.accounts.read().unwrap().len()
+    }
+
+    pub fn owner(&self, id: AccountId) -> Result<String, LedgerError> {
+        Ok(self.account(id)?.owner.clone())
+    }
+
+    pub fn balance(&self, id: AccountId) -> Result<Cents, LedgerError> {
+        Ok(*self.account(id)?.balance.lock().unwrap())
+    }
+
+    // Returns the new balance
+    pub fn deposit(&self, id: AccountId, amount: Cents) -> Result<Cents, LedgerError> {
+        Self::check_amount(amount)?;
+        let account = self.account(id)?;
+        let mut balance = account.balance.lock().unwrap();
+        // Can't overflow once the supply check passes
+        self.add_supply(id, amount)?;
+        *balance = *balance + amount;
+        self.record(TransactionKind::Deposit(id), amount);
+        Ok(*balance)
+    }
+
+    // Returns the new balance
+    pub fn withdraw(&self, id: AccountId, amount: Cents) -> Result<Cents, LedgerError> {
+        Self::check_amount(amount)?;
+        let account = self.account(id)?;
+        let mut balance = account.balance.lock().unwrap();
+        if *balance < amount {
+            return Err(LedgerError::InsufficientFunds {
+                account: id,
+                balance: *balance,
+                requested: amount,
+            });
+        }
+        *balance = *balance - amount;
+        let mut supply = self.supply.lock().unwrap();
+        *supply = *supply - amount;
+        drop(supply);
+        self.record(TransactionKind::Withdrawal(id), amount);
+        Ok(*balance)
+    }
+
+    // Moves `amount` between two accounts atomically: either both balances
+    // change and the transfer is journaled, or nothing happens. Returns the
+    // transaction id.
+    pub fn transfer(&self, from: AccountId, to: AccountId, amount: Cents) -> Result<u64, LedgerError> {
+        Self::check_amount(amount)?;
+        if from == to {
+            return Err(LedgerError::SameAccount(from));
+        }
+        let source = self.account(from)?;
+        let target = self.account(to)?;
+
+        // Lower id first; see the lock order at the top of the file
+        let (mut from_balance, mut to_balance) = if from < to {
+            let first = source.balance.lock().unwrap();
+            (first, target.balance.lock().unwrap())
+        } else {
+            let first = target.balance.lock().unwrap();
+            (source.balance.lock().unwrap(), first)
+        };
+
+        if *from_balance < amount {
+            return Err(LedgerError::InsufficientFunds {
+                account: from,
+                balance: *from_balance,
+                requested: amount,
+            });
+        }
+        let new_to = to_balance.checked_add(amount).ok_or(LedgerError::Overflow(to))?;
+        *from_balance = *from_balance - amount;
+        *to_balance = new_to;
+        Ok(self.record(TransactionKind::Transfer { from, to }, amount))
+    }
+
+    // Locks every account (in id order) so the total is a consistent
+    // snapshot even while transfers are running
+    fn lock_all<'a>(accounts: &'a [Arc<Account>]) -> Vec<MutexGuard<'a, Cents>> {
+        accounts.iter().map(|a| a.balance.lock().unwrap()).collect()
+    }
+
+    pub fn total(&self) -> Cents {
+        let accounts = self.accounts.read().unwrap();
+        let balances = Self::lock_all(&accounts);
+        balances.iter().map(|balance| **balance).sum()
+    }
+
+    pub fn balances(&self) -> Vec<(AccountId, Cents)> {
+        let accounts = self.accounts.read().unwrap();
+        let balances = Self::lock_all(&accounts);
+        balances
+            .iter()
+            .enumerate()
+            .map(|(i, balance)| (AccountId(i), **balance))
+            .collect()
+    }
+
+    // Copy of every transaction so far, oldest first
+    pub fn journal(&self) -> Vec<Transaction> {
+        self.journal.lock().unwrap().clone()
+    }
+
+    pub fn history(&self, id: AccountId) -> Vec<Transaction> {
+        self.journal
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|tx| tx.involves(id))
+            .copied()
+            .collect()
+    }
+
+    // Rebuilds every balance from the journal alone
+    pub fn replay(&self) -> HashMap<AccountId, Cents> {
+        let mut balances: HashMap<AccountId, Cents> = HashMap::new();
+        let mut apply = |id, change: fn(Cents, Cents) -> Cents, amount| {
+            let balance = balances.entry(id).or_default();
+            *balance = change(*balance, amount);
+        };
+        for tx in self.journal.lock().unwrap().iter() {
+            match tx.kind {
+                TransactionKind::Open(id) | TransactionKind::Deposit(id) => apply(id, Cents::add, tx.amount),
+                TransactionKind::Withdrawal(id) => apply(id, Cents::sub, tx.amount),
+                TransactionKind::Transfer { from, to } => {
+                    apply(from, Cents::sub, tx.amount);
+                    apply(to, Cents::add, tx.amount);
+                }
+            }
+        }
+        balances
+    }
+
+    // True if replaying the journal gives the current balances
+    pub fn audit(&self) -> bool {
+        let accounts = self.accounts.read().unwrap();
+        let guards = Self::lock_all(&accounts);
+        let replayed = self.replay();
+        guards.len() == replayed.len()
+            && guards
+                .iter()
+                .enumerate()
+                .all(|(i, balance)| replayed.get(&AccountId(i)) == Some(&**balance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // xorshift: enough randomness for picking accounts, no dependency needed
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_cents_display() {
+        assert_eq!(Cents(123_456).to_string(), "$1234.56");
+        assert_eq!(Cents(5).to_string(), "$0.05");
+        assert_eq!(Cents(-250).to_string(), "-$2.50");
+        assert_eq!(Cents::from_dollars(3), Cents(300));
+    }
+
+    #[test]
+    #[should_panic(expected = "too large an amount of cents")]
+    fn test_from_dollars_rejects_overflow() {
+        Cents::from_dollars(i64::MAX / 10);
+    }
+
+    #[test]
+    fn test_deposit_withdraw_transfer() {
+        let ledger = Ledger::new();
+        let alice = ledger.open_account("alice", Cents::from_dollars(100)).unwrap();
+        let bob = ledger.open_account("bob", Cents::ZERO).unwrap();
+
+        assert_eq!(ledger.deposit(alice, Cents(50)).unwrap(), Cents(10_050));
+        assert_eq!(ledger.withdraw(alice, Cents(1_050)).unwrap(), Cents(9_000));
+        ledger.transfer(alice, bob, Cents(2_500)).unwrap();
+
+        assert_eq!(ledger.balance(alice).unwrap(), Cents(6_500));
+        assert_eq!(ledger.balance(bob).unwrap(), Cents(2_500));
+        assert_eq!(ledger.owner(bob).unwrap(), "bob");
+        assert_eq!(ledger.history(bob).len(), 2);
+        assert!(ledger.audit());
+    }
+
+    #[test]
+    fn test_failed_operations_change_nothing() {
+        let ledger = Ledger::new();
+        let a = ledger.open_account("a", Cents(100)).unwrap();
+        let b = ledger.open_account("b", Cents(100)).unwrap();
+        let journal_len = ledger.journal().len();
+
+        assert_eq!(
+            ledger.transfer(a, b, Cents(101)),
+            Err(LedgerError::InsufficientFunds {
+                account: a,
+                balance: Cents(100),
+                requested: Cents(101)
+            })
+        );
+        assert_eq!(ledger.transfer(a, a, Cents(1)), Err(LedgerError::SameAccount(a)));
+        assert_eq!(ledger.deposit(a, Cents(-5)), Err(LedgerError::InvalidAmount(Cents(-5))));
+        assert_eq!(ledger.deposit(a, Cents(i64::MAX)), Err(LedgerError::Overflow(a)));
+        let unknown = AccountId(99);
+        assert_eq!(ledger.withdraw(unknown, Cents(1)), Err(LedgerError::UnknownAccount(unknown)));
+
+        assert_eq!(ledger.balances(), vec![(a, Cents(100)), (b, Cents(100))]);
+        assert_eq!(ledger.journal().len(), journal_len);
+    }
+
+    #[test]
+    fn test_total_money_cannot_overflow() {
+        let ledger = Ledger::new();
+        let half = Cents(i64::MAX / 2 + 1);
+        let a = ledger.open_account("a", half).unwrap();
+        // Fits in one account, but not in the ledger as a whole
+        assert_eq!(ledger.open_account("b", half), Err(LedgerError::Overflow(AccountId(1))));
+        let b = ledger.open_account("b", Cents(1)).unwrap();
+        assert_eq!(ledger.deposit(b, half), Err(LedgerError::Overflow(b)));
+        assert_eq!(ledger.account_count(), 2);
+
+        assert!(ledger.withdraw(a, Cents(10)).is_ok());
+        assert!(ledger.deposit(b, Cents(10)).is_ok());
+        assert_eq!(ledger.total(), Cents(i64::MAX / 2 + 2));
+        assert!(ledger.audit());
+    }
+
+    #[test]
+    fn test_money_is_conserved_under_random_concurrent_transfers() {
+        let ledger = Ledger::new();
+        let accounts: Vec<_> = (0..10)
+            .map(|i| ledger.open_account(&format!("acct{}", i), Cents(10_000)).unwrap())
+            .collect();
+        let initial_total = ledger.total();
+
+        thread::scope(|s| {
+            for t in 0..8 {
+                let (ledger, accounts) = (&ledger, &accounts);
+                s.spawn(move || {
+                    let mut rng = 0x2545_F491_4F6C_DD1D ^ (t + 1);
+                    for _ in 0..2_000 {
+                        let from = accounts[(next_random(&mut rng) % 10) as usize];
+                        let to = accounts[(next_random(&mut rng) % 10) as usize];
+                        let amount = Cents((next_random(&mut rng) % 5_000) as i64 + 1);
+                        // Failures (same account, not enough money) are fine;
+                        // they just must not leak money
+                        let _ = ledger.transfer(from, to, amount);
+                    }
+                });
+            }
+            // Totals taken mid-flight must already balance
+            s.spawn(|| {
+                for _ in 0..200 {
+                    assert_eq!(ledger.total(), initial_total);
+                }
+            });
+        });
+
+        assert_eq!(ledger.total(), initial_total);
+        assert!(ledger.balances().iter().all(|(_, balance)| *balance >= Cents::ZERO));
+        assert!(ledger.audit());
+    }
+
+    #[test]
+    fn test_opposite_transfers_do_not_deadlock() {
+        let ledger = Ledger::new();
+        let a = ledger.open_account("a", Cents(1_000)).unwrap();
+        let b = ledger.open_account("b", Cents(1_000)).unwrap();
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..5_000 {
+                    let _ = ledger.transfer(a, b, Cents(1));
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..5_000 {
+                    let _ = ledger.transfer(b, a, Cents(1));
+                }
+            });
+        });
+        assert_eq!(ledger.total(), Cents(2_000));
+    }
+
+    #[test]
+    fn test_journal_ids_are_sequential() {
+        let ledger = Ledger::new();
+        let a = ledger.open_account("a", Cents(500)).unwrap();
+        let b = ledger.open_account("b", Cents(0)).unwrap();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..50 {
+                        ledger.deposit(b, Cents(1)).unwrap();
+                        ledger.withdraw(b, Cents(1)).unwrap();
+                    }
+                });
+            }
+        });
+        ledger.transfer(a, b, Cents(500)).unwrap();
+
+        let journal = ledger.journal();
+        assert_eq!(journal.len(), 2 + 400 + 1);
+        assert!(journal.iter().enumerate().all(|(i, tx)| tx.id == i as u64));
+        assert_eq!(ledger.replay()[&b], Cents(500));
+    }
+}
@@ -0,0 +1,4 @@
+// Library root file - exposes modules for integration testing
+pub mod concurrent_map;
+pub mod ledger;
+pub mod tracked_mutex;
@@ -3,8 +3,11 @@
 
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::time::Duration;
-use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use shared_state::concurrent_map::ConcurrentMap;
+use shared_state::ledger::{AccountId, Cents, Ledger};
+use shared_state::tracked_mutex::{take_reports, TrackedMutex};
+use cache::{SyncCache, WTinyLfu};
 
 // TODO 1: Basic Mutex usage
 fn basic_mutex() {
@@ -129,56 +132,57 @@ fn shared_data_structure() {
 }
 
 // TODO 5: Bank account simulation
+// Amounts are integer cents: f64 dollars pick up rounding errors
 #[derive(Debug)]
 struct BankAccount {
-    balance: Mutex<f64>,
+    balance: Mutex<Cents>,
     account_number: String,
 }
 
 impl BankAccount {
-    fn new(account_number: String, initial_balance: f64) -> Self {
+    fn new(account_number: String, initial_balance: Cents) -> Self {
         BankAccount {
             balance: Mutex::new(initial_balance),
             account_number,
         }
     }
     
-    fn deposit(&self, amount: f64) {
+    fn deposit(&self, amount: Cents) {
         let mut balance = self.balance.lock().unwrap();
-        *balance += amount;
-        println!("Account {}: Deposited ${:.2}, new balance: ${:.2}", 
+        *balance = *balance + amount;
+        println!("Account {}: Deposited {}, new balance: {}", 
                 self.account_number, amount, *balance);
     }
     
-    fn withdraw(&self, amount: f64) -> bool {
+    fn withdraw(&self, amount: Cents) -> bool {
         let mut balance = self.balance.lock().unwrap();
         if *balance >= amount {
-            *balance -= amount;
-            println!("Account {}: Withdrew ${:.2}, new balance: ${:.2}", 
+            *balance = *balance - amount;
+            println!("Account {}: Withdrew {}, new balance: {}", 
                     self.account_number, amount, *balance);
             true
         } else {
-            println!("Account {}: Insufficient funds for ${:.2} withdrawal", 
+            println!("Account {}: Insufficient funds for {} withdrawal", 
                     self.account_number, amount);
             false
         }
     }
     
-    fn get_balance(&self) -> f64 {
+    fn get_balance(&self) -> Cents {
         *self.balance.lock().unwrap()
     }
 }
 
 fn bank_account_simulation() {
-    let account = Arc::new(BankAccount::new("12345".to_string(), 1000.0));
+    let account = Arc::new(BankAccount::new("12345".to_string(), Cents::from_dollars(1000)));
     let mut handles = vec![];
     
     // Multiple threads accessing the same account
     for i in 0..5 {
         let account = Arc::clone(&account);
         let handle = thread::spawn(move || {
-            account.deposit(50.0);
-            account.withdraw(30.0);
+            account.deposit(Cents::from_dollars(50));
+            account.withdraw(Cents::from_dollars(30));
             thread::sleep(Duration::from_millis(10));
         });
         handles.push(handle);
@@ -188,7 +192,57 @@ fn bank_account_simulation() {
         handle.join().unwrap();
     }
     
-    println!("Final balance: ${:.2}", account.get_balance());
+    println!("Final balance: {}", account.get_balance());
+}
+
+// A single account can't move money atomically to another one; the Ledger
+// locks both accounts (in id order, so it can't deadlock) and journals
+// every transfer
+fn ledger_example() {
+    let ledger = Arc::new(Ledger::new());
+    let accounts: Vec<AccountId> = ["alice", "bob", "carol", "dave"]
+        .iter()
+        .map(|owner| ledger.open_account(owner, Cents::from_dollars(250)).unwrap())
+        .collect();
+    let initial_total = ledger.total();
+    let mut handles = vec![];
+    
+    // Even threads send money clockwise and odd ones counter-clockwise, so
+    // opposite transfers between the same pair happen all the time. Thread
+    // t makes 250 * (t + 1) transfers, so money really moves around.
+    for t in 0..4 {
+        let ledger = Arc::clone(&ledger);
+        let accounts = accounts.clone();
+        let handle = thread::spawn(move || {
+            let mut failed = 0;
+            for i in 0..250 * (t + 1) {
+                let from = accounts[(t + i) % 4];
+                let to = if t % 2 == 0 { accounts[(t + i + 1) % 4] } else { accounts[(t + i + 3) % 4] };
+                let amount = Cents(((t * 31 + i * 17) % 5000) as i64 + 1);
+                if ledger.transfer(from, to, amount).is_err() {
+                    failed += 1;
+                }
+            }
+            failed
+        });
+        handles.push(handle);
+    }
+    
+    let failed: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+    
+    for (id, balance) in ledger.balances() {
+        println!("Account {} ({}): {}", id, ledger.owner(id).unwrap(), balance);
+    }
+    println!("Total before: {}, after: {} ({} transfers rejected)",
+             initial_total, ledger.total(), failed);
+    
+    if let Err(e) = ledger.transfer(accounts[0], accounts[1], Cents::from_dollars(10_000)) {
+        println!("Rejected: {}", e);
+    }
+    
+    let journal = ledger.journal();
+    println!("Journal has {} entries, last: {}", journal.len(), journal.last().unwrap());
+    println!("Journal replays to current balances: {}", ledger.audit());
 }
 
 // TODO 6: Deadlock demonstration (commented out to prevent actual deadlock)
@@ -230,31 +284,67 @@ fn deadlock_example() {
     println!("Deadlock example completed (deadlock prevented by commenting out conflicting locks)");
 }
 
-// TODO 7: Cache implementation with RwLock
+// TrackedMutex records the order in which each thread takes locks and
+// reports an inconsistent order even when the threads never actually
+// collide. Here they run one after the other, so nothing hangs, but the
+// two orders together are the classic deadlock.
+fn lock_order_detection() {
+    let resource1 = Arc::new(TrackedMutex::named("resource1", 1));
+    let resource2 = Arc::new(TrackedMutex::named("resource2", 2));
+    
+    let (r1, r2) = (Arc::clone(&resource1), Arc::clone(&resource2));
+    thread::Builder::new().name("thread-1".to_string()).spawn(move || {
+        let _guard1 = r1.lock().unwrap();
+        let _guard2 = r2.lock().unwrap();
+        println!("Thread 1: locked resource 1, then resource 2");
+    }).unwrap().join().unwrap();
+    
+    let (r1, r2) = (Arc::clone(&resource1), Arc::clone(&resource2));
+    thread::Builder::new().name("thread-2".to_string()).spawn(move || {
+        let _guard2 = r2.lock().unwrap();
+        let _guard1 = r1.lock().unwrap();
+        println!("Thread 2: locked resource 2, then resource 1");
+    }).unwrap().join().unwrap();
+    
+    // The report itself goes to stderr as soon as thread 2 takes resource 1
+    let reports = take_reports();
+    if reports.is_empty() {
+        println!("No report (lock tracking is off in release builds)");
+    } else {
+        println!("Detector reported {} potential deadlock(s)", reports.len());
+    }
+}
+
+// TODO 7: Bounded cache shared between threads
+// Even a lookup updates the LRU order and hit/miss counts, so SyncCache
+// guards its state with a Mutex rather than an RwLock
 struct Cache {
-    data: RwLock<HashMap<String, String>>,
+    data: SyncCache<String, String>,
 }
 
 impl Cache {
+    const DEFAULT_CAPACITY: usize = 1024;
+    
     fn new() -> Self {
+        Cache::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+    
+    fn with_capacity(capacity: usize) -> Self {
         Cache {
-            data: RwLock::new(HashMap::new()),
+            data: SyncCache::new(capacity),
         }
     }
     
     fn get(&self, key: &str) -> Option<String> {
-        let reader = self.data.read().unwrap();
-        reader.get(key).cloned()
+        self.data.get(key)
     }
     
     fn insert(&self, key: String, value: String) {
-        let mut writer = self.data.write().unwrap();
-        writer.insert(key, value);
+        self.data.insert(key, value);
     }
     
     fn len(&self) -> usize {
-        let reader = self.data.read().unwrap();
-        reader.len()
+        self.data.len()
     }
 }
 
@@ -291,6 +381,36 @@ fn cache_example() {
     }
     
     println!("Cache size: {}", cache.len());
+    println!("Cache stats: {}", cache.data.stats());
+    
+    // A small cache evicts entries once it is full
+    let small = Cache::with_capacity(2);
+    for i in 0..3 {
+        small.insert(format!("key{}", i), format!("value{}", i));
+    }
+    println!("Capacity 2 after 3 inserts: size {}, key0 evicted: {}",
+             small.len(), small.get("key0").is_none());
+    
+    // W-TinyLFU keeps frequently used keys even through a scan of new ones,
+    // and the loader fills misses (read-through)
+    let tiny_lfu = SyncCache::with_policy(100, WTinyLfu::new(100))
+        .with_ttl(Duration::from_secs(60));
+    for _ in 0..5 {
+        for i in 0..20 {
+            tiny_lfu.get_or_load(format!("hot{}", i), |key| key.to_uppercase());
+        }
+    }
+    for i in 0..500 {
+        tiny_lfu.get_or_load(format!("scan{}", i), |key| key.to_uppercase());
+    }
+    let hot_kept = (0..20).filter(|i| tiny_lfu.contains_key(&format!("hot{}", i))).count();
+    println!("W-TinyLFU kept {}/20 hot keys after a 500-key scan ({})",
+             hot_kept, tiny_lfu.stats());
+    
+    // Entries with a TTL expire on their own
+    tiny_lfu.insert_with_ttl("session".to_string(), "token".to_string(), Duration::from_millis(20));
+    thread::sleep(Duration::from_millis(30));
+    println!("Session after its TTL: {:?}", tiny_lfu.get("session"));
 }
 
 // TODO 8: Try lock example
@@ -331,6 +451,96 @@ fn try_lock_example() {
     }
 }
 
+// TODO 9: Sharded concurrent map
+fn concurrent_map_example() {
+    let map = Arc::new(ConcurrentMap::new());
+    println!("Map has {} shards", map.shard_count());
+    
+    // Word counting from several threads through the entry API
+    let texts = ["the quick brown fox", "the lazy dog", "the quick dog"];
+    let mut handles = vec![];
+    
+    for text in texts {
+        let map = Arc::clone(&map);
+        let handle = thread::spawn(move || {
+            for word in text.split_whitespace() {
+                *map.entry(word.to_string()).or_insert(0) += 1;
+            }
+        });
+        handles.push(handle);
+    }
+    
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    
+    let mut counts = map.snapshot();
+    counts.sort();
+    println!("Word counts: {:?}", counts);
+    
+    // compute_if_absent only runs the closure for missing keys
+    let length = map.compute_if_absent("elephant".to_string(), |word| word.len());
+    println!("compute_if_absent(\"elephant\") = {}", length);
+    let existing = map.compute_if_absent("the".to_string(), |_| unreachable!());
+    println!("compute_if_absent(\"the\") = {} (closure not called)", existing);
+    
+    // Resharding needs exclusive access, so unwrap the Arc first
+    let mut map = Arc::try_unwrap(map).ok().unwrap();
+    map.reshard(2);
+    println!("After reshard: {} shards with {:?} entries", map.shard_count(), map.shard_lens());
+}
+
+// TODO 10: Benchmark ConcurrentMap against the single-lock Cache
+fn run_mixed_workload<G, I>(threads: usize, ops: usize, read_percent: u64, get: G, insert: I) -> Duration
+where
+    G: Fn(&str) -> Option<String> + Sync,
+    I: Fn(String, String) + Sync,
+{
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..threads {
+            let (get, insert) = (&get, &insert);
+            s.spawn(move || {
+                // xorshift: cheap per-thread randomness without a dependency
+                let mut rng = 0x9E37_79B9_7F4A_7C15u64 ^ (t as u64 + 1);
+                for _ in 0..ops {
+                    rng ^= rng << 13;
+                    rng ^= rng >> 7;
+                    rng ^= rng << 17;
+                    let key = format!("key{}", rng % 1000);
+                    if rng % 100 < read_percent {
+                        get(&key);
+                    } else {
+                        insert(key, "value".to_string());
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+// Sharding only pays off when threads really run in parallel: on a single
+// core (or in a debug build) the extra hash to pick a shard can make
+// ConcurrentMap slower than the single-lock Cache. Try `cargo run --release`.
+fn concurrent_map_benchmark() {
+    let threads = thread::available_parallelism().map_or(4, |n| n.get()).max(4);
+    let ops = 50_000;
+    
+    for (name, read_percent) in [("read-heavy (90% reads)", 90), ("write-heavy (10% reads)", 10)] {
+        let cache = Cache::new();
+        let cache_time = run_mixed_workload(threads, ops, read_percent,
+            |k| cache.get(k), |k, v| cache.insert(k, v));
+        
+        let map = ConcurrentMap::new();
+        let map_time = run_mixed_workload(threads, ops, read_percent,
+            |k| map.get(k), |k, v| { map.insert(k, v); });
+        
+        println!("{}: Cache {:?}, ConcurrentMap {:?} ({} threads x {} ops)",
+                name, cache_time, map_time, threads, ops);
+    }
+}
+
 fn main() {
     println!("=== Shared State Concurrency ===\n");
     
@@ -349,14 +559,26 @@ fn main() {
     println!("\n--- Bank Account Simulation ---");
     bank_account_simulation();
     
+    println!("\n--- Ledger Example ---");
+    ledger_example();
+    
     println!("\n--- Deadlock Example ---");
     deadlock_example();
     
+    println!("\n--- Lock Order Detection ---");
+    lock_order_detection();
+    
     println!("\n--- Cache Example ---");
     cache_example();
     
     println!("\n--- Try Lock Example ---");
     try_lock_example();
     
+    println!("\n--- Concurrent Map Example ---");
+    concurrent_map_example();
+    
+    println!("\n--- Concurrent Map Benchmark ---");
+    concurrent_map_benchmark();
+    
     println!("\n=== All shared state examples completed! ===");
 }
@@ -0,0 +1,542 @@
+// Mutex with lock-order deadlock detection
+//
+// Every time a thread takes a TrackedMutex while already holding others, an
+// edge "held -> new" goes into a global lock-order graph, together with the
+// source locations of both lock() calls. If a new edge closes a cycle, some
+// pair of threads can take the same locks in opposite orders and deadlock,
+// even if this run got lucky. The cycle is reported right away, before the
+// thread blocks.
+//
+// Tracking only happens in debug builds; in release builds TrackedMutex is
+// a plain Mutex wrapper. Reports are printed to stderr and kept for
+// take_reports(); set_panic_on_deadlock(true) turns them into panics.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LockResult, Mutex, MutexGuard, PoisonError, TryLockError, TryLockResult};
+
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+#[cfg(debug_assertions)]
+use std::collections::{HashMap, HashSet};
+#[cfg(debug_assertions)]
+use std::sync::OnceLock;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+static PANIC_ON_DEADLOCK: AtomicBool = AtomicBool::new(false);
+
+pub struct TrackedMutex<T: ?Sized> {
+    id: usize,
+    name: Option<String>,
+    inner: Mutex<T>,
+}
+
+pub struct TrackedMutexGuard<'a, T: ?Sized> {
+    id: usize,
+    guard: MutexGuard<'a, T>,
+}
+
+// One observed ordering: `thread` locked `from` at `from_site`, then
+// `to` at `to_site` while still holding `from`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEdge {
+    pub from: String,
+    pub to: String,
+    pub from_site: &'static Location<'static>,
+    pub to_site: &'static Location<'static>,
+    pub thread: String,
+}
+
+// A cycle in the lock-order graph. The last edge is the one that was just
+// observed; the others were recorded earlier and lead back to its start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadlockReport {
+    pub cycle: Vec<LockEdge>,
+}
+
+impl DeadlockReport {
+    pub fn involves(&self, lock_name: &str) -> bool {
+        self.cycle.iter().any(|e| e.from == lock_name || e.to == lock_name)
+    }
+}
+
+impl fmt::Display for LockEdge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "thread '{}' locked {} at {}, then {} at {}",
+            self.thread, self.from, self.from_site, self.to, self.to_site
+        )
+    }
+}
+
+impl fmt::Display for DeadlockReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "potential deadlock: inconsistent lock order")?;
+        for edge in &self.cycle {
+            write!(f, "\n  {}", edge)?;
+        }
+        Ok(())
+    }
+}
+
+fn display_name(id: usize, name: Option<&str>) -> String {
+    match name {
+        Some(name) => name.to_string(),
+        None => format!("lock#{}", id),
+    }
+}
+
+pub fn set_panic_on_deadlock(enabled: bool) {
+    PANIC_ON_DEADLOCK.store(enabled, Ordering::SeqCst);
+}
+
+// Returns and clears every report made so far, from any thread
+pub fn take_reports() -> Vec<DeadlockReport> {
+    #[cfg(debug_assertions)]
+    {
+        std::mem::take(&mut graph().lock().unwrap().reports)
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        Vec::new()
+    }
+}
+
+impl<T> TrackedMutex<T> {
+    pub fn new(value: T) -> Self {
+        TrackedMutex {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name: None,
+            inner: Mutex::new(value),
+        }
+    }
+
+    // The name is used in reports instead of "lock#<id>"
+    pub fn named(name: &str, value: T) -> Self {
+        TrackedMutex {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            name: Some(name.to_string()),
+            inner: Mutex::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        // Drop would unregister the lock and then drop the fields; do the
+        // same by hand so the Mutex can be moved out
+        let this = std::mem::ManuallyDrop::new(self);
+        unregister(this.id);
+        let (name, inner) = unsafe { (std::ptr::read(&this.name), std::ptr::read(&this.inner)) };
+        drop(name);
+        inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> TrackedMutex<T> {
+    pub fn name(&self) -> String {
+        display_name(self.id, self.name.as_deref())
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> LockResult<TrackedMutexGuard<'_, T>> {
+        // The name is only turned into a String the first time the graph
+        // sees this lock, so release builds never build one here
+        before_lock(self.id, self.name.as_deref(), Location::caller());
+        let wrap = |guard| TrackedMutexGuard { id: self.id, guard };
+        let result = match self.inner.lock() {
+            Ok(guard) => Ok(wrap(guard)),
+            Err(poisoned) => Err(PoisonError::new(wrap(poisoned.into_inner()))),
+        };
+        after_lock(self.id, Location::caller());
+        result
+    }
+
+    // A failed try_lock can't block, so it can't deadlock either: only a
+    // successful one is recorded as held, and it adds no ordering edges
+    #[track_caller]
+    pub fn try_lock(&self) -> TryLockResult<TrackedMutexGuard<'_, T>> {
+        let wrap = |guard| TrackedMutexGuard { id: self.id, guard };
+        let result = match self.inner.try_lock() {
+            Ok(guard) => Ok(wrap(guard)),
+            Err(TryLockError::Poisoned(poisoned)) => {
+                Err(TryLockError::Poisoned(PoisonError::new(wrap(poisoned.into_inner()))))
+            }
+            Err(TryLockError::WouldBlock) => return Err(TryLockError::WouldBlock),
+        };
+        after_lock(self.id, Location::caller());
+        result
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: ?Sized> Drop for TrackedMutex<T> {
+    fn drop(&mut self) {
+        unregister(self.id);
+    }
+}
+
+impl<T: Default> Default for TrackedMutex<T> {
+    fn default() -> Self {
+        TrackedMutex::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for TrackedMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TrackedMutex")
+            .field("name", &self.name())
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<T: ?Sized> Deref for TrackedMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized> DerefMut for TrackedMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized> Drop for TrackedMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        after_unlock(self.id);
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TrackedMutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.guard, f)
+    }
+}
+
+// Bookkeeping shared by all TrackedMutexes. Compiled out in release builds.
+
+#[cfg(debug_assertions)]
+struct EdgeInfo {
+    from_site: &'static Location<'static>,
+    to_site: &'static Location<'static>,
+    thread: String,
+}
+
+#[cfg(debug_assertions)]
+#[derive(Default)]
+struct LockGraph {
+    names: HashMap<usize, String>,
+    // edges[a][b]: b was locked while a was held
+    edges: HashMap<usize, HashMap<usize, EdgeInfo>>,
+    reported: HashSet<(usize, usize)>,
+    reports: Vec<DeadlockReport>,
+}
+
+#[cfg(debug_assertions)]
+impl LockGraph {
+    // Depth-first search for a path start -> ... -> goal
+    fn find_path(&self, start: usize, goal: usize) -> Option<Vec<usize>> {
+        let mut stack = vec![vec![start]];
+        let mut seen = HashSet::from([start]);
+        while let Some(path) = stack.pop() {
+            let last = *path.last().unwrap();
+            if last == goal {
+                return Some(path);
+            }
+            for &next in self.edges.get(&last).into_iter().flat_map(|e| e.keys()) {
+                if seen.insert(next) {
+                    let mut longer = path.clone();
+                    longer.push(next);
+                    stack.push(longer);
+                }
+            }
+        }
+        None
+    }
+
+    fn edge(&self, from: usize, to: usize) -> LockEdge {
+        let info = &self.edges[&from][&to];
+        LockEdge {
+            from: self.names[&from].clone(),
+            to: self.names[&to].clone(),
+            from_site: info.from_site,
+            to_site: info.to_site,
+            thread: info.thread.clone(),
+        }
+    }
+}
+
+#[cfg(debug_assertions)]
+fn graph() -> &'static Mutex<LockGraph> {
+    static GRAPH: OnceLock<Mutex<LockGraph>> = OnceLock::new();
+    GRAPH.get_or_init(Default::default)
+}
+
+#[cfg(debug_assertions)]
+thread_local! {
+    // Locks held by this thread and where each was taken
+    static HELD: RefCell<Vec<(usize, &'static Location<'static>)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[cfg(debug_assertions)]
+fn before_lock(id: usize, name: Option<&str>, site: &'static Location<'static>) {
+    let held = HELD.with(|held| held.borrow().clone());
+    let thread = std::thread::current().name().unwrap_or("<unnamed>").to_string();
+    let mut new_reports = Vec::new();
+    {
+        // The graph lock is never held while taking a TrackedMutex
+        let mut graph = graph().lock().unwrap_or_else(PoisonError::into_inner);
+        graph.names.entry(id).or_insert_with(|| display_name(id, name));
+
+        for &(held_id, held_site) in &held {
+            if held_id == id {
+                // Locking a Mutex this thread already holds never returns
+                graph.edges.entry(id).or_default().entry(id).or_insert(EdgeInfo {
+                    from_site: held_site,
+                    to_site: site,
+                    thread: thread.clone(),
+                });
+                if graph.reported.insert((id, id)) {
+                    let report = DeadlockReport {
+                        cycle: vec![graph.edge(id, id)],
+                    };
+                    new_reports.push(report);
+                }
+                continue;
+            }
+            if graph.edges.get(&held_id).is_some_and(|e| e.contains_key(&id)) {
+                // Already known, and checked when it was first added
+                continue;
+            }
+            graph.edges.entry(held_id).or_default().insert(
+                id,
+                EdgeInfo {
+                    from_site: held_site,
+                    to_site: site,
+                    thread: thread.clone(),
+                },
+            );
+            if let Some(path) = graph.find_path(id, held_id) {
+                if graph.reported.insert((held_id, id)) {
+                    let mut cycle: Vec<LockEdge> =
+                        path.windows(2).map(|pair| graph.edge(pair[0], pair[1])).collect();
+                    cycle.push(graph.edge(held_id, id));
+                    new_reports.push(DeadlockReport { cycle });
+                }
+            }
+        }
+        graph.reports.extend(new_reports.iter().cloned());
+    }
+    for report in new_reports {
+        if PANIC_ON_DEADLOCK.load(Ordering::SeqCst) {
+            panic!("{}", report);
+        }
+        eprintln!("{}", report);
+    }
+}
+
+#[cfg(debug_assertions)]
+fn after_lock(id: usize, site: &'static Location<'static>) {
+    HELD.with(|held| held.borrow_mut().push((id, site)));
+}
+
+#[cfg(debug_assertions)]
+fn after_unlock(id: usize) {
+    // Guards may be dropped in any order
+    HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        if let Some(pos) = held.iter().rposition(|&(held_id, _)| held_id == id) {
+            held.remove(pos);
+        }
+    });
+}
+
+#[cfg(debug_assertions)]
+fn unregister(id: usize) {
+    let mut graph = graph().lock().unwrap_or_else(PoisonError::into_inner);
+    graph.names.remove(&id);
+    graph.edges.remove(&id);
+    for targets in graph.edges.values_mut() {
+        targets.remove(&id);
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn before_lock(_id: usize, _name: Option<&str>, _site: &'static Location<'static>) {}
+
+#[cfg(not(debug_assertions))]
+fn after_lock(_id: usize, _site: &'static Location<'static>) {}
+
+#[cfg(not(debug_assertions))]
+fn after_unlock(_id: usize) {}
+
+#[cfg(not(debug_assertions))]
+fn unregister(_id: usize) {}
+
+// Detection only exists in debug builds, which is what `cargo test` uses
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    // Tests that create cycles take this, so one test's reports (or panic
+    // setting) can't leak into another running in parallel
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    fn serial() -> MutexGuard<'static, ()> {
+        SERIAL.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn reports_for(name: &str) -> Vec<DeadlockReport> {
+        // Tests run in parallel and share the graph, so only look at our
+        // own locks (every test uses unique names)
+        take_reports().into_iter().filter(|r| r.involves(name)).collect()
+    }
+
+    #[test]
+    fn test_consistent_order_is_not_reported() {
+        let _serial = serial();
+        let a = TrackedMutex::named("consistent-a", 0);
+        let b = TrackedMutex::named("consistent-b", 0);
+        for _ in 0..3 {
+            let _ga = a.lock().unwrap();
+            let _gb = b.lock().unwrap();
+        }
+        assert!(reports_for("consistent-a").is_empty());
+    }
+
+    #[test]
+    fn test_inverted_order_reports_both_sites() {
+        let _serial = serial();
+        let a = TrackedMutex::named("inverted-a", 0);
+        let b = TrackedMutex::named("inverted-b", 0);
+        {
+            let _ga = a.lock().unwrap();
+            let _gb = b.lock().unwrap();
+        }
+        // Same thread, one after the other: no real deadlock, but another
+        // thread doing the first half at the same time would hang
+        let _gb = b.lock().unwrap();
+        let _ga = a.lock().unwrap();
+
+        let reports = reports_for("inverted-a");
+        assert_eq!(reports.len(), 1);
+        let cycle = &reports[0].cycle;
+        assert_eq!(cycle.len(), 2);
+        assert_eq!((cycle[0].from.as_str(), cycle[0].to.as_str()), ("inverted-a", "inverted-b"));
+        assert_eq!((cycle[1].from.as_str(), cycle[1].to.as_str()), ("inverted-b", "inverted-a"));
+        // Both call sites of each order, pointing at the lock() calls above
+        assert!(cycle.iter().all(|e| e.from_site.file() == file!() && e.to_site.file() == file!()));
+        assert!(cycle[0].to_site.line() < cycle[1].from_site.line());
+        assert_eq!(cycle[1].from_site.line() + 1, cycle[1].to_site.line());
+    }
+
+    #[test]
+    fn test_cycle_across_three_locks_and_threads() {
+        let _serial = serial();
+        let locks: Arc<Vec<_>> = Arc::new(
+            ["tri-0", "tri-1", "tri-2"]
+                .iter()
+                .map(|name| TrackedMutex::named(name, ()))
+                .collect(),
+        );
+        // Thread i takes lock i then lock i+1; run them one at a time so the
+        // program never actually deadlocks
+        for i in 0..3 {
+            let locks = Arc::clone(&locks);
+            thread::Builder::new()
+                .name(format!("tri-thread-{}", i))
+                .spawn(move || {
+                    let _first = locks[i].lock().unwrap();
+                    let _second = locks[(i + 1) % 3].lock().unwrap();
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+        }
+        let reports = reports_for("tri-0");
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].cycle.len(), 3);
+        assert_eq!(reports[0].cycle[2].thread, "tri-thread-2");
+        assert!(reports[0].to_string().contains("potential deadlock"));
+    }
+
+    #[test]
+    fn test_reported_only_once() {
+        let _serial = serial();
+        let a = TrackedMutex::named("once-a", ());
+        let b = TrackedMutex::named("once-b", ());
+        for _ in 0..5 {
+            {
+                let _ga = a.lock().unwrap();
+                let _gb = b.lock().unwrap();
+            }
+            let _gb = b.lock().unwrap();
+            let _ga = a.lock().unwrap();
+        }
+        assert_eq!(reports_for("once-a").len(), 1);
+    }
+
+    #[test]
+    fn test_try_lock_adds_no_edges() {
+        let _serial = serial();
+        let a = TrackedMutex::named("try-a", ());
+        let b = TrackedMutex::named("try-b", ());
+        {
+            let _ga = a.lock().unwrap();
+            let _gb = b.try_lock().unwrap();
+        }
+        let _gb = b.lock().unwrap();
+        let _ga = a.try_lock().unwrap();
+        assert!(reports_for("try-a").is_empty());
+    }
+
+    #[test]
+    fn test_panic_mode() {
+        let _serial = serial();
+        let a = Arc::new(TrackedMutex::named("panic-a", ()));
+        let b = Arc::new(TrackedMutex::named("panic-b", ()));
+        {
+            let _ga = a.lock().unwrap();
+            let _gb = b.lock().unwrap();
+        }
+        let (a2, b2) = (Arc::clone(&a), Arc::clone(&b));
+        let result = thread::spawn(move || {
+            // PANIC_ON_DEADLOCK is global, so it is only on around the
+            // lock call that must fail
+            let _gb = b2.lock().unwrap();
+            set_panic_on_deadlock(true);
+            let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let _ga = a2.lock();
+            }));
+            set_panic_on_deadlock(false);
+            outcome.is_err()
+        })
+        .join()
+        .unwrap();
+        assert!(result);
+        assert!(!a.is_poisoned());
+    }
+
+    #[test]
+    fn test_guards_and_into_inner() {
+        let m = TrackedMutex::new(vec![1]);
+        m.lock().unwrap().push(2);
+        assert_eq!(format!("{:?}", m.lock().unwrap()), "[1, 2]");
+        assert!(m.name().starts_with("lock#"));
+        assert_eq!(m.into_inner().unwrap(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,4 @@
+// Library root file - exposes modules for integration testing
+pub mod bank;
+pub mod counter;
+pub mod sched;
@@ -3,40 +3,13 @@
 
 use std::sync::{Arc, Mutex, Barrier, atomic::{AtomicUsize, Ordering}};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use std::collections::HashMap;
-
-// Example concurrent data structure to test
-struct ConcurrentCounter {
-    value: Mutex<i32>,
-}
-
-impl ConcurrentCounter {
-    fn new() -> Self {
-        ConcurrentCounter {
-            value: Mutex::new(0),
-        }
-    }
-    
-    fn increment(&self) {
-        let mut val = self.value.lock().unwrap();
-        *val += 1;
-    }
-    
-    fn decrement(&self) {
-        let mut val = self.value.lock().unwrap();
-        *val -= 1;
-    }
-    
-    fn get(&self) -> i32 {
-        *self.value.lock().unwrap()
-    }
-    
-    fn add(&self, amount: i32) {
-        let mut val = self.value.lock().unwrap();
-        *val += amount;
-    }
-}
+use testing_concurrency::bank::BankAccount;
+use testing_concurrency::counter::ConcurrentCounter;
+use testing_concurrency::sched;
+use property::{Arbitrary, Gen, Parallel, QuickCheck};
+use bench::Suite;
 
 // TODO 1: Basic stress testing
 fn stress_test_counter() {
@@ -71,6 +44,25 @@ fn stress_test_counter() {
     println!("Final counter value: {}", final_value);
     println!("Expected value: {}", expected);
     println!("Test {}", if final_value == expected { "PASSED" } else { "FAILED" });
+    
+    // A stress test only samples whatever interleavings the OS happens to
+    // produce; a small version can be checked against all of them
+    let result = sched::check_exhaustive(10_000, || {
+        let counter = Arc::new(ConcurrentCounter::new());
+        let other = Arc::clone(&counter);
+        let handle = sched::spawn(move || {
+            other.increment();
+            other.decrement();
+        });
+        counter.decrement();
+        counter.increment();
+        handle.join().unwrap();
+        assert_eq!(counter.get(), 0);
+    });
+    match result {
+        Ok(report) => println!("Exhaustive check PASSED ({} interleavings)", report.executions),
+        Err(failure) => println!("Exhaustive check FAILED - {}", failure),
+    }
 }
 
 // TODO 2: Deterministic testing with barriers
@@ -109,62 +101,70 @@ fn deterministic_test_with_barriers() {
 }
 
 // TODO 3: Race condition detection
+// Each access is locked, but the read-modify-write as a whole is not, so
+// two increments can interleave between the read and the write
 struct UnsafeCounter {
-    value: i32, // No mutex - intentionally unsafe!
+    value: sched::Mutex<i32>,
 }
 
 impl UnsafeCounter {
     fn new() -> Self {
-        UnsafeCounter { value: 0 }
+        UnsafeCounter { value: sched::Mutex::new(0) }
     }
     
-    fn increment(&mut self) {
-        let temp = self.value;
-        thread::sleep(Duration::from_nanos(1)); // Increase chance of race
-        self.value = temp + 1;
+    fn increment(&self) {
+        let temp = *self.value.lock().unwrap();
+        *self.value.lock().unwrap() = temp + 1;
     }
     
     fn get(&self) -> i32 {
-        self.value
+        *self.value.lock().unwrap()
     }
 }
 
-fn detect_race_conditions() {
-    println!("\n--- Race Condition Detection ---");
-    
-    // Run multiple iterations to catch race conditions
-    let mut race_detected = false;
-    
-    for iteration in 0..10 {
-        let counter = Arc::new(Mutex::new(UnsafeCounter::new()));
-        let mut handles = vec![];
-        
-        for _ in 0..5 {
+fn racy_increments() {
+    let counter = Arc::new(UnsafeCounter::new());
+    let handles: Vec<_> = (0..3)
+        .map(|_| {
             let counter = Arc::clone(&counter);
-            let handle = thread::spawn(move || {
-                for _ in 0..20 {
-                    counter.lock().unwrap().increment();
+            sched::spawn(move || {
+                for _ in 0..2 {
+                    counter.increment();
                 }
-            });
-            handles.push(handle);
-        }
-        
-        for handle in handles {
-            handle.join().unwrap();
-        }
-        
-        let final_value = counter.lock().unwrap().get();
-        let expected = 100;
-        
-        if final_value != expected {
-            println!("Iteration {}: Race detected! Got {}, expected {}", 
-                    iteration, final_value, expected);
-            race_detected = true;
-        }
+            })
+        })
+        .collect();
+    
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    
+    let final_value = counter.get();
+    if final_value != 6 {
+        panic!("race detected: got {}, expected 6", final_value);
     }
+}
+
+fn detect_race_conditions() {
+    println!("\n--- Race Condition Detection ---");
     
-    if !race_detected {
-        println!("No races detected in this run (try running multiple times)");
+    // Instead of sleeping and hoping, let the scheduler pick the
+    // interleavings. The same seed always explores the same schedules.
+    // The scheduler catches the expected panic, after the default hook has
+    // printed it.
+    let result = sched::check_random(42, 1000, racy_increments);
+    
+    match result {
+        Ok(report) => println!("No races found in {} schedules", report.executions),
+        Err(failure) => {
+            println!("{}", failure);
+            let seed = failure.seed.unwrap();
+            
+            // A failing seed reproduces the exact same interleaving
+            let replayed = sched::replay_seed(seed, racy_increments);
+            println!("Replaying seed {:#x}: {}", seed,
+                    if replayed.is_err() { "fails again" } else { "passed?!" });
+        }
     }
 }
 
@@ -210,83 +210,127 @@ fn test_for_deadlocks() {
 }
 
 // TODO 5: Property-based testing for concurrency
-fn property_based_testing() {
-    println!("\n--- Property-Based Testing ---");
+#[derive(Debug, Clone, Copy)]
+enum CounterOp {
+    Increment,
+    Decrement,
+    Add(i8),
+}
+
+impl CounterOp {
+    fn apply(&self, counter: &ConcurrentCounter) {
+        match *self {
+            CounterOp::Increment => counter.increment(),
+            CounterOp::Decrement => counter.decrement(),
+            CounterOp::Add(amount) => counter.add(amount as i32),
+        }
+    }
     
-    // Property: Total operations should equal final state change
-    let counter = Arc::new(ConcurrentCounter::new());
-    let operations = Arc::new(AtomicUsize::new(0));
-    let mut handles = vec![];
+    fn effect(&self) -> i32 {
+        match *self {
+            CounterOp::Increment => 1,
+            CounterOp::Decrement => -1,
+            CounterOp::Add(amount) => amount as i32,
+        }
+    }
+}
+
+impl Arbitrary for CounterOp {
+    fn arbitrary(g: &mut Gen) -> Self {
+        match g.below(3) {
+            0 => CounterOp::Increment,
+            1 => CounterOp::Decrement,
+            _ => CounterOp::Add(i8::arbitrary(g)),
+        }
+    }
     
-    for thread_id in 0..8 {
-        let counter = Arc::clone(&counter);
-        let operations = Arc::clone(&operations);
-        let handle = thread::spawn(move || {
-            for i in 0..50 {
-                if (thread_id + i) % 3 == 0 {
-                    counter.increment();
-                    operations.fetch_add(1, Ordering::SeqCst);
-                } else if (thread_id + i) % 3 == 1 {
-                    counter.decrement();
-                    operations.fetch_add(1, Ordering::SeqCst);
-                } else {
-                    counter.add(2);
-                    operations.fetch_add(1, Ordering::SeqCst);
-                }
+    // Increment is the simplest operation; Add shrinks its amount
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match *self {
+            CounterOp::Increment => Box::new(std::iter::empty()),
+            CounterOp::Decrement => Box::new(std::iter::once(CounterOp::Increment)),
+            CounterOp::Add(amount) => {
+                let smaller = amount.shrink().map(CounterOp::Add);
+                Box::new(std::iter::once(CounterOp::Increment).chain(smaller))
             }
-        });
-        handles.push(handle);
+        }
+    }
+}
+
+fn property_based_testing() {
+    println!("\n--- Property-Based Testing ---");
+    
+    // Property: for any mix of operations spread over several threads, the
+    // final state change equals the sum of the individual changes
+    let result = QuickCheck::new().seed(2024).run(|case: Parallel<CounterOp>| {
+        let counter = ConcurrentCounter::new();
+        case.run(&counter, |counter, _, op| op.apply(counter));
+        let expected: i32 = case.operations().map(CounterOp::effect).sum();
+        counter.get() == expected
+    });
+    match result {
+        Ok(report) => println!("Counter property held for {} generated cases", report.tests),
+        Err(failure) => println!("Counter property FAILED: {}", failure),
     }
     
-    for handle in handles {
-        handle.join().unwrap();
+    // Property: every transfer is counted and none is lost
+    let result = QuickCheck::new().seed(2024).run(|case: Parallel<i8>| {
+        let account = BankAccount::new(1000);
+        case.run(&account, |account, _, &amount| account.transfer(amount as i32));
+        let expected = 1000 + case.operations().map(|&amount| amount as i32).sum::<i32>();
+        account.get_balance() == expected && account.get_transaction_count() == case.len()
+    });
+    match result {
+        Ok(report) => println!("Bank account invariants held for {} generated cases", report.tests),
+        Err(failure) => println!("Bank account property FAILED: {}", failure),
     }
     
-    let total_operations = operations.load(Ordering::SeqCst);
-    println!("Total operations performed: {}", total_operations);
-    println!("Final counter value: {}", counter.get());
-    println!("Property verified: All operations completed atomically");
+    // A property that is false: the failing case is shrunk to a minimal one
+    let result = QuickCheck::new().seed(2024).run(|case: Parallel<CounterOp>| {
+        let counter = ConcurrentCounter::new();
+        case.run(&counter, |counter, _, op| op.apply(counter));
+        counter.get().abs() <= 100
+    });
+    if let Err(failure) = result {
+        println!("Expected failure, \"counter stays within 100\":\n{}", failure);
+    }
 }
 
 // TODO 6: Performance testing under load
+// Every iteration performs the same total number of increments, spread over
+// more and more threads, so the medians show how contention scales.
 fn performance_testing() {
     println!("\n--- Performance Testing ---");
     
-    let counter = Arc::new(ConcurrentCounter::new());
+    let counter = ConcurrentCounter::new();
     let iterations = 100_000;
-    let thread_counts = vec![1, 2, 4, 8];
+    let thread_counts = [1, 2, 4, 8];
     
+    let mut suite = Suite::new().warmup(Duration::from_millis(50)).samples(10);
     for num_threads in thread_counts {
-        let counter = Arc::clone(&counter);
-        let start_time = Instant::now();
-        let mut handles = vec![];
-        
-        for _ in 0..num_threads {
-            let counter = Arc::clone(&counter);
-            let handle = thread::spawn(move || {
-                for _ in 0..iterations / num_threads {
-                    counter.increment();
+        let counter = &counter;
+        suite.bench(&format!("{} threads", num_threads), move || {
+            thread::scope(|s| {
+                for _ in 0..num_threads {
+                    s.spawn(|| {
+                        for _ in 0..iterations / num_threads {
+                            counter.increment();
+                        }
+                    });
                 }
             });
-            handles.push(handle);
-        }
-        
-        for handle in handles {
-            handle.join().unwrap();
-        }
-        
-        let duration = start_time.elapsed();
-        let ops_per_second = iterations as f64 / duration.as_secs_f64();
-        
-        println!("Threads: {}, Time: {:?}, Ops/sec: {:.0}", 
-                num_threads, duration, ops_per_second);
-        
-        // Reset counter for next test
-        let final_value = counter.get();
-        for _ in 0..final_value {
-            counter.decrement();
-        }
+        });
+    }
+    let report = suite.run();
+    println!("{}", report);
+    
+    for result in &report.results {
+        println!("{}: {:.0} ops/sec", 
+                result.name, iterations as f64 * result.stats.throughput());
     }
+    println!("Every increment counted: {}", counter.get() as usize % iterations == 0);
+    
+    report.compare_with_saved("testing_concurrency");
 }
 
 // TODO 7: Memory ordering testing
@@ -346,34 +390,6 @@ fn memory_ordering_test() {
 }
 
 // TODO 8: Testing thread safety invariants
-struct BankAccount {
-    balance: Mutex<i32>,
-    transaction_count: AtomicUsize,
-}
-
-impl BankAccount {
-    fn new(initial_balance: i32) -> Self {
-        BankAccount {
-            balance: Mutex::new(initial_balance),
-            transaction_count: AtomicUsize::new(0),
-        }
-    }
-    
-    fn transfer(&self, amount: i32) {
-        let mut balance = self.balance.lock().unwrap();
-        *balance += amount;
-        self.transaction_count.fetch_add(1, Ordering::Relaxed);
-    }
-    
-    fn get_balance(&self) -> i32 {
-        *self.balance.lock().unwrap()
-    }
-    
-    fn get_transaction_count(&self) -> usize {
-        self.transaction_count.load(Ordering::Relaxed)
-    }
-}
-
 fn test_thread_safety_invariants() {
     println!("\n--- Thread Safety Invariants Test ---");
     
@@ -408,38 +424,34 @@ fn test_thread_safety_invariants() {
     println!("All invariants maintained!");
 }
 
-// TODO 9: Stress testing with random delays
+// TODO 9: Stress testing with random schedules
 fn chaos_testing() {
-    println!("\n--- Chaos Testing (Random Delays) ---");
-    
-    let counter = Arc::new(ConcurrentCounter::new());
-    let mut handles = vec![];
-    
-    for i in 0..5 {
-        let counter = Arc::clone(&counter);
-        let handle = thread::spawn(move || {
-            for j in 0..20 {
-                // Random delays to create different interleavings
-                let delay_ms = (i * 7 + j * 3) % 10;
-                thread::sleep(Duration::from_millis(delay_ms as u64));
-                
-                counter.increment();
-            }
-        });
-        handles.push(handle);
-    }
-    
-    for handle in handles {
-        handle.join().unwrap();
-    }
-    
-    let final_value = counter.get();
-    println!("Final counter value: {} (expected: 100)", final_value);
+    println!("\n--- Chaos Testing (Random Schedules) ---");
+    
+    // Random delays only shift timing a little; random schedules pick who
+    // runs at every lock, and a failure comes with the seed to replay it
+    let result = sched::check_random(7, 500, || {
+        let counter = Arc::new(ConcurrentCounter::new());
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                sched::spawn(move || {
+                    for _ in 0..5 {
+                        counter.increment();
+                    }
+                })
+            })
+            .collect();
+        
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(counter.get(), 20);
+    });
     
-    if final_value == 100 {
-        println!("Chaos test PASSED");
-    } else {
-        println!("Chaos test FAILED - race condition detected!");
+    match result {
+        Ok(report) => println!("Chaos test PASSED ({} random schedules)", report.executions),
+        Err(failure) => println!("Chaos test FAILED - {}", failure),
     }
 }
 
@@ -462,5 +474,5 @@ fn main() {
     println!("2. Use tools like 'cargo test --release' for realistic performance tests");
     println!("3. Consider using loom (https://crates.io/crates/loom) for model checking");
     println!("4. Use thread sanitizer: RUSTFLAGS=\"-Z sanitizer=thread\" cargo run");
-    println!("5. Add randomness and delays to expose timing-dependent bugs");
+    println!("5. Explore interleavings with sched::check_random / check_exhaustive instead of delays");
 }
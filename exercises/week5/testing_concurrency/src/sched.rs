@@ -0,0 +1,756 @@
+// Deterministic scheduler for testing concurrent code
+//
+// Code under test spawns threads with sched::spawn and shares data through
+// sched::Mutex. Inside a check_* call those threads are real OS threads,
+// but only one runs at a time: at every sync point (spawn, lock, join,
+// yield_now) the scheduler decides who runs next. That turns "run it a
+// thousand times and hope" into a search over interleavings:
+//
+// - check_random tries `executions` random schedules from a seed; a
+//   failure reports the seed of the failing run, and replay_seed(seed)
+//   runs exactly that schedule again
+// - check_exhaustive walks every schedule depth-first (up to a limit),
+//   and a failure reports its schedule for replay_schedule
+//
+// Only sync points are interleaved, so the body must be deterministic
+// apart from scheduling: no real time, no outside randomness, and no
+// shared state other than through sched types and atomics. Outside a
+// check, spawn and Mutex behave like their std counterparts, so the same
+// types can be used in normal code. Any panic or deadlock fails the run.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{self, Arc, Condvar, LockResult, PoisonError, TryLockError};
+use std::thread;
+
+// A single execution gives up after this many scheduling decisions
+const MAX_STEPS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Runnable,
+    BlockedOnLock(usize),
+    BlockedOnJoin(usize),
+    Finished,
+}
+
+// How the next thread is picked when more than one can run
+enum Chooser {
+    Random(Rng),
+    // Follows `prefix`, then always picks the first option; records every
+    // decision as (picked, options) so the explorer can backtrack
+    Guided {
+        prefix: Vec<usize>,
+        choices: Vec<(usize, usize)>,
+    },
+}
+
+impl Chooser {
+    fn choose(&mut self, options: usize) -> usize {
+        match self {
+            Chooser::Random(rng) => rng.below(options),
+            Chooser::Guided { prefix, choices } => {
+                let pick = prefix
+                    .get(choices.len())
+                    .copied()
+                    .unwrap_or(0)
+                    .min(options - 1);
+                choices.push((pick, options));
+                pick
+            }
+        }
+    }
+}
+
+// xorshift64*, plus splitmix64 to turn (seed, run) into a per-run seed
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) % n as u64) as usize
+    }
+}
+
+fn mix(seed: u64, run: u64) -> u64 {
+    let mut x = seed.wrapping_add(run.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+struct State {
+    status: Vec<Status>,
+    active: usize,
+    chooser: Chooser,
+    trace: Vec<usize>,
+    failure: Option<String>,
+}
+
+struct Execution {
+    state: sync::Mutex<State>,
+    turn: Condvar,
+    os_threads: sync::Mutex<Vec<thread::JoinHandle<()>>>,
+}
+
+// Panic payload used to unwind the threads of a failed execution
+struct Abort;
+
+#[derive(Clone)]
+struct Context {
+    execution: Arc<Execution>,
+    id: usize,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+fn context() -> Option<Context> {
+    CONTEXT.with(|c| c.borrow().clone())
+}
+
+impl Execution {
+    fn lock_state(&self) -> sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Picks the next thread to run. Called with the state locked by the
+    // thread that is giving up its turn.
+    fn schedule(&self, state: &mut State) {
+        let runnable: Vec<usize> = (0..state.status.len())
+            .filter(|&id| state.status[id] == Status::Runnable)
+            .collect();
+        if runnable.is_empty() {
+            if state.failure.is_none() && state.status.iter().any(|&s| s != Status::Finished) {
+                state.failure = Some(format!("deadlock: {}", describe_blocked(&state.status)));
+            }
+        } else if state.trace.len() >= MAX_STEPS {
+            state.failure = Some(format!("gave up after {} steps (livelock?)", MAX_STEPS));
+        } else {
+            let pick = if runnable.len() == 1 {
+                0
+            } else {
+                state.chooser.choose(runnable.len())
+            };
+            state.active = runnable[pick];
+            state.trace.push(state.active);
+        }
+        self.turn.notify_all();
+    }
+
+    // Blocks until it is thread `me`'s turn; unwinds if the execution failed
+    fn wait_turn(&self, mut state: sync::MutexGuard<'_, State>, me: usize) {
+        loop {
+            if state.failure.is_some() {
+                drop(state);
+                panic::resume_unwind(Box::new(Abort));
+            }
+            if state.active == me && state.status[me] == Status::Runnable {
+                return;
+            }
+            state = self
+                .turn
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    // A sync point: let the scheduler pick who runs next (maybe us again)
+    fn switch(&self, me: usize) {
+        let mut state = self.lock_state();
+        self.schedule(&mut state);
+        self.wait_turn(state, me);
+    }
+
+    fn block(&self, me: usize, status: Status) {
+        let mut state = self.lock_state();
+        state.status[me] = status;
+        self.schedule(&mut state);
+        self.wait_turn(state, me);
+    }
+
+    fn spawn_thread<F: FnOnce() + Send + 'static>(self: &Arc<Self>, id: usize, f: F) {
+        let execution = Arc::clone(self);
+        let handle = thread::spawn(move || {
+            CONTEXT.with(|c| {
+                *c.borrow_mut() = Some(Context {
+                    execution: Arc::clone(&execution),
+                    id,
+                })
+            });
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                execution.wait_turn(execution.lock_state(), id);
+                f();
+            }));
+            CONTEXT.with(|c| c.borrow_mut().take());
+
+            let mut state = execution.lock_state();
+            state.status[id] = Status::Finished;
+            for status in state.status.iter_mut() {
+                if *status == Status::BlockedOnJoin(id) {
+                    *status = Status::Runnable;
+                }
+            }
+            if let Err(payload) = result {
+                if !payload.is::<Abort>() && state.failure.is_none() {
+                    state.failure = Some(format!(
+                        "thread {} panicked: {}",
+                        id,
+                        panic_message(&payload)
+                    ));
+                }
+            }
+            if state.failure.is_none() {
+                execution.schedule(&mut state);
+            }
+            execution.turn.notify_all();
+        });
+        self.os_threads.lock().unwrap().push(handle);
+    }
+}
+
+fn describe_blocked(status: &[Status]) -> String {
+    status
+        .iter()
+        .enumerate()
+        .filter_map(|(id, s)| match s {
+            Status::BlockedOnLock(lock) => Some(format!("thread {} waits for mutex {}", id, lock)),
+            Status::BlockedOnJoin(other) => {
+                Some(format!("thread {} waits to join thread {}", id, other))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn panic_message(payload: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "<non-string panic>".to_string()
+    }
+}
+
+struct Outcome {
+    failure: Option<String>,
+    trace: Vec<usize>,
+    chooser: Chooser,
+}
+
+fn run_once(body: &Arc<dyn Fn() + Send + Sync>, chooser: Chooser) -> Outcome {
+    let execution = Arc::new(Execution {
+        state: sync::Mutex::new(State {
+            status: vec![Status::Runnable],
+            active: 0,
+            chooser,
+            trace: vec![0],
+            failure: None,
+        }),
+        turn: Condvar::new(),
+        os_threads: sync::Mutex::new(Vec::new()),
+    });
+    let body = Arc::clone(body);
+    execution.spawn_thread(0, move || body());
+
+    {
+        let mut state = execution.lock_state();
+        while state.failure.is_none() && state.status.iter().any(|&s| s != Status::Finished) {
+            state = execution
+                .turn
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+    loop {
+        let handles = std::mem::take(&mut *execution.os_threads.lock().unwrap());
+        if handles.is_empty() {
+            break;
+        }
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+
+    let mut state = execution.lock_state();
+    Outcome {
+        failure: state.failure.take(),
+        trace: std::mem::take(&mut state.trace),
+        chooser: std::mem::replace(&mut state.chooser, Chooser::Random(Rng::new(0))),
+    }
+}
+
+// A failed execution and how to reproduce it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub message: String,
+    // Which execution of the check failed (0-based)
+    pub execution: usize,
+    // For check_random: pass to replay_seed
+    pub seed: Option<u64>,
+    // For check_exhaustive: pass to replay_schedule
+    pub schedule: Vec<usize>,
+    // Thread that ran after each sync point
+    pub trace: Vec<usize>,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "execution {} failed: {}", self.execution, self.message)?;
+        match self.seed {
+            Some(seed) => write!(f, " (replay_seed({:#x}))", seed)?,
+            None => write!(f, " (replay_schedule(&{:?}))", self.schedule)?,
+        }
+        write!(f, "\n  thread order: {:?}", self.trace)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    pub executions: usize,
+    // For check_exhaustive: false if the limit was hit first
+    pub complete: bool,
+}
+
+fn boxed<F: Fn() + Send + Sync + 'static>(body: F) -> Arc<dyn Fn() + Send + Sync> {
+    Arc::new(body)
+}
+
+pub fn check_random<F>(seed: u64, executions: usize, body: F) -> Result<Report, Failure>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let body = boxed(body);
+    for execution in 0..executions {
+        let run_seed = mix(seed, execution as u64);
+        let outcome = run_once(&body, Chooser::Random(Rng::new(run_seed)));
+        if let Some(message) = outcome.failure {
+            return Err(Failure {
+                message,
+                execution,
+                seed: Some(run_seed),
+                schedule: Vec::new(),
+                trace: outcome.trace,
+            });
+        }
+    }
+    Ok(Report {
+        executions,
+        complete: false,
+    })
+}
+
+pub fn check_exhaustive<F>(max_executions: usize, body: F) -> Result<Report, Failure>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let body = boxed(body);
+    let mut prefix = Vec::new();
+    for execution in 0..max_executions {
+        let outcome = run_once(
+            &body,
+            Chooser::Guided {
+                prefix,
+                choices: Vec::new(),
+            },
+        );
+        let Chooser::Guided { mut choices, .. } = outcome.chooser else {
+            unreachable!("guided run returned another chooser");
+        };
+        if let Some(message) = outcome.failure {
+            return Err(Failure {
+                message,
+                execution,
+                seed: None,
+                schedule: choices.iter().map(|&(pick, _)| pick).collect(),
+                trace: outcome.trace,
+            });
+        }
+        // Backtrack: bump the deepest decision that still has options left
+        loop {
+            match choices.pop() {
+                Some((pick, options)) if pick + 1 < options => {
+                    prefix = choices.iter().map(|&(pick, _)| pick).collect();
+                    prefix.push(pick + 1);
+                    break;
+                }
+                Some(_) => continue,
+                None => {
+                    return Ok(Report {
+                        executions: execution + 1,
+                        complete: true,
+                    })
+                }
+            }
+        }
+    }
+    Ok(Report {
+        executions: max_executions,
+        complete: false,
+    })
+}
+
+pub fn replay_seed<F>(seed: u64, body: F) -> Result<(), Failure>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let outcome = run_once(&boxed(body), Chooser::Random(Rng::new(seed)));
+    match outcome.failure {
+        Some(message) => Err(Failure {
+            message,
+            execution: 0,
+            seed: Some(seed),
+            schedule: Vec::new(),
+            trace: outcome.trace,
+        }),
+        None => Ok(()),
+    }
+}
+
+pub fn replay_schedule<F>(schedule: &[usize], body: F) -> Result<(), Failure>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    let chooser = Chooser::Guided {
+        prefix: schedule.to_vec(),
+        choices: Vec::new(),
+    };
+    let outcome = run_once(&boxed(body), chooser);
+    match outcome.failure {
+        Some(message) => Err(Failure {
+            message,
+            execution: 0,
+            seed: None,
+            schedule: schedule.to_vec(),
+            trace: outcome.trace,
+        }),
+        None => Ok(()),
+    }
+}
+
+// A sync point with no other effect
+pub fn yield_now() {
+    match context() {
+        Some(ctx) => ctx.execution.switch(ctx.id),
+        None => thread::yield_now(),
+    }
+}
+
+pub struct JoinHandle<T> {
+    inner: JoinInner<T>,
+}
+
+enum JoinInner<T> {
+    Std(thread::JoinHandle<T>),
+    Model {
+        id: usize,
+        result: Arc<sync::Mutex<Option<T>>>,
+    },
+}
+
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let Some(ctx) = context() else {
+        return JoinHandle {
+            inner: JoinInner::Std(thread::spawn(f)),
+        };
+    };
+    let result = Arc::new(sync::Mutex::new(None));
+    let id = {
+        let mut state = ctx.execution.lock_state();
+        state.status.push(Status::Runnable);
+        state.status.len() - 1
+    };
+    let slot = Arc::clone(&result);
+    ctx.execution.spawn_thread(id, move || {
+        let value = f();
+        *slot.lock().unwrap() = Some(value);
+    });
+    // The new thread may get to run first
+    ctx.execution.switch(ctx.id);
+    JoinHandle {
+        inner: JoinInner::Model { id, result },
+    }
+}
+
+impl<T> JoinHandle<T> {
+    pub fn join(self) -> thread::Result<T> {
+        match self.inner {
+            JoinInner::Std(handle) => handle.join(),
+            JoinInner::Model { id, result } => {
+                let ctx = context().expect("joined a scheduled thread from outside its check");
+                let finished = ctx.execution.lock_state().status[id] == Status::Finished;
+                if !finished {
+                    ctx.execution.block(ctx.id, Status::BlockedOnJoin(id));
+                }
+                // A panicking thread fails the whole execution, so a
+                // finished thread always left its result behind
+                let value = result.lock().unwrap().take();
+                value.ok_or_else(|| Box::new("thread produced no result") as Box<_>)
+            }
+        }
+    }
+}
+
+static NEXT_MUTEX_ID: AtomicUsize = AtomicUsize::new(0);
+
+// std::sync::Mutex whose lock() is a sync point when run under a check
+pub struct Mutex<T: ?Sized> {
+    id: usize,
+    inner: sync::Mutex<T>,
+}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex_id: usize,
+    // Option so Drop can release the lock before waking waiters
+    guard: Option<sync::MutexGuard<'a, T>>,
+}
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Mutex {
+            id: NEXT_MUTEX_ID.fetch_add(1, Ordering::Relaxed),
+            inner: sync::Mutex::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    fn wrap<'a>(&self, guard: sync::MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        MutexGuard {
+            mutex_id: self.id,
+            guard: Some(guard),
+        }
+    }
+
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let Some(ctx) = context() else {
+            return match self.inner.lock() {
+                Ok(guard) => Ok(self.wrap(guard)),
+                Err(poisoned) => Err(PoisonError::new(self.wrap(poisoned.into_inner()))),
+            };
+        };
+        ctx.execution.switch(ctx.id);
+        loop {
+            // Only one thread runs at a time, so WouldBlock means another
+            // (paused) thread holds the lock
+            match self.inner.try_lock() {
+                Ok(guard) => return Ok(self.wrap(guard)),
+                Err(TryLockError::Poisoned(poisoned)) => {
+                    return Err(PoisonError::new(self.wrap(poisoned.into_inner())))
+                }
+                Err(TryLockError::WouldBlock) => {
+                    ctx.execution.block(ctx.id, Status::BlockedOnLock(self.id));
+                }
+            }
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mutex")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.guard.take();
+        if let Some(ctx) = context() {
+            let mut state = ctx.execution.lock_state();
+            for status in state.status.iter_mut() {
+                if *status == Status::BlockedOnLock(self.mutex_id) {
+                    *status = Status::Runnable;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads and writes under separate locks, so two increments can
+    // interleave and lose one: the classic lost update
+    fn racy_increment(value: &Mutex<i32>) {
+        let current = *value.lock().unwrap();
+        *value.lock().unwrap() = current + 1;
+    }
+
+    fn two_racy_increments() {
+        let value = Arc::new(Mutex::new(0));
+        let other = Arc::clone(&value);
+        let handle = spawn(move || racy_increment(&other));
+        racy_increment(&value);
+        handle.join().unwrap();
+        assert_eq!(*value.lock().unwrap(), 2, "lost update");
+    }
+
+    #[test]
+    fn test_exhaustive_finds_lost_update() {
+        let failure = check_exhaustive(1000, two_racy_increments).unwrap_err();
+        assert!(failure.message.contains("lost update"));
+        // The schedule reproduces it every time
+        for _ in 0..5 {
+            let replayed = replay_schedule(&failure.schedule, two_racy_increments).unwrap_err();
+            assert_eq!(replayed.trace, failure.trace);
+        }
+    }
+
+    #[test]
+    fn test_random_failure_replays_from_seed() {
+        let failure = check_random(7, 1000, two_racy_increments).unwrap_err();
+        let seed = failure.seed.unwrap();
+        for _ in 0..5 {
+            let replayed = replay_seed(seed, two_racy_increments).unwrap_err();
+            assert_eq!(replayed.trace, failure.trace);
+            assert_eq!(replayed.message, failure.message);
+        }
+    }
+
+    #[test]
+    fn test_same_seed_explores_same_schedules() {
+        let traces = || {
+            let log = Arc::new(sync::Mutex::new(Vec::new()));
+            let recorder = Arc::clone(&log);
+            check_random(99, 20, move || {
+                let order = Arc::new(Mutex::new(Vec::new()));
+                let handles: Vec<_> = (0..3)
+                    .map(|i| {
+                        let order = Arc::clone(&order);
+                        spawn(move || order.lock().unwrap().push(i))
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+                recorder.lock().unwrap().push(order.lock().unwrap().clone());
+            })
+            .unwrap();
+            let log = log.lock().unwrap().clone();
+            log
+        };
+        let first = traces();
+        assert_eq!(first, traces());
+        // 20 random runs of 3 racing pushes should not all agree
+        assert!(first.iter().any(|order| order != &first[0]));
+    }
+
+    #[test]
+    fn test_exhaustive_covers_every_order() {
+        let seen = Arc::new(sync::Mutex::new(std::collections::HashSet::new()));
+        let recorder = Arc::clone(&seen);
+        let report = check_exhaustive(10_000, move || {
+            let order = Arc::new(Mutex::new(Vec::new()));
+            let handles: Vec<_> = (0..3)
+                .map(|i| {
+                    let order = Arc::clone(&order);
+                    spawn(move || order.lock().unwrap().push(i))
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            recorder
+                .lock()
+                .unwrap()
+                .insert(order.lock().unwrap().clone());
+        })
+        .unwrap();
+        assert!(report.complete);
+        assert_eq!(seen.lock().unwrap().len(), 6);
+    }
+
+    #[test]
+    fn test_deadlock_is_reported() {
+        let failure = check_exhaustive(1000, || {
+            let a = Arc::new(Mutex::new(()));
+            let b = Arc::new(Mutex::new(()));
+            let (a2, b2) = (Arc::clone(&a), Arc::clone(&b));
+            let handle = spawn(move || {
+                let _b = b2.lock().unwrap();
+                let _a = a2.lock().unwrap();
+            });
+            {
+                let _a = a.lock().unwrap();
+                let _b = b.lock().unwrap();
+            }
+            handle.join().unwrap();
+        })
+        .unwrap_err();
+        assert!(failure.message.starts_with("deadlock"), "{}", failure);
+    }
+
+    #[test]
+    fn test_join_returns_value() {
+        check_exhaustive(100, || {
+            let handle = spawn(|| 6 * 7);
+            assert_eq!(handle.join().unwrap(), 42);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_works_as_std_outside_a_check() {
+        let value = Arc::new(Mutex::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let value = Arc::clone(&value);
+                spawn(move || {
+                    for _ in 0..100 {
+                        *value.lock().unwrap() += 1;
+                        yield_now();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*value.lock().unwrap(), 400);
+    }
+}